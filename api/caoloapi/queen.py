@@ -1,13 +1,54 @@
+import hashlib
+import hmac
+import time
+
 import grpc
-from .config import QUEEN_URL
+from .config import QUEEN_URL, AUTH_SECRET
 from .util import aio_with_backoff
 
 _QUEEN_CHANNEL_CACHE = {}
 
+_TOKEN_TTL_SECS = 60
+
+
+def service_token(secret: str, now=None) -> str:
+    """Sign a service token for the worker, see the `auth` module of the worker"""
+    now = now or time.time()
+    payload = f"service.{int(now) + _TOKEN_TTL_SECS}"
+    signature = hmac.new(
+        secret.encode("utf-8"), payload.encode("utf-8"), hashlib.sha256
+    ).hexdigest()
+    return f"{payload}.{signature}"
+
+
+class _ServiceAuthInterceptor(
+    grpc.aio.UnaryUnaryClientInterceptor, grpc.aio.UnaryStreamClientInterceptor
+):
+    def __init__(self, secret: str):
+        self.secret = secret
+
+    def _with_token(self, client_call_details):
+        metadata = list(client_call_details.metadata or [])
+        metadata.append(("authorization", f"Bearer {service_token(self.secret)}"))
+        return client_call_details._replace(metadata=metadata)
+
+    async def intercept_unary_unary(self, continuation, client_call_details, request):
+        return await continuation(self._with_token(client_call_details), request)
+
+    async def intercept_unary_stream(
+        self, continuation, client_call_details, request
+    ):
+        return await continuation(self._with_token(client_call_details), request)
+
 
 @aio_with_backoff(retries=None, max_sleep=10)
 async def queen_channel(queen_url=QUEEN_URL):
     global _QUEEN_CHANNEL_CACHE
     if queen_url not in _QUEEN_CHANNEL_CACHE:
-        _QUEEN_CHANNEL_CACHE[queen_url] = grpc.aio.insecure_channel(queen_url)
+        interceptors = None
+        if AUTH_SECRET:
+            interceptors = [_ServiceAuthInterceptor(AUTH_SECRET)]
+        _QUEEN_CHANNEL_CACHE[queen_url] = grpc.aio.insecure_channel(
+            queen_url, interceptors=interceptors
+        )
     return _QUEEN_CHANNEL_CACHE[queen_url]
@@ -6,6 +6,7 @@ import (
 	"io"
 	"log"
 	"net/http"
+	"os"
 	"time"
 
 	cao_world "github.com/caolo-game/cao-rt/cao_world_pb"
@@ -20,7 +21,7 @@ func listenToWorld(conn *grpc.ClientConn, worldState chan *cao_world.RoomEntitie
 	client := cao_world.NewWorldClient(conn)
 
 	for {
-		stream, err := client.Entities(context.Background(), &cao_world.Empty{})
+		stream, err := client.Entities(context.Background(), &cao_world.EntitiesRequest{})
 		if err != nil {
 			panic(err)
 		}
@@ -77,6 +78,12 @@ func main() {
 		},
 		MinConnectTimeout: time.Second * 10,
 	}))
+	if secret := os.Getenv("CAO_AUTH_SECRET"); secret != "" {
+		opts = append(opts, grpc.WithPerRPCCredentials(serviceCredentials{
+			secret: []byte(secret),
+			ttl:    time.Minute,
+		}))
+	}
 
 	log.Println("Starting")
 
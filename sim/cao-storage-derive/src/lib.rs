@@ -3,16 +3,210 @@
 #![crate_type = "proc-macro"]
 use proc_macro::TokenStream;
 use proc_macro2::TokenTree;
-use quote::quote;
+use quote::{quote, quote_spanned};
 use std::collections::HashMap;
-use syn::{parse_macro_input, AttrStyle, DeriveInput, Ident};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, AttrStyle, Data, DataStruct, DeriveInput, Fields, Ident, Meta,
+    NestedMeta,
+};
 
-#[proc_macro_derive(CaoStorage, attributes(cao_storage_table, cao_storage_iterby))]
+#[proc_macro_derive(
+    CaoStorage,
+    attributes(cao_storage_table, cao_storage_iterby, cao_storage_track)
+)]
 pub fn derive_storage(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     impl_storage(input)
 }
 
+/// Declare the tables storing the component.
+///
+/// ```ignore
+/// #[derive(CaoComponent)]
+/// #[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
+/// #[cao_storage(key = "UserId", table = "BTreeTable<UserId, Self>")]
+/// #[cao_storage(transient)]
+/// pub struct MyComponent;
+/// ```
+///
+/// Every `key`/`table` pair implements `Component<key>` and `ComponentSerde<key>` for the type.
+/// The `TABLE_NAME` of the implementations is the path of the type followed by the key.
+/// The `Id` of the `table` must be `key`.
+/// `transient` components are not serialized with the archetypes holding them.
+///
+/// The derive does not register the component in the World, it still has to be listed in the
+/// `archetype!` of its key, which names its field in the store.
+#[proc_macro_derive(CaoComponent, attributes(cao_storage))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    impl_component(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+struct ComponentTable {
+    key: syn::Type,
+    table: syn::Type,
+}
+
+fn parse_type(lit: &syn::Lit) -> syn::Result<syn::Type> {
+    match lit {
+        // spans of the parsed type point to the literal
+        syn::Lit::Str(lit) => lit.parse(),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            "expected a string literal containing a type",
+        )),
+    }
+}
+
+fn parse_component_attrs(input: &DeriveInput) -> syn::Result<(Vec<ComponentTable>, bool)> {
+    let mut tables = Vec::new();
+    let mut transient = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cao_storage"))
+    {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected `#[cao_storage(...)]`",
+                ))
+            }
+        };
+        let mut key = None;
+        let mut table = None;
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("transient") => {
+                    transient = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("key") => {
+                    key = Some(parse_type(&nv.lit)?);
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("table") => {
+                    table = Some(parse_type(&nv.lit)?);
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "expected `key = \"...\"`, `table = \"...\"` or `transient`",
+                    ))
+                }
+            }
+        }
+        match (key, table) {
+            (Some(key), Some(table)) => tables.push(ComponentTable { key, table }),
+            (None, None) => {}
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "`key` and `table` must be declared together",
+                ))
+            }
+        }
+    }
+    if tables.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "components must declare their table via `#[cao_storage(key = \"...\", table = \"...\")]`",
+        ));
+    }
+    Ok((tables, transient))
+}
+
+fn impl_component(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let (tables, transient) = parse_component_attrs(&input)?;
+    let name = &input.ident;
+
+    let mut generics = input.generics.clone();
+    generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(Self: crate::tables::TableRow));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let implementations = tables.iter().map(|ComponentTable { key, table }| {
+        let mut serde_generics = generics.clone();
+        let (serialize, deserialize) = if transient {
+            (
+                quote! {
+                    let _ = table;
+                    serializer.serialize_unit()
+                },
+                quote! {
+                    <serde::de::IgnoredAny as serde::Deserialize>::deserialize(deserializer)?;
+                    Ok(Default::default())
+                },
+            )
+        } else {
+            serde_generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote!(#table: serde::Serialize + serde::de::DeserializeOwned));
+            (
+                quote! {
+                    serde::Serialize::serialize(table, serializer)
+                },
+                quote! {
+                    serde::Deserialize::deserialize(deserializer)
+                },
+            )
+        };
+        let serde_where = &serde_generics.where_clause;
+        quote! {
+            impl #impl_generics crate::tables::Component<#key> for #name #ty_generics #where_clause {
+                type Table = #table;
+
+                const TABLE_NAME: &'static str =
+                    concat!(module_path!(), "::", stringify!(#name), "<", stringify!(#key), ">");
+            }
+
+            impl #impl_generics crate::tables::ComponentSerde<#key> for #name #ty_generics #serde_where {
+                const TRANSIENT: bool = #transient;
+
+                fn serialize_table<S: serde::Serializer>(
+                    table: &<Self as crate::tables::Component<#key>>::Table,
+                    serializer: S,
+                ) -> Result<S::Ok, S::Error> {
+                    #serialize
+                }
+
+                fn deserialize_table<'de, D: serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<<Self as crate::tables::Component<#key>>::Table, D::Error> {
+                    #deserialize
+                }
+            }
+        }
+    });
+
+    // point the errors of inconsistent keys at the `table` of the attribute
+    let key_checks = tables.iter().map(|ComponentTable { key, table }| {
+        quote_spanned! {table.span()=>
+            {
+                fn key_of<T: crate::tables::Table<Id = #key>>() {}
+                key_of::<#table>();
+            }
+        }
+    });
+
+    Ok(quote! {
+        #(#implementations)*
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[allow(dead_code)]
+            fn __cao_storage_check_keys() {
+                #(#key_checks)*
+            }
+        }
+    })
+}
+
 #[derive(Debug)]
 struct TableMeta {
     /// type of the key/id
@@ -197,9 +391,115 @@ fn impl_iterators<'a>(
     }
 }
 
+/// Implement a `components` getter returning every component of a single key.
+/// Only generated for the keys that have an `iterby`, as those rows are known to be serializable.
+fn impl_components_getter(
+    name: &Ident,
+    generics: &syn::Generics,
+    meta: Option<&TableMeta>,
+) -> proc_macro2::TokenStream {
+    let TableMeta { key, fields, rows } = match meta {
+        Some(meta) => meta,
+        None => return quote! {},
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ty_fields = fields.iter().zip(rows.iter()).map(|(f, r)| {
+        quote! {
+            pub #f : Option<&'boi #r>
+        }
+    });
+    let gets = fields.iter().map(|f| {
+        quote! {
+            let #f = self.#f.get_by_id(id)
+        }
+    });
+    let is_some = fields.iter().map(|f| {
+        quote! {
+            self.#f.is_some()
+        }
+    });
+    quote! {
+        /// Every component of a single key
+        #[allow(non_camel_case_types)]
+        #[derive(serde::Serialize)]
+        pub struct Components_Tuple <'boi> {
+            pub __id: #key,
+
+            #(
+                #[serde(skip_serializing_if = "Option::is_none")]
+                #ty_fields
+            ),*,
+        }
+
+        impl <'boi> Components_Tuple <'boi> {
+            /// Returns true if the key has no components
+            pub fn is_empty(&self) -> bool {
+                !(#(#is_some)||*)
+            }
+        }
+
+        impl <#impl_generics> #name #ty_generics #where_clause {
+            pub fn components <'boi> (&'boi self, id: #key) -> Components_Tuple <'boi> {
+                #(#gets);*;
+                Components_Tuple {
+                    __id: id,
+                    #(#fields),*
+                }
+            }
+        }
+    }
+}
+
+/// Implement change tracking for the fields marked with `#[cao_storage_track]`
+fn impl_change_tracking(
+    name: &Ident,
+    generics: &syn::Generics,
+    tracked: &[Ident],
+) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl <#impl_generics> #name #ty_generics #where_clause {
+            /// Start recording the changes of the tracked tables
+            pub fn enable_change_tracking(&mut self) {
+                #(
+                    crate::tables::TrackedTable::change_tracker_mut(&mut self.#tracked).enable();
+                )*
+            }
+
+            /// Publish the changes recorded since the last commit of the tracked tables
+            pub fn commit_changes(&mut self) {
+                #(
+                    crate::tables::TrackedTable::change_tracker_mut(&mut self.#tracked).commit();
+                )*
+            }
+        }
+    }
+}
+
+fn tracked_fields(data: &Data) -> Vec<Ident> {
+    let fields = match data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => return vec![],
+    };
+    fields
+        .iter()
+        .filter(|field| {
+            field
+                .attrs
+                .iter()
+                .any(|attr| attr.path.is_ident("cao_storage_track"))
+        })
+        .filter_map(|field| field.ident.clone())
+        .collect()
+}
+
 fn impl_storage(input: DeriveInput) -> TokenStream {
     let name: &Ident = &input.ident;
     let generics: syn::Generics = input.generics;
+    let tracked = tracked_fields(&input.data);
 
     let mut table_groups: HashMap<String, TableMeta> = HashMap::with_capacity(16);
     let mut iterators = Vec::with_capacity(16);
@@ -247,6 +547,14 @@ fn impl_storage(input: DeriveInput) -> TokenStream {
 
     let tables = impl_tables(name, &generics, &table_groups);
 
+    let components = impl_components_getter(
+        name,
+        &generics,
+        iterators
+            .first()
+            .and_then(|(_, key)| table_groups.get(format!("{}", key).as_str())),
+    );
+
     let iters = impl_iterators(
         name,
         &generics,
@@ -264,10 +572,16 @@ fn impl_storage(input: DeriveInput) -> TokenStream {
         }),
     );
 
+    let change_tracking = impl_change_tracking(name, &generics, tracked.as_slice());
+
     let result = quote! {
         #tables
 
         #iters
+
+        #components
+
+        #change_tracking
     };
 
     TokenStream::from(result)
@@ -0,0 +1,392 @@
+//! Batch runner for balancing experiments.
+//!
+//! Runs every variant of an experiment with every seed and writes the outcome of the runs into
+//! the output directory:
+//!
+//! - `runs.json`: every sample of every run
+//! - `samples.csv`: the same as `runs.json`, one row per sample
+//! - `summary.csv`: the final state of the runs, averaged by variant
+//!
+//! Experiments are described in YAML:
+//!
+//! ```yaml
+//! ticks: 500
+//! sample_interval: 50
+//! users: 4
+//! seeds: [1, 2, 3]
+//! # relative to the experiment file
+//! scripts:
+//!   - ../simulation/src/programs/mining_program.yaml
+//! # GameConfig shared by the variants
+//! base:
+//!   world_radius: 2
+//!   room_radius: 16
+//! variants:
+//!   - name: baseline
+//!   # merged over the base config
+//!   - name: cheap_bots
+//!     config:
+//!       spawn:
+//!         bot_cost: 200
+//! ```
+use crate::scripts;
+use anyhow::Context;
+use caolo_sim::events::{Attacked, Events};
+use caolo_sim::lifecycle::EntityDeletions;
+use caolo_sim::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Experiment {
+    pub ticks: u64,
+    /// Take a sample every N ticks, the final state is always sampled
+    #[serde(default = "default_sample_interval")]
+    pub sample_interval: u64,
+    #[serde(default = "default_users")]
+    pub users: usize,
+    pub seeds: Vec<u64>,
+    #[serde(default)]
+    pub scripts: Vec<PathBuf>,
+    #[serde(default = "empty_mapping")]
+    pub base: Value,
+    pub variants: Vec<Variant>,
+}
+
+fn default_sample_interval() -> u64 {
+    10
+}
+
+fn default_users() -> usize {
+    1
+}
+
+fn empty_mapping() -> Value {
+    Value::Mapping(Default::default())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    /// Overrides of the base GameConfig
+    #[serde(default = "empty_mapping")]
+    pub config: Value,
+}
+
+/// Cumulative outcome of a run up until `time`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Sample {
+    pub time: u64,
+    pub energy_mined: u64,
+    pub bots_alive: u64,
+    pub bots_spawned: u64,
+    /// Bots whose hp fell to 0 in a tick they took damage from an attack
+    pub deaths_by_combat: u64,
+    /// Bots deleted for any other reason than their hp falling to 0, e.g. by their scripts
+    pub deaths_by_deletion: u64,
+    /// Bots whose hp fell to 0 without taking damage from an attack
+    pub deaths_by_decay: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResult {
+    pub variant: String,
+    pub seed: u64,
+    pub samples: Vec<Sample>,
+}
+
+/// csv does not support flattened structs, so the fields of `Sample` are repeated here
+#[derive(Debug, Serialize)]
+struct SampleRow<'a> {
+    variant: &'a str,
+    seed: u64,
+    time: u64,
+    energy_mined: u64,
+    bots_alive: u64,
+    bots_spawned: u64,
+    deaths_by_combat: u64,
+    deaths_by_deletion: u64,
+    deaths_by_decay: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct SummaryRow {
+    variant: String,
+    runs: usize,
+    energy_mined: f64,
+    bots_alive: f64,
+    bots_spawned: f64,
+    deaths_by_combat: f64,
+    deaths_by_deletion: f64,
+    deaths_by_decay: f64,
+}
+
+impl Experiment {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open experiment {:?}", path))?;
+        let mut experiment: Experiment = serde_yaml::from_reader(file)
+            .with_context(|| format!("Failed to parse experiment {:?}", path))?;
+        if let Some(dir) = path.parent() {
+            for script in experiment.scripts.iter_mut() {
+                *script = dir.join(&script);
+            }
+        }
+        Ok(experiment)
+    }
+
+    /// The GameConfig of the variant
+    pub fn config(&self, variant: &Variant, seed: u64) -> anyhow::Result<GameConfig> {
+        let mut config = self.base.clone();
+        merge(&mut config, variant.config.clone());
+        let mut config: GameConfig = serde_yaml::from_value(config)
+            .with_context(|| format!("Invalid config in variant {}", variant.name))?;
+        config.map_seed = Some(seed);
+        config
+            .validate()
+            .with_context(|| format!("Invalid config in variant {}", variant.name))?;
+        Ok(config)
+    }
+
+    /// Execute all runs of the experiment in parallel
+    pub fn run(&self) -> anyhow::Result<Vec<RunResult>> {
+        let scripts = self
+            .scripts
+            .iter()
+            .map(|path| scripts::load_script(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let rt = caolo_sim::RuntimeGuard::new();
+
+        let runs = self
+            .variants
+            .iter()
+            .flat_map(|variant| self.seeds.iter().map(move |seed| (variant, *seed)))
+            .map(|(variant, seed)| {
+                Ok::<_, anyhow::Error>((variant, seed, self.config(variant, seed)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        info!("Executing {} runs", runs.len());
+        runs.into_par_iter()
+            .map(|(variant, seed, config)| {
+                let samples = self.run_single(&rt, config, scripts.as_slice())?;
+                info!("Run {} seed {} done", variant.name, seed);
+                Ok::<_, anyhow::Error>(RunResult {
+                    variant: variant.name.clone(),
+                    seed,
+                    samples,
+                })
+            })
+            .collect()
+    }
+
+    fn run_single(
+        &self,
+        rt: &caolo_sim::RuntimeGuard,
+        config: GameConfig,
+        scripts: &[cao_lang::compiler::CaoIr],
+    ) -> anyhow::Result<Vec<Sample>> {
+        let mut executor = SimpleExecutor;
+        let mut world = executor.initialize(config);
+        caolo_sim::init::init_world_entities(&mut *world, self.users);
+
+        let mut script_ids = Vec::with_capacity(scripts.len());
+        for ir in scripts {
+            script_ids.push(scripts::insert_script(&mut *world, ir.clone())?);
+        }
+        scripts::assign_scripts(&mut *world, script_ids.as_slice());
+        // the initial entities should not count as spawned
+        world.commit_changes();
+
+        let mut current = Sample::default();
+        let mut samples = Vec::with_capacity((self.ticks / self.sample_interval.max(1)) as usize);
+        for tick in 1..=self.ticks {
+            rt.block_on(executor.forward(&mut *world))
+                .expect("world forward");
+            record_tick(&world, &mut current);
+            if tick % self.sample_interval.max(1) == 0 || tick == self.ticks {
+                samples.push(current.clone());
+            }
+        }
+        Ok(samples)
+    }
+}
+
+/// Record the changes of the last tick into `sample`
+fn record_tick(world: &World, sample: &mut Sample) {
+    sample.time = world.time();
+    sample.energy_mined += world
+        .view::<EntityId, MineEventComponent>()
+        .iter()
+        .map(|(_, MineEventComponent(_, mined))| *mined as u64)
+        .sum::<u64>();
+    sample.bots_alive = world.view::<EntityId, Bot>().iter().count() as u64;
+
+    let bots = world.view_changes::<EntityId, Bot>();
+    sample.bots_spawned += bots.inserted().count() as u64;
+
+    let deletions = world.view::<EmptyKey, EntityDeletions>();
+    let attacked = world.view::<EmptyKey, Events<Attacked>>();
+    for id in bots.deleted() {
+        // the deletions of the previous tick are kept too, the last one is of this tick
+        let cause = deletions
+            .value
+            .iter()
+            .flat_map(|deletions| deletions.0.iter())
+            .rev()
+            .find(|deletion| deletion.id == id)
+            .map(|deletion| deletion.cause);
+        match cause {
+            Some(DeleteCause::Died) => {
+                if attacked
+                    .value
+                    .iter()
+                    .flat_map(|events| events.iter())
+                    .any(|event| event.defender == id && event.damage > 0)
+                {
+                    sample.deaths_by_combat += 1;
+                } else {
+                    sample.deaths_by_decay += 1;
+                }
+            }
+            _ => sample.deaths_by_deletion += 1,
+        }
+    }
+}
+
+/// Recursively merge the mappings of `patch` into `base`
+fn merge(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Mapping(base), Value::Mapping(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+pub fn write_results(results: &[RunResult], dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+    let file = std::fs::File::create(dir.join("runs.json"))?;
+    serde_json::to_writer_pretty(file, results)?;
+
+    let mut samples = csv::Writer::from_path(dir.join("samples.csv"))?;
+    for run in results {
+        for sample in run.samples.iter() {
+            samples.serialize(SampleRow {
+                variant: run.variant.as_str(),
+                seed: run.seed,
+                time: sample.time,
+                energy_mined: sample.energy_mined,
+                bots_alive: sample.bots_alive,
+                bots_spawned: sample.bots_spawned,
+                deaths_by_combat: sample.deaths_by_combat,
+                deaths_by_deletion: sample.deaths_by_deletion,
+                deaths_by_decay: sample.deaths_by_decay,
+            })?;
+        }
+    }
+    samples.flush()?;
+
+    let mut summary = csv::Writer::from_path(dir.join("summary.csv"))?;
+    for row in summarize(results) {
+        summary.serialize(row)?;
+    }
+    summary.flush()?;
+    Ok(())
+}
+
+/// Average the final samples of the runs by variant, in the order the variants first appear
+fn summarize(results: &[RunResult]) -> Vec<SummaryRow> {
+    let mut rows: Vec<SummaryRow> = Vec::new();
+    for run in results {
+        let last = match run.samples.last() {
+            Some(s) => s,
+            None => continue,
+        };
+        let row = match rows.iter_mut().position(|row| row.variant == run.variant) {
+            Some(i) => &mut rows[i],
+            None => {
+                rows.push(SummaryRow {
+                    variant: run.variant.clone(),
+                    ..Default::default()
+                });
+                rows.last_mut().unwrap()
+            }
+        };
+        row.runs += 1;
+        row.energy_mined += last.energy_mined as f64;
+        row.bots_alive += last.bots_alive as f64;
+        row.bots_spawned += last.bots_spawned as f64;
+        row.deaths_by_combat += last.deaths_by_combat as f64;
+        row.deaths_by_deletion += last.deaths_by_deletion as f64;
+        row.deaths_by_decay += last.deaths_by_decay as f64;
+    }
+    for row in rows.iter_mut() {
+        let n = row.runs as f64;
+        row.energy_mined /= n;
+        row.bots_alive /= n;
+        row.bots_spawned /= n;
+        row.deaths_by_combat /= n;
+        row.deaths_by_deletion /= n;
+        row.deaths_by_decay /= n;
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_are_merged_over_the_base() {
+        let experiment: Experiment = serde_yaml::from_str(
+            r#"
+ticks: 4
+sample_interval: 2
+users: 2
+seeds: [1, 2]
+base:
+  world_radius: 1
+  room_radius: 10
+  spawn:
+    spawn_time: 5
+variants:
+  - name: baseline
+  - name: cheap_bots
+    config:
+      spawn:
+        bot_cost: 200
+"#,
+        )
+        .unwrap();
+
+        let config = experiment.config(&experiment.variants[1], 3).unwrap();
+        assert_eq!(config.room_radius, 10);
+        assert_eq!(config.spawn.spawn_time, 5);
+        assert_eq!(config.spawn.bot_cost, 200);
+        assert_eq!(config.map_seed, Some(3));
+
+        let results = experiment.run().unwrap();
+        assert_eq!(results.len(), 4);
+        for run in results.iter() {
+            let times: Vec<_> = run.samples.iter().map(|s| s.time).collect();
+            assert_eq!(times, vec![2, 4]);
+        }
+
+        let summary = summarize(results.as_slice());
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].variant, "baseline");
+        assert_eq!(summary[0].runs, 2);
+    }
+}
@@ -0,0 +1,189 @@
+//! Headless runner of Cao-Lo simulations.
+//!
+//! ```sh
+//! caolo-cli run --world-radius 2 --room-radius 16 --seed 42 --users 4 \
+//!     --script simulation/src/programs/mining_program.yaml --ticks 100 --snapshot-interval 10
+//! ```
+mod balance;
+mod scripts;
+mod snapshot;
+
+use anyhow::Context;
+use caolo_sim::prelude::*;
+use snapshot::{Report, Snapshot};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tracing::info;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "caolo-cli", about = "Run Cao-Lo simulations without a worker")]
+enum Command {
+    /// Generate a world, run it for a number of ticks and print the snapshots as JSON
+    Run(RunArgs),
+    /// Run a balancing experiment and write the comparison tables into the output directory
+    Balance {
+        /// YAML file describing the experiment
+        #[structopt(parse(from_os_str))]
+        experiment: PathBuf,
+        #[structopt(long, parse(from_os_str), default_value = "balance-results")]
+        output_dir: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct RunArgs {
+    /// YAML file holding the GameConfig, the defaults are used if not given
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Overrides the world_radius of the GameConfig
+    #[structopt(long)]
+    world_radius: Option<u32>,
+    /// Overrides the room_radius of the GameConfig
+    #[structopt(long)]
+    room_radius: Option<u32>,
+    /// Seed of the map generation
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Number of users to place in the world
+    #[structopt(long, default_value = "1")]
+    users: usize,
+    /// `CaoIr` programs in JSON or YAML format, assigned to the users in a round-robin fashion.
+    /// The users run the default mining program if none is given.
+    #[structopt(long = "script", parse(from_os_str))]
+    scripts: Vec<PathBuf>,
+    #[structopt(long, default_value = "100")]
+    ticks: u64,
+    /// Take a snapshot every N ticks. Only the final state is reported if not given
+    #[structopt(long)]
+    snapshot_interval: Option<u64>,
+    /// Write the report into this file instead of stdout
+    #[structopt(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(tracing_subscriber::filter::EnvFilter::from_default_env())
+        .init();
+
+    match Command::from_args() {
+        Command::Run(args) => run(args),
+        Command::Balance {
+            experiment,
+            output_dir,
+        } => {
+            let experiment = balance::Experiment::from_file(&experiment)?;
+            let results = experiment.run()?;
+            balance::write_results(results.as_slice(), &output_dir)
+        }
+    }
+}
+
+fn game_config(args: &RunArgs) -> anyhow::Result<GameConfig> {
+    let mut config: GameConfig = match args.config.as_ref() {
+        Some(path) => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open config {:?}", path))?;
+            serde_yaml::from_reader(file)
+                .with_context(|| format!("Failed to parse config {:?}", path))?
+        }
+        None => GameConfig {
+            world_radius: 2,
+            room_radius: 16,
+            ..Default::default()
+        },
+    };
+    if let Some(radius) = args.world_radius {
+        config.world_radius = radius;
+    }
+    if let Some(radius) = args.room_radius {
+        config.room_radius = radius;
+    }
+    if args.seed.is_some() {
+        config.map_seed = args.seed;
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+fn run(args: RunArgs) -> anyhow::Result<()> {
+    let config = game_config(&args)?;
+    let report = simulate(&args, config)?;
+
+    match args.output.as_ref() {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create output {:?}", path))?;
+            serde_json::to_writer_pretty(file, &report)?;
+        }
+        None => serde_json::to_writer_pretty(std::io::stdout(), &report)?,
+    }
+    Ok(())
+}
+
+fn simulate(args: &RunArgs, config: GameConfig) -> anyhow::Result<Report> {
+    let rt = caolo_sim::RuntimeGuard::new();
+    let mut executor = SimpleExecutor;
+
+    info!("Generating world");
+    let mut world = executor.initialize(config.clone());
+    caolo_sim::init::init_world_entities(&mut *world, args.users);
+
+    let mut script_ids = Vec::with_capacity(args.scripts.len());
+    for path in args.scripts.iter() {
+        let ir = scripts::load_script(path)?;
+        script_ids.push(scripts::insert_script(&mut *world, ir)?);
+    }
+    scripts::assign_scripts(&mut *world, script_ids.as_slice());
+
+    info!("Running {} ticks", args.ticks);
+    let mut snapshots = Vec::new();
+    for tick in 1..=args.ticks {
+        rt.block_on(executor.forward(&mut *world))
+            .expect("world forward");
+        if let Some(interval) = args.snapshot_interval {
+            if interval > 0 && tick % interval == 0 && tick != args.ticks {
+                snapshots.push(Snapshot::from_world(&world)?);
+            }
+        }
+    }
+    snapshots.push(Snapshot::from_world(&world)?);
+
+    Ok(Report { config, snapshots })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_snapshots_by_the_interval() {
+        let args = RunArgs::from_iter(&[
+            "run",
+            "--world-radius",
+            "1",
+            "--room-radius",
+            "10",
+            "--seed",
+            "42",
+            "--users",
+            "2",
+            "--script",
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../simulation/src/programs/mining_program.yaml"
+            ),
+            "--ticks",
+            "5",
+            "--snapshot-interval",
+            "2",
+        ]);
+        let config = game_config(&args).unwrap();
+        let report = simulate(&args, config).unwrap();
+
+        let times: Vec<_> = report.snapshots.iter().map(|s| s.time).collect();
+        assert_eq!(times, vec![2, 4, 5]);
+        assert_eq!(report.snapshots.last().unwrap().users.len(), 2);
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::Context;
+use cao_lang::compiler::CaoIr;
+use caolo_sim::prelude::*;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Load a `CaoIr` from a JSON or YAML file, by the file's extension
+pub fn load_script(path: &Path) -> anyhow::Result<CaoIr> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open script {:?}", path))?;
+    let ir = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse script {:?}", path))?,
+        _ => serde_yaml::from_reader(file)
+            .with_context(|| format!("Failed to parse script {:?}", path))?,
+    };
+    Ok(ir)
+}
+
+/// Compile the script and insert it into the World
+pub fn insert_script(world: &mut World, ir: CaoIr) -> anyhow::Result<ScriptId> {
+    let program = cao_lang::prelude::compile(ir.clone(), None)
+        .map_err(|err| anyhow::anyhow!("Failed to compile script: {}", err))?;
+
+    let script_id = ScriptId(Uuid::new_v4());
+    world
+        .unsafe_view::<ScriptId, CompiledScriptComponent>()
+        .insert_or_update(script_id, CompiledScriptComponent(program));
+    world
+        .unsafe_view::<ScriptId, CaoIrComponent>()
+        .insert_or_update(script_id, CaoIrComponent(ir));
+    Ok(script_id)
+}
+
+/// Assign the scripts to the users in a round-robin fashion.
+///
+/// The script becomes the default script of the user and is run by all bots the user owns.
+pub fn assign_scripts(world: &mut World, scripts: &[ScriptId]) {
+    if scripts.is_empty() {
+        return;
+    }
+    let users: Vec<UserId> = world
+        .view::<UserId, EntityScript>()
+        .iter()
+        .map(|(id, _)| id)
+        .collect();
+    for (user_id, script_id) in users.into_iter().zip(scripts.iter().cycle()) {
+        world
+            .unsafe_view::<UserId, EntityScript>()
+            .insert_or_update(user_id, EntityScript(*script_id));
+
+        let bot_table = world.view::<EntityId, Bot>();
+        let bots: Vec<EntityId> = world
+            .view::<EntityId, OwnedEntity>()
+            .iter()
+            .filter(|(id, owner)| owner.owner_id == user_id && bot_table.contains_id(id))
+            .map(|(id, _)| id)
+            .collect();
+        let mut entity_scripts = world.unsafe_view::<EntityId, EntityScript>();
+        for bot in bots {
+            entity_scripts.insert_or_update(bot, EntityScript(*script_id));
+        }
+    }
+}
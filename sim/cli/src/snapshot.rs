@@ -0,0 +1,60 @@
+use caolo_sim::diagnostics::Diagnostics;
+use caolo_sim::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub config: GameConfig,
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// State of the World at the end of a tick
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub time: u64,
+    pub diagnostics: Option<Diagnostics>,
+    pub users: Vec<UserSnapshot>,
+    pub bots: Value,
+    pub structures: Value,
+    pub resources: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserSnapshot {
+    pub id: UserId,
+    pub default_script: ScriptId,
+    pub bots: usize,
+}
+
+impl Snapshot {
+    pub fn from_world(world: &World) -> serde_json::Result<Self> {
+        let owners = world.view::<EntityId, OwnedEntity>();
+        let bot_table = world.view::<EntityId, Bot>();
+        let users = world
+            .view::<UserId, EntityScript>()
+            .iter()
+            .map(|(id, script)| UserSnapshot {
+                id,
+                default_script: script.0,
+                bots: owners
+                    .iter()
+                    .filter(|(entity_id, owner)| {
+                        owner.owner_id == id && bot_table.contains_id(entity_id)
+                    })
+                    .count(),
+            })
+            .collect();
+
+        Ok(Self {
+            time: world.time(),
+            diagnostics: world.view::<EmptyKey, Diagnostics>().value.clone(),
+            users,
+            bots: serde_json::to_value(world.entities.iterby_bot().collect::<Vec<_>>())?,
+            structures: serde_json::to_value(
+                world.entities.iterby_structure().collect::<Vec<_>>(),
+            )?,
+            resources: serde_json::to_value(world.entities.iterby_resource().collect::<Vec<_>>())?,
+        })
+    }
+}
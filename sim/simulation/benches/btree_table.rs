@@ -56,7 +56,7 @@ fn update_all_iter_2pow14_sparse(c: &mut Criterion) {
             while table.contains(id) {
                 id = EntityId(rng.gen_range(
                     0,
-                    u32::try_from(LEN * 6 / 5).expect("max len to fit into u32"),
+                    u64::try_from(LEN * 6 / 5).expect("max len to fit into u64"),
                 ));
             }
             table.insert_or_update(id, i);
@@ -77,7 +77,7 @@ fn update_all_iter_2pow14_dense(c: &mut Criterion) {
         const LEN: usize = 1 << 14;
         let mut table = BTreeTable::<EntityId, usize>::new();
         for i in 0..LEN {
-            let id = EntityId(i as u32);
+            let id = EntityId(i as u64);
             table.insert_or_update(id, i);
         }
         b.iter(|| {
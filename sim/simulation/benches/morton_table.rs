@@ -1,6 +1,7 @@
 use caolo_sim::components::EntityComponent;
 use caolo_sim::geometry::Axial;
-use caolo_sim::indices::EntityId;
+use caolo_sim::indices::{EntityId, WorldPosition};
+use caolo_sim::tables::morton_hierarchy::MortonMortonTable;
 use caolo_sim::tables::morton_table::MortonTable;
 use criterion::{black_box, criterion_group, BenchmarkId, Criterion};
 use rand::RngCore;
@@ -274,6 +275,83 @@ fn random_update(c: &mut Criterion) {
     group.finish();
 }
 
+/// Move a tenth of the entities each iteration, like a busy tick would. The entities are spread
+/// over the rooms of the world and stay in their room, like in the position index of the World.
+fn update_positions(c: &mut Criterion) {
+    const ROOMS: i32 = 8;
+    const ROOM_DIAMETER: i32 = 60;
+
+    let mut group = c.benchmark_group("morton table update_positions");
+    for &size in [10_000usize, 100_000].iter() {
+        let rows = |positions: &[WorldPosition]| {
+            positions
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (*p, EntityComponent(EntityId(i as u64))))
+                .collect::<Vec<_>>()
+        };
+        let init = |rng: &mut SmallRng| {
+            let positions = (0..size)
+                .map(|_| WorldPosition {
+                    room: Axial::new(rng.gen_range(0, ROOMS), rng.gen_range(0, ROOMS)),
+                    pos: Axial::new(
+                        rng.gen_range(0, ROOM_DIAMETER),
+                        rng.gen_range(0, ROOM_DIAMETER),
+                    ),
+                })
+                .collect::<Vec<_>>();
+            let mut table = MortonMortonTable::new();
+            table
+                .extend_from_slice(rows(&positions).as_mut_slice())
+                .unwrap();
+            (positions, table)
+        };
+        let step = |rng: &mut SmallRng, p: WorldPosition| WorldPosition {
+            room: p.room,
+            pos: Axial {
+                q: (p.pos.q + rng.gen_range(-1, 2))
+                    .max(0)
+                    .min(ROOM_DIAMETER - 1),
+                r: (p.pos.r + rng.gen_range(-1, 2))
+                    .max(0)
+                    .min(ROOM_DIAMETER - 1),
+            },
+        };
+
+        group.bench_with_input(BenchmarkId::new("incremental", size), &size, |b, &size| {
+            let mut rng = SmallRng::seed_from_u64(0xdeadbeef);
+            let (mut positions, mut table) = init(&mut rng);
+            b.iter(|| {
+                for _ in 0..size / 10 {
+                    let i = rng.gen_range(0, size);
+                    let from = positions[i];
+                    let to = step(&mut rng, from);
+                    let id = EntityId(i as u64);
+                    table.delete_if(from, |EntityComponent(e)| *e == id);
+                    table.insert(to, EntityComponent(id)).unwrap();
+                    positions[i] = to;
+                }
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("rebuild", size), &size, |b, &size| {
+            let mut rng = SmallRng::seed_from_u64(0xdeadbeef);
+            let (mut positions, mut table) = init(&mut rng);
+            b.iter(|| {
+                for _ in 0..size / 10 {
+                    let i = rng.gen_range(0, size);
+                    positions[i] = step(&mut rng, positions[i]);
+                }
+                table.clear();
+                table
+                    .extend_from_slice(rows(&positions).as_mut_slice())
+                    .unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     morton_benches,
     contains_rand,
@@ -285,4 +363,5 @@ criterion_group!(
     at_in_table_rand,
     at_rand,
     random_update,
+    update_positions,
 );
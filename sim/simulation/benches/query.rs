@@ -0,0 +1,132 @@
+use caolo_sim::join;
+use caolo_sim::prelude::*;
+use caolo_sim::storage::query::Without;
+use caolo_sim::tables::JoinIterator;
+use criterion::{black_box, criterion_group, Criterion};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::pin::Pin;
+
+fn get_rand() -> impl rand::Rng {
+    SmallRng::seed_from_u64(0xdeadbeef)
+}
+
+/// Every entity has hp, energy and a position, 1 in `bot_ratio` is a bot, 1 in `regen_ratio`
+/// regenerates energy
+fn setup_world(len: usize, bot_ratio: u32, regen_ratio: u32) -> Pin<Box<World>> {
+    let mut rng = get_rand();
+    let mut world = World::new();
+    for _ in 0..len {
+        let id = world.insert_entity();
+        world
+            .unsafe_view::<EntityId, HpComponent>()
+            .insert_or_update(id, HpComponent { hp: 1, hp_max: 10 });
+        world
+            .unsafe_view::<EntityId, EnergyComponent>()
+            .insert_or_update(
+                id,
+                EnergyComponent {
+                    energy: 0,
+                    energy_max: 100,
+                },
+            );
+        world
+            .unsafe_view::<EntityId, PositionComponent>()
+            .insert_or_update(id, PositionComponent::default());
+        if rng.gen_range(0, bot_ratio) == 0 {
+            world.unsafe_view::<EntityId, Bot>().insert(id);
+        }
+        if rng.gen_range(0, regen_ratio) == 0 {
+            world
+                .unsafe_view::<EntityId, EnergyRegenComponent>()
+                .insert_or_update(id, EnergyRegenComponent { amount: 1 });
+        }
+    }
+    world
+}
+
+fn query_update_2pow14(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_update_2pow14");
+    let mut world = setup_world(1 << 14, 2, 2);
+    group.bench_function("join", |b| {
+        b.iter(|| {
+            let mut energy = world.unsafe_view::<EntityId, EnergyComponent>();
+            let regen = world.view::<EntityId, EnergyRegenComponent>();
+            let energy = energy.iter_mut_tracked();
+            let regen = regen.iter();
+            for (id, (mut e, er)) in join!([energy, regen]) {
+                e.energy = (e.energy + er.amount).min(e.energy_max);
+                black_box(id);
+            }
+        });
+    });
+    group.bench_function("query", |b| {
+        b.iter(|| {
+            for (id, (mut e, er)) in world.query::<(&mut EnergyComponent, &EnergyRegenComponent)>()
+            {
+                e.energy = (e.energy + er.amount).min(e.energy_max);
+                black_box(id);
+            }
+        });
+    });
+    group.finish();
+}
+
+/// Few bots joined with tables holding every entity
+fn query_small_driver_2pow14(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_small_driver_2pow14");
+    let mut world = setup_world(1 << 14, 64, 2);
+    group.bench_function("join", |b| {
+        b.iter(|| {
+            let bots = world.view::<EntityId, Bot>();
+            let hps = world.view::<EntityId, HpComponent>();
+            let positions = world.view::<EntityId, PositionComponent>();
+            let bots = bots.iter();
+            let hps = hps.iter();
+            let positions = positions.iter();
+            for joined in join!([bots, hps, positions]) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("query", |b| {
+        b.iter(|| {
+            for joined in world.query::<(&Bot, &HpComponent, &PositionComponent)>() {
+                black_box(joined);
+            }
+        });
+    });
+    group.finish();
+}
+
+/// Entities that are not bots, but regenerate energy
+fn query_without_2pow14(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_without_2pow14");
+    let mut world = setup_world(1 << 14, 2, 8);
+    group.bench_function("join_filter", |b| {
+        b.iter(|| {
+            let bots = world.view::<EntityId, Bot>();
+            let regen = world.view::<EntityId, EnergyRegenComponent>();
+            let energy = world.view::<EntityId, EnergyComponent>();
+            let regen = regen.iter();
+            let energy = energy.iter();
+            for joined in join!([regen, energy]).filter(|(id, _)| bots.get_by_id(*id).is_none()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("query", |b| {
+        b.iter(|| {
+            for joined in world.query::<(&EnergyRegenComponent, &EnergyComponent, Without<Bot>)>() {
+                black_box(joined);
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    query_benches,
+    query_update_2pow14,
+    query_small_driver_2pow14,
+    query_without_2pow14
+);
@@ -1,6 +1,7 @@
 mod btree_table;
 mod morton_table;
 mod pathfinding_benches;
+mod query;
 mod table_join;
 mod vec_table;
 
@@ -11,5 +12,6 @@ criterion_main!(
     btree_table::btree_benches,
     table_join::join_benches,
     vec_table::vec_benches,
-    pathfinding_benches::pathfinding_benches
+    pathfinding_benches::pathfinding_benches,
+    query::query_benches
 );
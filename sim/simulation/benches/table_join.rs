@@ -1,4 +1,7 @@
-use caolo_sim::tables::{btree_table::BTreeTable, dense_table::DenseTable, JoinIterator};
+use caolo_sim::tables::{
+    btree_table::BTreeTable, dense_table::DenseTable, sparse_set_table::SparseSetTable,
+    JoinIterator,
+};
 use caolo_sim::{indices::EntityId, tables::flag_table::SparseFlagTable};
 use criterion::{black_box, criterion_group, Criterion};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
@@ -21,7 +24,7 @@ struct LargeComponent {
     _f: [u8; 10],
 }
 
-fn random_vec_table(len: usize, domain: u32) -> DenseTable<EntityId, LargeComponent> {
+fn random_vec_table(len: usize, domain: u64) -> DenseTable<EntityId, LargeComponent> {
     let mut rng = get_rand();
     let mut table = DenseTable::with_capacity(domain as usize);
     for _ in 0..len {
@@ -34,7 +37,7 @@ fn random_vec_table(len: usize, domain: u32) -> DenseTable<EntityId, LargeCompon
     table
 }
 
-fn random_bt_table(len: usize, domain: u32) -> BTreeTable<EntityId, LargeComponent> {
+fn random_bt_table(len: usize, domain: u64) -> BTreeTable<EntityId, LargeComponent> {
     let mut rng = get_rand();
     let mut table = BTreeTable::new();
     for _ in 0..len {
@@ -47,6 +50,16 @@ fn random_bt_table(len: usize, domain: u32) -> BTreeTable<EntityId, LargeCompone
     table
 }
 
+fn random_sparse_table(len: usize, domain: u64) -> SparseSetTable<EntityId, LargeComponent> {
+    let mut rng = get_rand();
+    let mut table = SparseSetTable::with_capacity(len);
+    while table.len() < len {
+        let id = EntityId(rng.gen_range(0, domain));
+        table.insert_or_update(id, LargeComponent::default());
+    }
+    table
+}
+
 fn join_vec_btree_2pow15_sparse(c: &mut Criterion) {
     c.bench_function("join_vec_btree_2pow15_sparse", |b| {
         let bt = random_bt_table(1 << 15, 1 << 16);
@@ -169,6 +182,89 @@ fn join_flag_vec_sparse(c: &mut Criterion) {
     });
 }
 
+fn join_sparse_set_2pow15_sparse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("join_sparse_set_2pow15_sparse");
+    let sp = random_sparse_table(1 << 15, 1 << 16);
+    let sp2 = random_sparse_table(1 << 15, 1 << 16);
+    let ve = random_vec_table(1 << 15, 1 << 16);
+    let bt = random_bt_table(1 << 15, 1 << 16);
+    group.bench_function("sparse_vec", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(sp.iter(), ve.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_btree", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(sp.iter(), bt.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_sparse", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(sp.iter(), sp2.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_sparse_fast_path", |b| {
+        b.iter(|| {
+            for joined in sp.join_sparse(&sp2) {
+                black_box(joined);
+            }
+        });
+    });
+    group.finish();
+}
+
+/// A small component joined with large tables, e.g. spawns with the positions of every entity
+fn join_small_with_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("join_2pow10_with_2pow15");
+    let small_sparse = random_sparse_table(1 << 10, 1 << 16);
+    let small_vec = random_vec_table(1 << 10, 1 << 16);
+    let small_bt = random_bt_table(1 << 10, 1 << 16);
+    let ve = random_vec_table(1 << 15, 1 << 16);
+    let bt = random_bt_table(1 << 15, 1 << 16);
+    group.bench_function("vec_vec", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(small_vec.iter(), ve.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("btree_btree", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(small_bt.iter(), bt.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_vec", |b| {
+        b.iter(|| {
+            for joined in JoinIterator::new(small_sparse.iter(), ve.iter()) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_vec_fast_path", |b| {
+        b.iter(|| {
+            for joined in small_sparse.join(&ve) {
+                black_box(joined);
+            }
+        });
+    });
+    group.bench_function("sparse_btree_fast_path", |b| {
+        b.iter(|| {
+            for joined in small_sparse.join(&bt) {
+                black_box(joined);
+            }
+        });
+    });
+    group.finish();
+}
+
 criterion_group!(
     join_benches,
     join_bt_bt_2pow15_dense,
@@ -180,5 +276,7 @@ criterion_group!(
     join_btree_vec_2pow15_sparse,
     join_vec_btree_2pow15_sparse,
     join_bt_bt_2pow15_sparse,
-    join_flag_vec_sparse
+    join_flag_vec_sparse,
+    join_sparse_set_2pow15_sparse,
+    join_small_with_large
 );
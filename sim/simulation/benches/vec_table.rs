@@ -1,5 +1,7 @@
 use caolo_sim::indices::EntityId;
 use caolo_sim::tables::dense_table::DenseTable;
+use caolo_sim::tables::sparse_set_table::SparseSetTable;
+use caolo_sim::tables::Table;
 use criterion::{black_box, criterion_group, BenchmarkId, Criterion};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use std::convert::TryFrom;
@@ -48,7 +50,7 @@ fn update_all_iter_2pow14_sparse(c: &mut Criterion) {
             while table.contains_id(id) {
                 id = EntityId(rng.gen_range(
                     0,
-                    u32::try_from(LEN * 6 / 5).expect("max len to fit into u32"),
+                    u64::try_from(LEN * 6 / 5).expect("max len to fit into u64"),
                 ));
             }
             table.insert_or_update(id, i);
@@ -69,7 +71,7 @@ fn update_all_iter_2pow14_dense(c: &mut Criterion) {
         const LEN: usize = 1 << 14;
         let mut table = DenseTable::<EntityId, usize>::with_capacity(LEN);
         for i in 0..LEN {
-            let id = EntityId(i as u32);
+            let id = EntityId(i as u64);
             table.insert_or_update(id, i);
         }
         b.iter(|| {
@@ -91,7 +93,7 @@ fn get_by_id_random_2_pow_16(c: &mut Criterion) {
             let mut id = Default::default();
             while table.contains_id(id) {
                 id = EntityId(
-                    rng.gen_range(0, u32::try_from(LEN * 2).expect("max len to fit into u32")),
+                    rng.gen_range(0, u64::try_from(LEN * 2).expect("max len to fit into u64")),
                 );
             }
             table.insert_or_update(id, i);
@@ -120,7 +122,7 @@ fn override_update_random(c: &mut Criterion) {
                 let mut id = Default::default();
                 while table.contains_id(id) {
                     id = EntityId(
-                        rng.gen_range(0, u32::try_from(size * 2).expect("max len to fit into u32")),
+                        rng.gen_range(0, u64::try_from(size * 2).expect("max len to fit into u64")),
                     );
                 }
                 table.insert_or_update(id, i);
@@ -151,7 +153,7 @@ fn override_update_all_serial(c: &mut Criterion) {
                 let mut id = Default::default();
                 while table.contains_id(id) {
                     id = EntityId(
-                        rng.gen_range(0, u32::try_from(size * 2).expect("max len to fit into u32")),
+                        rng.gen_range(0, u64::try_from(size * 2).expect("max len to fit into u64")),
                     );
                 }
                 table.insert_or_update(id, i);
@@ -165,6 +167,116 @@ fn override_update_all_serial(c: &mut Criterion) {
     group.finish();
 }
 
+fn sparse_set_insert_at_random(c: &mut Criterion) {
+    c.bench_function("sparse_set_table insert_at_random", |b| {
+        let mut rng = get_rand();
+        let mut table = SparseSetTable::<EntityId, i32>::new();
+        b.iter(|| {
+            let id = rng.gen_range(0, 1 << 20);
+            let id = EntityId(id);
+            let res = table.insert_or_update(id, rng.gen_range(0, 200));
+            debug_assert!(res);
+            res
+        });
+    });
+}
+
+fn sparse_set_insert_serial(c: &mut Criterion) {
+    c.bench_function("sparse_set_table insert_serial", |b| {
+        let mut table = SparseSetTable::<EntityId, usize>::new();
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            table.insert_or_update(EntityId(i as u64), i)
+        });
+    });
+}
+
+fn sparse_set_delete_at_random(c: &mut Criterion) {
+    c.bench_function("sparse_set_table delete_at_random_2pow14", |b| {
+        const LEN: u64 = 1 << 14;
+        let mut rng = get_rand();
+        let mut table = SparseSetTable::<EntityId, u64>::with_capacity(LEN as usize);
+        for i in 0..LEN {
+            table.insert_or_update(EntityId(i), i);
+        }
+        b.iter(|| {
+            // delete and reinsert to keep the size of the table constant
+            let id = EntityId(rng.gen_range(0, LEN));
+            let row = table.delete(id).unwrap();
+            table.insert_or_update(id, row)
+        });
+    });
+}
+
+/// Compare the backends when only every `1/density` id of the domain has a row
+fn compare_update_all_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_all_iter_2pow14_domain");
+    const LEN: usize = 1 << 14;
+    for density in [1u64, 8, 64].iter() {
+        let ids = || (0..LEN as u64).map(|i| EntityId(i * density));
+
+        group.bench_with_input(BenchmarkId::new("dense", density), density, |b, _| {
+            let mut table = DenseTable::<EntityId, usize>::new();
+            for (i, id) in ids().enumerate() {
+                table.insert_or_update(id, i);
+            }
+            b.iter(|| {
+                table.iter_mut().for_each(|(_, val)| {
+                    *val += 8;
+                });
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("sparse_set", density), density, |b, _| {
+            let mut table = SparseSetTable::<EntityId, usize>::new();
+            for (i, id) in ids().enumerate() {
+                table.insert_or_update(id, i);
+            }
+            b.iter(|| {
+                table.iter_mut().for_each(|(_, val)| {
+                    *val += 8;
+                });
+            });
+        });
+    }
+    group.finish();
+}
+
+fn compare_get_by_id_random(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_by_id_random_2pow16");
+    const LEN: usize = 1 << 16;
+    let mut rng = get_rand();
+    let ids = (0..LEN)
+        .map(|_| {
+            EntityId(rng.gen_range(0, u64::try_from(LEN * 2).expect("max len to fit into u64")))
+        })
+        .collect::<Vec<_>>();
+
+    group.bench_function("dense", |b| {
+        let mut rng = get_rand();
+        let mut table = DenseTable::<EntityId, usize>::with_capacity(LEN);
+        for (i, id) in ids.iter().enumerate() {
+            table.insert_or_update(*id, i);
+        }
+        b.iter(|| {
+            let id = ids[rng.gen_range(0, LEN)];
+            table.get_by_id(id)
+        });
+    });
+    group.bench_function("sparse_set", |b| {
+        let mut rng = get_rand();
+        let mut table = SparseSetTable::<EntityId, usize>::with_capacity(LEN);
+        for (i, id) in ids.iter().enumerate() {
+            table.insert_or_update(*id, i);
+        }
+        b.iter(|| {
+            let id = ids[rng.gen_range(0, LEN)];
+            table.get_by_id(id)
+        });
+    });
+    group.finish();
+}
+
 criterion_group!(
     vec_benches,
     insert_at_random,
@@ -173,5 +285,10 @@ criterion_group!(
     get_by_id_random_2_pow_16,
     override_update_random,
     override_update_all_serial,
-    insert_at_random_w_reserve
+    insert_at_random_w_reserve,
+    sparse_set_insert_at_random,
+    sparse_set_insert_serial,
+    sparse_set_delete_at_random,
+    compare_update_all_iter,
+    compare_get_by_id_random
 );
@@ -9,38 +9,127 @@ pub use resources::*;
 pub use rooms::*;
 pub use script_components::*;
 
+use crate::geometry::Axial;
 use crate::indices::{EntityId, Room, UserId, WorldPosition};
+use crate::tables::btree_table::BTreeTable;
+use crate::tables::dense_table::DenseTable;
+use crate::tables::flag_table::SparseFlagTable;
+use crate::tables::morton_hierarchy::{MortonMortonTable, MortonMultiMortonTable};
+use crate::tables::morton_multi_table::{Layered, MortonMultiTable};
+use crate::tables::morton_table::MortonTable;
+use crate::tables::sparse_set_table::SparseSetTable;
+use cao_storage_derive::CaoComponent;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
 
 /// For tables that store entity ids as values
-#[derive(Debug, Clone, Serialize, Deserialize, Copy, Default, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    Copy,
+    Default,
+    Ord,
+    PartialOrd,
+    Eq,
+    PartialEq,
+    CaoComponent,
+)]
+#[cao_storage(key = "WorldPosition", table = "MortonMortonTable<Self>")]
+#[cao_storage(key = "Axial", table = "MortonTable<Self>")]
+#[cao_storage(transient)]
 #[serde(rename_all = "camelCase")]
 pub struct EntityComponent(pub EntityId);
 
+/// Layers of the map, entities on different layers may share a tile
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityLayer {
+    /// Bots and structures, at most one per tile. Blocks movement.
+    Blocking,
+    /// Resources, items on the ground, etc.
+    Ground,
+}
+
+impl Default for EntityLayer {
+    fn default() -> Self {
+        EntityLayer::Blocking
+    }
+}
+
+/// For tables that store several entities per tile
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    Copy,
+    Default,
+    Ord,
+    PartialOrd,
+    Eq,
+    PartialEq,
+    CaoComponent,
+)]
+#[cao_storage(key = "WorldPosition", table = "MortonMultiMortonTable<Self>")]
+#[cao_storage(key = "Axial", table = "MortonMultiTable<Self>")]
+#[cao_storage(transient)]
+#[serde(rename_all = "camelCase")]
+pub struct LayeredEntity {
+    pub layer: EntityLayer,
+    pub id: EntityId,
+}
+
+impl Layered for LayeredEntity {
+    type Layer = EntityLayer;
+
+    fn layer(&self) -> EntityLayer {
+        self.layer
+    }
+}
+
 /// Has a body so it's not `null` when serializing
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "SparseFlagTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct Structure;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "Axial", table = "MortonTable<Self>")]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct OwnedEntity {
     pub owner_id: UserId,
 }
 
-#[derive(Default, Debug, Clone, Copy, Ord, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    Ord,
+    PartialOrd,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    CaoComponent,
+)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct PositionComponent(pub WorldPosition);
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct EnergyComponent {
     pub energy: u16,
     pub energy_max: u16,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "SparseSetTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct SpawnComponent {
     /// Time to spawn the current entity
@@ -49,27 +138,51 @@ pub struct SpawnComponent {
     pub spawning: Option<EntityId>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "SparseSetTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct SpawnQueueComponent {
     /// Entities waiting for spawn
     pub queue: VecDeque<EntityId>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct HpComponent {
     pub hp: u16,
     pub hp_max: u16,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+/// Entities see the other entities of their room within `range` tiles
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
+#[serde(rename_all = "camelCase")]
+pub struct VisionComponent {
+    pub range: u16,
+}
+
+/// Entities a user can see, updated at the end of every tick
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "UserId", table = "BTreeTable<UserId, Self>")]
+#[cao_storage(transient)]
+pub struct Visibility(pub BTreeSet<EntityId>);
+
+impl Visibility {
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.0.contains(&id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct EnergyRegenComponent {
     pub amount: u16,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct SpawnBotComponent {
     pub bot: Bot,
@@ -83,11 +196,13 @@ pub struct LogEntry {
     pub payload: String,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "UserId", table = "SparseFlagTable<UserId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct UserComponent;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "UserId", table = "BTreeTable<UserId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct UserProperties {
     pub level: u16,
@@ -99,8 +214,10 @@ impl Default for UserProperties {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "UserId", table = "BTreeTable<UserId, Self>")]
 pub struct Rooms(pub Vec<Room>);
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "BTreeTable<EntityId, Self>")]
 pub struct RespawnTimer(pub i32);
@@ -1,22 +1,29 @@
-use crate::indices::{EntityId, RoomPosition, ScriptId, WorldPosition};
+use crate::indices::{EntityId, RoomPosition, ScriptId, UserId, WorldPosition};
+use crate::tables::btree_table::BTreeTable;
+use crate::tables::dense_table::DenseTable;
+use crate::tables::flag_table::SparseFlagTable;
 use arrayvec::{ArrayString, ArrayVec};
+use cao_storage_derive::CaoComponent;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct MeleeAttackComponent {
     pub strength: u16,
 }
 
 /// Has a body so it's not `null` when serializing
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "SparseFlagTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct Bot;
 
 /// Represent time to decay of bots
 /// On decay the bot will loose hp
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct DecayComponent {
     pub hp_amount: u16,
@@ -24,7 +31,8 @@ pub struct DecayComponent {
     pub time_remaining: u8,
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct CarryComponent {
     pub carry: u16,
@@ -32,14 +40,18 @@ pub struct CarryComponent {
 }
 
 /// Entity - Script join table
-#[derive(Debug, Clone, Serialize, Deserialize, Default, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Copy, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
+#[cao_storage(key = "UserId", table = "BTreeTable<UserId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct EntityScript(pub ScriptId);
 
 unsafe impl Send for EntityScript {}
 
 pub const PATH_CACHE_LEN: usize = 64;
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
+#[cao_storage(transient)]
 #[serde(rename_all = "camelCase")]
 pub struct PathCacheComponent {
     pub target: WorldPosition,
@@ -48,14 +60,18 @@ pub struct PathCacheComponent {
 
 pub const SAY_MAX_LEN: usize = 64;
 pub type SayPayload = ArrayString<SAY_MAX_LEN>;
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct SayComponent(pub SayPayload);
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "BTreeTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
-pub struct MineEventComponent(pub EntityId);
+/// The resource mined and the amount of energy mined in the last tick
+pub struct MineEventComponent(pub EntityId, pub u16);
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "BTreeTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct DropoffEventComponent(pub EntityId);
@@ -1,6 +1,48 @@
+use crate::indices::ConfigKey;
+use crate::tables::unique_table::UniqueTable;
+use cao_storage_derive::CaoComponent;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Smallest room radius the map generation can work with, bridges are at least 3 tiles long and
+/// need room on both sides
+pub const MIN_ROOM_RADIUS: u32 = 7;
+
+/// Largest world radius, including the rings added by expanding the world.
+/// The overworld is laid out around a center this far from the origin, so every ring fits into
+/// the non-negative room coordinates
+pub const MAX_WORLD_RADIUS: u32 = 64;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum GameConfigError {
+    #[error("{field} must be greater than 0")]
+    Zero { field: &'static str },
+
+    #[error(
+        "room_radius is {room_radius}, but it must be at least {}",
+        MIN_ROOM_RADIUS
+    )]
+    RoomTooSmall { room_radius: u32 },
+
+    #[error(
+        "world_radius is {world_radius}, but it may be at most {}",
+        MAX_WORLD_RADIUS
+    )]
+    WorldTooLarge { world_radius: u32 },
+
+    #[error("Spawning a bot costs {cost} energy, but spawns may only hold {energy_max}")]
+    SpawnCostTooHigh { cost: u16, energy_max: u16 },
+
+    #[error("Starter kit radius {kit_radius} is larger than the room radius {room_radius}")]
+    StarterKitTooLarge { kit_radius: u32, room_radius: u32 },
+
+    #[error("{field} can not be changed while the simulation is running")]
+    NotReloadable { field: &'static str },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "ConfigKey", table = "UniqueTable<ConfigKey, Self>")]
+#[serde(default)]
 pub struct GameConfig {
     pub world_radius: u32,
     pub room_radius: u32,
@@ -10,6 +52,12 @@ pub struct GameConfig {
     pub queen_tag: String,
     /// maximum number of steps pathfinding can test
     pub path_finding_limit: u32,
+    /// Seed of the map generation, a random seed is used if not set
+    pub map_seed: Option<u64>,
+    /// Entities given to users when they claim their first room
+    pub starter_kit: StarterKitConfig,
+    pub spawn: SpawnConfig,
+    pub bot: BotConfig,
 }
 
 impl Default for GameConfig {
@@ -21,6 +69,247 @@ impl Default for GameConfig {
             world_radius: 32,
             room_radius: 50,
             path_finding_limit: 1000,
+            map_seed: None,
+            starter_kit: Default::default(),
+            spawn: Default::default(),
+            bot: Default::default(),
+        }
+    }
+}
+
+macro_rules! non_zero {
+    ($config: ident, $($field: ident).+) => {
+        if $config.$($field).+ == 0 {
+            return Err(GameConfigError::Zero {
+                field: stringify!($($field).+),
+            });
+        }
+    };
+}
+
+impl GameConfig {
+    /// Check that a world can be generated and played with this config
+    pub fn validate(&self) -> Result<(), GameConfigError> {
+        non_zero!(self, world_radius);
+        if self.world_radius > MAX_WORLD_RADIUS {
+            return Err(GameConfigError::WorldTooLarge {
+                world_radius: self.world_radius,
+            });
+        }
+        if self.room_radius < MIN_ROOM_RADIUS {
+            return Err(GameConfigError::RoomTooSmall {
+                room_radius: self.room_radius,
+            });
+        }
+        if self.starter_kit.radius > self.room_radius {
+            return Err(GameConfigError::StarterKitTooLarge {
+                kit_radius: self.starter_kit.radius,
+                room_radius: self.room_radius,
+            });
+        }
+        self.validate_gameplay()
+    }
+
+    /// Check the gameplay constants only, for worlds that are not built by the map generation
+    pub fn validate_gameplay(&self) -> Result<(), GameConfigError> {
+        non_zero!(self, execution_limit);
+        non_zero!(self, target_tick_ms);
+        non_zero!(self, spawn.energy_max);
+        non_zero!(self, spawn.hp);
+        non_zero!(self, bot.hp);
+        non_zero!(self, bot.mine_amount);
+
+        // signed, so `non_zero` would let negative values through
+        if self.spawn.spawn_time <= 0 {
+            return Err(GameConfigError::Zero {
+                field: "spawn.spawn_time",
+            });
+        }
+        if self.spawn.bot_cost > self.spawn.energy_max {
+            return Err(GameConfigError::SpawnCostTooHigh {
+                cost: self.spawn.bot_cost,
+                energy_max: self.spawn.energy_max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Replace the gameplay constants by the ones in `new`.
+    ///
+    /// The room radius can not change while the world exists, `new` has to have the same value as
+    /// `self`. The world radius and the queen tag of `self` are kept, the world radius grows when
+    /// the world is expanded, so the one in `new` is ignored.
+    pub fn reload(&mut self, new: GameConfig) -> Result<(), GameConfigError> {
+        new.validate()?;
+        if new.room_radius != self.room_radius {
+            return Err(GameConfigError::NotReloadable {
+                field: "room_radius",
+            });
+        }
+        let queen_tag = std::mem::take(&mut self.queen_tag);
+        *self = GameConfig {
+            queen_tag,
+            world_radius: self.world_radius,
+            ..new
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StarterKitConfig {
+    /// Number of bots spawned next to the spawn
+    pub bots: u32,
+    /// Number of resources placed near the spawn
+    pub resources: u32,
+    /// Bots and resources are placed within this distance of the spawn, if possible
+    pub radius: u32,
+}
+
+impl Default for StarterKitConfig {
+    fn default() -> Self {
+        Self {
+            bots: 2,
+            resources: 2,
+            radius: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpawnConfig {
+    /// Energy consumed by spawning a single bot
+    pub bot_cost: u16,
+    /// Number of ticks it takes to spawn a bot
+    pub spawn_time: i16,
+    pub energy_max: u16,
+    /// Energy regenerated per tick
+    pub energy_regen: u16,
+    pub hp: u16,
+    /// Range of vision of spawns in tiles
+    pub vision_range: u16,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            bot_cost: 500,
+            spawn_time: 10,
+            energy_max: 500,
+            energy_regen: 20,
+            hp: 500,
+            vision_range: 12,
         }
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub hp: u16,
+    pub carry_max: u16,
+    /// Energy mined by a bot in a single tick
+    pub mine_amount: u16,
+    /// Bots loose `decay_amount` hp every `decay_interval` ticks
+    pub decay_interval: u8,
+    pub decay_amount: u16,
+    /// Range of vision of bots in tiles
+    pub vision_range: u16,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            hp: 100,
+            carry_max: 150,
+            mine_amount: 10,
+            decay_interval: 10,
+            decay_amount: 10,
+            vision_range: 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        GameConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn spawn_cost_can_not_exceed_the_spawn_energy() {
+        let mut config = GameConfig::default();
+        config.spawn.bot_cost = config.spawn.energy_max + 1;
+
+        let err = config.validate().unwrap_err();
+        assert!(matches!(err, GameConfigError::SpawnCostTooHigh { .. }));
+    }
+
+    #[test]
+    fn configs_the_map_generation_can_not_handle_are_rejected() {
+        let config = GameConfig {
+            room_radius: MIN_ROOM_RADIUS - 1,
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            GameConfigError::RoomTooSmall {
+                room_radius: MIN_ROOM_RADIUS - 1
+            }
+        );
+
+        let config = GameConfig {
+            world_radius: MAX_WORLD_RADIUS + 1,
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            GameConfigError::WorldTooLarge {
+                world_radius: MAX_WORLD_RADIUS + 1
+            }
+        );
+
+        let mut config = GameConfig::default();
+        config.spawn.spawn_time = -1;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            GameConfigError::Zero {
+                field: "spawn.spawn_time"
+            }
+        );
+    }
+
+    #[test]
+    fn reload_keeps_the_map_and_the_tag() {
+        let mut config = GameConfig::default();
+        let tag = config.queen_tag.clone();
+
+        let mut new = GameConfig {
+            queen_tag: "winnie".to_owned(),
+            ..GameConfig::default()
+        };
+        new.spawn.bot_cost = 200;
+        config.reload(new.clone()).unwrap();
+        assert_eq!(config.spawn.bot_cost, 200);
+        assert_eq!(config.queen_tag, tag);
+
+        // the world radius grows as the world expands
+        config.world_radius += 1;
+        config.reload(new.clone()).unwrap();
+        assert_eq!(config.world_radius, new.world_radius + 1);
+
+        new.room_radius += 1;
+        let err = config.reload(new).unwrap_err();
+        assert_eq!(
+            err,
+            GameConfigError::NotReloadable {
+                field: "room_radius"
+            }
+        );
+    }
+}
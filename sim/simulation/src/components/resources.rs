@@ -34,7 +34,8 @@ impl TryFrom<Value> for Resource {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "SparseSetTable<EntityId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceComponent(pub Resource);
 
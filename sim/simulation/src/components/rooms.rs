@@ -1,5 +1,12 @@
 use crate::geometry::Axial;
+use crate::indices::{ConfigKey, WorldPosition};
+use crate::map_generation::biome::Biome;
+use crate::tables::hex_grid::HexGrid;
+use crate::tables::morton_hierarchy::MortonGridTable;
+use crate::tables::morton_table::MortonTable;
+use crate::tables::unique_table::UniqueTable;
 use crate::terrain::TileTerrainType;
+use cao_storage_derive::CaoComponent;
 use serde::{Deserialize, Serialize};
 
 /// Represents a connection of a room to another.
@@ -15,21 +22,35 @@ pub struct RoomConnection {
 }
 
 /// Represents connections a room has to their neighbours. At most 6.
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "Axial", table = "MortonTable<Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct RoomConnections(pub [Option<RoomConnection>; 6]);
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default, Copy)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default, Copy, CaoComponent)]
+#[cao_storage(key = "WorldPosition", table = "MortonGridTable<Self>")]
+#[cao_storage(key = "Axial", table = "HexGrid<Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct TerrainComponent(pub TileTerrainType);
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "ConfigKey", table = "UniqueTable<ConfigKey, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct RoomProperties {
     pub radius: u32,
     pub center: Axial,
+    /// Seed of the biome noise, derived from the map seed.
+    /// Rooms added by expanding the map use it to fit into the existing biomes.
+    #[serde(default)]
+    pub biome_seed: u64,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "Axial", table = "MortonTable<Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct RoomComponent;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "Axial", table = "MortonTable<Self>")]
+#[serde(rename_all = "camelCase")]
+pub struct BiomeComponent(pub Biome);
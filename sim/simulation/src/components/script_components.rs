@@ -1,5 +1,8 @@
-use crate::indices::EntityId;
+use crate::indices::{EntityId, ScriptId};
+use crate::tables::btree_table::BTreeTable;
+use crate::tables::dense_table::DenseTable;
 use cao_lang::{prelude, program::CaoProgram};
+use cao_storage_derive::CaoComponent;
 use prelude::CaoIr;
 use serde::{Deserialize, Serialize};
 
@@ -11,16 +14,20 @@ pub struct ScriptHistoryEntry {
 }
 
 /// Currently does nothing as Cao-Lang not yet supports history
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "DenseTable<EntityId, Self>")]
+#[cao_storage(transient)]
 pub struct ScriptHistory(());
 
 /// Entities with Scripts
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, CaoComponent)]
+#[cao_storage(key = "ScriptId", table = "BTreeTable<ScriptId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct CompiledScriptComponent(pub CaoProgram);
 
 /// Pre-compiled scripts
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "ScriptId", table = "BTreeTable<ScriptId, Self>")]
 #[serde(rename_all = "camelCase")]
 pub struct CaoIrComponent(pub CaoIr);
 
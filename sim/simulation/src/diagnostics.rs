@@ -1,14 +1,19 @@
 mod serde_impl;
 
+use crate::indices::EmptyKey;
+use crate::intents::BotIntents;
+use crate::tables::unique_table::UniqueTable;
+use cao_storage_derive::CaoComponent;
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use tracing::Level;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DiagDur(pub Duration);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct StatsField {
+pub struct StatsField {
     name: String,
     count: u64,
     current: f64,
@@ -19,13 +24,20 @@ struct StatsField {
     std_aggregator: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EmptyKey", table = "UniqueTable<EmptyKey, Self>")]
 pub struct Diagnostics {
     tick_stats: StatsField,
     scripts_execution_time: StatsField,
     scripts_ran: StatsField,
     scripts_error: StatsField,
     systems: StatsField,
+    /// execution time of individual systems in microseconds
+    systems_by_name: BTreeMap<String, StatsField>,
+    /// number of intents by intent type
+    intents_by_type: BTreeMap<String, StatsField>,
+    /// number of entities by kind (bots, structures, resources)
+    entities_by_kind: BTreeMap<String, StatsField>,
 }
 
 impl Default for Diagnostics {
@@ -36,6 +48,9 @@ impl Default for Diagnostics {
             scripts_ran: StatsField::new("scripts_ran".to_string()),
             scripts_error: StatsField::new("scripts_error".to_string()),
             systems: StatsField::new("systems_time".to_string()),
+            systems_by_name: BTreeMap::new(),
+            intents_by_type: BTreeMap::new(),
+            entities_by_kind: BTreeMap::new(),
         }
     }
 }
@@ -73,6 +88,10 @@ impl StatsField {
         self.count += 1;
     }
 
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
     #[allow(unused)]
     pub fn current(&self) -> f64 {
         self.current
@@ -122,6 +141,14 @@ impl Diagnostics {
         self.systems.emit_tracing_event();
         self.scripts_ran.emit_tracing_event();
         self.scripts_error.emit_tracing_event();
+        for stats in self
+            .systems_by_name
+            .values()
+            .chain(self.intents_by_type.values())
+            .chain(self.entities_by_kind.values())
+        {
+            stats.emit_tracing_event();
+        }
     }
 
     pub fn clear(&mut self) {
@@ -130,6 +157,46 @@ impl Diagnostics {
         self.scripts_ran.clear();
         self.scripts_error.clear();
         self.systems.clear();
+        for stats in self
+            .systems_by_name
+            .values_mut()
+            .chain(self.intents_by_type.values_mut())
+            .chain(self.entities_by_kind.values_mut())
+        {
+            stats.clear();
+        }
+    }
+
+    pub fn tick_latency(&self) -> &StatsField {
+        &self.tick_stats
+    }
+
+    pub fn scripts_execution_time(&self) -> &StatsField {
+        &self.scripts_execution_time
+    }
+
+    pub fn scripts_ran(&self) -> &StatsField {
+        &self.scripts_ran
+    }
+
+    pub fn scripts_error(&self) -> &StatsField {
+        &self.scripts_error
+    }
+
+    pub fn systems_time(&self) -> &StatsField {
+        &self.systems
+    }
+
+    pub fn systems_by_name(&self) -> impl Iterator<Item = (&str, &StatsField)> {
+        self.systems_by_name.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn intents_by_type(&self) -> impl Iterator<Item = (&str, &StatsField)> {
+        self.intents_by_type.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn entities_by_kind(&self) -> impl Iterator<Item = (&str, &StatsField)> {
+        self.entities_by_kind.iter().map(|(k, v)| (k.as_str(), v))
     }
 
     pub fn update_latency(&mut self, duration: Duration) {
@@ -142,6 +209,38 @@ impl Diagnostics {
         self.systems.update(latency as f64);
     }
 
+    pub fn update_system(&mut self, name: &str, duration: Duration) {
+        let latency = duration.num_microseconds().unwrap_or(i64::MAX);
+        update_named(&mut self.systems_by_name, name, "time_us", latency as f64);
+    }
+
+    pub fn update_intents(&mut self, intents: &[BotIntents]) {
+        let count = |has_intent: fn(&BotIntents) -> bool| {
+            intents.iter().filter(|intent| has_intent(intent)).count() as f64
+        };
+        let counts = [
+            ("move", count(|i| i.move_intent.is_some())),
+            ("mine", count(|i| i.mine_intent.is_some())),
+            ("dropoff", count(|i| i.dropoff_intent.is_some())),
+            ("melee", count(|i| i.melee_attack_intent.is_some())),
+            ("spawn", count(|i| i.spawn_intent.is_some())),
+        ];
+        for (name, count) in counts.iter() {
+            update_named(&mut self.intents_by_type, name, "intents", *count);
+        }
+    }
+
+    pub fn update_entities(&mut self, bots: usize, structures: usize, resources: usize) {
+        let counts = [
+            ("bots", bots),
+            ("structures", structures),
+            ("resources", resources),
+        ];
+        for (name, count) in counts.iter() {
+            update_named(&mut self.entities_by_kind, name, "count", *count as f64);
+        }
+    }
+
     pub fn update_scripts(
         &mut self,
         duration: Duration,
@@ -154,3 +253,14 @@ impl Diagnostics {
         self.scripts_ran.update(number_executed as f64);
     }
 }
+
+fn update_named(fields: &mut BTreeMap<String, StatsField>, name: &str, suffix: &str, value: f64) {
+    match fields.get_mut(name) {
+        Some(stats) => stats.update(value),
+        None => {
+            let mut stats = StatsField::new(format!("{}_{}", name, suffix));
+            stats.update(value);
+            fields.insert(name.to_owned(), stats);
+        }
+    }
+}
@@ -24,7 +24,9 @@ use crate::query;
 ///
 /// ```
 pub fn init_structure_spawn(id: EntityId, owner_id: Uuid, pos: WorldPosition, world: &mut World) {
-    // TODO tweak these numbas
+    let config = UnwrapView::<ConfigKey, GameConfig>::from_world(world)
+        .spawn
+        .clone();
     query!(
         mutate world
         {
@@ -40,24 +42,30 @@ pub fn init_structure_spawn(id: EntityId, owner_id: Uuid, pos: WorldPosition, wo
             EntityId, EnergyComponent, .insert_or_update(
                 id,
                 EnergyComponent {
-                    energy: 500,
-                    energy_max: 500,
+                    energy: config.energy_max,
+                    energy_max: config.energy_max,
                 }
             );
-            EntityId, EnergyRegenComponent, .insert_or_update(id, EnergyRegenComponent { amount: 20 });
+            EntityId, EnergyRegenComponent, .insert_or_update(
+                id,
+                EnergyRegenComponent { amount: config.energy_regen }
+            );
+            EntityId, VisionComponent, .insert_or_update(
+                id,
+                VisionComponent { range: config.vision_range }
+            );
             EntityId, HpComponent, .insert_or_update(
                 id,
                 HpComponent {
-                    hp: 500,
-                    hp_max: 500,
+                    hp: config.hp,
+                    hp_max: config.hp,
                 }
             );
-            EntityId, PositionComponent, .insert_or_update(id, PositionComponent(pos));
-            WorldPosition, EntityComponent, .insert(pos, EntityComponent(id))
-                .expect("entities_by_pos insert failed");
-
         }
     );
+    PositionsMut::from_world_mut(world)
+        .insert(id, pos, EntityLayer::Blocking)
+        .expect("entities_by_pos insert failed");
 }
 
 type InitBotTables = (
@@ -65,9 +73,14 @@ type InitBotTables = (
     UnsafeView<EntityId, HpComponent>,
     UnsafeView<EntityId, DecayComponent>,
     UnsafeView<EntityId, CarryComponent>,
-    UnsafeView<EntityId, PositionComponent>,
+    PositionsMut,
     UnsafeView<EntityId, OwnedEntity>,
     UnsafeView<EntityId, EntityScript>,
+    UnsafeView<EntityId, VisionComponent>,
+);
+type InitBotConst<'a> = (
+    View<'a, UserId, EntityScript>,
+    UnwrapView<'a, ConfigKey, GameConfig>,
 );
 pub fn init_bot(
     entity_id: EntityId,
@@ -81,34 +94,45 @@ pub fn init_bot(
         mut positions,
         mut owned,
         mut script_table,
+        mut vision,
     ): InitBotTables,
-    user_default_scripts: View<UserId, EntityScript>,
+    (user_default_scripts, config): InitBotConst,
 ) {
+    let config = &config.bot;
     bots.insert(entity_id);
     hps.insert_or_update(
         entity_id,
         HpComponent {
-            hp: 100,
-            hp_max: 100,
+            hp: config.hp,
+            hp_max: config.hp,
         },
     );
     decay.insert_or_update(
         entity_id,
         DecayComponent {
-            interval: 10,
-            time_remaining: 10,
-            hp_amount: 10,
+            interval: config.decay_interval,
+            time_remaining: config.decay_interval,
+            hp_amount: config.decay_amount,
         },
     );
     carry.insert_or_update(
         entity_id,
         CarryComponent {
             carry: 0,
-            carry_max: 150,
+            carry_max: config.carry_max,
+        },
+    );
+
+    vision.insert_or_update(
+        entity_id,
+        VisionComponent {
+            range: config.vision_range,
         },
     );
 
-    positions.insert_or_update(entity_id, PositionComponent(pos));
+    positions
+        .insert(entity_id, pos, EntityLayer::Blocking)
+        .expect("entities_by_pos insert failed");
 
     if let Some(owner_id) = owner_id {
         if let Some(script) = user_default_scripts.get_by_id(UserId(owner_id)) {
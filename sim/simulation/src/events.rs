@@ -0,0 +1,218 @@
+//! Outcomes of the systems, the things that _happened_ in a tick.
+//!
+//! Systems push the outcomes of their updates into the `Events<T>` resource tables. The tables are
+//! cleared before the systems of the next tick run, so the scripts and the clients see the events
+//! of the last tick.
+//!
+use crate::indices::{EmptyKey, EntityId, WorldPosition};
+use crate::prelude::World;
+use crate::storage::views::{FromWorldMut, UnsafeView};
+use crate::tables::unique_table::UniqueTable;
+use cao_storage_derive::CaoComponent;
+use serde::{Deserialize, Serialize};
+
+pub trait GameEvent {
+    /// Returns whether the entity took part in the event
+    fn involves(&self, id: EntityId) -> bool;
+}
+
+/// Hp of the entity fell to 0
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityDied {
+    pub id: EntityId,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attacked {
+    pub attacker: EntityId,
+    pub defender: EntityId,
+    /// Hp lost by the defender
+    pub damage: u16,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mined {
+    pub bot: EntityId,
+    pub resource: EntityId,
+    pub amount: u16,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DroppedOff {
+    pub bot: EntityId,
+    pub structure: EntityId,
+    pub amount: u16,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Spawned {
+    pub spawn: EntityId,
+    pub bot: EntityId,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveFailure {
+    /// The moving entity is not a bot
+    NotABot,
+    /// The target position is occupied
+    Occupied,
+    /// The target position is not part of the world
+    InvalidPosition,
+    /// Another bot moved to the same position in the same tick
+    LostTieBreak,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveFailed {
+    pub bot: EntityId,
+    pub position: WorldPosition,
+    pub reason: MoveFailure,
+}
+
+impl GameEvent for EntityDied {
+    fn involves(&self, id: EntityId) -> bool {
+        self.id == id
+    }
+}
+
+impl GameEvent for Attacked {
+    fn involves(&self, id: EntityId) -> bool {
+        self.attacker == id || self.defender == id
+    }
+}
+
+impl GameEvent for Mined {
+    fn involves(&self, id: EntityId) -> bool {
+        self.bot == id || self.resource == id
+    }
+}
+
+impl GameEvent for DroppedOff {
+    fn involves(&self, id: EntityId) -> bool {
+        self.bot == id || self.structure == id
+    }
+}
+
+impl GameEvent for Spawned {
+    fn involves(&self, id: EntityId) -> bool {
+        self.spawn == id || self.bot == id
+    }
+}
+
+impl GameEvent for MoveFailed {
+    fn involves(&self, id: EntityId) -> bool {
+        self.bot == id
+    }
+}
+
+/// Implements the functions working on every event table.
+/// The tables are registered in the `resource_store` of the World.
+macro_rules! events {
+    ($($name: ident),+,) => {
+        #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        #[repr(i32)]
+        pub enum EventKind {
+            $($name),*
+        }
+
+        impl EventKind {
+            /// Every kind, indexed by the kind
+            pub const ALL: &'static [EventKind] = &[$(EventKind::$name),*];
+        }
+
+        /// Clear the events of the last tick
+        pub fn clear_events(s: &mut World) {
+            $(
+                let mut events = UnsafeView::<EmptyKey, Events<$name>>::from_world_mut(s);
+                match events.value.as_mut() {
+                    Some(events) => events.0.clear(),
+                    None => {
+                        events.value = Some(Default::default());
+                    }
+                }
+            )*
+        }
+
+        /// Number of events of `kind` the entity took part in during the last tick
+        pub fn count_involving(s: &World, kind: EventKind, id: EntityId) -> usize {
+            match kind {
+                $(
+                    EventKind::$name => s
+                        .view::<EmptyKey, Events<$name>>()
+                        .value
+                        .as_ref()
+                        .map(|events| events.iter().filter(|e| e.involves(id)).count())
+                        .unwrap_or(0),
+                )*
+            }
+        }
+    };
+}
+
+events!(EntityDied, Attacked, Mined, DroppedOff, Spawned, MoveFailed,);
+
+/// Newtype wrapper on events to implement Component
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EmptyKey", table = "UniqueTable<EmptyKey, Self>")]
+#[cao_storage(transient)]
+pub struct Events<T>(pub Vec<T>);
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> std::ops::DerefMut for Events<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut_slice()
+    }
+}
+
+impl<T> std::ops::Deref for Events<T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        self.0.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::views::UnwrapViewMut;
+
+    #[test]
+    fn events_are_counted_by_the_entities_involved() {
+        let mut world = World::new();
+        let a = world.insert_entity();
+        let b = world.insert_entity();
+        let c = world.insert_entity();
+
+        let mut attacks = UnwrapViewMut::<EmptyKey, Events<Attacked>>::from_world_mut(&mut *world);
+        attacks.0.push(Attacked {
+            attacker: a,
+            defender: b,
+            damage: 3,
+        });
+        attacks.0.push(Attacked {
+            attacker: c,
+            defender: b,
+            damage: 3,
+        });
+
+        assert_eq!(count_involving(&world, EventKind::Attacked, a), 1);
+        assert_eq!(count_involving(&world, EventKind::Attacked, b), 2);
+        assert_eq!(count_involving(&world, EventKind::EntityDied, b), 0);
+
+        clear_events(&mut *world);
+        assert_eq!(count_involving(&world, EventKind::Attacked, b), 0);
+    }
+}
@@ -1,22 +1,23 @@
 use std::{convert::Infallible, pin::Pin};
 
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::{
-    components::EntityScript,
+    components::{Bot, EntityScript, ResourceComponent, Structure},
     diagnostics::Diagnostics,
     intents,
     map_generation::room::RoomGenerationParams,
     map_generation::MapGenError,
-    map_generation::{generate_full_map, overworld::OverworldGenerationParams},
+    map_generation::{expand_map, generate_full_map, overworld::OverworldGenerationParams},
     prelude::EntityId,
-    prelude::{EmptyKey, FromWorldMut},
+    prelude::{Axial, EntityComponent, FromWorldMut, LayeredEntity, Room, WorldPosition},
     profile,
-    systems::{execute_world_update, script_execution::execute_scripts},
+    systems::{execute_world_update, script_execution::execute_scripts, update_visibility},
     world::World,
 };
 
 pub use crate::components::game_config::GameConfig;
+use crate::components::game_config::MIN_ROOM_RADIUS;
 
 const STATS_INTERVAL: u64 = 64;
 
@@ -36,12 +37,15 @@ impl SimpleExecutor {
 
         debug!("Tick starting");
 
-        let mut diag = world.unsafe_view::<EmptyKey, Diagnostics>();
-        let diag: &mut Diagnostics = diag.unwrap_mut_or_default();
-
         if tick % STATS_INTERVAL == 1 {
             debug!("Clearing stats");
-            diag.clear();
+            diagnostics(world).clear();
+        }
+
+        if tick == 0 {
+            // visibility is computed at the end of the ticks, the scripts of the first tick need
+            // the visibility of the initial entities
+            update_visibility(world);
         }
 
         let scripts_table = world.view::<EntityId, EntityScript>();
@@ -60,6 +64,7 @@ impl SimpleExecutor {
             let start = chrono::Utc::now();
 
             debug!("Got {} intents", intents.len());
+            diagnostics(world).update_intents(intents.as_slice());
             intents::move_into_storage(world, intents);
 
             debug!("Executing systems update");
@@ -68,11 +73,18 @@ impl SimpleExecutor {
             debug!("Executing post-processing");
             world.post_process();
 
+            let bots = world.view::<EntityId, Bot>().iter().count();
+            let structures = world.view::<EntityId, Structure>().iter().count();
+            let resources = world.view::<EntityId, ResourceComponent>().iter().count();
+            let diag = diagnostics(world);
+            diag.update_entities(bots, structures, resources);
+
             let end = chrono::Utc::now();
             diag.update_systems(end - start);
         }
         let end = chrono::Utc::now();
 
+        let diag = diagnostics(world);
         diag.update_latency(end - start);
         debug!("Tick done");
         if tick % STATS_INTERVAL == 0 {
@@ -93,10 +105,90 @@ impl SimpleExecutor {
     }
 }
 
+/// The diagnostics are borrowed through `world`, so no other reference to them outlives the
+/// statements using them. The systems update them as well.
+fn diagnostics(world: &mut World) -> &mut Diagnostics {
+    world
+        .resources
+        .diagnostics
+        .value
+        .get_or_insert_with(Default::default)
+}
+
 fn execute_map_generation(world: &mut World, config: &GameConfig) -> Result<(), MapGenError> {
+    let (params, room_params) = map_generation_params(config);
+    debug!("generating map {:#?} {:#?}", params, room_params);
+
+    generate_full_map(
+        &params,
+        &room_params,
+        config.map_seed.map(seed_bytes),
+        FromWorldMut::from_world_mut(world),
+    )?;
+
+    debug!("world generation done");
+    Ok(())
+}
+
+/// Add a new ring of rooms to the world, leaving the existing entities intact. The new rooms get
+/// the resources of their biomes.
+/// Fails without modifying the world once the world radius reaches `MAX_WORLD_RADIUS`.
+///
+/// Returns the ids of the new rooms.
+pub fn expand_world(world: &mut World) -> Result<Vec<Axial>, MapGenError> {
+    let config = world
+        .config
+        .game_config
+        .value
+        .clone()
+        .expect("expected the world to have a GameConfig");
+    let (params, room_params) = map_generation_params(&config);
+    debug!("expanding map {:#?} {:#?}", params, room_params);
+
+    let new_rooms = expand_map(
+        &params,
+        &room_params,
+        None,
+        FromWorldMut::from_world_mut(world),
+    )?;
+
+    world
+        .unsafe_view::<WorldPosition, EntityComponent>()
+        .extend_rooms(new_rooms.iter().copied().map(Room))
+        .expect("expected to be able to insert the new rooms");
+    world
+        .unsafe_view::<WorldPosition, LayeredEntity>()
+        .extend_rooms(new_rooms.iter().copied().map(Room))
+        .expect("expected to be able to insert the new rooms");
+    let mut rng = rand::thread_rng();
+    for room in new_rooms.iter().copied() {
+        if let Err(err) = crate::init::init_room_resources(world, Room(room), &mut rng) {
+            warn!(
+                "Failed to place the resources of the new room {:?}: {}",
+                room, err
+            );
+        }
+    }
+    if let Some(config) = world.config.game_config.value.as_mut() {
+        config.world_radius += 1;
+    }
+
+    debug!("world expansion done, added {} rooms", new_rooms.len());
+    Ok(new_rooms)
+}
+
+fn seed_bytes(seed: u64) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..].copy_from_slice(&seed.to_be_bytes());
+    bytes
+}
+
+/// The terrain parameters of the rooms are set by their biomes, see `map_generation::biome`
+fn map_generation_params(config: &GameConfig) -> (OverworldGenerationParams, RoomGenerationParams) {
     let world_radius = config.world_radius;
     let room_radius = config.room_radius;
-    assert!(room_radius > 6);
+    assert!(room_radius >= MIN_ROOM_RADIUS);
     let params = OverworldGenerationParams::builder()
         .with_radius(world_radius as u32)
         .with_room_radius(room_radius)
@@ -106,20 +198,137 @@ fn execute_map_generation(world: &mut World, config: &GameConfig) -> Result<(),
         .unwrap();
     let room_params = RoomGenerationParams::builder()
         .with_radius(room_radius)
-        .with_chance_plain(0.13)
-        .with_chance_wall(1.0 - 0.13)
-        .with_plain_dilation(2)
         .build()
         .unwrap();
-    debug!("generating map {:#?} {:#?}", params, room_params);
+    (params, room_params)
+}
 
-    generate_full_map(
-        &params,
-        &room_params,
-        None,
-        FromWorldMut::from_world_mut(world),
-    )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{BiomeComponent, RoomProperties};
+    use crate::indices::ConfigKey;
+    use crate::prelude::{PositionComponent, RoomComponent, TerrainComponent};
 
-    debug!("world generation done");
-    Ok(())
+    #[test]
+    fn expanding_the_world_leaves_entities_intact() {
+        let mut exc = SimpleExecutor;
+        let mut world = exc.initialize(GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        crate::init::init_world_entities(&mut *world, 6);
+
+        let positions = world
+            .view::<EntityId, PositionComponent>()
+            .iter()
+            .map(|(id, pos)| (id, pos.0))
+            .collect::<Vec<_>>();
+        let num_rooms = world.view::<Axial, RoomComponent>().len();
+
+        let new_rooms = expand_world(&mut *world).unwrap();
+
+        assert!(!new_rooms.is_empty());
+        assert_eq!(
+            world.view::<Axial, RoomComponent>().len(),
+            num_rooms + new_rooms.len()
+        );
+        let biomes = world.view::<Axial, BiomeComponent>();
+        let expected_resources = new_rooms
+            .iter()
+            .map(|room| biomes.at(*room).unwrap().0.properties().resource_density as usize)
+            .sum::<usize>();
+        let new_resources = world
+            .view::<EntityId, ResourceComponent>()
+            .iter()
+            .filter_map(|(id, _)| world.view::<EntityId, PositionComponent>().get_by_id(id))
+            .filter(|pos| new_rooms.contains(&pos.0.room))
+            .count();
+        assert_eq!(new_resources, expected_resources);
+
+        let terrain = world.view::<WorldPosition, TerrainComponent>();
+        let entities = world.view::<WorldPosition, EntityComponent>();
+        for room in new_rooms {
+            assert!(terrain.contains_room(Room(room)));
+            assert!(entities.contains_room(Room(room)));
+        }
+        for (id, pos) in positions {
+            assert_eq!(
+                world
+                    .view::<EntityId, PositionComponent>()
+                    .get_by_id(id)
+                    .map(|p| p.0),
+                Some(pos)
+            );
+            assert!(terrain.at(pos).unwrap().0.is_walkable());
+        }
+        assert_eq!(
+            world
+                .config
+                .game_config
+                .value
+                .as_ref()
+                .unwrap()
+                .world_radius,
+            3
+        );
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_map() {
+        let config = GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            map_seed: Some(42),
+            ..Default::default()
+        };
+        let walkable = |world: &World| {
+            world
+                .view::<WorldPosition, TerrainComponent>()
+                .iter()
+                .map(|(pos, t)| (pos, t.0.is_walkable()))
+                .collect::<Vec<_>>()
+        };
+
+        let a = SimpleExecutor.initialize(config.clone());
+        let b = SimpleExecutor.initialize(config);
+
+        assert_eq!(walkable(&a), walkable(&b));
+    }
+
+    #[test]
+    fn biomes_are_seeded_by_the_map_seed() {
+        use crate::map_generation::biome::choose_biome;
+        use crate::noise::PerlinNoise;
+
+        let biome_seed = |world: &World| {
+            world
+                .view::<ConfigKey, RoomProperties>()
+                .value
+                .as_ref()
+                .unwrap()
+                .biome_seed
+        };
+        let config = |map_seed| GameConfig {
+            world_radius: 1,
+            room_radius: 10,
+            map_seed: Some(map_seed),
+            ..Default::default()
+        };
+
+        let mut a = SimpleExecutor.initialize(config(42));
+        let b = SimpleExecutor.initialize(config(42));
+        let c = SimpleExecutor.initialize(config(43));
+        assert_eq!(biome_seed(&a), biome_seed(&b));
+        assert_ne!(biome_seed(&a), biome_seed(&c));
+
+        // the new rooms are sampled from the noise of the original map
+        let noise = PerlinNoise::new(biome_seed(&a));
+        let new_rooms = expand_world(&mut *a).unwrap();
+        let biomes = a.view::<Axial, BiomeComponent>();
+        for room in new_rooms {
+            assert_eq!(biomes.at(room).unwrap().0, choose_biome(&noise, room));
+        }
+    }
 }
@@ -13,10 +13,14 @@ use std::ops::Add;
 )]
 pub struct EntityTime(pub EntityId, pub u64);
 
-#[derive(
-    Debug, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Copy, Hash, Serialize, Deserialize,
-)]
-pub struct EntityId(pub u32);
+/// Generational entity id.
+///
+/// The low 32 bits hold the index of the entity, the high bits hold the generation of the index.
+/// The generation is bumped every time a freed index is reused, so ids held on to after their
+/// entity was deleted no longer resolve.
+/// The generation is limited to 31 bits so ids always fit in a positive `i64` (scripts, protos).
+#[derive(Debug, Clone, Default, Eq, PartialEq, Copy, Hash, Serialize, Deserialize)]
+pub struct EntityId(pub u64);
 
 #[derive(
     Debug, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Copy, Hash, Serialize, Deserialize,
@@ -41,13 +45,49 @@ impl SerialId for IntentId {
     }
 }
 
+impl EntityId {
+    const INDEX_BITS: u64 = 32;
+    const INDEX_MASK: u64 = (1 << Self::INDEX_BITS) - 1;
+    const GEN_MASK: u64 = (1 << 31) - 1;
+
+    pub fn new(index: u32, gen: u32) -> Self {
+        Self(((gen as u64 & Self::GEN_MASK) << Self::INDEX_BITS) | index as u64)
+    }
+
+    pub fn index(self) -> u32 {
+        (self.0 & Self::INDEX_MASK) as u32
+    }
+
+    pub fn gen(self) -> u32 {
+        (self.0 >> Self::INDEX_BITS) as u32
+    }
+
+    /// The id of the next entity reusing this index
+    pub fn next_gen(self) -> Self {
+        Self::new(self.index(), self.gen().wrapping_add(1))
+    }
+}
+
+/// Ordered by index first, so tables indexed by `as_usize` iterate in `Ord` order
+impl Ord for EntityId {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.index(), self.gen()).cmp(&(other.index(), other.gen()))
+    }
+}
+
+impl PartialOrd for EntityId {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl SerialId for EntityId {
     fn next(&self) -> Self {
-        Self(self.0 + 1)
+        Self::new(self.index() + 1, 0)
     }
 
     fn as_usize(&self) -> usize {
-        self.0 as usize
+        self.index() as usize
     }
 }
 
@@ -59,7 +99,7 @@ impl TryFrom<Value> for EntityId {
                 if i < 0 {
                     return Err(s);
                 }
-                Ok(EntityId(i as u32))
+                Ok(EntityId(i as u64))
             }
             _ => Err(s),
         }
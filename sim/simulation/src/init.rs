@@ -1,16 +1,36 @@
+use crate::map_generation::biome::BiomeProperties;
 use crate::prelude::*;
+use crate::tables::{morton_hierarchy::MortonMultiMortonTable, morton_multi_table::Layered};
 use cao_lang::{compiler::CompileOptions, prelude::*};
-use rand::Rng;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use thiserror::Error;
 use tracing::{debug, trace};
 use uuid::Uuid;
 
-/// World should be already initialized with a GameConfig
+#[derive(Debug, Clone, Error)]
+pub enum InitError {
+    #[error("Failed to find a free position in {room:?} {bounds:?} in {tries} tries")]
+    NoFreePosition {
+        room: Room,
+        bounds: Hexagon,
+        tries: usize,
+    },
+}
+
+/// World should be already initialized with a GameConfig.
+///
+/// Every room gets the resources of its biome, the rooms of the users get a spawn as well.
+/// The placement of the entities and the ids of the users are derived from the `map_seed` of the
+/// GameConfig, if it is set.
 pub fn init_world_entities(storage: &mut World, n_fake_users: usize) {
     debug!("initializing world");
 
-    let mut rng = rand::thread_rng();
+    let mut rng = match UnwrapView::<ConfigKey, GameConfig>::from_world(storage).map_seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    };
 
-    let mining_script_id = ScriptId(Uuid::new_v4());
+    let mining_script_id = ScriptId(random_uuid(&mut rng));
     let script: CaoIr = serde_yaml::from_str(include_str!("./programs/mining_program.yaml"))
         .expect("deserialize example program");
     debug!("compiling default program");
@@ -42,6 +62,17 @@ pub fn init_world_entities(storage: &mut World, n_fake_users: usize) {
                 .map(|(roomid, _)| (roomid, Default::default())),
         )
         .expect("entities_by_pos init");
+    let mut layered_entities = storage.unsafe_view::<WorldPosition, LayeredEntity>();
+    layered_entities.clear();
+    layered_entities
+        .table
+        .extend(
+            storage
+                .view::<Axial, RoomComponent>()
+                .iter()
+                .map(|(roomid, _)| (roomid, Default::default())),
+        )
+        .expect("layered_entities init");
     let bounds = Hexagon {
         center: Axial::new(radius as i32, radius as i32),
         radius: radius as i32,
@@ -55,112 +86,278 @@ pub fn init_world_entities(storage: &mut World, n_fake_users: usize) {
     let mut taken_rooms = Vec::with_capacity(n_fake_users as usize);
     for i in 0..n_fake_users {
         trace!("initializing room #{}", i);
-        let spawnid = storage.insert_entity();
 
         let room = rng.gen_range(0, rooms.len());
         let room = rooms[room];
         taken_rooms.push(room);
 
         trace!("initializing room #{} in room {:?}", i, room);
-        let user_id = Uuid::new_v4();
-        init_spawn(&bounds, spawnid, user_id, Room(room), &mut rng, storage);
-        trace!("spawning entities");
+        let user_id = random_uuid(&mut rng);
         storage
             .unsafe_view::<UserId, EntityScript>()
             .insert_or_update(UserId(user_id), EntityScript(mining_script_id));
-        let id = storage.insert_entity();
+        trace!("spawning entities");
+        init_spawn(&bounds, user_id, Room(room), &mut rng, storage)
+            .expect("failed to initialize the room entities");
+        trace!("initializing room #{} done", i);
+    }
+
+    for room in rooms {
+        init_room_resources(storage, Room(room), &mut rng)
+            .expect("failed to initialize the room resources");
+    }
+
+    debug!("init done");
+}
+
+/// Spawn the entities a user starts with in the given room: a spawn, bots running the user's
+/// default script and resources near the spawn.
+/// The amounts are set by the `starter_kit` of the GameConfig.
+///
+/// The room should be owned by the user.
+/// Fails without spawning anything if the room has no free position left for an entity of the
+/// kit.
+pub fn init_starter_kit(world: &mut World, owner_id: UserId, room: Room) -> Result<(), InitError> {
+    debug!("initializing starter kit of {:?} in {:?}", owner_id, room);
+    spawn_kit(world, owner_id, room, true, &mut rand::thread_rng())
+}
+
+/// Spawn the spawn and the bots of the starter kit again, in a room the user got a kit in before.
+///
+/// Resources are not owned, so the resources of the first kit are still in the room and are not
+/// added again.
+pub fn respawn_starter_kit(
+    world: &mut World,
+    owner_id: UserId,
+    room: Room,
+) -> Result<(), InitError> {
+    debug!("respawning starter kit of {:?} in {:?}", owner_id, room);
+    spawn_kit(world, owner_id, room, false, &mut rand::thread_rng())
+}
+
+fn spawn_kit(
+    world: &mut World,
+    owner_id: UserId,
+    room: Room,
+    with_resources: bool,
+    rng: &mut impl Rng,
+) -> Result<(), InitError> {
+    let kit = UnwrapView::<ConfigKey, GameConfig>::from_world(world)
+        .starter_kit
+        .clone();
+    let bounds = room_bounds(world);
+    let n_resources = if with_resources { kit.resources } else { 0 };
+
+    // find the position of every entity of the kit before inserting any of them, so a kit that
+    // does not fit leaves the room untouched
+    let spawn_pos = uncontested_pos(
+        room,
+        &bounds,
+        &*world.view::<WorldPosition, LayeredEntity>(),
+        &*world.view::<WorldPosition, TerrainComponent>(),
+        &[],
+        rng,
+    )?;
+    let nearby = Hexagon::new(spawn_pos.pos, kit.radius as i32);
+    let mut taken = Vec::with_capacity(1 + kit.bots as usize + n_resources as usize);
+    taken.push(spawn_pos);
+    for _ in 0..kit.bots + n_resources {
+        let pos = nearby_pos(room, &nearby, &bounds, &taken, rng, world)?;
+        taken.push(pos);
+    }
+
+    let id = world.insert_entity();
+    crate::entity_archetypes::init_structure_spawn(id, owner_id.0, spawn_pos, world);
+
+    let mut positions = taken[1..].iter().copied();
+
+    for pos in positions.by_ref().take(kit.bots as usize) {
+        let id = world.insert_entity();
+        crate::entity_archetypes::init_bot(
+            id,
+            Some(owner_id.0),
+            pos,
+            FromWorldMut::from_world_mut(world),
+            FromWorld::from_world(world),
+        );
+    }
+
+    let biome = biome_properties(world, room);
+    for pos in positions {
+        let id = world.insert_entity();
         init_resource(
+            id,
+            Resource::Energy,
+            biome.resource_energy,
+            pos,
+            FromWorldMut::from_world_mut(world),
+        );
+    }
+    Ok(())
+}
+
+/// Place the resources of the biome of `room` at random free positions of the room.
+/// The room has to be in the position tables of the World.
+pub fn init_room_resources(
+    world: &mut World,
+    room: Room,
+    rng: &mut impl Rng,
+) -> Result<(), InitError> {
+    let bounds = room_bounds(world);
+    let biome = biome_properties(world, room);
+    for _ in 0..biome.resource_density {
+        let pos = uncontested_pos(
+            room,
             &bounds,
+            &*world.view::<WorldPosition, LayeredEntity>(),
+            &*world.view::<WorldPosition, TerrainComponent>(),
+            &[],
+            rng,
+        )?;
+        let id = world.insert_entity();
+        init_resource(
             id,
-            Room(room),
-            &mut rng,
-            FromWorldMut::from_world_mut(storage),
-            FromWorld::from_world(storage),
+            Resource::Energy,
+            biome.resource_energy,
+            pos,
+            FromWorldMut::from_world_mut(world),
         );
-        trace!("initializing room #{} done", i);
     }
+    Ok(())
+}
+
+fn room_bounds(world: &World) -> Hexagon {
+    let radius = UnwrapView::<ConfigKey, GameConfig>::from_world(world).room_radius as i32;
+    Hexagon {
+        center: Axial::new(radius, radius),
+        radius,
+    }
+}
 
-    debug!("init done");
+fn random_uuid(rng: &mut impl Rng) -> Uuid {
+    uuid::Builder::from_bytes(rng.gen())
+        .set_variant(uuid::Variant::RFC4122)
+        .set_version(uuid::Version::Random)
+        .build()
+}
+
+fn biome_properties(world: &World, room: Room) -> BiomeProperties {
+    world
+        .view::<Axial, BiomeComponent>()
+        .at(room.0)
+        .map(|BiomeComponent(biome)| *biome)
+        .unwrap_or_default()
+        .properties()
 }
 
-#[allow(clippy::too_many_arguments)] // its just a helper function let it be
 fn init_spawn(
     bounds: &Hexagon,
-    id: EntityId,
     owner_id: Uuid,
     room: Room,
     rng: &mut impl Rng,
     world: &mut World,
-) {
+) -> Result<WorldPosition, InitError> {
     trace!("init_spawn");
     let pos = uncontested_pos(
         room,
         bounds,
-        &*world.view::<WorldPosition, EntityComponent>(),
+        &*world.view::<WorldPosition, LayeredEntity>(),
         &*world.view::<WorldPosition, TerrainComponent>(),
+        &[],
         rng,
-    );
+    )?;
 
+    let id = world.insert_entity();
     crate::entity_archetypes::init_structure_spawn(id, owner_id, pos, world);
     trace!("init_spawn done");
+    Ok(pos)
 }
 
-type InitResourceMuts = (
-    UnsafeView<EntityId, PositionComponent>,
+pub(crate) type InitResourceMuts = (
+    PositionsMut,
     UnsafeView<EntityId, ResourceComponent>,
     UnsafeView<EntityId, EnergyComponent>,
     UnsafeView<EntityId, RespawnTimer>,
-    UnsafeView<WorldPosition, EntityComponent>,
 );
 
-type InitResourceConst<'a> = (View<'a, WorldPosition, TerrainComponent>,);
-
-fn init_resource(
-    bounds: &Hexagon,
+pub(crate) fn init_resource(
     id: EntityId,
-    room: Room,
-    rng: &mut impl Rng,
-    (
-        mut positions_table,
-        mut resources_table,
-        mut energy_table,
-        mut respawn_timer,
-        mut entities_by_pos,
-    ): InitResourceMuts,
-    (terrain,): InitResourceConst,
+    resource: Resource,
+    energy: u16,
+    pos: WorldPosition,
+    (mut positions, mut resources_table, mut energy_table, mut respawn_timer): InitResourceMuts,
 ) {
-    resources_table.insert_or_update(id, ResourceComponent(Resource::Energy));
+    resources_table.insert_or_update(id, ResourceComponent(resource));
     energy_table.insert_or_update(
         id,
         EnergyComponent {
-            energy: 100,
-            energy_max: 100,
+            energy,
+            energy_max: energy,
         },
     );
     respawn_timer.insert_or_update(id, RespawnTimer(2));
 
-    let pos = uncontested_pos(room, bounds, &*entities_by_pos, &*terrain, rng);
+    positions
+        .insert(id, pos, EntityLayer::Ground)
+        .expect("expected room to be in entities_by_pos table");
+}
 
-    positions_table.insert_or_update(id, PositionComponent(pos));
-    entities_by_pos
-        .table
-        .at_mut(room.0)
-        .expect("expected room to be in entities_by_pos table")
-        .insert(pos.pos, EntityComponent(id))
-        .expect("entities_by_pos insert");
+/// Find a free position in `nearby`, fall back to the whole room if there is none.
+/// Positions in `taken` are not free.
+fn nearby_pos(
+    room: Room,
+    nearby: &Hexagon,
+    bounds: &Hexagon,
+    taken: &[WorldPosition],
+    rng: &mut impl Rng,
+    world: &World,
+) -> Result<WorldPosition, InitError> {
+    const TRIES: usize = 1_000;
+    let entities = world.view::<WorldPosition, LayeredEntity>();
+    let terrain = world.view::<WorldPosition, TerrainComponent>();
+    match try_uncontested_pos(room, nearby, &*entities, &*terrain, taken, TRIES, rng) {
+        Some(pos) => Ok(pos),
+        None => uncontested_pos(room, bounds, &*entities, &*terrain, taken, rng),
+    }
 }
 
-fn uncontested_pos<T: crate::tables::TableRow + Send + Sync + Default>(
+fn uncontested_pos<T: crate::tables::TableRow + Send + Sync + Default + Layered>(
     room: Room,
     bounds: &Hexagon,
-    positions_table: &crate::tables::morton_hierarchy::MortonMortonTable<T>,
+    positions_table: &MortonMultiMortonTable<T>,
     terrain_table: &<TerrainComponent as Component<WorldPosition>>::Table,
+    taken: &[WorldPosition],
     rng: &mut impl Rng,
-) -> WorldPosition {
+) -> Result<WorldPosition, InitError> {
     const TRIES: usize = 10_000;
+    try_uncontested_pos(
+        room,
+        bounds,
+        positions_table,
+        terrain_table,
+        taken,
+        TRIES,
+        rng,
+    )
+    .ok_or(InitError::NoFreePosition {
+        room,
+        bounds: *bounds,
+        tries: TRIES,
+    })
+}
+
+fn try_uncontested_pos<T: crate::tables::TableRow + Send + Sync + Default + Layered>(
+    room: Room,
+    bounds: &Hexagon,
+    positions_table: &MortonMultiMortonTable<T>,
+    terrain_table: &<TerrainComponent as Component<WorldPosition>>::Table,
+    taken: &[WorldPosition],
+    tries: usize,
+    rng: &mut impl Rng,
+) -> Option<WorldPosition> {
     let from = bounds.center - Axial::new(bounds.radius, bounds.radius);
     let to = bounds.center + Axial::new(bounds.radius, bounds.radius);
-    for _ in 0..TRIES {
+    for _ in 0..tries {
         let x = rng.gen_range(from.q, to.q);
         let y = rng.gen_range(from.r, to.r);
 
@@ -176,15 +373,13 @@ fn uncontested_pos<T: crate::tables::TableRow + Send + Sync + Default>(
         let pos = WorldPosition { room: room.0, pos };
 
         if let Some(TerrainComponent(terrain)) = terrain_table.get_by_id(pos) {
-            if terrain.is_walkable() && !positions_table.contains_key(&pos) {
-                return pos;
+            if terrain.is_walkable() && !positions_table.contains_key(&pos) && !taken.contains(&pos)
+            {
+                return Some(pos);
             }
         }
     }
-    panic!(
-        "Failed to find an uncontested_pos in {:?} {:?} in {} iterations",
-        from, to, TRIES
-    );
+    None
 }
 
 #[cfg(test)]
@@ -203,4 +398,206 @@ mod tests {
         // smoke test: can the game be even initialized?
         init_world_entities(&mut *world, 12);
     }
+
+    #[test]
+    fn entities_are_seeded_by_the_map_seed() {
+        let init = || {
+            let mut world = SimpleExecutor.initialize(crate::executor::GameConfig {
+                world_radius: 2,
+                room_radius: 10,
+                map_seed: Some(42),
+                ..Default::default()
+            });
+            init_world_entities(&mut *world, 4);
+            let positions = world
+                .view::<EntityId, PositionComponent>()
+                .iter()
+                .map(|(id, pos)| (id, pos.0))
+                .collect::<Vec<_>>();
+            let users = world
+                .view::<UserId, EntityScript>()
+                .iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            (positions, users)
+        };
+
+        let (positions, users) = init();
+        assert!(!positions.is_empty());
+        assert_eq!(users.len(), 4);
+        assert_eq!(init(), (positions, users));
+    }
+
+    #[test]
+    fn every_room_gets_the_resources_of_its_biome() {
+        let mut world = SimpleExecutor.initialize(crate::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        init_world_entities(&mut *world, 0);
+
+        let resources = world.view::<EntityId, ResourceComponent>();
+        let energy = world.view::<EntityId, EnergyComponent>();
+        let positions = world.view::<EntityId, PositionComponent>();
+        for (room, BiomeComponent(biome)) in world.view::<Axial, BiomeComponent>().iter() {
+            let props = biome.properties();
+            let in_room = resources
+                .iter()
+                .map(|(id, _)| id)
+                .filter(|id| positions.get_by_id(*id).unwrap().0.room == room)
+                .collect::<Vec<_>>();
+            assert_eq!(in_room.len(), props.resource_density as usize);
+            for id in in_room {
+                assert_eq!(
+                    energy.get_by_id(id).unwrap().energy_max,
+                    props.resource_energy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn starter_kit_bots_run_the_default_script() {
+        let mut exc = SimpleExecutor;
+        let mut world = exc.initialize(crate::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        let kit = world
+            .config
+            .game_config
+            .value
+            .as_ref()
+            .unwrap()
+            .starter_kit
+            .clone();
+
+        let user_id = UserId(Uuid::new_v4());
+        let script_id = ScriptId(Uuid::new_v4());
+        world
+            .unsafe_view::<UserId, EntityScript>()
+            .insert_or_update(user_id, EntityScript(script_id));
+        let room = world
+            .view::<Axial, RoomComponent>()
+            .iter()
+            .map(|(room, _)| Room(room))
+            .next()
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(room))
+            .unwrap();
+
+        init_starter_kit(&mut *world, user_id, room).unwrap();
+
+        let bots = world
+            .view::<EntityId, Bot>()
+            .iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(bots.len(), kit.bots as usize);
+        for id in bots {
+            assert_eq!(
+                world
+                    .view::<EntityId, EntityScript>()
+                    .get_by_id(id)
+                    .map(|s| s.0),
+                Some(script_id)
+            );
+            let pos = world
+                .view::<EntityId, PositionComponent>()
+                .get_by_id(id)
+                .unwrap()
+                .0;
+            assert_eq!(pos.room, room.0);
+            assert!(world
+                .view::<WorldPosition, EntityComponent>()
+                .contains_key(&pos));
+        }
+        assert_eq!(world.view::<EntityId, Structure>().iter().count(), 1);
+        assert_eq!(
+            world.view::<EntityId, ResourceComponent>().iter().count(),
+            kit.resources as usize
+        );
+    }
+
+    #[test]
+    fn starter_kit_fails_in_a_room_without_free_positions() {
+        let mut exc = SimpleExecutor;
+        let mut world = exc.initialize(crate::executor::GameConfig {
+            world_radius: 1,
+            room_radius: 10,
+            ..Default::default()
+        });
+        let room = world
+            .view::<Axial, RoomComponent>()
+            .iter()
+            .map(|(room, _)| Room(room))
+            .next()
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(room))
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, LayeredEntity>()
+            .extend_rooms(std::iter::once(room))
+            .unwrap();
+        let mut terrain = world.unsafe_view::<WorldPosition, TerrainComponent>();
+        for (_, tile) in terrain.table.at_mut(room.0).unwrap().iter_mut() {
+            *tile = TerrainComponent(crate::terrain::TileTerrainType::Wall);
+        }
+
+        let res = init_starter_kit(&mut *world, UserId(Uuid::new_v4()), room);
+
+        assert!(matches!(res, Err(InitError::NoFreePosition { .. })));
+        assert_eq!(world.view::<EntityId, Structure>().iter().count(), 0);
+        assert_eq!(world.view::<EntityId, Bot>().iter().count(), 0);
+    }
+
+    #[test]
+    fn starter_kit_is_not_spawned_partially() {
+        let mut exc = SimpleExecutor;
+        let mut world = exc.initialize(crate::executor::GameConfig {
+            world_radius: 1,
+            room_radius: 10,
+            ..Default::default()
+        });
+        let room = world
+            .view::<Axial, RoomComponent>()
+            .iter()
+            .map(|(room, _)| Room(room))
+            .next()
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(room))
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, LayeredEntity>()
+            .extend_rooms(std::iter::once(room))
+            .unwrap();
+        // room for the spawn and a single bot
+        let free = [Axial::new(10, 10), Axial::new(11, 10)];
+        let mut terrain = world.unsafe_view::<WorldPosition, TerrainComponent>();
+        for (pos, tile) in terrain.table.at_mut(room.0).unwrap().iter_mut() {
+            *tile = if free.contains(&pos) {
+                TerrainComponent(crate::terrain::TileTerrainType::Plain)
+            } else {
+                TerrainComponent(crate::terrain::TileTerrainType::Wall)
+            };
+        }
+
+        let res = init_starter_kit(&mut *world, UserId(Uuid::new_v4()), room);
+
+        assert!(matches!(res, Err(InitError::NoFreePosition { .. })));
+        assert_eq!(world.view::<EntityId, Structure>().iter().count(), 0);
+        assert_eq!(world.view::<EntityId, Bot>().iter().count(), 0);
+        assert_eq!(
+            world.view::<EntityId, ResourceComponent>().iter().count(),
+            0
+        );
+    }
 }
@@ -21,7 +21,12 @@ pub use self::spawn_intent::*;
 use crate::components::ScriptHistoryEntry;
 use crate::indices::{EmptyKey, EntityId};
 use crate::prelude::World;
+use crate::scripting_api::OperationResult;
+use crate::tables::btree_table::BTreeTable;
+use crate::tables::unique_table::UniqueTable;
+use cao_storage_derive::CaoComponent;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 
 impl BotIntents {
     pub fn with_log<'a, S: Into<&'a str>>(
@@ -73,7 +78,8 @@ macro_rules! intents {
         }
 
         /// Newtype wrapper on intents to implement Component
-        #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+        #[derive(Debug, Clone, Default, Serialize, Deserialize, CaoComponent)]
+        #[cao_storage(key = "EmptyKey", table = "UniqueTable<EmptyKey, Self>")]
         pub struct Intents<T> (pub Vec<T>);
 
         impl<T> std::ops::DerefMut for Intents<T> {
@@ -98,6 +104,26 @@ macro_rules! intents {
     };
 }
 
+/// Final status of the intent of type `T` of an entity, as resolved by the systems in the last
+/// tick. The scripts only see the result of the validation when they submit the intent.
+#[derive(Debug, Clone, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EntityId", table = "BTreeTable<EntityId, Self>")]
+#[cao_storage(transient)]
+pub struct IntentResult<T> {
+    pub result: OperationResult,
+    #[serde(skip)]
+    _intent: PhantomData<T>,
+}
+
+impl<T> IntentResult<T> {
+    pub fn new(result: OperationResult) -> Self {
+        Self {
+            result,
+            _intent: PhantomData,
+        }
+    }
+}
+
 intents!(
     move_intent: MoveIntent,
     spawn_intent: SpawnIntent,
@@ -54,7 +54,7 @@ pub fn check_mine_intent(
         Some(pos) => pos,
         None => {
             debug!("{:?} has no position", target);
-            return OperationResult::InvalidInput;
+            return OperationResult::InvalidTarget;
         }
     };
 
@@ -3,20 +3,23 @@ use prelude::World;
 pub mod components;
 pub mod diagnostics;
 pub mod entity_archetypes;
+pub mod events;
 pub mod executor;
 pub mod geometry;
 pub mod indices;
 pub mod init;
+pub mod lifecycle;
 pub mod map_generation;
 pub mod noise;
 pub mod pathfinding;
 pub mod prelude;
+pub mod scenario;
 pub mod scripting_api;
 pub mod storage;
 pub mod tables;
 pub mod terrain;
 
-mod intents;
+pub mod intents;
 mod systems;
 mod utils;
 pub mod world;
@@ -25,12 +28,33 @@ pub mod version {
     include!(concat!(env!("OUT_DIR"), "/cao_sim_version.rs"));
 }
 
-#[derive(Clone, Debug, Default, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Copy,
+    serde::Serialize,
+    serde::Deserialize,
+    cao_storage_derive::CaoComponent,
+)]
+#[cao_storage(
+    key = "indices::EmptyKey",
+    table = "tables::unique_table::UniqueTable<indices::EmptyKey, Self>"
+)]
 pub struct Time(pub u64);
 
 impl<'a> storage::views::FromWorld<'a> for Time {
-    fn from_world(w: &'a World) -> Self {
-        Time(w.time())
+    unsafe fn from_world_ptr(w: std::ptr::NonNull<World>) -> Self {
+        let time: storage::views::View<indices::EmptyKey, Time> =
+            storage::views::FromWorld::from_world_ptr(w);
+        time.value.unwrap_or_default()
+    }
+
+    const READS: storage::views::StaticAccesses =
+        storage::views::StaticAccesses::table::<indices::EmptyKey, Time>();
+
+    fn reads(out: &mut Vec<storage::views::WorldAccess>) {
+        out.push(storage::views::WorldAccess::table::<indices::EmptyKey, Time>());
     }
 }
 
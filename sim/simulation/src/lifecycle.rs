@@ -0,0 +1,352 @@
+//! Entity lifecycle.
+//!
+//! Deleting an entity removes its rows from the `entity_store` tables, components referencing the
+//! entity from other rows clean up after it in their on-delete hooks (see `OnEntityDelete`).
+//! The hooks run when the deferred deletes are executed in `World::post_process`, so references
+//! to deleted entities do not outlive the tick.
+//!
+//! Every deletion is recorded as an `EntityDeletion` event in the `EntityDeletions` resource.
+//! The events are kept until the `post_process` of the tick after the deletion.
+//!
+use crate::components::{
+    DropoffEventComponent, MineEventComponent, PathCacheComponent, SpawnComponent,
+    SpawnQueueComponent, Visibility,
+};
+use crate::indices::{EmptyKey, EntityId, UserId, WorldPosition};
+use crate::prelude::World;
+use crate::storage::HasTable;
+use crate::tables::btree_table::BTreeTable;
+use crate::tables::unique_table::UniqueTable;
+use crate::tables::{Component, Table, TableId, TableRow};
+use cao_storage_derive::CaoComponent;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeleteCause {
+    Unspecified,
+    /// The hp of the entity fell to 0
+    Died,
+    /// A script requested the deletion
+    Intent,
+    /// The entity could not be respawned, e.g. a depleted mineral without room around it
+    RespawnFailed,
+    /// The owner of the entity was removed from the game
+    OwnerRemoved,
+    /// Deleted by an administrator
+    Admin,
+}
+
+impl Default for DeleteCause {
+    fn default() -> Self {
+        DeleteCause::Unspecified
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityDeletion {
+    pub id: EntityId,
+    pub cause: DeleteCause,
+    /// The tick the entity was deleted in
+    pub time: u64,
+    /// The users that could see the entity before it was deleted.
+    /// The `Visibility` hook removes the entity from the visible sets.
+    pub seen_by: Vec<UserId>,
+}
+
+/// The entities deleted in the current and the previous tick
+#[derive(Debug, Clone, Default, Serialize, Deserialize, CaoComponent)]
+#[cao_storage(key = "EmptyKey", table = "UniqueTable<EmptyKey, Self>")]
+pub struct EntityDeletions(pub Vec<EntityDeletion>);
+
+/// The entities deleted in a batch of deferred deletes
+#[derive(Debug, Default)]
+pub struct DeletedEntities {
+    /// sorted ids
+    ids: Vec<EntityId>,
+    /// sorted positions the entities had when they were deleted
+    positions: Vec<WorldPosition>,
+}
+
+impl DeletedEntities {
+    pub fn new(
+        ids: impl IntoIterator<Item = EntityId>,
+        positions: impl IntoIterator<Item = WorldPosition>,
+    ) -> Self {
+        let mut ids: Vec<_> = ids.into_iter().collect();
+        let mut positions: Vec<_> = positions.into_iter().collect();
+        ids.sort_unstable();
+        positions.sort_unstable();
+        Self { ids, positions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn ids(&self) -> &[EntityId] {
+        self.ids.as_slice()
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.ids.binary_search(&id).is_ok()
+    }
+
+    /// Returns whether one of the deleted entities was at `pos`
+    pub fn was_at(&self, pos: WorldPosition) -> bool {
+        self.positions.binary_search(&pos).is_ok()
+    }
+}
+
+/// Components holding references to other entities clean them up when those entities are deleted
+pub trait OnEntityDelete<Id: TableId>: Component<Id> {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities);
+}
+
+fn run_hook<Id, C>(world: &mut World, deleted: &DeletedEntities)
+where
+    Id: TableId,
+    C: OnEntityDelete<Id>,
+    World: HasTable<Id, C>,
+{
+    let mut table = world.unsafe_view::<Id, C>();
+    C::on_entity_delete(&mut *table, deleted);
+}
+
+/// Run the on-delete hooks of every component referencing entities
+pub(crate) fn run_delete_hooks(world: &mut World, deleted: &DeletedEntities) {
+    if deleted.is_empty() {
+        return;
+    }
+    trace!("Running on-delete hooks for {:?}", deleted.ids());
+    run_hook::<EntityId, SpawnComponent>(world, deleted);
+    run_hook::<EntityId, SpawnQueueComponent>(world, deleted);
+    run_hook::<EntityId, MineEventComponent>(world, deleted);
+    run_hook::<EntityId, DropoffEventComponent>(world, deleted);
+    run_hook::<EntityId, PathCacheComponent>(world, deleted);
+    run_hook::<UserId, Visibility>(world, deleted);
+}
+
+/// Delete the rows of a table that reference deleted entities
+fn delete_referencing<Id, Row, F>(table: &mut BTreeTable<Id, Row>, references: F)
+where
+    Id: TableId,
+    Row: TableRow,
+    F: Fn(&Row) -> bool,
+{
+    let ids = table
+        .iter()
+        .filter(|(_, row)| references(row))
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    for id in ids {
+        table.delete(id);
+    }
+}
+
+impl OnEntityDelete<EntityId> for SpawnComponent {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        for (_, spawn) in table.iter_mut() {
+            if spawn
+                .spawning
+                .map(|id| deleted.contains(id))
+                .unwrap_or(false)
+            {
+                spawn.spawning = None;
+                spawn.time_to_spawn = 0;
+            }
+        }
+    }
+}
+
+impl OnEntityDelete<EntityId> for SpawnQueueComponent {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        for (_, spawn_queue) in table.iter_mut() {
+            if spawn_queue.queue.iter().any(|id| deleted.contains(*id)) {
+                spawn_queue.queue.retain(|id| !deleted.contains(*id));
+            }
+        }
+    }
+}
+
+impl OnEntityDelete<EntityId> for MineEventComponent {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        delete_referencing(table, |MineEventComponent(target, _)| {
+            deleted.contains(*target)
+        });
+    }
+}
+
+impl OnEntityDelete<EntityId> for DropoffEventComponent {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        delete_referencing(table, |DropoffEventComponent(target)| {
+            deleted.contains(*target)
+        });
+    }
+}
+
+impl OnEntityDelete<EntityId> for PathCacheComponent {
+    /// Paths leading to deleted entities are dropped
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        let ids = table
+            .iter()
+            .filter(|(_, cache)| deleted.was_at(cache.target))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            table.delete(id);
+        }
+    }
+}
+
+impl OnEntityDelete<UserId> for Visibility {
+    fn on_entity_delete(table: &mut Self::Table, deleted: &DeletedEntities) {
+        for (_, visibility) in table.iter_mut() {
+            if deleted.ids().iter().any(|id| visibility.0.contains(id)) {
+                for id in deleted.ids() {
+                    visibility.0.remove(id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{EntityComponent, EntityLayer, LayeredEntity, PositionComponent};
+    use crate::geometry::Axial;
+    use crate::indices::Room;
+    use crate::storage::views::{FromWorldMut, PositionsMut};
+    use crate::storage::DeferredDeleteById;
+
+    #[test]
+    fn references_to_deleted_entities_are_cleaned_up() {
+        let mut world = World::new();
+        let spawn = world.insert_entity();
+        let queued = world.insert_entity();
+        let spawning = world.insert_entity();
+        let miner = world.insert_entity();
+
+        world
+            .unsafe_view::<EntityId, SpawnComponent>()
+            .insert_or_update(
+                spawn,
+                SpawnComponent {
+                    time_to_spawn: 5,
+                    spawning: Some(spawning),
+                },
+            );
+        world
+            .unsafe_view::<EntityId, SpawnQueueComponent>()
+            .insert_or_update(
+                spawn,
+                SpawnQueueComponent {
+                    queue: vec![queued, miner].into_iter().collect(),
+                },
+            );
+        world
+            .unsafe_view::<EntityId, MineEventComponent>()
+            .insert_or_update(miner, MineEventComponent(spawning, 10));
+
+        world.deferred_delete_with_cause(spawning, DeleteCause::Admin);
+        world.deferred_delete(queued);
+        world.post_process();
+
+        let spawns = world.view::<EntityId, SpawnComponent>();
+        let spawn_row = spawns.get_by_id(spawn).unwrap();
+        assert_eq!(spawn_row.spawning, None);
+        assert_eq!(spawn_row.time_to_spawn, 0);
+        let queues = world.view::<EntityId, SpawnQueueComponent>();
+        assert_eq!(
+            queues
+                .get_by_id(spawn)
+                .unwrap()
+                .queue
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![miner]
+        );
+        assert!(world
+            .view::<EntityId, MineEventComponent>()
+            .get_by_id(miner)
+            .is_none());
+
+        let mut events = world
+            .view::<EmptyKey, EntityDeletions>()
+            .value
+            .clone()
+            .unwrap()
+            .0;
+        events.sort_by_key(|e| e.id);
+        let events = events
+            .into_iter()
+            .map(|e| (e.id, e.cause, e.time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (queued, DeleteCause::Unspecified, 0),
+                (spawning, DeleteCause::Admin, 0)
+            ]
+        );
+
+        // the events of the previous tick are dropped
+        world.post_process();
+        assert!(world
+            .view::<EmptyKey, EntityDeletions>()
+            .value
+            .as_ref()
+            .unwrap()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn paths_to_deleted_entities_are_dropped() {
+        let mut world = World::new();
+        let room = Axial::new(0, 0);
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(Room(room)))
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, LayeredEntity>()
+            .extend_rooms(std::iter::once(Room(room)))
+            .unwrap();
+
+        let target = world.insert_entity();
+        let bot = world.insert_entity();
+        let pos = WorldPosition {
+            room,
+            pos: Axial::new(3, 3),
+        };
+        PositionsMut::from_world_mut(&mut *world)
+            .insert(target, pos, EntityLayer::Blocking)
+            .unwrap();
+        world
+            .unsafe_view::<EntityId, PathCacheComponent>()
+            .insert_or_update(
+                bot,
+                PathCacheComponent {
+                    target: pos,
+                    path: Default::default(),
+                },
+            );
+
+        world.deferred_delete(target);
+        world.post_process();
+
+        assert!(world
+            .view::<EntityId, PathCacheComponent>()
+            .get_by_id(bot)
+            .is_none());
+        assert!(world
+            .view::<EntityId, PositionComponent>()
+            .get_by_id(target)
+            .is_none());
+    }
+}
@@ -2,14 +2,23 @@
 //! - overworld: the large-scale overview of the map.
 //! - room: a self-contained slice of the map. Hexagon shaped.
 //!
+pub mod biome;
 pub mod overworld;
 pub mod room;
 
-use self::overworld::{generate_room_layout, OverworldGenerationError, OverworldGenerationParams};
-use self::room::{generate_room, RoomGenerationError, RoomGenerationParams};
-use crate::storage::views::UnsafeView;
+use self::biome::{choose_biome, generate_biomes};
+use self::overworld::{
+    extend_room_layout, generate_room_layout, OverworldGenerationError, OverworldGenerationParams,
+    RoomLayoutExpansion,
+};
+use self::room::{generate_room, stitch_bridge, RoomGenerationError, RoomGenerationParams};
+use crate::noise::PerlinNoise;
+use crate::storage::views::{UnsafeView, View};
+use crate::tables::morton_table::ExtendFailure;
 use crate::{
-    components::{RoomComponent, RoomConnections, RoomProperties, TerrainComponent},
+    components::{
+        BiomeComponent, RoomComponent, RoomConnections, RoomProperties, TerrainComponent,
+    },
     prelude::Axial,
 };
 use crate::{
@@ -30,6 +39,9 @@ pub enum MapGenError {
 
     #[error("Failed to generate overworld: {err}")]
     OverworldGenerationError { err: OverworldGenerationError },
+
+    #[error("Failed to generate biomes: {err}")]
+    BiomeGenerationError { err: ExtendFailure },
 }
 
 pub type MapGenerationTables = (
@@ -37,24 +49,29 @@ pub type MapGenerationTables = (
     UnsafeView<Axial, RoomComponent>,
     UnsafeView<ConfigKey, RoomProperties>,
     UnsafeView<Axial, RoomConnections>,
+    UnsafeView<Axial, BiomeComponent>,
 );
 
+/// Generate the rooms of the world and their terrain.
+///
+/// `chance_plain`, `chance_wall` and `plain_dilation` of `room_params` are replaced by the ones of
+/// the biome of each room, see [biome](biome::Biome::properties).
 pub fn generate_full_map(
     overworld_params: &OverworldGenerationParams,
     room_params: &RoomGenerationParams,
     seed: Option<[u8; 16]>,
-    (mut terrain, rooms, mut room_props, room_connections): MapGenerationTables,
+    (mut terrain, rooms, mut room_props, room_connections, biomes): MapGenerationTables,
 ) -> Result<(), MapGenError> {
-    let seed = seed.unwrap_or_else(|| {
-        let mut bytes = [0; 16];
-        thread_rng().fill_bytes(&mut bytes);
-        bytes
-    });
-    let mut rng = SmallRng::from_seed(seed);
+    let mut rng = SmallRng::from_seed(seed.unwrap_or_else(random_seed));
+    // derive the biome seed from the map seed and persist it in the room properties, so rooms added
+    // later fit into the existing biomes
+    let biome_seed = rng.next_u64();
     generate_room_layout(overworld_params, &mut rng, (rooms, room_connections))
         .map_err(|err| MapGenError::OverworldGenerationError { err })?;
 
-    let radius = room_params.radius as usize;
+    let noise = PerlinNoise::new(biome_seed);
+    generate_biomes(&noise, View::from_table(&*rooms), biomes)
+        .map_err(|err| MapGenError::BiomeGenerationError { err })?;
 
     // setup properties table
     {
@@ -64,6 +81,7 @@ pub fn generate_full_map(
         room_props.value = Some(RoomProperties {
             radius: room_radius,
             center: crate::prelude::Hexagon::from_radius(room_radius.try_into().unwrap()).center,
+            biome_seed,
         });
     }
 
@@ -71,37 +89,12 @@ pub fn generate_full_map(
         Vec::with_capacity(rooms.len()),
         |mut terrain_tables, (room, _)| {
             // TODO: do this in parallel?
-            let mut terrain_table = HexGrid::new(radius as usize);
-            let room_connections = room_connections
-                .at(room)
-                .expect("Expected just built room to have room_connections");
-            let room_connections = room_connections
-                .0
-                .iter()
-                .filter_map(|c| c.as_ref())
-                .cloned()
-                .collect::<ArrayVec<_, 6>>();
-            let room_params = RoomGenerationParams {
-                room: Room(room),
-                ..room_params.clone()
-            };
-            let s = tracing::span!(
-                tracing::Level::INFO,
-                "generate_room",
-                q = room.q,
-                r = room.r
-            );
-            let _e = s.enter();
-            generate_room(
-                &room_params,
-                room_connections.as_slice(),
-                (UnsafeView::from_table(&mut terrain_table),),
-            )
-            .map_err(|err| MapGenError::RoomGenerationError {
-                err,
-                room: Room(room),
-            })?;
-
+            let terrain_table = generate_room_terrain(
+                room,
+                room_params,
+                View::from_table(&*room_connections),
+                View::from_table(&*biomes),
+            )?;
             terrain_tables.push((room, terrain_table));
             Ok(terrain_tables)
         },
@@ -112,3 +105,139 @@ pub fn generate_full_map(
         .expect("expected to be able to insert the room terrain tables");
     Ok(())
 }
+
+pub type ExpansionTables = (
+    UnsafeView<WorldPosition, TerrainComponent>,
+    UnsafeView<Axial, RoomComponent>,
+    UnsafeView<Axial, RoomConnections>,
+    UnsafeView<Axial, BiomeComponent>,
+    UnsafeView<ConfigKey, RoomProperties>,
+);
+
+/// Add a new ring of rooms around the existing world.
+///
+/// Edge rooms of the existing world are stitched to the new rooms by carving bridges into their
+/// terrain, the rest of their terrain is left intact. The terrain parameters of the new rooms are
+/// set by their biomes, like in [generate_full_map](generate_full_map).
+///
+/// Returns the ids of the new rooms.
+pub fn expand_map(
+    overworld_params: &OverworldGenerationParams,
+    room_params: &RoomGenerationParams,
+    seed: Option<[u8; 16]>,
+    (mut terrain, rooms, room_connections, mut biomes, room_props): ExpansionTables,
+) -> Result<Vec<Axial>, MapGenError> {
+    let biome_seed = room_props
+        .value
+        .as_ref()
+        .expect("expected the generated map to have RoomProperties")
+        .biome_seed;
+    let mut rng = SmallRng::from_seed(seed.unwrap_or_else(random_seed));
+    let RoomLayoutExpansion {
+        new_rooms,
+        stitched,
+    } = extend_room_layout(overworld_params, &mut rng, (rooms, room_connections))
+        .map_err(|err| MapGenError::OverworldGenerationError { err })?;
+
+    let noise = PerlinNoise::new(biome_seed);
+    biomes
+        .extend(
+            new_rooms
+                .iter()
+                .map(|room| (*room, BiomeComponent(choose_biome(&noise, *room)))),
+        )
+        .map_err(|err| MapGenError::BiomeGenerationError { err })?;
+
+    let terrain_tables = new_rooms
+        .iter()
+        .map(|room| {
+            let terrain_table = generate_room_terrain(
+                *room,
+                room_params,
+                View::from_table(&*room_connections),
+                View::from_table(&*biomes),
+            )?;
+            Ok((*room, terrain_table))
+        })
+        .collect::<Result<Vec<_>, MapGenError>>()?;
+
+    for (room, connection) in stitched {
+        let s = tracing::span!(
+            tracing::Level::INFO,
+            "stitch_bridge",
+            q = room.q,
+            r = room.r
+        );
+        let _e = s.enter();
+        let room_terrain = terrain
+            .table
+            .at_mut(room)
+            .expect("expected existing rooms to have terrain");
+        stitch_bridge(&connection, UnsafeView::from_table(room_terrain), &mut rng).map_err(
+            |err| MapGenError::RoomGenerationError {
+                err,
+                room: Room(room),
+            },
+        )?;
+    }
+
+    terrain
+        .table
+        .extend(terrain_tables.into_iter())
+        .expect("expected to be able to insert the room terrain tables");
+    Ok(new_rooms)
+}
+
+fn random_seed() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn generate_room_terrain(
+    room: Axial,
+    room_params: &RoomGenerationParams,
+    room_connections: View<Axial, RoomConnections>,
+    biomes: View<Axial, BiomeComponent>,
+) -> Result<HexGrid<TerrainComponent>, MapGenError> {
+    let mut terrain_table = HexGrid::new(room_params.radius as usize);
+    let room_connections = room_connections
+        .at(room)
+        .expect("Expected just built room to have room_connections");
+    let room_connections = room_connections
+        .0
+        .iter()
+        .filter_map(|c| c.as_ref())
+        .cloned()
+        .collect::<ArrayVec<_, 6>>();
+    let biome = biomes
+        .at(room)
+        .map(|BiomeComponent(biome)| *biome)
+        .unwrap_or_default();
+    let biome_props = biome.properties();
+    let room_params = RoomGenerationParams {
+        room: Room(room),
+        chance_plain: biome_props.chance_plain,
+        chance_wall: biome_props.chance_wall,
+        plain_dilation: biome_props.plain_dilation,
+        ..room_params.clone()
+    };
+    let s = tracing::span!(
+        tracing::Level::INFO,
+        "generate_room",
+        q = room.q,
+        r = room.r,
+        biome = ?biome
+    );
+    let _e = s.enter();
+    generate_room(
+        &room_params,
+        room_connections.as_slice(),
+        (UnsafeView::from_table(&mut terrain_table),),
+    )
+    .map_err(|err| MapGenError::RoomGenerationError {
+        err,
+        room: Room(room),
+    })?;
+    Ok(terrain_table)
+}
@@ -0,0 +1,167 @@
+//! Overworld scale biomes.
+//!
+//! Each room is assigned a single biome, chosen by sampling world-space noise at the room's
+//! position. The biome decides the terrain parameters and the resources of the room.
+//!
+use crate::components::{BiomeComponent, RoomComponent};
+use crate::geometry::Axial;
+use crate::indices::WorldPosition;
+use crate::noise::PerlinNoise;
+use crate::storage::views::{UnsafeView, View};
+use crate::tables::morton_table::ExtendFailure;
+use serde::{Deserialize, Serialize};
+
+/// Scales room coordinates before sampling the noise.
+/// Smaller values produce larger, more coherent biomes.
+pub const BIOME_SCALE: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[repr(u8)]
+pub enum Biome {
+    Plains = 0,
+    Hills = 1,
+    Mountains = 2,
+    Wastes = 3,
+}
+
+impl Default for Biome {
+    fn default() -> Self {
+        Biome::Plains
+    }
+}
+
+/// Gameplay and generation properties of a Biome
+///
+/// The terrain parameters replace the ones of the `RoomGenerationParams` the map is generated
+/// with.
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeProperties {
+    pub chance_plain: f32,
+    pub chance_wall: f32,
+    pub plain_dilation: u32,
+    /// Number of resources to place in a room of this biome
+    pub resource_density: u32,
+    /// Energy of the resources placed in a room of this biome. Energy is the only resource type
+    /// of the game, so biomes differ in the size of their deposits.
+    pub resource_energy: u16,
+}
+
+impl Biome {
+    pub fn properties(self) -> BiomeProperties {
+        match self {
+            Biome::Plains => BiomeProperties {
+                chance_plain: 0.25,
+                chance_wall: 0.75,
+                plain_dilation: 3,
+                resource_density: 2,
+                resource_energy: 100,
+            },
+            Biome::Hills => BiomeProperties {
+                chance_plain: 0.13,
+                chance_wall: 0.87,
+                plain_dilation: 2,
+                resource_density: 3,
+                resource_energy: 150,
+            },
+            Biome::Mountains => BiomeProperties {
+                chance_plain: 0.08,
+                chance_wall: 0.92,
+                plain_dilation: 1,
+                resource_density: 5,
+                resource_energy: 250,
+            },
+            Biome::Wastes => BiomeProperties {
+                chance_plain: 0.2,
+                chance_wall: 0.8,
+                plain_dilation: 2,
+                resource_density: 1,
+                resource_energy: 400,
+            },
+        }
+    }
+
+    /// Map a noise value in the interval [-1, 1] to a biome
+    pub fn from_noise(value: f32) -> Self {
+        if value < -0.3 {
+            Biome::Wastes
+        } else if value < 0.1 {
+            Biome::Plains
+        } else if value < 0.4 {
+            Biome::Hills
+        } else {
+            Biome::Mountains
+        }
+    }
+}
+
+/// Choose the biome of a room by sampling the world-space noise at the room's position.
+pub fn choose_biome(noise: &PerlinNoise, room: Axial) -> Biome {
+    let value = noise.world_perlin(
+        WorldPosition {
+            room,
+            pos: Axial::default(),
+        },
+        BIOME_SCALE,
+    );
+    Biome::from_noise(value)
+}
+
+/// Assign a biome to every room in `rooms`, overriding the existing ones.
+pub fn generate_biomes(
+    noise: &PerlinNoise,
+    rooms: View<Axial, RoomComponent>,
+    mut biomes: UnsafeView<Axial, BiomeComponent>,
+) -> Result<(), ExtendFailure> {
+    biomes.clear();
+    biomes.extend(
+        rooms
+            .iter()
+            .map(|(room, _)| (room, BiomeComponent(choose_biome(noise, room)))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_generation::room::RoomGenerationParams;
+    use crate::tables::morton_table::MortonTable;
+
+    #[test]
+    fn biome_properties_are_valid_room_params() {
+        for biome in [Biome::Plains, Biome::Hills, Biome::Mountains, Biome::Wastes].iter() {
+            let props = biome.properties();
+            RoomGenerationParams::builder()
+                .with_radius(16)
+                .with_chance_plain(props.chance_plain)
+                .with_chance_wall(props.chance_wall)
+                .with_plain_dilation(props.plain_dilation)
+                .build()
+                .unwrap_or_else(|err| panic!("{:?} has invalid properties {:?}", biome, err));
+            assert!(props.resource_energy > 0);
+        }
+    }
+
+    #[test]
+    fn every_room_gets_a_biome() {
+        let rooms = MortonTable::from_iterator(
+            crate::geometry::Hexagon::new(Axial::new(8, 8), 8)
+                .iter_points()
+                .map(|p| (p, RoomComponent)),
+        )
+        .unwrap();
+        let mut biomes = MortonTable::new();
+
+        generate_biomes(
+            &PerlinNoise::new(42),
+            View::from_table(&rooms),
+            UnsafeView::from_table(&mut biomes),
+        )
+        .unwrap();
+
+        assert_eq!(rooms.len(), biomes.len());
+        for (room, _) in rooms.iter() {
+            assert!(biomes.contains_key(room));
+        }
+    }
+}
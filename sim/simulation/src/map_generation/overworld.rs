@@ -3,11 +3,13 @@
 mod params;
 pub use params::*;
 
+use crate::components::game_config::MAX_WORLD_RADIUS;
 use crate::components::{RoomComponent, RoomConnection, RoomConnections};
 use crate::geometry::{Axial, Hexagon};
-use crate::storage::views::UnsafeView;
+use crate::storage::views::{UnsafeView, View};
 use crate::tables::morton_table::{ExtendFailure, MortonTable};
 use rand::Rng;
+use std::collections::HashSet;
 use thiserror::Error;
 use tracing::{debug, error};
 
@@ -21,6 +23,18 @@ pub enum OverworldGenerationError {
 
     #[error("Failed to build Room weight table: {0:?}")]
     WeightMapInitFail(ExtendFailure),
+
+    #[error(
+        "Can not grow the world to radius {radius}, the maximum is {}",
+        MAX_WORLD_RADIUS
+    )]
+    WorldTooLarge { radius: u32 },
+}
+
+/// Center of the overworld. Rooms are laid out around it, so the rings added by
+/// [extend_room_layout](extend_room_layout) keep non-negative coordinates.
+pub fn world_center() -> Axial {
+    Axial::new(MAX_WORLD_RADIUS as i32, MAX_WORLD_RADIUS as i32)
 }
 
 /// Insert the given number of rooms in the given radius (where the unit is a room).
@@ -42,10 +56,15 @@ pub fn generate_room_layout(
         UnsafeView<Axial, RoomConnections>,
     ),
 ) -> Result<(), OverworldGenerationError> {
+    if *radius > MAX_WORLD_RADIUS {
+        return Err(OverworldGenerationError::WorldTooLarge { radius: *radius });
+    }
     let radius = *radius as i32;
     let room_radius = *room_radius as i32;
-    let center = Axial::new(radius, radius);
-    let bounds = Hexagon { center, radius };
+    let bounds = Hexagon {
+        center: world_center(),
+        radius,
+    };
 
     // Init the grid
     rooms.clear();
@@ -86,6 +105,179 @@ pub fn generate_room_layout(
     Ok(())
 }
 
+/// Rooms added by [extend_room_layout](extend_room_layout)
+#[derive(Debug, Clone, Default)]
+pub struct RoomLayoutExpansion {
+    /// The newly inserted rooms
+    pub new_rooms: Vec<Axial>,
+    /// New connections of the pre-existing rooms, pointing into the new rooms
+    pub stitched: Vec<(Axial, RoomConnection)>,
+}
+
+/// Insert a new ring of rooms around the existing rooms.
+///
+/// Every free neighbour of the existing rooms becomes a new room. Fails without modifying the
+/// tables if the new ring would be further from the [world_center](world_center) than
+/// `MAX_WORLD_RADIUS`.
+/// New rooms are connected to each other and to their existing neighbours. Every new room is
+/// guaranteed to be reachable from the existing rooms.
+///
+/// `radius` of the params is ignored, the size of the ring is determined by the existing rooms.
+pub fn extend_room_layout(
+    OverworldGenerationParams {
+        room_radius,
+        min_bridge_len,
+        max_bridge_len,
+        ..
+    }: &OverworldGenerationParams,
+    rng: &mut impl Rng,
+    (mut rooms, mut room_connections): (
+        UnsafeView<Axial, RoomComponent>,
+        UnsafeView<Axial, RoomConnections>,
+    ),
+) -> Result<RoomLayoutExpansion, OverworldGenerationError> {
+    let new_rooms = rooms
+        .iter()
+        .flat_map(|(room, _)| room.hex_neighbours().to_vec())
+        .filter(|p| !rooms.contains_key(*p))
+        .collect::<HashSet<_>>();
+    let center = world_center();
+    if let Some(radius) = new_rooms
+        .iter()
+        .map(|p| p.hex_distance(center))
+        .filter(|d| *d > MAX_WORLD_RADIUS)
+        .max()
+    {
+        return Err(OverworldGenerationError::WorldTooLarge { radius });
+    }
+    // the edge rooms of the existing world
+    let edge_rooms = rooms
+        .iter()
+        .map(|(room, _)| room)
+        .filter(|room| room.hex_neighbours().iter().any(|n| new_rooms.contains(n)))
+        .collect::<Vec<_>>();
+    let mut new_rooms = new_rooms.into_iter().collect::<Vec<_>>();
+    new_rooms.sort_by_key(|p| (p.q, p.r));
+
+    debug!(
+        "Extending the room layout by {} rooms, stitching to {} edge rooms",
+        new_rooms.len(),
+        edge_rooms.len()
+    );
+
+    rooms
+        .extend(new_rooms.iter().map(|p| (*p, RoomComponent)))
+        .map_err(OverworldGenerationError::ExtendFail)?;
+    room_connections
+        .extend(new_rooms.iter().map(|p| (*p, Default::default())))
+        .map_err(OverworldGenerationError::ExtendFail)?;
+
+    let connection_weights = MortonTable::from_iterator(
+        new_rooms
+            .iter()
+            .chain(edge_rooms.iter())
+            .map(|p| (*p, sigmoid(rng.gen_range(-4.0, 6.0)))),
+    )
+    .map_err(OverworldGenerationError::WeightMapInitFail)?;
+
+    for point in new_rooms.iter().copied() {
+        update_room_connections(
+            *room_radius,
+            *min_bridge_len,
+            *max_bridge_len,
+            point,
+            &connection_weights,
+            rng,
+            room_connections,
+        );
+    }
+
+    // connect the new rooms that are not reachable from the existing world
+    let mut reachable = edge_rooms.iter().copied().collect::<HashSet<_>>();
+    let mut todo = edge_rooms.clone();
+    for point in new_rooms.iter().copied() {
+        flood_connections(
+            &mut reachable,
+            &mut todo,
+            View::from_table(&*room_connections),
+        );
+        if reachable.contains(&point) {
+            continue;
+        }
+        // every new room neighbours at least one edge room
+        let neighbour = point
+            .hex_neighbours()
+            .iter()
+            .copied()
+            .filter(|n| reachable.contains(n))
+            .min_by_key(|n| (n.q, n.r))
+            .expect("expected the new room to have a reachable neighbour");
+        let connection = random_connection(
+            neighbour - point,
+            *room_radius,
+            *min_bridge_len,
+            *max_bridge_len,
+            rng,
+        );
+        let inverse = mirror_connection(&connection);
+        let i = Axial::neighbour_index(connection.direction)
+            .expect("expected neighbour to be a valid neighbour posision");
+        let j = Axial::neighbour_index(inverse.direction)
+            .expect("expected neighbour inverse to be a valid neighbour posision");
+        room_connections.update_with(point, |conn| conn.0[i] = Some(connection));
+        room_connections.update_with(neighbour, |conn| conn.0[j] = Some(inverse));
+        reachable.insert(point);
+        todo.push(point);
+    }
+
+    let stitched = edge_rooms
+        .iter()
+        .copied()
+        .flat_map(|room| {
+            room_connections
+                .at(room)
+                .map(|RoomConnections(conn)| conn.clone())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|c| c.as_ref())
+                .filter(|c| rooms_contains(&new_rooms, room + c.direction))
+                .map(|c| (room, *c))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    Ok(RoomLayoutExpansion {
+        new_rooms,
+        stitched,
+    })
+}
+
+fn rooms_contains(sorted_rooms: &[Axial], room: Axial) -> bool {
+    sorted_rooms
+        .binary_search_by_key(&(room.q, room.r), |p| (p.q, p.r))
+        .is_ok()
+}
+
+/// Visit every room reachable from `todo` via room connections
+fn flood_connections(
+    reachable: &mut HashSet<Axial>,
+    todo: &mut Vec<Axial>,
+    room_connections: View<Axial, RoomConnections>,
+) {
+    while let Some(room) = todo.pop() {
+        let RoomConnections(conn) = match room_connections.at(room) {
+            Some(c) => c,
+            None => continue,
+        };
+        for c in conn.iter().filter_map(|c| c.as_ref()) {
+            let neighbour = room + c.direction;
+            if reachable.insert(neighbour) {
+                todo.push(neighbour);
+            }
+        }
+    }
+}
+
 fn sigmoid(f: f32) -> f32 {
     1.0 / (1.0 + std::f32::consts::E.powf(-f))
 }
@@ -133,17 +325,9 @@ fn update_room_connections(
         room_connections.update_with(point, |RoomConnections(ref mut conn)| {
             for (i, c) in to_connect.iter_mut().enumerate() {
                 if conn[i].is_none() && c.is_some() {
-                    let bridge_len = rng.gen_range(min_bridge_len, max_bridge_len);
-                    let padding = room_radius - bridge_len;
-
-                    let offset_start = rng.gen_range(0, padding);
-                    let offset_end = padding - offset_start;
-
                     // this is a new connection
-                    conn[i] = c.map(|c| RoomConnection {
-                        direction: c,
-                        offset_start,
-                        offset_end,
+                    conn[i] = c.map(|c| {
+                        random_connection(c, room_radius, min_bridge_len, max_bridge_len, rng)
                     });
                 } else {
                     // if we don't have to update this posision then set it to None so we don't
@@ -163,23 +347,47 @@ fn update_room_connections(
         .cloned()
     {
         room_connections.update_with(point + neighbour.direction, |conn| {
-            let inverse = neighbour.direction * -1;
-            let i = Axial::neighbour_index(inverse)
+            let inverse = mirror_connection(&neighbour);
+            let i = Axial::neighbour_index(inverse.direction)
                 .expect("expected neighbour inverse to be a valid neighbour posision");
-            // this one's offsets are the current room's inverse
-            let offset_end = neighbour.offset_start;
-            let offset_end = offset_end.max(1) - 1; // offset_end - 1 or 0
-            let offset_start = neighbour.offset_end + 1;
-
-            conn.0[i] = Some(RoomConnection {
-                direction: inverse,
-                offset_start,
-                offset_end,
-            });
+            conn.0[i] = Some(inverse);
         });
     }
 }
 
+fn random_connection(
+    direction: Axial,
+    room_radius: u32,
+    min_bridge_len: u32,
+    max_bridge_len: u32,
+    rng: &mut impl Rng,
+) -> RoomConnection {
+    let bridge_len = rng.gen_range(min_bridge_len, max_bridge_len);
+    let padding = room_radius - bridge_len;
+
+    let offset_start = rng.gen_range(0, padding);
+    let offset_end = padding - offset_start;
+
+    RoomConnection {
+        direction,
+        offset_start,
+        offset_end,
+    }
+}
+
+/// Returns the connection the neighbour has to have to pair with `connection`
+fn mirror_connection(connection: &RoomConnection) -> RoomConnection {
+    // the neighbour's offsets are the current room's inverse
+    let offset_end = connection.offset_start;
+    let offset_end = offset_end.max(1) - 1; // offset_end - 1 or 0
+    let offset_start = connection.offset_end + 1;
+    RoomConnection {
+        direction: connection.direction * -1,
+        offset_start,
+        offset_end,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,4 +433,103 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn extended_layout_is_connected() {
+        let mut rooms = MortonTable::new();
+        let mut room_connections = MortonTable::new();
+
+        let params = OverworldGenerationParams::builder()
+            .with_radius(4)
+            .with_room_radius(16)
+            .with_min_bridge_len(3)
+            .with_max_bridge_len(12)
+            .build()
+            .unwrap();
+        let mut rng = rand::thread_rng();
+        generate_room_layout(
+            &params,
+            &mut rng,
+            (
+                UnsafeView::from_table(&mut rooms),
+                UnsafeView::from_table(&mut room_connections),
+            ),
+        )
+        .unwrap();
+        let len = rooms.len();
+
+        let expansion = extend_room_layout(
+            &params,
+            &mut rng,
+            (
+                UnsafeView::from_table(&mut rooms),
+                UnsafeView::from_table(&mut room_connections),
+            ),
+        )
+        .unwrap();
+
+        // the ring around a radius 4 hexagon is 30 rooms long
+        assert_eq!(expansion.new_rooms.len(), 30);
+        assert_eq!(rooms.len(), len + 30);
+        assert_eq!(rooms.len(), room_connections.len());
+        assert!(!expansion.stitched.is_empty());
+
+        let mut reachable = expansion
+            .stitched
+            .iter()
+            .map(|(room, _)| *room)
+            .collect::<HashSet<_>>();
+        let mut todo = reachable.iter().copied().collect();
+        flood_connections(
+            &mut reachable,
+            &mut todo,
+            View::from_table(&room_connections),
+        );
+        for room in expansion.new_rooms.iter() {
+            assert!(reachable.contains(room), "{:?} is not reachable", room);
+        }
+
+        for (room, RoomConnections(ref room_conn)) in room_connections.iter() {
+            for conn in room_conn.iter().filter_map(|x| x.as_ref()) {
+                let RoomConnections(ref conn_pairs) = room_connections
+                    .at(room + conn.direction)
+                    .expect("Expected the neighbour to be in the room_connections table");
+
+                let i = Axial::neighbour_index(conn.direction * -1).unwrap();
+                assert!(conn_pairs[i].is_some(), "The pair connection was not found");
+            }
+        }
+    }
+
+    #[test]
+    fn extending_past_the_max_radius_fails() {
+        let mut rooms = MortonTable::new();
+        let mut room_connections = MortonTable::new();
+        let edge = world_center() + Axial::new(MAX_WORLD_RADIUS as i32, 0);
+        rooms.insert(edge, RoomComponent).unwrap();
+        room_connections.insert(edge, Default::default()).unwrap();
+
+        let params = OverworldGenerationParams::builder()
+            .with_radius(1)
+            .with_room_radius(16)
+            .with_min_bridge_len(3)
+            .with_max_bridge_len(12)
+            .build()
+            .unwrap();
+        let res = extend_room_layout(
+            &params,
+            &mut rand::thread_rng(),
+            (
+                UnsafeView::from_table(&mut rooms),
+                UnsafeView::from_table(&mut room_connections),
+            ),
+        );
+
+        assert!(matches!(
+            res,
+            Err(OverworldGenerationError::WorldTooLarge { radius }) if radius == MAX_WORLD_RADIUS + 1
+        ));
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(room_connections.len(), 1);
+    }
 }
@@ -163,6 +163,52 @@ pub fn generate_room(
     Ok(heightmap_props)
 }
 
+/// Carve a new bridge into an already generated room and connect it to the room's largest plain
+/// chunk.
+///
+/// Unlike [generate_room](generate_room) this leaves the rest of the terrain intact.
+pub fn stitch_bridge(
+    edge: &RoomConnection,
+    terrain: UnsafeView<Axial, TerrainComponent>,
+    rng: &mut impl Rng,
+) -> Result<(), RoomGenerationError> {
+    trace!("Stitching bridge {:?}", edge);
+    let Hexagon { center, radius } = terrain.bounds();
+    let mut chunk_metadata = calculate_plain_chunks(View::from_table(&*terrain));
+    if chunk_metadata.chunks.is_empty() {
+        error!("Expected at least 1 chunk when stitching a bridge, intead got 0",);
+        return Err(RoomGenerationError::ExpectedSingleChunk(0));
+    }
+    // only connect to the mainland
+    chunk_metadata.chunks.truncate(1);
+    let mut neighbours = *edge;
+    neighbours.offset_start = 1.max(neighbours.offset_start) - 1;
+    neighbours.offset_end = 1.max(neighbours.offset_end) - 1;
+    chunk_metadata
+        .chunks
+        .push(HashSet::with_capacity(radius as usize));
+    fill_edge(
+        center,
+        radius - 1,
+        TileTerrainType::Plain,
+        &neighbours,
+        terrain,
+        chunk_metadata.chunks.last_mut().unwrap(),
+    )?;
+    connect_chunks(&radius - 2, rng, &chunk_metadata.chunks, terrain);
+    let mut bridge = HashSet::with_capacity(radius as usize);
+    fill_edge(
+        center,
+        radius,
+        TileTerrainType::Bridge,
+        edge,
+        terrain,
+        &mut bridge,
+    )?;
+    trace!("Stitching bridge done");
+    Ok(())
+}
+
 fn fill_edges(
     edges: &[RoomConnection],
     mut terrain: UnsafeView<Axial, TerrainComponent>,
@@ -705,4 +751,42 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn stitched_bridge_is_connected_to_the_mainland() {
+        let mut terrain = HexGrid::new(8);
+
+        let params = RoomGenerationParams::builder()
+            .with_radius(8)
+            .with_plain_dilation(1)
+            .build()
+            .unwrap();
+        generate_room(&params, &[], (UnsafeView::from_table(&mut terrain),)).unwrap();
+
+        let edge = RoomConnection {
+            direction: Axial::new(1, 0),
+            offset_start: 2,
+            offset_end: 3,
+        };
+        stitch_bridge(
+            &edge,
+            UnsafeView::from_table(&mut terrain),
+            &mut rand::thread_rng(),
+        )
+        .unwrap();
+
+        let bounds = terrain.bounds();
+        let chunks = calculate_plain_chunks(View::from_table(&terrain));
+        let bridge = iter_edge(bounds.center, bounds.radius as u32, &edge)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert!(!bridge.is_empty());
+        for point in bridge {
+            assert_eq!(
+                terrain.at(point),
+                Some(&TerrainComponent(TileTerrainType::Bridge))
+            );
+            assert!(chunks.chunks[0].contains(&point));
+        }
+    }
 }
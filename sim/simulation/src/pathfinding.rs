@@ -173,7 +173,7 @@ fn find_path_multiroom(
         .as_ref()
         .expect("expected a connection to the next room!");
 
-    let RoomProperties { radius, center } = room_properties
+    let RoomProperties { radius, center, .. } = room_properties
         .value
         .as_ref()
         .expect("expected RoomProperties to be set");
@@ -2,6 +2,7 @@ pub use crate::components::*;
 pub use crate::executor::*;
 pub use crate::geometry::*;
 pub use crate::indices::*;
+pub use crate::lifecycle::DeleteCause;
 pub use crate::storage::views::*;
 pub use crate::storage::*;
 pub use crate::tables::{self, Component, Table};
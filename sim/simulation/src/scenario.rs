@@ -0,0 +1,711 @@
+//! Scenario based gameplay tests.
+//!
+//! A scenario describes a small world with a single room in YAML: the terrain of the room, the
+//! users, the entities with their components and the scripts attached to them. The scenario is
+//! run for `ticks` number of ticks and its expectations are checked after the tick they name.
+//!
+//! ```yaml
+//! ticks: 2
+//! room_radius: 4
+//! # every other tile of the room is plain
+//! walls: [[4, 2]]
+//! # GameConfig overrides, `room_radius` is always taken from the scenario
+//! config:
+//!   bot:
+//!     mine_amount: 10
+//! users: [alice]
+//! # CaoIr programs by name
+//! scripts:
+//!   miner:
+//!     lanes:
+//!       - cards: ...
+//! entities:
+//!   # `kind` is one of bot, spawn or resource. Entities are initialized like the game would
+//!   # initialize them, the listed components are overridden
+//!   - name: bot
+//!     kind: bot
+//!     owner: alice
+//!     pos: [4, 4]
+//!     script: miner
+//!     carry: { carry: 0, carryMax: 20 }
+//!   - name: mineral
+//!     kind: resource
+//!     pos: [5, 4]
+//! expect:
+//!   # `tick` defaults to the last tick, 0 checks the initial state
+//!   - entity: bot
+//!     tick: 1
+//!     # components are matched by their listed fields, `true`/`false` checks existence
+//!     components:
+//!       carry: { carry: 10 }
+//!     # results of the actions the entity's script takes when it runs on this state
+//!     results:
+//!       mine: ok
+//!   - entity: mineral
+//!     components:
+//!       energy: { energy: 80 }
+//! ```
+//!
+//! ```ignore
+//! caolo_sim::scenario::Scenario::from_yaml(include_str!("my_scenario.yaml"))
+//!     .unwrap()
+//!     .assert_passes();
+//! ```
+use crate::components::game_config::{GameConfig, GameConfigError};
+use crate::components::*;
+use crate::entity_archetypes::{init_bot, init_structure_spawn};
+use crate::indices::{ConfigKey, EntityId, Room, ScriptId, UserId, WorldPosition};
+use crate::scripting_api::{make_import, OperationResult};
+use crate::storage::views::{FromWorld, FromWorldMut, UnwrapView};
+use crate::systems::script_execution::ScriptExecutionData;
+use crate::tables::{hex_grid::HexGrid, Component, Table};
+use crate::terrain::TileTerrainType;
+use crate::{executor::SimpleExecutor, geometry::Axial, prelude::Hexagon, world::World};
+use cao_lang::{compiler::CaoIr, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("Failed to parse the scenario: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("Invalid GameConfig: {0}")]
+    Config(#[from] GameConfigError),
+    #[error("Script {name} failed to compile: {error}")]
+    Compile { name: String, error: String },
+    #[error("Entity {name} references unknown {kind} {reference}")]
+    UnknownReference {
+        name: String,
+        kind: &'static str,
+        reference: String,
+    },
+    #[error("Entity {0} is listed more than once")]
+    DuplicateEntity(String),
+    #[error("Entity {name} is placed on an invalid position {pos:?}")]
+    InvalidPosition { name: String, pos: Axial },
+    #[error("Spawn {0} has to have an owner")]
+    MissingOwner(String),
+    #[error("Expectation of {entity} is checked after tick {tick}, but the scenario runs for {ticks} ticks")]
+    InvalidTick {
+        entity: String,
+        tick: u64,
+        ticks: u64,
+    },
+    #[error("Unknown component {0}")]
+    UnknownComponent(String),
+    #[error("Failed to run the script of {entity}: {error}")]
+    Script { entity: String, error: String },
+    #[error("Scenario failed:\n{}", .0.join("\n"))]
+    Failed(Vec<String>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub ticks: u64,
+    #[serde(default = "default_room_radius")]
+    pub room_radius: u32,
+    #[serde(default)]
+    pub walls: Vec<Axial>,
+    #[serde(default)]
+    pub config: GameConfig,
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub scripts: BTreeMap<String, CaoIr>,
+    #[serde(default)]
+    pub entities: Vec<EntityFixture>,
+    #[serde(default)]
+    pub expect: Vec<Expectation>,
+}
+
+fn default_room_radius() -> u32 {
+    8
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    Bot,
+    Spawn,
+    Resource,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct EntityFixture {
+    pub name: String,
+    pub kind: EntityKind,
+    /// Position in the room
+    pub pos: Axial,
+    pub owner: Option<String>,
+    pub script: Option<String>,
+    pub hp: Option<HpComponent>,
+    pub energy: Option<EnergyComponent>,
+    pub energy_regen: Option<EnergyRegenComponent>,
+    pub carry: Option<CarryComponent>,
+    pub decay: Option<DecayComponent>,
+    pub melee: Option<MeleeAttackComponent>,
+    pub vision: Option<VisionComponent>,
+    pub resource: Option<Resource>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    pub entity: String,
+    /// Check after this tick, defaults to the last tick of the scenario
+    pub tick: Option<u64>,
+    #[serde(default = "default_true")]
+    pub exists: bool,
+    /// Position in the room
+    pub pos: Option<Axial>,
+    #[serde(default)]
+    pub components: BTreeMap<String, serde_yaml::Value>,
+    /// Expected results by action name
+    #[serde(default)]
+    pub results: BTreeMap<String, OperationResult>,
+}
+
+/// The World built from a Scenario
+pub struct ScenarioWorld {
+    pub world: Pin<Box<World>>,
+    pub room: Room,
+    pub users: BTreeMap<String, UserId>,
+    pub entities: BTreeMap<String, EntityId>,
+}
+
+impl Scenario {
+    pub fn from_yaml(yaml: &str) -> Result<Self, ScenarioError> {
+        let scenario = serde_yaml::from_str(yaml)?;
+        Ok(scenario)
+    }
+
+    /// Panic with the failed expectations if the scenario does not pass
+    pub fn assert_passes(&self) {
+        if let Err(err) = self.run() {
+            panic!("{}", err);
+        }
+    }
+
+    /// Run the scenario and check its expectations.
+    ///
+    /// All failed expectations are returned in `ScenarioError::Failed`.
+    pub fn run(&self) -> Result<ScenarioWorld, ScenarioError> {
+        for expectation in self.expect.iter() {
+            let tick = expectation.tick.unwrap_or(self.ticks);
+            if tick > self.ticks {
+                return Err(ScenarioError::InvalidTick {
+                    entity: expectation.entity.clone(),
+                    tick,
+                    ticks: self.ticks,
+                });
+            }
+        }
+
+        let mut scenario = self.build()?;
+        let mut failures = Vec::new();
+        self.check(&scenario, 0, &mut failures)?;
+
+        let rt = crate::RuntimeGuard::new();
+        let mut executor = SimpleExecutor;
+        for tick in 1..=self.ticks {
+            rt.block_on(executor.forward(&mut *scenario.world))
+                .expect("world forward");
+            self.check(&scenario, tick, &mut failures)?;
+        }
+
+        if !failures.is_empty() {
+            return Err(ScenarioError::Failed(failures));
+        }
+        Ok(scenario)
+    }
+
+    /// Build the World of the scenario, without running it
+    pub fn build(&self) -> Result<ScenarioWorld, ScenarioError> {
+        let mut config = self.config.clone();
+        config.room_radius = self.room_radius;
+        // the room is built by hand, the map generation limits on its size do not apply
+        config.validate_gameplay()?;
+
+        let mut world = World::new();
+        let room = Room(Axial::new(0, 0));
+        let bounds = Hexagon::from_radius(self.room_radius as i32);
+        init_room(&mut *world, room, bounds, self.walls.as_slice());
+        world.config.game_config.value = Some(config);
+
+        let mut users = BTreeMap::new();
+        for name in self.users.iter() {
+            let user_id = UserId(Uuid::new_v4());
+            world.unsafe_view::<UserId, UserComponent>().insert(user_id);
+            world
+                .unsafe_view::<UserId, UserProperties>()
+                .insert_or_update(user_id, Default::default());
+            users.insert(name.clone(), user_id);
+        }
+
+        let mut scripts = BTreeMap::new();
+        for (name, ir) in self.scripts.iter() {
+            let program = compile(ir.clone(), None).map_err(|err| ScenarioError::Compile {
+                name: name.clone(),
+                error: err.to_string(),
+            })?;
+            let script_id = ScriptId(Uuid::new_v4());
+            world
+                .unsafe_view::<ScriptId, CompiledScriptComponent>()
+                .insert_or_update(script_id, CompiledScriptComponent(program));
+            world
+                .unsafe_view::<ScriptId, CaoIrComponent>()
+                .insert_or_update(script_id, CaoIrComponent(ir.clone()));
+            scripts.insert(name.clone(), script_id);
+        }
+
+        let mut entities = BTreeMap::new();
+        for fixture in self.entities.iter() {
+            if entities.contains_key(&fixture.name) {
+                return Err(ScenarioError::DuplicateEntity(fixture.name.clone()));
+            }
+            let is_free = world
+                .view::<WorldPosition, TerrainComponent>()
+                .get_by_id(WorldPosition {
+                    room: room.0,
+                    pos: fixture.pos,
+                })
+                .map(|TerrainComponent(t)| t.is_walkable())
+                .unwrap_or(false)
+                && !world
+                    .view::<EntityId, PositionComponent>()
+                    .iter()
+                    .any(|(_, PositionComponent(p))| p.pos == fixture.pos);
+            if !is_free {
+                return Err(ScenarioError::InvalidPosition {
+                    name: fixture.name.clone(),
+                    pos: fixture.pos,
+                });
+            }
+            let owner = fixture
+                .owner
+                .as_ref()
+                .map(|owner| lookup(&users, &fixture.name, "user", owner))
+                .transpose()?;
+            let script = fixture
+                .script
+                .as_ref()
+                .map(|script| lookup(&scripts, &fixture.name, "script", script))
+                .transpose()?;
+
+            let id = world.insert_entity();
+            fixture.spawn(id, room, owner, &mut *world)?;
+            if let Some(script_id) = script {
+                world
+                    .unsafe_view::<EntityId, EntityScript>()
+                    .insert_or_update(id, EntityScript(script_id));
+            }
+            debug!("Scenario entity {} is {:?}", fixture.name, id);
+            entities.insert(fixture.name.clone(), id);
+        }
+        crate::systems::update_visibility(&mut *world);
+        // the fixtures are not changes made by the simulation
+        world.commit_changes();
+
+        Ok(ScenarioWorld {
+            world,
+            room,
+            users,
+            entities,
+        })
+    }
+
+    fn check(
+        &self,
+        scenario: &ScenarioWorld,
+        tick: u64,
+        failures: &mut Vec<String>,
+    ) -> Result<(), ScenarioError> {
+        for expectation in self
+            .expect
+            .iter()
+            .filter(|e| e.tick.unwrap_or(self.ticks) == tick)
+        {
+            let id = match scenario.entities.get(&expectation.entity) {
+                Some(id) => *id,
+                None => {
+                    return Err(ScenarioError::UnknownReference {
+                        name: expectation.entity.clone(),
+                        kind: "entity",
+                        reference: expectation.entity.clone(),
+                    })
+                }
+            };
+            let mut fail = |msg: String| {
+                failures.push(format!("tick {}: {}: {}", tick, expectation.entity, msg))
+            };
+
+            let world = &*scenario.world;
+            let pos = world
+                .view::<EntityId, PositionComponent>()
+                .get_by_id(id)
+                .map(|PositionComponent(p)| p.pos);
+            match (expectation.exists, pos) {
+                (true, None) => {
+                    fail("expected the entity to exist".to_owned());
+                    continue;
+                }
+                (false, Some(_)) => {
+                    fail("expected the entity to be deleted".to_owned());
+                    continue;
+                }
+                (false, None) => continue,
+                (true, Some(pos)) => {
+                    if let Some(expected) = expectation.pos {
+                        if expected != pos {
+                            fail(format!("expected position {:?}, found {:?}", expected, pos));
+                        }
+                    }
+                }
+            }
+
+            for (name, expected) in expectation.components.iter() {
+                let actual = component_value(world, id, name)?;
+                match (expected, actual) {
+                    (serde_yaml::Value::Bool(true), None) => {
+                        fail(format!("expected component {} to exist", name))
+                    }
+                    (serde_yaml::Value::Bool(false), Some(_)) => {
+                        fail(format!("expected component {} to not exist", name))
+                    }
+                    (serde_yaml::Value::Bool(_), _) => {}
+                    (_, None) => fail(format!("component {} does not exist", name)),
+                    (expected, Some(actual)) => {
+                        if !matches_value(expected, &actual) {
+                            fail(format!(
+                                "expected {} to match {:?}, found {:?}",
+                                name, expected, actual
+                            ));
+                        }
+                    }
+                }
+            }
+
+            if !expectation.results.is_empty() {
+                let results = script_results(world, id).map_err(|error| ScenarioError::Script {
+                    entity: expectation.entity.clone(),
+                    error,
+                })?;
+                for (action, expected) in expectation.results.iter() {
+                    let actual = results
+                        .iter()
+                        .rev()
+                        .find(|(a, _)| a == action)
+                        .map(|(_, r)| *r);
+                    if actual != Some(*expected) {
+                        fail(format!(
+                            "expected {} to return {:?}, found {:?}",
+                            action, expected, actual
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EntityFixture {
+    fn spawn(
+        &self,
+        id: EntityId,
+        room: Room,
+        owner: Option<UserId>,
+        world: &mut World,
+    ) -> Result<(), ScenarioError> {
+        let pos = WorldPosition {
+            room: room.0,
+            pos: self.pos,
+        };
+        match self.kind {
+            EntityKind::Bot => {
+                init_bot(
+                    id,
+                    owner.map(|UserId(id)| id),
+                    pos,
+                    FromWorldMut::from_world_mut(world),
+                    FromWorld::from_world(world),
+                );
+            }
+            EntityKind::Spawn => {
+                let UserId(owner) =
+                    owner.ok_or_else(|| ScenarioError::MissingOwner(self.name.clone()))?;
+                init_structure_spawn(id, owner, pos, world);
+            }
+            EntityKind::Resource => {
+                crate::init::init_resource(
+                    id,
+                    self.resource.unwrap_or_default(),
+                    100,
+                    pos,
+                    FromWorldMut::from_world_mut(world),
+                );
+                if let Some(owner_id) = owner {
+                    world
+                        .unsafe_view::<EntityId, OwnedEntity>()
+                        .insert_or_update(id, OwnedEntity { owner_id });
+                }
+            }
+        }
+
+        if let Some(hp) = self.hp {
+            world
+                .unsafe_view::<EntityId, HpComponent>()
+                .insert_or_update(id, hp);
+        }
+        if let Some(energy) = self.energy {
+            world
+                .unsafe_view::<EntityId, EnergyComponent>()
+                .insert_or_update(id, energy);
+        }
+        if let Some(regen) = self.energy_regen {
+            world
+                .unsafe_view::<EntityId, EnergyRegenComponent>()
+                .insert_or_update(id, regen);
+        }
+        if let Some(carry) = self.carry {
+            world
+                .unsafe_view::<EntityId, CarryComponent>()
+                .insert_or_update(id, carry);
+        }
+        if let Some(decay) = self.decay {
+            world
+                .unsafe_view::<EntityId, DecayComponent>()
+                .insert_or_update(id, decay);
+        }
+        if let Some(melee) = self.melee {
+            world
+                .unsafe_view::<EntityId, MeleeAttackComponent>()
+                .insert_or_update(id, melee);
+        }
+        if let Some(vision) = self.vision {
+            world
+                .unsafe_view::<EntityId, VisionComponent>()
+                .insert_or_update(id, vision);
+        }
+        Ok(())
+    }
+}
+
+fn lookup<T: Copy>(
+    items: &BTreeMap<String, T>,
+    name: &str,
+    kind: &'static str,
+    reference: &str,
+) -> Result<T, ScenarioError> {
+    items
+        .get(reference)
+        .copied()
+        .ok_or_else(|| ScenarioError::UnknownReference {
+            name: name.to_owned(),
+            kind,
+            reference: reference.to_owned(),
+        })
+}
+
+/// Set up the tables of a single room with plain terrain, except for the `walls`
+fn init_room(world: &mut World, room: Room, bounds: Hexagon, walls: &[Axial]) {
+    let mut terrain = HexGrid::<TerrainComponent>::new(bounds.radius as usize);
+    for (pos, tile) in terrain.iter_mut() {
+        *tile = if walls.contains(&pos) {
+            TerrainComponent(TileTerrainType::Wall)
+        } else {
+            TerrainComponent(TileTerrainType::Plain)
+        };
+    }
+    world
+        .unsafe_view::<WorldPosition, TerrainComponent>()
+        .table
+        .extend(std::iter::once((room.0, terrain)))
+        .expect("expected to be able to insert the room terrain");
+    world
+        .unsafe_view::<WorldPosition, EntityComponent>()
+        .extend_rooms(std::iter::once(room))
+        .expect("expected to be able to insert the room");
+    world
+        .unsafe_view::<WorldPosition, LayeredEntity>()
+        .extend_rooms(std::iter::once(room))
+        .expect("expected to be able to insert the room");
+    world
+        .unsafe_view::<Axial, RoomComponent>()
+        .insert(room.0, RoomComponent)
+        .expect("expected to be able to insert the room");
+    world
+        .unsafe_view::<Axial, RoomConnections>()
+        .insert(room.0, Default::default())
+        .expect("expected to be able to insert the room connections");
+    world.config.room_properties.value = Some(RoomProperties {
+        radius: bounds.radius as u32,
+        center: bounds.center,
+        ..Default::default()
+    });
+}
+
+/// Serialized value of the component `name` of the entity
+fn component_value(
+    world: &World,
+    id: EntityId,
+    name: &str,
+) -> Result<Option<serde_yaml::Value>, ScenarioError> {
+    fn get<C>(world: &World, id: EntityId) -> Option<serde_yaml::Value>
+    where
+        C: Component<EntityId> + Serialize,
+        World: crate::storage::HasTable<EntityId, C>,
+    {
+        world
+            .view::<EntityId, C>()
+            .get_by_id(id)
+            .map(|c| serde_yaml::to_value(c).expect("failed to serialize component"))
+    }
+
+    let value = match name {
+        "bot" => get::<Bot>(world, id),
+        "structure" => get::<Structure>(world, id),
+        "hp" => get::<HpComponent>(world, id),
+        "energy" => get::<EnergyComponent>(world, id),
+        "energyRegen" => get::<EnergyRegenComponent>(world, id),
+        "carry" => get::<CarryComponent>(world, id),
+        "decay" => get::<DecayComponent>(world, id),
+        "melee" => get::<MeleeAttackComponent>(world, id),
+        "vision" => get::<VisionComponent>(world, id),
+        "resource" => get::<ResourceComponent>(world, id),
+        "spawn" => get::<SpawnComponent>(world, id),
+        "say" => get::<SayComponent>(world, id),
+        "mineEvent" => get::<MineEventComponent>(world, id),
+        "dropoffEvent" => get::<DropoffEventComponent>(world, id),
+        _ => return Err(ScenarioError::UnknownComponent(name.to_owned())),
+    };
+    Ok(value)
+}
+
+/// Mappings match if every listed field matches, other values have to be equal
+fn matches_value(expected: &serde_yaml::Value, actual: &serde_yaml::Value) -> bool {
+    match (expected, actual) {
+        (serde_yaml::Value::Mapping(expected), serde_yaml::Value::Mapping(actual)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).map_or(false, |a| matches_value(value, a))),
+        (expected, actual) => expected == actual,
+    }
+}
+
+/// Run the script of the entity on the current state of the World and return the results of
+/// the actions it took. The intents of the script are discarded.
+fn script_results(
+    world: &World,
+    id: EntityId,
+) -> Result<Vec<(&'static str, OperationResult)>, String> {
+    let EntityScript(script_id) = *world
+        .view::<EntityId, EntityScript>()
+        .get_by_id(id)
+        .ok_or_else(|| "entity has no script".to_owned())?;
+    let program = world
+        .view::<ScriptId, CompiledScriptComponent>()
+        .get_by_id(script_id)
+        .ok_or_else(|| format!("{:?} was not found", script_id))?;
+    let owner = world
+        .view::<EntityId, OwnedEntity>()
+        .get_by_id(id)
+        .map(|OwnedEntity { owner_id }| *owner_id);
+
+    let data = ScriptExecutionData::new(world, Default::default(), id, owner);
+    let mut vm = Vm::new(data).expect("Failed to initialize VM");
+    vm.max_instr = UnwrapView::<ConfigKey, GameConfig>::from_world(world).execution_limit as u64;
+    make_import().execute_imports(&mut vm);
+    vm.run(&program.0).map_err(|err| format!("{:?}", err))?;
+    Ok(vm.unwrap_aux().results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bots_mine_adjacent_resources() {
+        Scenario::from_yaml(include_str!("./scenarios/mine_adjacent_resource.yaml"))
+            .unwrap()
+            .assert_passes();
+    }
+
+    #[test]
+    fn bots_kill_enemies_in_melee() {
+        Scenario::from_yaml(include_str!("./scenarios/melee_attack.yaml"))
+            .unwrap()
+            .assert_passes();
+    }
+
+    #[test]
+    fn bots_do_not_find_resources_out_of_sight() {
+        Scenario::from_yaml(include_str!("./scenarios/fog_of_war.yaml"))
+            .unwrap()
+            .assert_passes();
+    }
+
+    #[test]
+    fn failed_expectations_are_reported() {
+        let scenario = Scenario::from_yaml(
+            r#"
+ticks: 1
+room_radius: 3
+walls: [[3, 2]]
+entities:
+  - name: mineral
+    kind: resource
+    pos: [3, 3]
+    energy: { energy: 50, energyMax: 100 }
+expect:
+  - entity: mineral
+    tick: 0
+    components:
+      energy: { energy: 50 }
+      resource: energy
+  - entity: mineral
+    components:
+      energy: { energy: 100 }
+      carry: true
+"#,
+        )
+        .unwrap();
+
+        let err = scenario.run().err().expect("expected the scenario to fail");
+        let failures = match err {
+            ScenarioError::Failed(failures) => failures,
+            err => panic!("unexpected error {:?}", err),
+        };
+        assert_eq!(failures.len(), 2, "{:?}", failures);
+        assert!(failures[0].starts_with("tick 1: mineral: expected energy"));
+        assert!(failures[1].contains("carry"));
+    }
+
+    #[test]
+    fn entities_can_not_be_placed_on_walls() {
+        let scenario = Scenario::from_yaml(
+            r#"
+ticks: 1
+room_radius: 3
+walls: [[3, 2]]
+entities:
+  - name: mineral
+    kind: resource
+    pos: [3, 2]
+"#,
+        )
+        .unwrap();
+
+        let err = scenario.build().err().unwrap();
+        assert!(matches!(err, ScenarioError::InvalidPosition { .. }));
+    }
+}
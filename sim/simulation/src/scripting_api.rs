@@ -6,6 +6,7 @@
 mod tests;
 
 pub mod bots;
+pub mod events_api;
 pub mod find_api;
 use crate::geometry::Axial;
 use crate::indices::{EntityId, WorldPosition};
@@ -13,9 +14,9 @@ use crate::profile;
 use crate::systems::script_execution::ScriptExecutionData;
 use crate::{
     components::{self, SayPayload},
-    intents::SayIntent,
+    intents::{DropoffIntent, IntentResult, MeleeIntent, MineIntent, MoveIntent, SayIntent},
 };
-use cao_lang::prelude::*;
+use cao_lang::{prelude::*, StrPointer};
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{TryFrom, TryInto},
@@ -23,7 +24,8 @@ use std::{
 };
 use tracing::{error, trace};
 
-#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[derive(Debug, Clone, Eq, PartialEq, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 #[repr(i32)]
 pub enum OperationResult {
     Ok = 0,
@@ -35,6 +37,12 @@ pub enum OperationResult {
     Empty = 6,
     Full = 7,
     PathNotFound = 8,
+    /// Another entity claimed the target before the intent was resolved
+    Contested = 9,
+    /// Multiple intents competed for the same target in the same tick and another one was chosen
+    LostTieBreak = 10,
+    /// No entity matched the search
+    NotFound = 11,
 }
 
 impl TryFrom<Value> for OperationResult {
@@ -51,6 +59,9 @@ impl TryFrom<Value> for OperationResult {
             Value::Integer(6) => OperationResult::Empty,
             Value::Integer(7) => OperationResult::Full,
             Value::Integer(8) => OperationResult::PathNotFound,
+            Value::Integer(9) => OperationResult::Contested,
+            Value::Integer(10) => OperationResult::LostTieBreak,
+            Value::Integer(11) => OperationResult::NotFound,
             _ => {
                 return Err(i);
             }
@@ -65,6 +76,73 @@ impl From<OperationResult> for Value {
     }
 }
 
+/// Record the result of `action` in the auxiliary data of the VM
+pub(crate) fn record_result(
+    vm: &mut Vm<ScriptExecutionData>,
+    action: &'static str,
+    result: OperationResult,
+) {
+    vm.get_aux_mut().results.push((action, result));
+}
+
+/// Push the result of `action` onto the stack and record it in the auxiliary data of the VM
+pub(crate) fn push_result(
+    vm: &mut Vm<ScriptExecutionData>,
+    action: &'static str,
+    result: OperationResult,
+) -> Result<(), ExecutionError> {
+    record_result(vm, action, result);
+    vm.stack_push(result)?;
+    Ok(())
+}
+
+/// Push the result the systems resolved the last intent of `action` with.
+/// Pushes `Nil` if the entity had no such intent in the last tick
+pub fn last_result(
+    vm: &mut Vm<ScriptExecutionData>,
+    action: StrPointer,
+) -> Result<(), ExecutionError> {
+    profile!("last_result");
+    let action = unsafe {
+        vm.get_str(action).ok_or_else(|| {
+            ExecutionError::invalid_argument("last_result called with non-string param".to_owned())
+        })?
+    };
+    let aux = vm.get_aux();
+    let storage = aux.storage();
+    let entity_id = aux.entity_id;
+    let result = match action {
+        "approach_entity" | "move_to_position" => storage
+            .view::<EntityId, IntentResult<MoveIntent>>()
+            .get_by_id(entity_id)
+            .map(|r| r.result),
+        "melee_attack" => storage
+            .view::<EntityId, IntentResult<MeleeIntent>>()
+            .get_by_id(entity_id)
+            .map(|r| r.result),
+        "mine" => storage
+            .view::<EntityId, IntentResult<MineIntent>>()
+            .get_by_id(entity_id)
+            .map(|r| r.result),
+        "unload" => storage
+            .view::<EntityId, IntentResult<DropoffIntent>>()
+            .get_by_id(entity_id)
+            .map(|r| r.result),
+        _ => {
+            return Err(ExecutionError::invalid_argument(format!(
+                "last_result got an unknown action {}",
+                action
+            )));
+        }
+    };
+    trace!("{:?} last {} result: {:?}", entity_id, action, result);
+    match result {
+        Some(result) => vm.stack_push(result)?,
+        None => vm.stack_push(Value::Nil)?,
+    }
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Script {
@@ -356,6 +434,39 @@ pub fn make_import() -> Schema {
                 ),
                 fo: Box::new(into_f1(say)),
             },
+            FunctionRow {
+                desc: subprogram_description!(
+                    "last_result",
+                    "Result of the last intent of the given action, as resolved by the world in the last tick. Returns `Nil` if there was no such intent",
+                    SubProgramType::Function,
+                    ["Text"],
+                    ["OperationResult"],
+                    []
+                ),
+                fo: Box::new(into_f1(last_result)),
+            },
+            FunctionRow {
+                desc: subprogram_description!(
+                    "parse_event_kind",
+                    "Converts string literal to an event kind",
+                    SubProgramType::Function,
+                    ["Text"],
+                    ["EventKind"],
+                    []
+                ),
+                fo: Box::new(into_f1(events_api::parse_event_kind)),
+            },
+            FunctionRow {
+                desc: subprogram_description!(
+                    "events_last_tick",
+                    "Number of events of the given `EventKind` the current entity took part in during the last tick",
+                    SubProgramType::Function,
+                    ["EventKind"],
+                    ["Integer"],
+                    []
+                ),
+                fo: Box::new(into_f1(events_api::events_last_tick)),
+            },
         ],
     }
 }
@@ -39,7 +39,7 @@ pub fn melee_attack(vm: &mut Vm<ScriptExecutionData>, target: i64) -> Result<(),
     if let OperationResult::Ok = res {
         vm.get_aux_mut().intents.melee_attack_intent = Some(intent);
     }
-    vm.stack_push(res)?;
+    push_result(vm, "melee_attack", res)?;
     Ok(())
 }
 
@@ -86,7 +86,7 @@ pub fn unload(
     if let OperationResult::Ok = checkresult {
         vm.get_aux_mut().intents.dropoff_intent = Some(dropoff_intent);
     }
-    vm.stack_push(checkresult)?;
+    push_result(vm, "unload", checkresult)?;
     Ok(())
 }
 
@@ -114,7 +114,7 @@ pub fn mine_resource(vm: &mut Vm<ScriptExecutionData>, target: i64) -> Result<()
     };
 
     let checkresult = check_mine_intent(&intent, user_id, FromWorld::from_world(storage));
-    vm.stack_push(checkresult)?;
+    push_result(vm, "mine", checkresult)?;
     trace!("result: {:?}", checkresult);
     if let OperationResult::Ok = checkresult {
         vm.get_aux_mut().intents.mine_intent = Some(intent);
@@ -149,7 +149,7 @@ pub fn approach_entity(
         Some(x) => x,
         None => {
             warn!("entity {:?} does not have position component!", target);
-            vm.stack_push(OperationResult::InvalidInput)?;
+            push_result(vm, "approach_entity", OperationResult::InvalidInput)?;
             return Ok(());
         }
     };
@@ -173,7 +173,7 @@ pub fn approach_entity(
         }
         Err(e) => e,
     };
-    vm.stack_push(checkresult)?;
+    push_result(vm, "approach_entity", checkresult)?;
     Ok(())
 }
 
@@ -211,7 +211,7 @@ pub fn move_bot_to_position(
         }
         Err(e) => e,
     };
-    vm.stack_push(checkresult)?;
+    push_result(vm, "move_to_position", checkresult)?;
     Ok(())
 }
 
@@ -433,7 +433,7 @@ mod tests {
                 EntityId, OwnedEntity,
                     .insert_or_update(bot_id, OwnedEntity{owner_id:user_id});
                 ConfigKey, RoomProperties,
-                    .update(Some(RoomProperties{radius:room_radius as u32, center: room_center}));
+                    .update(Some(RoomProperties{radius:room_radius as u32, center: room_center, ..Default::default()}));
 
                 WorldPosition, EntityComponent,
                     .extend_rooms([Room(from.room),Room(Axial::new(0,1)), Room(to.room)].iter().cloned())
@@ -501,4 +501,78 @@ mod tests {
         assert_eq!(bot, bot_id);
         assert_eq!(position.room, next_room);
     }
+
+    #[test]
+    fn stale_targets_are_invalid() {
+        let mut storage = World::new();
+        let user_id = UserId::default();
+        let room = Axial::new(0, 0);
+        let pos = |q| WorldPosition {
+            room,
+            pos: Axial::new(q, 1),
+        };
+
+        let bot_id = storage.insert_entity();
+        let target = storage.insert_entity();
+        query!(
+            mutate
+            storage
+            {
+                EntityId, Bot,
+                    .insert(bot_id);
+                EntityId, PositionComponent,
+                    .insert_or_update(bot_id, PositionComponent(pos(1)));
+                EntityId, OwnedEntity,
+                    .insert_or_update(bot_id, OwnedEntity{owner_id:user_id});
+                EntityId, CarryComponent,
+                    .insert_or_update(bot_id, CarryComponent{carry: 0, carry_max: 50});
+                EntityId, MeleeAttackComponent,
+                    .insert_or_update(bot_id, MeleeAttackComponent{strength: 5});
+        });
+
+        let populate = |storage: &mut World, id: EntityId| {
+            query!(
+                mutate
+                storage
+                {
+                    EntityId, PositionComponent,
+                        .insert_or_update(id, PositionComponent(pos(2)));
+                    EntityId, ResourceComponent,
+                        .insert_or_update(id, ResourceComponent(Resource::Energy));
+                    EntityId, EnergyComponent,
+                        .insert_or_update(id, EnergyComponent{energy: 100, energy_max: 100});
+                    EntityId, HpComponent,
+                        .insert_or_update(id, HpComponent{hp: 100, hp_max: 100});
+            });
+        };
+        populate(&mut *storage, target);
+
+        // delete the target and reuse its index
+        storage.deferred_delete(target);
+        storage.execute_deferred_deletes();
+        let reused = storage.insert_entity();
+        assert_eq!(reused.index(), target.index());
+        assert_ne!(reused, target);
+        assert!(!storage.is_alive(target));
+        populate(&mut *storage, reused);
+
+        let mine = |resource| {
+            let intent = MineIntent {
+                bot: bot_id,
+                resource,
+            };
+            check_mine_intent(&intent, user_id, FromWorld::from_world(&storage))
+        };
+        let melee = |defender| {
+            let intent = MeleeIntent {
+                attacker: bot_id,
+                defender,
+            };
+            check_melee_intent(&intent, user_id, FromWorld::from_world(&storage))
+        };
+        assert_eq!(mine(target), OperationResult::InvalidTarget);
+        assert_eq!(melee(target), OperationResult::InvalidTarget);
+        assert_eq!(mine(reused), OperationResult::Ok);
+        assert_eq!(melee(reused), OperationResult::Ok);
+    }
 }
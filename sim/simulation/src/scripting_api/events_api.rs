@@ -0,0 +1,71 @@
+use super::*;
+use crate::events::{count_involving, EventKind};
+use crate::profile;
+use cao_lang::{prelude::*, StrPointer};
+use std::convert::TryFrom;
+use tracing::trace;
+
+impl TryFrom<Value> for EventKind {
+    type Error = Value;
+    fn try_from(i: Value) -> Result<Self, Value> {
+        match i {
+            Value::Integer(i) if i >= 0 => EventKind::ALL
+                .get(i as usize)
+                .copied()
+                .ok_or(Value::Integer(i)),
+            _ => Err(i),
+        }
+    }
+}
+
+pub fn parse_event_kind(
+    vm: &mut Vm<ScriptExecutionData>,
+    param: StrPointer,
+) -> Result<(), ExecutionError> {
+    profile!("parse_event_kind");
+    trace!("parse_event_kind");
+    let param = unsafe {
+        vm.get_str(param).ok_or_else(|| {
+            trace!("parse_event_kind called with invalid param");
+            ExecutionError::invalid_argument(
+                "parse_event_kind called with non-string param".to_owned(),
+            )
+        })?
+    };
+    let kind = match param {
+        "entity_died" | "ENTITY_DIED" | "EntityDied" => EventKind::EntityDied,
+        "attacked" | "ATTACKED" | "Attacked" => EventKind::Attacked,
+        "mined" | "MINED" | "Mined" => EventKind::Mined,
+        "dropped_off" | "DROPPED_OFF" | "DroppedOff" => EventKind::DroppedOff,
+        "spawned" | "SPAWNED" | "Spawned" => EventKind::Spawned,
+        "move_failed" | "MOVE_FAILED" | "MoveFailed" => EventKind::MoveFailed,
+        _ => {
+            trace!("parse_event_kind got an invalid event kind {}", param);
+            return Err(ExecutionError::invalid_argument(format!(
+                "parse_event_kind got an invalid event kind {}",
+                param
+            )));
+        }
+    };
+    vm.stack_push(kind as i64)?;
+    Ok(())
+}
+
+/// Push the number of events of the given kind the current entity took part in during the last
+/// tick
+pub fn events_last_tick(
+    vm: &mut Vm<ScriptExecutionData>,
+    kind: EventKind,
+) -> Result<(), ExecutionError> {
+    profile!("events_last_tick");
+    let aux = vm.get_aux();
+    let count = count_involving(aux.storage(), kind, aux.entity_id);
+    trace!(
+        "{:?} took part in {} {:?} events",
+        aux.entity_id,
+        count,
+        kind
+    );
+    vm.stack_push(count as i64)?;
+    Ok(())
+}
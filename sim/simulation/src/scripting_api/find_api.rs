@@ -1,6 +1,6 @@
 use super::*;
-use crate::components::{EntityComponent, PositionComponent};
-use crate::indices::WorldPosition;
+use crate::components::{EntityLayer, LayeredEntity, PositionComponent};
+use crate::indices::{UserId, WorldPosition};
 use crate::profile;
 use crate::world::World;
 use cao_lang::{prelude::*, StrPointer};
@@ -104,16 +104,30 @@ impl FindConstant {
 
         let storage = vm.get_aux().storage();
         let user_id = vm.get_aux().user_id;
+        // scripts of users only find the entities their user can see
+        let visibility = user_id.map(|user_id| {
+            storage
+                .view::<UserId, components::Visibility>()
+                .reborrow()
+                .get_by_id(user_id)
+        });
+        let visible = |id: EntityId| match visibility {
+            Some(visibility) => visibility.map(|v| v.contains(id)).unwrap_or(false),
+            None => true,
+        };
         let candidate = match self {
             FindConstant::Resource => {
                 let resources = storage.view::<EntityId, components::ResourceComponent>();
-                find_closest_entity_impl(storage, position, |id| resources.contains(id))
+                find_closest_entity_impl(storage, position, |id| {
+                    visible(id) && resources.contains_id(id)
+                })
             }
             FindConstant::Spawn => {
                 let owner = storage.view::<EntityId, components::OwnedEntity>();
                 let spawns = storage.view::<EntityId, components::SpawnComponent>();
                 find_closest_entity_impl(storage, position, |id| {
-                    spawns.contains_id(id)
+                    visible(id)
+                        && spawns.contains_id(id)
                         && owner.get_by_id(id).map(|owner_id| owner_id.owner_id) == user_id
                 })
             }
@@ -121,19 +135,23 @@ impl FindConstant {
                 let owner = storage.view::<EntityId, components::OwnedEntity>();
                 let bots = storage.view::<EntityId, components::Bot>();
                 find_closest_entity_impl(storage, position, |id| {
-                    bots.contains_id(&id)
+                    visible(id)
+                        && bots.contains_id(&id)
                         && owner.get_by_id(id).map(|owner_id| owner_id.owner_id) != user_id
                 })
             }
         }?;
+        // the id or nil is pushed, the result is only recorded
         match candidate {
             Some(entity) => {
                 trace!("Found entity {:?}", entity);
                 let id = entity.0; // move out of the result to free the storage borrow
+                record_result(vm, "find_closest", OperationResult::Ok);
                 vm.stack_push(id as i64)?;
             }
             None => {
                 trace!("No stuff was found");
+                record_result(vm, "find_closest", OperationResult::NotFound);
                 vm.stack_push(Value::Nil)?;
             }
         }
@@ -150,7 +168,7 @@ where
     F: Fn(EntityId) -> bool,
 {
     let WorldPosition { room, pos } = position;
-    let entities_by_pos = storage.view::<WorldPosition, EntityComponent>();
+    let entities_by_pos = storage.view::<WorldPosition, LayeredEntity>();
 
     let room = entities_by_pos
         .table
@@ -161,9 +179,9 @@ where
                 .into(),
         })?;
 
-    // search the whole room
-    let candidate = room.find_closest_by_filter(pos, |_, entity| filter(entity.0));
-    let candidate = candidate.map(|(_, _, EntityComponent(id))| *id);
+    // search the whole room, on every layer
+    let candidate = room.find_closest_by_filter(pos, None, |_, entity| filter(entity.id));
+    let candidate = candidate.map(|(_, _, entity)| entity.id);
     Ok(candidate)
 }
 
@@ -188,10 +206,16 @@ mod tests {
         let mut storage = World::new();
 
         let mut entity_positions = storage.unsafe_view::<EntityId, PositionComponent>();
-        let mut position_entities = storage.unsafe_view::<WorldPosition, EntityComponent>();
+        let mut position_entities = storage.unsafe_view::<WorldPosition, LayeredEntity>();
 
         position_entities
-            .insert(expected_pos, EntityComponent(expected_id))
+            .insert(
+                expected_pos,
+                LayeredEntity {
+                    layer: EntityLayer::Ground,
+                    id: expected_id,
+                },
+            )
             .expect("Initial insert 2");
 
         for _ in 0..128 {
@@ -212,7 +236,10 @@ mod tests {
                         room: Axial::new(0, 0),
                         pos,
                     },
-                    EntityComponent(id),
+                    LayeredEntity {
+                        layer: EntityLayer::Ground,
+                        id,
+                    },
                 )
                 .expect("Initial insert 3");
         }
@@ -232,7 +259,7 @@ mod tests {
             storage
                 .unsafe_view::<EntityId, components::ResourceComponent>()
                 .insert_or_update(
-                    entity_id.0,
+                    entity_id.id,
                     components::ResourceComponent(components::Resource::Energy),
                 );
         }
@@ -241,7 +268,13 @@ mod tests {
 
         entity_positions.insert_or_update(entity_id, PositionComponent(center_pos));
         position_entities
-            .insert(center_pos, EntityComponent(entity_id))
+            .insert(
+                center_pos,
+                LayeredEntity {
+                    layer: EntityLayer::Blocking,
+                    id: entity_id,
+                },
+            )
             .expect("Initial insert 1");
         storage
     }
@@ -321,4 +354,67 @@ mod tests {
             panic!("Expected pointer, got {:?}", res_id);
         }
     }
+
+    #[test]
+    fn users_only_find_visible_entities() {
+        let entity_id = EntityId(1024);
+        let center_pos = WorldPosition {
+            room: Axial::new(0, 0),
+            pos: Axial::new(14, 14),
+        };
+
+        let expected_id = EntityId(2040);
+        let expected_pos = WorldPosition {
+            room: Axial::new(0, 0),
+            pos: Axial::new(69, 69),
+        };
+
+        let mut storage = init_resource_storage(entity_id, center_pos, expected_id, expected_pos);
+        let user_id = UserId(uuid::Uuid::new_v4());
+        storage
+            .unsafe_view::<UserId, components::Visibility>()
+            .insert_or_update(
+                user_id,
+                components::Visibility([entity_id].iter().copied().collect()),
+            );
+        {
+            let data = ScriptExecutionData::new(
+                &*storage.as_ref(),
+                Default::default(),
+                entity_id,
+                Some(user_id),
+            );
+            let mut vm = Vm::new(data).unwrap();
+
+            find_closest_by_range(&mut vm, FindConstant::Resource)
+                .expect("find_closest_by_range exec");
+
+            let res = vm.stack_pop();
+            assert!(matches!(res, Value::Nil), "Expected nil, got {:?}", res);
+        }
+
+        storage
+            .unsafe_view::<UserId, components::Visibility>()
+            .get_by_id_mut(user_id)
+            .unwrap()
+            .0
+            .insert(expected_id);
+        let data = ScriptExecutionData::new(
+            &*storage.as_ref(),
+            Default::default(),
+            entity_id,
+            Some(user_id),
+        );
+        let mut vm = Vm::new(data).unwrap();
+
+        find_closest_by_range(&mut vm, FindConstant::Resource).expect("find_closest_by_range exec");
+
+        let res = vm.stack_pop();
+        assert!(
+            matches!(res, Value::Integer(id) if id == expected_id.0 as i64),
+            "Expected {:?}, got {:?}",
+            expected_id,
+            res
+        );
+    }
 }
@@ -145,3 +145,108 @@ lanes:
     vm.register_function("say", into_f1(say));
     vm.run(&program).unwrap_err();
 }
+
+#[test]
+fn test_events_last_tick() {
+    use crate::events::{Attacked, Events};
+    use crate::indices::EmptyKey;
+
+    let mut storage = World::new();
+
+    let entity_id = storage.insert_entity();
+    let attacker = storage.insert_entity();
+    storage
+        .unsafe_view::<EmptyKey, Events<Attacked>>()
+        .value
+        .as_mut()
+        .unwrap()
+        .0
+        .push(Attacked {
+            attacker,
+            defender: entity_id,
+            damage: 10,
+        });
+
+    let mut vm = Vm::new(ScriptExecutionData::new(
+        &*storage.as_ref(),
+        Default::default(),
+        entity_id,
+        Default::default(),
+    ))
+    .unwrap();
+
+    fn assert_attacked_once(
+        _vm: &mut Vm<ScriptExecutionData>,
+        count: i64,
+    ) -> Result<(), ExecutionError> {
+        assert_eq!(count, 1);
+        Ok(())
+    }
+
+    const PROGRAM: &str = r#"
+lanes:
+    - cards:
+        - ty: StringLiteral
+          val: "attacked"
+        - ty: CallNative
+          val: "parse_event_kind"
+        - ty: CallNative
+          val: "events_last_tick"
+        - ty: CallNative
+          val: "assert_attacked_once"
+    "#;
+
+    let program = serde_yaml::from_str(PROGRAM).unwrap();
+    let program = compile(program, None).unwrap();
+
+    vm.register_function("parse_event_kind", into_f1(events_api::parse_event_kind));
+    vm.register_function("events_last_tick", into_f1(events_api::events_last_tick));
+    vm.register_function("assert_attacked_once", into_f1(assert_attacked_once));
+    vm.run(&program).unwrap();
+}
+
+#[test]
+fn test_last_result() {
+    use crate::intents::{IntentResult, MineIntent};
+
+    let mut storage = World::new();
+
+    let entity_id = storage.insert_entity();
+    storage
+        .unsafe_view::<EntityId, IntentResult<MineIntent>>()
+        .insert_or_update(entity_id, IntentResult::new(OperationResult::Contested));
+
+    let mut vm = Vm::new(ScriptExecutionData::new(
+        &*storage.as_ref(),
+        Default::default(),
+        entity_id,
+        Default::default(),
+    ))
+    .unwrap();
+
+    fn assert_contested(
+        _vm: &mut Vm<ScriptExecutionData>,
+        result: OperationResult,
+    ) -> Result<(), ExecutionError> {
+        assert_eq!(result, OperationResult::Contested);
+        Ok(())
+    }
+
+    const PROGRAM: &str = r#"
+lanes:
+    - cards:
+        - ty: StringLiteral
+          val: "mine"
+        - ty: CallNative
+          val: "last_result"
+        - ty: CallNative
+          val: "assert_contested"
+    "#;
+
+    let program = serde_yaml::from_str(PROGRAM).unwrap();
+    let program = compile(program, None).unwrap();
+
+    vm.register_function("last_result", into_f1(last_result));
+    vm.register_function("assert_contested", into_f1(assert_contested));
+    vm.run(&program).unwrap();
+}
@@ -0,0 +1,369 @@
+//! Runtime tracking of the parts of the World that are borrowed.
+//!
+//! Views are built from raw pointers (see `FromWorldMut`), so the compiler can not tell if two
+//! systems, or a system and the scripts, access the same table at the same time. Only the views of
+//! a single system are checked at compile time, see `NotAliased`.
+//!
+//! The running systems and scripts register the parts of the World they declared to access (see
+//! `BorrowTracker::borrow`). Every view records its access when it is built (see
+//! `BorrowTracker::record`), debug builds panic if the view touches a part its owner did not
+//! declare, or one that another owner borrowed. Release builds do not record anything.
+//!
+use super::views::WorldAccess;
+use crate::prelude::World;
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+use std::ptr::NonNull;
+#[cfg(debug_assertions)]
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BorrowKind {
+    Read,
+    Write,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug)]
+struct Borrow {
+    guard_id: u64,
+    owner: &'static str,
+    access: WorldAccess,
+    kind: BorrowKind,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug, Default)]
+struct ActiveBorrows {
+    next_guard_id: u64,
+    borrows: Vec<Borrow>,
+}
+
+#[cfg(debug_assertions)]
+type SharedBorrows = Arc<Mutex<ActiveBorrows>>;
+
+/// A guard alive on the current thread
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+    tracker: usize,
+    guard_id: u64,
+    owner: &'static str,
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// The guards of the current thread, the last one owns the views built on the thread
+    static SCOPES: RefCell<Vec<Scope>> = RefCell::new(Vec::new());
+}
+
+#[cfg(debug_assertions)]
+fn lock(borrows: &SharedBorrows) -> MutexGuard<ActiveBorrows> {
+    // a conflict panics while the lock is released, but be lenient with poisoned locks anyway, the
+    // guards are dropped while unwinding
+    borrows
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BorrowTracker {
+    #[cfg(debug_assertions)]
+    borrows: SharedBorrows,
+}
+
+/// Releases the borrows of its owner when dropped
+#[must_use = "the borrows are released when the guard is dropped"]
+pub struct BorrowGuard {
+    #[cfg(debug_assertions)]
+    borrows: SharedBorrows,
+    #[cfg(debug_assertions)]
+    id: u64,
+}
+
+impl BorrowTracker {
+    /// Record that `owner` reads and writes the given parts of the World until the returned guard
+    /// is dropped. The views built on the current thread while the guard is alive belong to
+    /// `owner`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the accesses conflict with the ones of another owner
+    #[cfg(debug_assertions)]
+    pub fn borrow(
+        &self,
+        owner: &'static str,
+        reads: &[WorldAccess],
+        writes: &[WorldAccess],
+    ) -> BorrowGuard {
+        let requested = reads
+            .iter()
+            .map(|access| (*access, BorrowKind::Read))
+            .chain(writes.iter().map(|access| (*access, BorrowKind::Write)));
+
+        let mut active = lock(&self.borrows);
+        let mut conflicts = Vec::new();
+        for (access, kind) in requested.clone() {
+            conflicts.extend(
+                active
+                    .borrows
+                    .iter()
+                    .filter(|b| {
+                        (kind == BorrowKind::Write || b.kind == BorrowKind::Write)
+                            && access.conflicts(&b.access)
+                    })
+                    .map(|b| format!("{:?} {:?} held by {}", b.kind, b.access, b.owner)),
+            );
+        }
+        if !conflicts.is_empty() {
+            drop(active);
+            panic!(
+                "{} can not borrow the World, conflicting borrows: {:?}",
+                owner, conflicts
+            );
+        }
+
+        let guard_id = active.next_guard_id;
+        active.next_guard_id += 1;
+        active
+            .borrows
+            .extend(requested.map(|(access, kind)| Borrow {
+                guard_id,
+                owner,
+                access,
+                kind,
+            }));
+        drop(active);
+
+        SCOPES.with(|scopes| {
+            scopes.borrow_mut().push(Scope {
+                tracker: self.id(),
+                guard_id,
+                owner,
+            })
+        });
+        BorrowGuard {
+            borrows: Arc::clone(&self.borrows),
+            id: guard_id,
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline]
+    pub fn borrow(
+        &self,
+        _owner: &'static str,
+        _reads: &[WorldAccess],
+        _writes: &[WorldAccess],
+    ) -> BorrowGuard {
+        BorrowGuard {}
+    }
+
+    /// Record that a view accessing `access` is built.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the view is built while a guard is alive on the current thread, and its
+    /// owner did not declare the access. Or if the access conflicts with the borrows of the other
+    /// owners.
+    #[cfg(debug_assertions)]
+    pub fn record(&self, access: WorldAccess, kind: BorrowKind) {
+        let tracker = self.id();
+        let scope = SCOPES.with(|scopes| {
+            scopes
+                .borrow()
+                .iter()
+                .rev()
+                .find(|scope| scope.tracker == tracker)
+                .copied()
+        });
+
+        let active = lock(&self.borrows);
+        if let Some(scope) = scope {
+            let declared = active
+                .borrows
+                .iter()
+                .filter(|b| b.guard_id == scope.guard_id)
+                .any(|b| {
+                    (b.access == access || b.access == WorldAccess::Exclusive)
+                        && (kind == BorrowKind::Read || b.kind == BorrowKind::Write)
+                });
+            if !declared {
+                drop(active);
+                panic!(
+                    "{} built a view with {:?} access to {:?}, which it did not declare",
+                    scope.owner, kind, access
+                );
+            }
+        }
+        let conflicts: Vec<_> = active
+            .borrows
+            .iter()
+            .filter(|b| scope.map(|s| s.guard_id != b.guard_id).unwrap_or(true))
+            .filter(|b| {
+                (kind == BorrowKind::Write || b.kind == BorrowKind::Write)
+                    && access.conflicts(&b.access)
+            })
+            .map(|b| format!("{:?} {:?} held by {}", b.kind, b.access, b.owner))
+            .collect();
+        if !conflicts.is_empty() {
+            drop(active);
+            panic!(
+                "{:?} access to {:?} conflicts with the borrows: {:?}",
+                kind, access, conflicts
+            );
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline]
+    pub fn record(&self, _access: WorldAccess, _kind: BorrowKind) {}
+
+    #[cfg(debug_assertions)]
+    fn id(&self) -> usize {
+        Arc::as_ptr(&self.borrows) as usize
+    }
+
+    /// Number of parts of the World borrowed at the moment
+    #[cfg(debug_assertions)]
+    pub fn active_borrows(&self) -> usize {
+        lock(&self.borrows).borrows.len()
+    }
+
+    /// Borrows are not tracked in release builds
+    #[cfg(not(debug_assertions))]
+    pub fn active_borrows(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for BorrowGuard {
+    fn drop(&mut self) {
+        let id = self.id;
+        lock(&self.borrows)
+            .borrows
+            .retain(|borrow| borrow.guard_id != id);
+        let tracker = Arc::as_ptr(&self.borrows) as usize;
+        // `try_with` because the guard may be dropped while the thread locals are destroyed
+        let _ = SCOPES.try_with(|scopes| {
+            scopes
+                .borrow_mut()
+                .retain(|scope| scope.tracker != tracker || scope.guard_id != id)
+        });
+    }
+}
+
+/// Record the access of a view built from a pointer to the World, see `BorrowTracker::record`
+///
+/// # Safety
+///
+/// `world` must point to a live World
+pub(crate) unsafe fn record_view(world: NonNull<World>, access: WorldAccess, kind: BorrowKind) {
+    // only the tracker is borrowed, the views of other threads may point into the World
+    let tracker = &*std::ptr::addr_of!((*world.as_ptr()).borrows);
+    tracker.record(access, kind);
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use crate::components::{EnergyComponent, HpComponent};
+    use crate::indices::EntityId;
+
+    #[test]
+    fn reads_are_shared() {
+        let tracker = BorrowTracker::default();
+        let hp = WorldAccess::table::<EntityId, HpComponent>();
+
+        let a = tracker.borrow("a", &[hp], &[]);
+        let b = tracker.borrow("b", &[hp, WorldAccess::Exclusive], &[]);
+        assert_eq!(tracker.active_borrows(), 3);
+        drop(a);
+        drop(b);
+        assert_eq!(tracker.active_borrows(), 0);
+
+        // released borrows may be taken mutably
+        let _c = tracker.borrow("c", &[], &[hp]);
+    }
+
+    #[test]
+    fn disjoint_writes_are_allowed() {
+        let tracker = BorrowTracker::default();
+        let _a = tracker.borrow("a", &[], &[WorldAccess::table::<EntityId, HpComponent>()]);
+        let _b = tracker.borrow(
+            "b",
+            &[],
+            &[WorldAccess::table::<EntityId, EnergyComponent>()],
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn writes_conflict_with_reads() {
+        let tracker = BorrowTracker::default();
+        let hp = WorldAccess::table::<EntityId, HpComponent>();
+        let _a = tracker.borrow("a", &[hp], &[]);
+        let _b = tracker.borrow("b", &[], &[hp]);
+    }
+
+    #[test]
+    fn views_of_declared_tables_are_allowed() {
+        let tracker = BorrowTracker::default();
+        let hp = WorldAccess::table::<EntityId, HpComponent>();
+        let energy = WorldAccess::table::<EntityId, EnergyComponent>();
+        let _a = tracker.borrow("a", &[energy], &[hp]);
+        tracker.record(hp, BorrowKind::Write);
+        tracker.record(hp, BorrowKind::Read);
+        tracker.record(energy, BorrowKind::Read);
+    }
+
+    #[test]
+    #[should_panic]
+    fn views_of_undeclared_tables_panic() {
+        let tracker = BorrowTracker::default();
+        let _a = tracker.borrow("a", &[], &[WorldAccess::table::<EntityId, HpComponent>()]);
+        tracker.record(
+            WorldAccess::table::<EntityId, EnergyComponent>(),
+            BorrowKind::Read,
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn writing_through_a_declared_read_panics() {
+        let tracker = BorrowTracker::default();
+        let hp = WorldAccess::table::<EntityId, HpComponent>();
+        let _a = tracker.borrow("a", &[hp], &[]);
+        tracker.record(hp, BorrowKind::Write);
+    }
+
+    #[test]
+    #[should_panic]
+    fn views_conflicting_with_the_borrows_of_other_threads_panic() {
+        let tracker = BorrowTracker::default();
+        let hp = WorldAccess::table::<EntityId, HpComponent>();
+        let (borrowed_tx, borrowed_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn({
+            let tracker = tracker.clone();
+            move || {
+                let _a = tracker.borrow("a", &[], &[hp]);
+                borrowed_tx.send(()).unwrap();
+                // returns when `done_tx` is dropped
+                let _ = done_rx.recv();
+            }
+        });
+        borrowed_rx.recv().unwrap();
+        let _done = done_tx;
+        tracker.record(hp, BorrowKind::Read);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reading_the_whole_world_conflicts_with_writes() {
+        let tracker = BorrowTracker::default();
+        let _scripts = tracker.borrow("scripts", &[WorldAccess::Exclusive], &[]);
+        let _a = tracker.borrow("a", &[], &[WorldAccess::EntityIds]);
+    }
+}
@@ -292,13 +292,18 @@ macro_rules! join {
 /// more performant.
 ///
 /// This is mostly here for serialization, when communicating with clients.
+///
+/// # `component`
+///
+/// The tables of the archetype. The table backend and serde behaviour of each are declared by the
+/// component itself, via `#[derive(CaoComponent)]`, the archetype only names the field.
 #[macro_export(local_inner_macros)]
 macro_rules! archetype {
     (
         module $module: ident
         key $id: ty,
         $(
-            $(attr $attr: meta )* table $row: ty : $table_ty: ty = $name: ident
+            $(attr $attr: meta )* component $row: ty = $name: ident
         ),*
 
         $(
@@ -307,7 +312,7 @@ macro_rules! archetype {
     ) => {
         pub mod $module {
             use super::*;
-            use crate::tables::{Table, Component};
+            use crate::tables::Table;
             use crate::storage::views::{UnsafeView, View};
             use crate::storage::{HasTable, DeleteById, DeferredDeleteById};
             use serde::{Serialize, Deserialize};
@@ -322,11 +327,17 @@ macro_rules! archetype {
             )*
             pub struct Archetype {
                 $( $(#[ $attr ] )*
+                #[serde(
+                    default,
+                    serialize_with = "crate::tables::component_serde::serialize",
+                    deserialize_with = "crate::tables::component_serde::deserialize",
+                    skip_serializing_if = "crate::tables::component_serde::is_transient"
+                )]
                 pub(crate) $name: <$row as crate::tables::Component<$id>>::Table ),
                 +,
             }
 
-            archetype!(@implement_tables $($name, $id,  $row, $table_ty )*);
+            archetype!(@implement_tables $($name, $id,  $row )*);
 
             impl Archetype {
                 #[allow(unused)]
@@ -346,13 +357,9 @@ macro_rules! archetype {
 
     (
         @implement_tables
-        $($name: ident, $id: ty, $row: ty, $table_ty: ty )*
+        $($name: ident, $id: ty, $row: ty )*
     ) => {
         $(
-            impl Component<$id> for $row {
-                type Table=$table_ty;
-            }
-
             impl HasTable<$id, $row> for Archetype {
                 fn view(&'_ self) -> View<'_, $id, $row>{
                     View::from_table(&self.$name)
@@ -361,6 +368,14 @@ macro_rules! archetype {
                 fn unsafe_view(&mut self) -> UnsafeView<$id, $row>{
                     UnsafeView::from_table(&mut self.$name)
                 }
+
+                unsafe fn table_ptr(
+                    this: std::ptr::NonNull<Self>,
+                ) -> std::ptr::NonNull<<$row as crate::tables::Component<$id>>::Table> {
+                    std::ptr::NonNull::new_unchecked(std::ptr::addr_of_mut!(
+                        (*this.as_ptr()).$name
+                    ))
+                }
             }
         )*
     };
@@ -1,12 +1,22 @@
+pub mod borrows;
 mod macros;
+pub mod query;
 pub mod views;
 
 use crate::tables::{Component, TableId};
+use std::ptr::NonNull;
 use views::{UnsafeView, View};
 
 pub trait HasTable<Id: TableId, Row: Component<Id>> {
     fn view(&self) -> View<Id, Row>;
     fn unsafe_view(&mut self) -> UnsafeView<Id, Row>;
+
+    /// Pointer to the table, projected from `this` without creating a reference to `Self`
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live instance of `Self`
+    unsafe fn table_ptr(this: NonNull<Self>) -> NonNull<Row::Table>;
 }
 
 pub trait DeleteById<Id> {
@@ -0,0 +1,559 @@
+//! Typed queries over the entity tables of the World.
+//!
+//! A query is described by a term, or a tuple of terms:
+//!
+//! - `&C` matches the entities having `C`
+//! - `&mut C` matches the entities having `C` and borrows it mutably, through a
+//!   [RowMut](crate::tables::change_tracking::RowMut) guard
+//! - `Option<&C>` and `Option<&mut C>` yield `C` if the entity has it, without filtering
+//! - `Without<C>` matches the entities that do not have `C`
+//!
+//! At least one of the terms has to be `&C` or `&mut C`. The table with the fewest rows of these
+//! drives the query, the other tables are looked up by the ids of the driver. The ids of the driver
+//! are collected before the first row is borrowed.
+//!
+//! ```
+//! use caolo_sim::prelude::*;
+//! use caolo_sim::storage::query::Without;
+//!
+//! let mut world = World::new();
+//! let entity = world.insert_entity();
+//! world
+//!     .unsafe_view::<EntityId, HpComponent>()
+//!     .insert_or_update(entity, HpComponent { hp: 1, hp_max: 10 });
+//! world
+//!     .unsafe_view::<EntityId, PositionComponent>()
+//!     .insert_or_update(entity, PositionComponent::default());
+//!
+//! for (_id, (_pos, mut hp, owner, ())) in world.query::<(
+//!     &PositionComponent,
+//!     &mut HpComponent,
+//!     Option<&OwnedEntity>,
+//!     Without<Structure>,
+//! )>() {
+//!     assert!(owner.is_none());
+//!     hp.hp = hp.hp_max;
+//! }
+//!
+//! assert_eq!(world.view::<EntityId, HpComponent>().get_by_id(entity).unwrap().hp, 10);
+//! ```
+//!
+//! Queries may be system parameters as well, the scheduler derives the tables they read and write
+//! from their terms. Queries walk the entities of their tables, systems driven by intents look up
+//! the rows of the ids in the intents and insert rows, so they keep using views.
+//!
+use super::views::{FromWorldMut, StaticAccesses, UnsafeView, WorldAccess};
+use super::HasTable;
+use crate::indices::EntityId;
+use crate::prelude::World;
+use crate::tables::{change_tracking::RowMut, Component, QueryTable, QueryTableMut, Table};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+/// Ids of the rows driving a query. Collected up front, so no borrow of the driving table is alive
+/// while its rows are borrowed mutably.
+pub type DriverIds = Vec<EntityId>;
+
+/// A term of a typed query. `'a` is the lifetime of the borrow of the World.
+pub trait QueryTerm<'a> {
+    /// The value yielded for each matching entity
+    type Item;
+    /// Views of the tables of the term
+    type State: Copy;
+
+    /// # Safety
+    ///
+    /// `world` must point to a World that is alive for `'a`
+    unsafe fn state(world: NonNull<World>) -> Self::State;
+
+    /// Tables this term reads, checked for aliasing at compile time
+    const READS: StaticAccesses = StaticAccesses::NONE;
+    /// Tables this term writes, checked for aliasing at compile time
+    const WRITES: StaticAccesses = StaticAccesses::NONE;
+
+    /// Push the tables this term reads into `out`
+    fn reads(_out: &mut Vec<WorldAccess>) {}
+    /// Push the tables this term writes into `out`
+    fn writes(_out: &mut Vec<WorldAccess>) {}
+
+    /// Number of rows of the smallest table the term requires the entities to be in.
+    /// `None` if the term does not require the entities to be in any table.
+    fn driver_len(state: &Self::State) -> Option<usize>;
+
+    /// Ids of the table `driver_len` was returned for
+    ///
+    /// # Safety
+    ///
+    /// The tables of `state` must be alive for `'a`
+    unsafe fn driver_ids(state: &Self::State) -> Option<DriverIds>;
+
+    /// Return `None` if the entity does not match the term
+    ///
+    /// # Safety
+    ///
+    /// The tables of `state` must be alive for `'a` and no other borrows of the rows of `id` may be
+    /// alive
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item>;
+}
+
+/// Matches the entities that do not have `C`
+pub struct Without<C>(PhantomData<C>);
+
+unsafe fn table<'a, C: Component<EntityId>>(view: &UnsafeView<EntityId, C>) -> &'a C::Table {
+    let mut view = *view;
+    &*view.as_ptr()
+}
+
+unsafe fn row_mut<'a, C>(
+    view: &UnsafeView<EntityId, C>,
+    id: EntityId,
+) -> Option<RowMut<'a, EntityId, C>>
+where
+    C: Component<EntityId>,
+    C::Table: QueryTableMut<Id = EntityId>,
+{
+    let mut view = *view;
+    QueryTableMut::row_mut(NonNull::new_unchecked(view.as_ptr()), id)
+}
+
+impl<'a, 'b, C> QueryTerm<'a> for &'b C
+where
+    C: Component<EntityId>,
+    C::Table: QueryTable<Id = EntityId>,
+    World: HasTable<EntityId, C>,
+{
+    type Item = &'a C;
+    type State = UnsafeView<EntityId, C>;
+
+    unsafe fn state(world: NonNull<World>) -> Self::State {
+        UnsafeView::read_only_from_world_ptr(world)
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<EntityId, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<EntityId, C>());
+    }
+
+    fn driver_len(state: &Self::State) -> Option<usize> {
+        Some(state.row_count())
+    }
+
+    unsafe fn driver_ids(state: &Self::State) -> Option<DriverIds> {
+        Some(table(state).ids().collect())
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+        table(state).get_by_id(id)
+    }
+}
+
+impl<'a, 'b, C> QueryTerm<'a> for &'b mut C
+where
+    C: Component<EntityId>,
+    C::Table: QueryTableMut<Id = EntityId>,
+    World: HasTable<EntityId, C>,
+{
+    type Item = RowMut<'a, EntityId, C>;
+    type State = UnsafeView<EntityId, C>;
+
+    unsafe fn state(world: NonNull<World>) -> Self::State {
+        UnsafeView::from_world_mut_ptr(world)
+    }
+
+    const WRITES: StaticAccesses = StaticAccesses::table::<EntityId, C>();
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<EntityId, C>());
+    }
+
+    fn driver_len(state: &Self::State) -> Option<usize> {
+        Some(state.row_count())
+    }
+
+    unsafe fn driver_ids(state: &Self::State) -> Option<DriverIds> {
+        Some(table(state).ids().collect())
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+        row_mut(state, id)
+    }
+}
+
+impl<'a, 'b, C> QueryTerm<'a> for Option<&'b C>
+where
+    C: Component<EntityId>,
+    C::Table: Table<Id = EntityId>,
+    World: HasTable<EntityId, C>,
+{
+    type Item = Option<&'a C>;
+    type State = UnsafeView<EntityId, C>;
+
+    unsafe fn state(world: NonNull<World>) -> Self::State {
+        UnsafeView::read_only_from_world_ptr(world)
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<EntityId, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<EntityId, C>());
+    }
+
+    fn driver_len(_state: &Self::State) -> Option<usize> {
+        None
+    }
+
+    unsafe fn driver_ids(_state: &Self::State) -> Option<DriverIds> {
+        None
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+        Some(table(state).get_by_id(id))
+    }
+}
+
+impl<'a, 'b, C> QueryTerm<'a> for Option<&'b mut C>
+where
+    C: Component<EntityId>,
+    C::Table: QueryTableMut<Id = EntityId>,
+    World: HasTable<EntityId, C>,
+{
+    type Item = Option<RowMut<'a, EntityId, C>>;
+    type State = UnsafeView<EntityId, C>;
+
+    unsafe fn state(world: NonNull<World>) -> Self::State {
+        UnsafeView::from_world_mut_ptr(world)
+    }
+
+    const WRITES: StaticAccesses = StaticAccesses::table::<EntityId, C>();
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<EntityId, C>());
+    }
+
+    fn driver_len(_state: &Self::State) -> Option<usize> {
+        None
+    }
+
+    unsafe fn driver_ids(_state: &Self::State) -> Option<DriverIds> {
+        None
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+        Some(row_mut(state, id))
+    }
+}
+
+impl<'a, C> QueryTerm<'a> for Without<C>
+where
+    C: Component<EntityId>,
+    C::Table: Table<Id = EntityId>,
+    World: HasTable<EntityId, C>,
+{
+    type Item = ();
+    type State = UnsafeView<EntityId, C>;
+
+    unsafe fn state(world: NonNull<World>) -> Self::State {
+        UnsafeView::read_only_from_world_ptr(world)
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<EntityId, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<EntityId, C>());
+    }
+
+    fn driver_len(_state: &Self::State) -> Option<usize> {
+        None
+    }
+
+    unsafe fn driver_ids(_state: &Self::State) -> Option<DriverIds> {
+        None
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+        match table(state).get_by_id(id) {
+            Some(_) => None,
+            None => Some(()),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t: ident = $i: tt),*) => {
+        impl<'a, $($t: QueryTerm<'a>),*> QueryTerm<'a> for ($($t,)*) {
+            type Item = ($($t::Item,)*);
+            type State = ($($t::State,)*);
+
+            unsafe fn state(world: NonNull<World>) -> Self::State {
+                ($($t::state(world),)*)
+            }
+
+            const READS: StaticAccesses = StaticAccesses::NONE $(.merge($t::READS))*;
+            const WRITES: StaticAccesses = StaticAccesses::NONE $(.merge($t::WRITES))*;
+
+            fn reads(out: &mut Vec<WorldAccess>) {
+                $($t::reads(out);)*
+            }
+
+            fn writes(out: &mut Vec<WorldAccess>) {
+                $($t::writes(out);)*
+            }
+
+            fn driver_len(state: &Self::State) -> Option<usize> {
+                let mut res: Option<usize> = None;
+                $(
+                    if let Some(len) = $t::driver_len(&state.$i) {
+                        res = Some(res.map_or(len, |res| res.min(len)));
+                    }
+                )*
+                res
+            }
+
+            unsafe fn driver_ids(state: &Self::State) -> Option<DriverIds> {
+                let len = Self::driver_len(state)?;
+                $(
+                    if $t::driver_len(&state.$i) == Some(len) {
+                        return $t::driver_ids(&state.$i);
+                    }
+                )*
+                None
+            }
+
+            unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+                Some(($($t::fetch(&state.$i, id)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple!(A = 0);
+impl_tuple!(A = 0, B = 1);
+impl_tuple!(A = 0, B = 1, C = 2);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4, F = 5);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4, F = 5, G = 6);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4, F = 5, G = 6, H = 7);
+
+/// Typed query over the entity tables, see the [module documentation](self)
+pub struct Query<'a, Q: QueryTerm<'a>> {
+    state: Q::State,
+    _world: PhantomData<&'a mut World>,
+}
+
+impl<'a, Q: QueryTerm<'a>> Query<'a, Q> {
+    /// # Panics
+    ///
+    /// If the terms borrow a table mutably more than once, or none of the terms requires a
+    /// component
+    pub fn new(world: &'a mut World) -> Self {
+        Self::from_state(unsafe { Q::state(NonNull::from(world)) })
+    }
+
+    fn from_state(state: Q::State) -> Self {
+        check_aliasing::<Q>();
+        assert!(
+            Q::driver_len(&state).is_some(),
+            "Query {} needs at least one `&C` or `&mut C` term",
+            std::any::type_name::<Q>()
+        );
+        Self {
+            state,
+            _world: PhantomData,
+        }
+    }
+
+    /// Upper bound of the number of matching entities, the number of rows of the driving table
+    pub fn size_hint(&self) -> usize {
+        Q::driver_len(&self.state).unwrap_or(0)
+    }
+
+    /// Return the components of `id` if it matches the query
+    pub fn get(self, id: EntityId) -> Option<Q::Item> {
+        // the query is consumed, so the rows of `id` are borrowed only once
+        unsafe { Q::fetch(&self.state, id) }
+    }
+}
+
+fn check_aliasing<'a, Q: QueryTerm<'a>>() {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    Q::reads(&mut reads);
+    Q::writes(&mut writes);
+    if let Some(write) = WorldAccess::find_aliased(&reads, &writes) {
+        panic!(
+            "Query {} aliases a mutable borrow of {:?}",
+            std::any::type_name::<Q>(),
+            write
+        );
+    }
+}
+
+impl<'a, Q: QueryTerm<'a>> IntoIterator for Query<'a, Q> {
+    type Item = (EntityId, Q::Item);
+    type IntoIter = QueryIter<'a, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let ids = unsafe { Q::driver_ids(&self.state) }
+            .expect("Queries are checked to have a driver on construction");
+        QueryIter {
+            state: self.state,
+            ids: ids.into_iter(),
+            _world: PhantomData,
+        }
+    }
+}
+
+pub struct QueryIter<'a, Q: QueryTerm<'a>> {
+    state: Q::State,
+    ids: std::vec::IntoIter<EntityId>,
+    _world: PhantomData<&'a mut World>,
+}
+
+impl<'a, Q: QueryTerm<'a>> Iterator for QueryIter<'a, Q> {
+    type Item = (EntityId, Q::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = &self.state;
+        // driver ids are unique, so the rows of each entity are borrowed only once
+        self.ids
+            .by_ref()
+            .find_map(|id| unsafe { Q::fetch(state, id) }.map(|item| (id, item)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.ids.len()))
+    }
+}
+
+impl<'a, Q: QueryTerm<'a>> FromWorldMut for Query<'a, Q> {
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        Self::from_state(Q::state(w))
+    }
+
+    const WRITES: StaticAccesses = Q::WRITES;
+    const READS: StaticAccesses = Q::READS;
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        Q::writes(out);
+    }
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        Q::reads(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{Bot, EnergyComponent, HpComponent, OwnedEntity, Structure};
+    use crate::indices::UserId;
+
+    fn setup() -> (std::pin::Pin<Box<World>>, Vec<EntityId>) {
+        let mut world = World::new();
+        let ids = (0..6).map(|_| world.insert_entity()).collect::<Vec<_>>();
+        for (i, id) in ids.iter().enumerate() {
+            world
+                .unsafe_view::<EntityId, HpComponent>()
+                .insert_or_update(*id, HpComponent { hp: 1, hp_max: 10 });
+            if i % 2 == 0 {
+                world.unsafe_view::<EntityId, Bot>().insert(*id);
+            }
+            if i % 3 == 0 {
+                world
+                    .unsafe_view::<EntityId, OwnedEntity>()
+                    .insert_or_update(
+                        *id,
+                        OwnedEntity {
+                            owner_id: UserId::default(),
+                        },
+                    );
+            }
+        }
+        (world, ids)
+    }
+
+    #[test]
+    fn matches_the_required_components() {
+        let (mut world, ids) = setup();
+
+        let mut healed = vec![];
+        for (id, (mut hp, _bot, owner)) in
+            world.query::<(&mut HpComponent, &Bot, Option<&OwnedEntity>)>()
+        {
+            hp.hp = hp.hp_max;
+            healed.push((id, owner.is_some()));
+        }
+        assert_eq!(
+            healed,
+            vec![(ids[0], true), (ids[2], false), (ids[4], false)]
+        );
+
+        let hps = world.view::<EntityId, HpComponent>();
+        for (i, id) in ids.iter().enumerate() {
+            let expected = if i % 2 == 0 { 10 } else { 1 };
+            assert_eq!(hps.get_by_id(*id).unwrap().hp, expected);
+        }
+    }
+
+    #[test]
+    fn excludes_components() {
+        let (mut world, ids) = setup();
+        world.unsafe_view::<EntityId, Structure>().insert(ids[1]);
+
+        let found = world
+            .query::<(&HpComponent, Without<Bot>, Without<Structure>)>()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![ids[3], ids[5]]);
+    }
+
+    #[test]
+    fn smallest_table_drives_the_query() {
+        let (mut world, ids) = setup();
+        world
+            .unsafe_view::<EntityId, EnergyComponent>()
+            .insert_or_update(
+                ids[2],
+                EnergyComponent {
+                    energy: 0,
+                    energy_max: 10,
+                },
+            );
+
+        let query = world.query::<(&HpComponent, &Bot, &EnergyComponent)>();
+        assert_eq!(query.size_hint(), 1);
+        let found = query.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(found, vec![ids[2]]);
+    }
+
+    #[test]
+    fn only_written_rows_are_marked_updated() {
+        use crate::tables::change_tracking::Change;
+
+        let (mut world, ids) = setup();
+        world.post_process();
+
+        for (id, mut hp) in world.query::<&mut HpComponent>() {
+            if id == ids[1] {
+                hp.hp = hp.hp_max;
+            }
+        }
+        world.post_process();
+
+        let changes = world.view_changes::<EntityId, HpComponent>();
+        assert_eq!(
+            changes.iter().collect::<Vec<_>>(),
+            vec![(ids[1], Change::Updated)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn aliasing_mutable_borrows_panic() {
+        let (mut world, _) = setup();
+        let _query = world.query::<(&mut HpComponent, &HpComponent)>();
+    }
+}
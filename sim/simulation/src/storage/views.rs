@@ -23,27 +23,73 @@
 //! update_minerals(FromWorldMut::from_world_mut(&mut storage), FromWorld::from_world(&storage));
 //! ```
 //!
+mod access;
+mod changes;
 mod unsafe_view;
 mod unwrap;
 mod unwrap_mut;
 mod view;
 
+pub use access::*;
+pub use changes::*;
 pub use unsafe_view::*;
 pub use unwrap::*;
 pub use unwrap_mut::*;
 pub use view::*;
 
+use super::borrows::{record_view, BorrowKind};
 use super::{Component, DeleteById, TableId};
-use crate::indices::EntityId;
+use crate::components::{EntityComponent, EntityLayer, LayeredEntity, PositionComponent};
+use crate::indices::{EntityId, WorldPosition};
+use crate::lifecycle::DeleteCause;
 use crate::prelude::World;
+use crate::tables::{morton_hierarchy::ExtendFailure, Table};
 use std::ptr::NonNull;
 
-pub trait FromWorld<'a> {
-    fn from_world(w: &'a World) -> Self;
+pub trait FromWorld<'a>: Sized {
+    fn from_world(w: &'a World) -> Self {
+        unsafe { Self::from_world_ptr(NonNull::from(w)) }
+    }
+
+    /// Build the view from a pointer to the World. Only the parts of the World this view reads are
+    /// borrowed, no reference to the whole World is created.
+    ///
+    /// # Safety
+    ///
+    /// `w` must point to a World that is alive for `'a`, and the parts of it returned by `reads`
+    /// must not be written while the view is alive
+    unsafe fn from_world_ptr(w: NonNull<World>) -> Self;
+
+    /// Tables this view reads, checked for aliasing at compile time, see `NotAliased`
+    const READS: StaticAccesses = StaticAccesses::NONE;
+
+    /// Push the parts of the World this view reads into `out`
+    fn reads(out: &mut Vec<WorldAccess>);
 }
 
-pub trait FromWorldMut {
-    fn from_world_mut(w: &mut World) -> Self;
+pub trait FromWorldMut: Sized {
+    fn from_world_mut(w: &mut World) -> Self {
+        unsafe { Self::from_world_mut_ptr(NonNull::from(w)) }
+    }
+
+    /// Build the view from a pointer to the World. Only the parts of the World this view accesses
+    /// are borrowed, no reference to the whole World is created.
+    ///
+    /// # Safety
+    ///
+    /// `w` must point to a World that outlives the view, and the parts of it returned by `writes`
+    /// and `reads` must not be accessed by others while the view is alive
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self;
+
+    /// Tables this view writes, checked for aliasing at compile time, see `NotAliased`
+    const WRITES: StaticAccesses = StaticAccesses::NONE;
+    /// Tables this view only reads
+    const READS: StaticAccesses = StaticAccesses::NONE;
+
+    /// Push the parts of the World this view writes into `out`
+    fn writes(out: &mut Vec<WorldAccess>);
+    /// Push the parts of the World this view only reads into `out`
+    fn reads(_out: &mut Vec<WorldAccess>) {}
 }
 
 #[derive(Clone, Copy)]
@@ -61,19 +107,19 @@ where
     /// # Safety
     /// This function should only be called if the pointed to Storage is in memory and no other
     /// threads have access to it at this time!
-    pub unsafe fn delete_entity(&mut self, id: EntityId) {
-        use super::DeferredDeleteById;
-
-        let world = self.world.as_mut();
-        world.deferred_delete(id);
+    pub unsafe fn delete_entity(&mut self, id: EntityId, cause: DeleteCause) {
+        World::deferred_delete_raw(self.world, id, cause);
     }
 }
 
 impl FromWorldMut for DeferredDeleteEntityView {
-    fn from_world_mut(w: &mut World) -> Self {
-        Self {
-            world: unsafe { NonNull::new_unchecked(w) },
-        }
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        record_view(w, WorldAccess::DeferredDeletes, BorrowKind::Write);
+        Self { world: w }
+    }
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::DeferredDeletes);
     }
 }
 
@@ -93,16 +139,20 @@ where
     /// This function should only be called if the pointed to Storage is in memory and no other
     /// threads have access to it at this time!
     pub unsafe fn delete_entity(&mut self, id: EntityId) {
-        let storage = &mut self.storage.as_mut().entities;
+        let storage = &mut *std::ptr::addr_of_mut!((*self.storage.as_ptr()).entities);
         storage.delete(id);
     }
 }
 
 impl FromWorldMut for DeleteEntityView {
-    fn from_world_mut(w: &mut World) -> Self {
-        Self {
-            storage: unsafe { NonNull::new_unchecked(w) },
-        }
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        record_view(w, WorldAccess::Exclusive, BorrowKind::Write);
+        Self { storage: w }
+    }
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        // deletes from every entity table
+        out.push(WorldAccess::Exclusive);
     }
 }
 
@@ -115,10 +165,13 @@ unsafe impl Send for InsertEntityView {}
 unsafe impl Sync for InsertEntityView {}
 
 impl FromWorldMut for InsertEntityView {
-    fn from_world_mut(w: &mut World) -> Self {
-        Self {
-            storage: unsafe { NonNull::new_unchecked(w) },
-        }
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        record_view(w, WorldAccess::EntityIds, BorrowKind::Write);
+        Self { storage: w }
+    }
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::EntityIds);
     }
 }
 
@@ -127,8 +180,113 @@ impl InsertEntityView {
     /// This function should only be called if the pointed to Storage is in memory and no other
     /// threads have access to it at this time!
     pub unsafe fn insert_entity(&mut self) -> EntityId {
-        let storage = self.storage.as_mut();
-        storage.insert_entity()
+        World::insert_entity_raw(self.storage)
+    }
+}
+
+/// Writes the positions of entities and keeps the position indices in sync,
+/// so systems running later in the tick see the moves of the earlier ones.
+#[derive(Clone, Copy)]
+pub struct PositionsMut {
+    pub positions: UnsafeView<EntityId, PositionComponent>,
+    /// Single occupancy index of the entities on the blocking layer
+    pub entities_by_pos: UnsafeView<WorldPosition, EntityComponent>,
+    /// Every positioned entity, tagged with its layer
+    pub layered_entities: UnsafeView<WorldPosition, LayeredEntity>,
+}
+
+impl PositionsMut {
+    /// Place the entity at `pos` on `layer`. Entities that already have a position are moved.
+    ///
+    /// On failure the entity is left at its previous position.
+    pub fn insert(
+        &mut self,
+        id: EntityId,
+        pos: WorldPosition,
+        layer: EntityLayer,
+    ) -> Result<(), ExtendFailure> {
+        let from = self
+            .positions
+            .get_by_id(id)
+            .map(|PositionComponent(from)| *from);
+        let from_layer = self.layer_of(id);
+        if let Some(from) = from {
+            self.unindex(id, from);
+        }
+        if let Err(err) = self.index(id, pos, layer) {
+            self.unindex(id, pos);
+            if let (Some(from), Some(from_layer)) = (from, from_layer) {
+                self.index(id, from, from_layer)
+                    .expect("expected the previous position to be indexable");
+            }
+            return Err(err);
+        }
+        self.positions.insert_or_update(id, PositionComponent(pos));
+        Ok(())
+    }
+
+    /// Move the entity to `pos`, keeping its layer.
+    /// Entities without a position are placed on the blocking layer.
+    pub fn move_entity(&mut self, id: EntityId, pos: WorldPosition) -> Result<(), ExtendFailure> {
+        let layer = self.layer_of(id).unwrap_or(EntityLayer::Blocking);
+        self.insert(id, pos, layer)
+    }
+
+    /// The layer the entity occupies, if it has a position
+    pub fn layer_of(&self, id: EntityId) -> Option<EntityLayer> {
+        let PositionComponent(pos) = self.positions.get_by_id(id)?;
+        self.layered_entities
+            .all_at(*pos)
+            .find(|e| e.id == id)
+            .map(|e| e.layer)
+    }
+
+    /// Remove the position of the entity, returns the position it was removed from
+    pub fn remove(&mut self, id: EntityId) -> Option<WorldPosition> {
+        let PositionComponent(pos) = self.positions.delete(id)?;
+        self.unindex(id, pos);
+        Some(pos)
+    }
+
+    fn index(
+        &mut self,
+        id: EntityId,
+        pos: WorldPosition,
+        layer: EntityLayer,
+    ) -> Result<(), ExtendFailure> {
+        if layer == EntityLayer::Blocking {
+            self.entities_by_pos.insert(pos, EntityComponent(id))?;
+        }
+        self.layered_entities
+            .insert(pos, LayeredEntity { layer, id })
+    }
+
+    fn unindex(&mut self, id: EntityId, pos: WorldPosition) {
+        self.entities_by_pos
+            .delete_if(pos, |EntityComponent(e)| *e == id);
+        self.layered_entities
+            .delete_if(pos, |LayeredEntity { id: e, .. }| *e == id);
+    }
+}
+
+impl FromWorldMut for PositionsMut {
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        Self {
+            positions: FromWorldMut::from_world_mut_ptr(w),
+            entities_by_pos: FromWorldMut::from_world_mut_ptr(w),
+            layered_entities: FromWorldMut::from_world_mut_ptr(w),
+        }
+    }
+
+    const WRITES: StaticAccesses = StaticAccesses::NONE
+        .merge(UnsafeView::<EntityId, PositionComponent>::WRITES)
+        .merge(UnsafeView::<WorldPosition, EntityComponent>::WRITES)
+        .merge(UnsafeView::<WorldPosition, LayeredEntity>::WRITES);
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        UnsafeView::<EntityId, PositionComponent>::writes(out);
+        UnsafeView::<WorldPosition, EntityComponent>::writes(out);
+        UnsafeView::<WorldPosition, LayeredEntity>::writes(out);
     }
 }
 
@@ -136,8 +294,15 @@ impl InsertEntityView {
 #[derive(Clone, Copy)]
 pub struct WorldTime(pub u64);
 impl<'a> FromWorld<'a> for WorldTime {
-    fn from_world(w: &'a World) -> Self {
-        Self(w.time())
+    unsafe fn from_world_ptr(w: NonNull<World>) -> Self {
+        let crate::Time(time) = FromWorld::from_world_ptr(w);
+        Self(time)
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<crate::indices::EmptyKey, crate::Time>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<crate::indices::EmptyKey, crate::Time>());
     }
 }
 
@@ -147,23 +312,40 @@ macro_rules! implement_tuple {
             FromWorld <'a> for ( $v, )
             {
                 #[allow(unused)]
-                fn from_world(storage: &'a World) -> Self {
+                unsafe fn from_world_ptr(storage: NonNull<World>) -> Self {
                     (
-                        $v::from_world(storage) ,
+                        $v::from_world_ptr(storage) ,
                     )
                 }
+
+                const READS: StaticAccesses = <$v as FromWorld<'a>>::READS;
+
+                fn reads(out: &mut Vec<WorldAccess>) {
+                    $v::reads(out);
+                }
             }
 
         impl<$v:FromWorldMut >
             FromWorldMut  for ( $v, )
             {
                 #[allow(unused)]
-                fn from_world_mut(storage: &mut World) -> Self {
+                unsafe fn from_world_mut_ptr(storage: NonNull<World>) -> Self {
                     (
-                        $v::from_world_mut(storage),
+                        $v::from_world_mut_ptr(storage),
                     )
                 }
 
+                const WRITES: StaticAccesses = <$v as FromWorldMut>::WRITES;
+                const READS: StaticAccesses = <$v as FromWorldMut>::READS;
+
+                fn writes(out: &mut Vec<WorldAccess>) {
+                    $v::writes(out);
+                }
+
+                fn reads(out: &mut Vec<WorldAccess>) {
+                    <$v as FromWorldMut>::reads(out);
+                }
+
             }
     };
 
@@ -173,11 +355,19 @@ macro_rules! implement_tuple {
             {
                 #[allow(unused)]
                 #[allow(clippy::unused_unit)]
-                fn from_world(storage: &'a World) -> Self {
+                unsafe fn from_world_ptr(storage: NonNull<World>) -> Self {
                     (
-                        $($vv::from_world(storage)),*
+                        $($vv::from_world_ptr(storage)),*
                     )
                 }
+
+                const READS: StaticAccesses = StaticAccesses::NONE
+                    $(.merge(<$vv as FromWorld<'a>>::READS))*;
+
+                #[allow(unused)]
+                fn reads(out: &mut Vec<WorldAccess>) {
+                    $($vv::reads(out);)*
+                }
             }
 
         impl<'a, $($vv:FromWorldMut),* >
@@ -185,11 +375,26 @@ macro_rules! implement_tuple {
             {
                 #[allow(unused)]
                 #[allow(clippy::unused_unit)]
-                fn from_world_mut(storage: &mut World) -> Self {
+                unsafe fn from_world_mut_ptr(storage: NonNull<World>) -> Self {
                     (
-                        $($vv::from_world_mut(storage)),*
+                        $($vv::from_world_mut_ptr(storage)),*
                     )
                 }
+
+                const WRITES: StaticAccesses = StaticAccesses::NONE
+                    $(.merge(<$vv as FromWorldMut>::WRITES))*;
+                const READS: StaticAccesses = StaticAccesses::NONE
+                    $(.merge(<$vv as FromWorldMut>::READS))*;
+
+                #[allow(unused)]
+                fn writes(out: &mut Vec<WorldAccess>) {
+                    $($vv::writes(out);)*
+                }
+
+                #[allow(unused)]
+                fn reads(out: &mut Vec<WorldAccess>) {
+                    $(<$vv as FromWorldMut>::reads(out);)*
+                }
             }
     };
 }
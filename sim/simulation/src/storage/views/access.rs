@@ -0,0 +1,166 @@
+use super::{Component, FromWorld, FromWorldMut};
+use crate::tables::TableId;
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+/// A part of the World that a view reads or writes.
+///
+/// Used to find out which systems can safely run in parallel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum WorldAccess {
+    Table {
+        id: TypeId,
+        name: &'static str,
+    },
+    /// Allocation of new entity ids
+    EntityIds,
+    DeferredDeletes,
+    /// Access to the whole World, conflicts with everything
+    Exclusive,
+}
+
+impl WorldAccess {
+    pub fn table<Id: TableId, C: Component<Id>>() -> Self {
+        WorldAccess::Table {
+            id: TypeId::of::<(Id, C)>(),
+            name: std::any::type_name::<C>(),
+        }
+    }
+
+    pub fn conflicts(&self, other: &WorldAccess) -> bool {
+        matches!(self, WorldAccess::Exclusive)
+            || matches!(other, WorldAccess::Exclusive)
+            || self == other
+    }
+
+    /// Find a write in `writes` that conflicts with another write, or with any of the `reads`
+    pub fn find_aliased<'b>(
+        reads: &'b [WorldAccess],
+        writes: &'b [WorldAccess],
+    ) -> Option<&'b WorldAccess> {
+        writes.iter().enumerate().find_map(|(i, write)| {
+            writes[i + 1..]
+                .iter()
+                .chain(reads.iter())
+                .any(|access| write.conflicts(access))
+                .then(|| write)
+        })
+    }
+}
+
+/// Most tables the views of one side of a system (see `NotAliased`) may name
+pub const MAX_STATIC_ACCESSES: usize = 32;
+
+/// Tables a view accesses, known at compile time.
+///
+/// Tables are identified by `Component::TABLE_NAME`. Only table accesses are listed, the other
+/// kinds of `WorldAccess` are checked at runtime.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticAccesses {
+    tables: [&'static str; MAX_STATIC_ACCESSES],
+    len: usize,
+}
+
+impl StaticAccesses {
+    pub const NONE: Self = Self {
+        tables: [""; MAX_STATIC_ACCESSES],
+        len: 0,
+    };
+
+    pub const fn table<Id: TableId, C: Component<Id>>() -> Self {
+        Self::NONE.with(C::TABLE_NAME)
+    }
+
+    pub const fn with(mut self, table: &'static str) -> Self {
+        assert!(
+            self.len < MAX_STATIC_ACCESSES,
+            "the views of a system name too many tables"
+        );
+        self.tables[self.len] = table;
+        self.len += 1;
+        self
+    }
+
+    pub const fn merge(mut self, other: Self) -> Self {
+        let mut i = 0;
+        while i < other.len {
+            self = self.with(other.tables[i]);
+            i += 1;
+        }
+        self
+    }
+
+    /// Find a table in `writes` that is written again, or is also in `reads`.
+    /// Compile-time counterpart of `WorldAccess::find_aliased`.
+    pub const fn find_aliased(reads: Self, writes: Self) -> Option<&'static str> {
+        let mut i = 0;
+        while i < writes.len {
+            let write = writes.tables[i];
+            let mut j = i + 1;
+            while j < writes.len {
+                if str_eq(write, writes.tables[j]) {
+                    return Some(write);
+                }
+                j += 1;
+            }
+            let mut j = 0;
+            while j < reads.len {
+                if str_eq(write, reads.tables[j]) {
+                    return Some(write);
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Fails to compile if the mutable views `M` and the immutable views `C` of a system alias a table,
+/// the error names the aliased table. Used by the scheduler when systems are added.
+///
+/// The check is evaluated when the code using `CHECK` is built, `cargo check` does not report it.
+///
+/// ```
+/// use caolo_sim::prelude::*;
+///
+/// type Mut = (UnsafeView<EntityId, HpComponent>, UnsafeView<EntityId, EnergyComponent>);
+/// type Const<'a> = (View<'a, EntityId, HpComponent>,);
+///
+/// let () = NotAliased::<Mut, ()>::CHECK;
+/// let () = NotAliased::<(UnsafeView<EntityId, EnergyComponent>,), Const<'static>>::CHECK;
+/// ```
+///
+/// ```compile_fail
+/// use caolo_sim::prelude::*;
+///
+/// type Mut = (UnsafeView<EntityId, HpComponent>, UnsafeView<EntityId, EnergyComponent>);
+/// type Const<'a> = (View<'a, EntityId, HpComponent>,);
+///
+/// let () = NotAliased::<Mut, Const<'static>>::CHECK;
+/// ```
+pub struct NotAliased<M, C>(PhantomData<(M, C)>);
+
+impl<M: FromWorldMut, C: FromWorld<'static>> NotAliased<M, C> {
+    pub const CHECK: () = {
+        let reads = <M as FromWorldMut>::READS.merge(<C as FromWorld<'static>>::READS);
+        if let Some(table) = StaticAccesses::find_aliased(reads, <M as FromWorldMut>::WRITES) {
+            panic!("{}", table);
+        }
+    };
+}
@@ -0,0 +1,63 @@
+use super::super::HasTable;
+use super::{Component, FromWorld, StaticAccesses, TableId, View, World, WorldAccess};
+use crate::tables::change_tracking::ChangeSet;
+use crate::tables::TrackedTable;
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+/// Fetch the changes a tracked table committed in the last tick
+///
+pub struct ChangesView<'a, Id: TableId, C: Component<Id>>(&'a ChangeSet<Id>, View<'a, Id, C>);
+
+impl<'a, Id: TableId, C: Component<Id>> Clone for ChangesView<'a, Id, C> {
+    fn clone(&self) -> Self {
+        ChangesView(self.0, self.1)
+    }
+}
+
+impl<'a, Id: TableId, C: Component<Id>> Copy for ChangesView<'a, Id, C> {}
+
+unsafe impl<'a, Id: TableId, C: Component<Id>> Send for ChangesView<'a, Id, C> {}
+unsafe impl<'a, Id: TableId, C: Component<Id>> Sync for ChangesView<'a, Id, C> {}
+
+impl<'a, Id: TableId, C: Component<Id>> ChangesView<'a, Id, C>
+where
+    C::Table: TrackedTable<Id = Id>,
+{
+    pub fn from_table(t: &'a C::Table) -> Self {
+        Self(t.change_tracker().changes(), View::from_table(t))
+    }
+
+    /// The table the changes were recorded on
+    pub fn table(self) -> View<'a, Id, C> {
+        self.1
+    }
+
+    pub fn reborrow(self) -> &'a ChangeSet<Id> {
+        self.0
+    }
+}
+
+impl<'a, Id: TableId, C: Component<Id>> Deref for ChangesView<'a, Id, C> {
+    type Target = ChangeSet<Id>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a, Id: TableId, C: Component<Id>> FromWorld<'a> for ChangesView<'a, Id, C>
+where
+    crate::world::World: HasTable<Id, C>,
+    C::Table: TrackedTable<Id = Id>,
+{
+    unsafe fn from_world_ptr(w: NonNull<World>) -> Self {
+        Self::from_table(View::<Id, C>::from_world_ptr(w).reborrow())
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<Id, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<Id, C>());
+    }
+}
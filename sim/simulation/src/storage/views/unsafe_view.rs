@@ -1,5 +1,6 @@
+use super::super::borrows::{record_view, BorrowKind};
 use super::super::HasTable;
-use super::{Component, FromWorldMut, TableId};
+use super::{Component, FromWorldMut, StaticAccesses, TableId, WorldAccess};
 use crate::prelude::World;
 use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
@@ -23,14 +24,34 @@ impl<Id: TableId, C: Component<Id>> UnsafeView<Id, C> {
         let res: UnsafeView<Id, C> = Self(ptr);
         res
     }
+
+    /// Build a view its holder only reads through, the borrow tracker records it as a read
+    ///
+    /// # Safety
+    ///
+    /// See `FromWorld::from_world_ptr`
+    pub(crate) unsafe fn read_only_from_world_ptr(w: NonNull<World>) -> Self
+    where
+        World: HasTable<Id, C>,
+    {
+        record_view(w, WorldAccess::table::<Id, C>(), BorrowKind::Read);
+        Self(<World as HasTable<Id, C>>::table_ptr(w))
+    }
 }
 
 impl<Id: TableId, C: Component<Id>> FromWorldMut for UnsafeView<Id, C>
 where
     crate::world::World: HasTable<Id, C>,
 {
-    fn from_world_mut(w: &mut World) -> Self {
-        <World as HasTable<Id, C>>::unsafe_view(w)
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        record_view(w, WorldAccess::table::<Id, C>(), BorrowKind::Write);
+        Self(<World as HasTable<Id, C>>::table_ptr(w))
+    }
+
+    const WRITES: StaticAccesses = StaticAccesses::table::<Id, C>();
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<Id, C>());
     }
 }
 
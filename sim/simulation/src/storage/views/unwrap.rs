@@ -1,8 +1,9 @@
 use super::super::HasTable;
-use super::{Component, FromWorld, View, World};
+use super::{Component, FromWorld, StaticAccesses, View, World, WorldAccess};
 use crate::tables::unique_table::UniqueTable;
 use crate::tables::TableId;
 use std::ops::Deref;
+use std::ptr::NonNull;
 
 /// Fetch read-only tables from a Storage
 ///
@@ -46,8 +47,14 @@ impl<'a, Id: TableId, C: Default + Component<Id, Table = UniqueTable<Id, C>>> Fr
 where
     crate::world::World: HasTable<Id, C>,
 {
-    fn from_world(w: &'a World) -> Self {
-        let table: &UniqueTable<Id, C> = View::from_world(w).reborrow();
+    unsafe fn from_world_ptr(w: NonNull<World>) -> Self {
+        let table: &UniqueTable<Id, C> = View::from_world_ptr(w).reborrow();
         UnwrapView(table)
     }
+
+    const READS: StaticAccesses = StaticAccesses::table::<Id, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<Id, C>());
+    }
 }
@@ -1,5 +1,5 @@
 use super::super::HasTable;
-use super::{Component, FromWorldMut, UnsafeView, World};
+use super::{Component, FromWorldMut, StaticAccesses, UnsafeView, World, WorldAccess};
 use crate::tables::unique_table::UniqueTable;
 use crate::tables::TableId;
 use std::ops::{Deref, DerefMut};
@@ -50,8 +50,14 @@ impl<'a, Id: TableId, C: Default + Component<Id, Table = UniqueTable<Id, C>>> Fr
 where
     crate::world::World: HasTable<Id, C>,
 {
-    fn from_world_mut(w: &mut World) -> Self {
-        let table = UnsafeView::from_world_mut(w).as_ptr();
-        UnwrapViewMut(NonNull::new(table).unwrap())
+    unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+        let table = UnsafeView::from_world_mut_ptr(w).as_ptr();
+        UnwrapViewMut(NonNull::new_unchecked(table))
+    }
+
+    const WRITES: StaticAccesses = StaticAccesses::table::<Id, C>();
+
+    fn writes(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<Id, C>());
     }
 }
@@ -1,6 +1,8 @@
+use super::super::borrows::{record_view, BorrowKind};
 use super::super::HasTable;
-use super::{Component, FromWorld, TableId, World};
+use super::{Component, FromWorld, StaticAccesses, TableId, World, WorldAccess};
 use std::ops::Deref;
+use std::ptr::NonNull;
 
 /// Fetch read-only tables from a Storage
 ///
@@ -39,7 +41,14 @@ impl<'a, Id: TableId, C: Component<Id>> FromWorld<'a> for View<'a, Id, C>
 where
     crate::world::World: HasTable<Id, C>,
 {
-    fn from_world(w: &'a World) -> Self {
-        <World as HasTable<Id, C>>::view(w)
+    unsafe fn from_world_ptr(w: NonNull<World>) -> Self {
+        record_view(w, WorldAccess::table::<Id, C>(), BorrowKind::Read);
+        Self(&*<World as HasTable<Id, C>>::table_ptr(w).as_ptr())
+    }
+
+    const READS: StaticAccesses = StaticAccesses::table::<Id, C>();
+
+    fn reads(out: &mut Vec<WorldAccess>) {
+        out.push(WorldAccess::table::<Id, C>());
     }
 }
@@ -11,9 +11,11 @@ pub mod move_intent_system;
 pub mod path_cache_intent_system;
 pub mod positions_system;
 pub mod say_intent_system;
+pub mod scheduler;
 pub mod script_execution;
 pub mod script_history_system;
 pub mod spawn_system;
+pub mod visibility_system;
 
 use attack_system::attack_system_update;
 use death_system::death_update;
@@ -30,56 +32,52 @@ use positions_system::positions_update;
 use say_intent_system::say_intents_update;
 use script_history_system::script_history_update;
 use spawn_system::{update_spawn_intents, update_spawns};
+use visibility_system::visibility_update;
 
 use crate::storage::views::{FromWorld, FromWorldMut};
 use crate::{prelude::World, profile};
+use once_cell::sync::Lazy;
+use scheduler::Scheduler;
+
+/// Systems in the order they are executed in each tick
+static SCHEDULE: Lazy<Scheduler> = Lazy::new(|| {
+    let mut schedule = Scheduler::new();
+    schedule
+        // pre processing
+        .add(spawn_system::update_cont_spawns)
+        // main processing
+        .add(attack_system_update)
+        .add(move_intents_update)
+        .add(mine_intents_update)
+        .add(dropoff_intents_update)
+        .add(update_spawn_intents)
+        .add(log_intents_update)
+        .add(path_cache_intents_update)
+        .add(script_history_update)
+        .add(say_intents_update)
+        // systems that run regardless of player actions
+        .add(decay_update)
+        .add(death_update)
+        .add(energy_update)
+        .add(update_spawns)
+        .add(mineral_update)
+        .add(positions_update)
+        .add(visibility_update)
+        .add(log_update);
+    schedule
+});
 
 pub fn execute_world_update(storage: &mut World) {
     profile!("execute_systems_update");
 
-    execute_intents(storage);
-    execute_automated_systems(storage);
-}
-
-fn execute_intents(storage: &mut World) {
-    profile!("execute_intents");
-
-    // pre processing
-    execute_update(spawn_system::update_cont_spawns, storage);
-
-    // main processing
-    execute_update(attack_system_update, storage);
-    execute_update(move_intents_update, storage);
-    execute_update(mine_intents_update, storage);
-    execute_update(dropoff_intents_update, storage);
-    execute_update(update_spawn_intents, storage);
-    execute_update(log_intents_update, storage);
-    execute_update(path_cache_intents_update, storage);
-    execute_update(script_history_update, storage);
-    execute_update(say_intents_update, storage);
-}
-
-/// Execute systems that run regardless of player actions
-fn execute_automated_systems(storage: &mut World) {
-    profile!("execute_automated_systems");
-
-    execute_update(decay_update, storage);
-    execute_update(death_update, storage);
-    execute_update(energy_update, storage);
-    execute_update(update_spawns, storage);
-    execute_update(mineral_update, storage);
-    execute_update(positions_update, storage);
-    execute_update(log_update, storage);
+    crate::events::clear_events(storage);
+    SCHEDULE.run(storage);
 }
 
-#[inline]
-fn execute_update<'a, M, C, Sys>(sys: Sys, storage: &'a mut World)
-where
-    Sys: Fn(M, C) + 'a,
-    M: FromWorldMut + Clone + 'a,
-    C: FromWorld<'a> + 'a,
-{
-    let m = M::from_world_mut(storage);
-    let c = C::from_world(storage as &_);
-    sys(M::clone(&m), c);
+/// Compute the visibility of the users outside of the tick, e.g. on the initial state of the World
+pub fn update_visibility(storage: &mut World) {
+    visibility_update(
+        FromWorldMut::from_world_mut(storage),
+        FromWorld::from_world(storage),
+    );
 }
@@ -1,26 +1,38 @@
 use crate::components::{HpComponent, MeleeAttackComponent};
+use crate::events::{Attacked, Events};
 use crate::indices::*;
 use crate::intents::*;
 use crate::profile;
+use crate::scripting_api::OperationResult;
 use crate::storage::views::{UnsafeView, UnwrapViewMut, View};
 use tracing::{debug, error};
 
 type Mut = (
     UnsafeView<EntityId, HpComponent>,
     UnwrapViewMut<EmptyKey, Intents<MeleeIntent>>,
+    UnwrapViewMut<EmptyKey, Events<Attacked>>,
+    UnsafeView<EntityId, IntentResult<MeleeIntent>>,
 );
 type Const<'a> = (View<'a, EntityId, MeleeAttackComponent>,);
 
-pub fn attack_system_update((mut hp_table, mut intents): Mut, (attack_table,): Const) {
+pub fn attack_system_update(
+    (mut hp_table, mut intents, mut events, mut results): Mut,
+    (attack_table,): Const,
+) {
     profile!("AttackSystem update");
 
+    results.clear();
     pre_process(&mut intents.0);
 
     for intent in intents.iter() {
+        let mut resolve = |result| {
+            results.insert_or_update(intent.attacker, IntentResult::new(result));
+        };
         let attack = match attack_table.get_by_id(intent.attacker) {
             Some(s) => s,
             None => {
                 error!("Attacker has no attack component. {:?}", intent);
+                resolve(OperationResult::OperationFailed);
                 continue;
             }
         };
@@ -28,11 +40,19 @@ pub fn attack_system_update((mut hp_table, mut intents): Mut, (attack_table,): C
             Some(s) => s,
             None => {
                 error!("Defender has no hp component. {:?}", intent);
+                resolve(OperationResult::InvalidTarget);
                 continue;
             }
         };
         // hp can not fall below 0
-        hp.hp -= hp.hp.min(attack.strength);
+        let damage = hp.hp.min(attack.strength);
+        hp.hp -= damage;
+        events.0.push(Attacked {
+            attacker: intent.attacker,
+            defender: intent.defender,
+            damage,
+        });
+        resolve(OperationResult::Ok);
     }
 }
 
@@ -43,7 +63,7 @@ fn pre_process(intents: &mut Vec<MeleeIntent>) {
     }
     // dedupe
     intents.sort_unstable_by_key(|intent| intent.attacker);
-    for current in (0..len).rev() {
+    for current in (0..len - 1).rev() {
         let last = current + 1;
         let a = &intents[last];
         let b = &intents[current];
@@ -53,3 +73,70 @@ fn pre_process(intents: &mut Vec<MeleeIntent>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::World;
+    use crate::storage::views::{FromWorld, FromWorldMut};
+
+    #[test]
+    fn every_attacker_attacks_once() {
+        let mut world = World::new();
+        let defender = world.insert_entity();
+        let attackers = [world.insert_entity(), world.insert_entity()];
+
+        world
+            .unsafe_view::<EntityId, HpComponent>()
+            .insert_or_update(
+                defender,
+                HpComponent {
+                    hp: 100,
+                    hp_max: 100,
+                },
+            );
+        let mut intents = Vec::new();
+        // the first attacker submits its intent twice
+        for attacker in [attackers[0], attackers[1], attackers[0]].iter().copied() {
+            world
+                .unsafe_view::<EntityId, MeleeAttackComponent>()
+                .insert_or_update(attacker, MeleeAttackComponent { strength: 10 });
+            intents.push(BotIntents {
+                entity_id: attacker,
+                melee_attack_intent: Some(MeleeIntent { attacker, defender }),
+                ..Default::default()
+            });
+        }
+        move_into_storage(&mut *world, intents);
+
+        attack_system_update(
+            FromWorldMut::from_world_mut(&mut *world),
+            FromWorld::from_world(&*world),
+        );
+
+        let results = world.view::<EntityId, IntentResult<MeleeIntent>>();
+        for attacker in attackers.iter() {
+            assert_eq!(
+                results.get_by_id(*attacker).map(|r| r.result),
+                Some(OperationResult::Ok)
+            );
+        }
+        assert_eq!(
+            world
+                .view::<EntityId, HpComponent>()
+                .get_by_id(defender)
+                .unwrap()
+                .hp,
+            80
+        );
+        assert_eq!(
+            world
+                .view::<EmptyKey, Events<Attacked>>()
+                .value
+                .as_ref()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use crate::events::{EntityDied, Events};
 use crate::indices::*;
+use crate::lifecycle::DeleteCause;
 use crate::profile;
-use crate::storage::views::{DeferredDeleteEntityView, View};
+use crate::storage::views::{DeferredDeleteEntityView, UnwrapViewMut, View};
 use crate::{
     components::HpComponent,
     intents::{DeleteEntityIntent, Intents},
@@ -9,7 +11,10 @@ use crate::{
 use tracing::{debug, trace};
 
 pub fn death_update(
-    mut delete: DeferredDeleteEntityView,
+    (mut delete, mut events): (
+        DeferredDeleteEntityView,
+        UnwrapViewMut<EmptyKey, Events<EntityDied>>,
+    ),
     (hps, delete_intents): (
         View<EntityId, HpComponent>,
         UnwrapView<EmptyKey, Intents<DeleteEntityIntent>>,
@@ -21,8 +26,9 @@ pub fn death_update(
     hps.iter().for_each(|(id, hp)| {
         if hp.hp == 0 {
             trace!("Entity {:?} has died, deleting", id);
+            events.0.push(EntityDied { id });
             unsafe {
-                delete.delete_entity(id);
+                delete.delete_entity(id, DeleteCause::Died);
             }
         }
     });
@@ -31,7 +37,7 @@ pub fn death_update(
         .0
         .iter()
         .for_each(|DeleteEntityIntent { id }| unsafe {
-            delete.delete_entity(*id);
+            delete.delete_entity(*id, DeleteCause::Intent);
         });
 
     debug!("update death system done");
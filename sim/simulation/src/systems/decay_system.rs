@@ -1,45 +1,24 @@
 use crate::components::{DecayComponent, HpComponent};
-use crate::indices::EntityId;
-use crate::join;
 use crate::profile;
-use crate::storage::views::UnsafeView;
-use crate::tables::JoinIterator;
+use crate::storage::query::Query;
 use tracing::{debug, trace};
 
-pub fn decay_update(
-    (mut hps, mut decays): (
-        UnsafeView<EntityId, HpComponent>,
-        UnsafeView<EntityId, DecayComponent>,
-    ),
-    (): (),
-) {
+pub fn decay_update(decays: Query<(&mut DecayComponent, &mut HpComponent)>, (): ()) {
     profile!("DecaySystem update");
     debug!("update decay system called");
 
-    let decays = decays.iter_mut();
-    let hps = hps.iter_mut();
-    join!([decays, hps]).for_each(
-        |(
-            _id,
-            (
-                DecayComponent {
-                    hp_amount,
-                    interval,
-                    time_remaining,
-                },
-                HpComponent { hp, .. },
-            ),
-        )| match time_remaining {
+    decays
+        .into_iter()
+        .for_each(|(_id, (mut decay, mut hp))| match decay.time_remaining {
             0 => {
-                *hp = (*hp).saturating_sub(*hp_amount);
-                *time_remaining = *interval;
-                trace!("Decayed entity {:?}. Current hp: {}", _id, *hp);
+                hp.hp = hp.hp.saturating_sub(decay.hp_amount);
+                decay.time_remaining = decay.interval;
+                trace!("Decayed entity {:?}. Current hp: {}", _id, hp.hp);
             }
             _ => {
-                *time_remaining -= 1;
+                decay.time_remaining -= 1;
             }
-        },
-    );
+        });
 
     debug!("update decay system done");
 }
@@ -1,25 +1,36 @@
 use crate::components::{CarryComponent, DropoffEventComponent, EnergyComponent};
+use crate::events::{DroppedOff, Events};
 use crate::indices::*;
 use crate::intents::*;
 use crate::profile;
-use crate::storage::views::{UnsafeView, UnwrapView};
+use crate::scripting_api::OperationResult;
+use crate::storage::views::{UnsafeView, UnwrapView, UnwrapViewMut};
 use tracing::{trace, warn};
 
 type Mut = (
     UnsafeView<EntityId, EnergyComponent>,
     UnsafeView<EntityId, CarryComponent>,
     UnsafeView<EntityId, DropoffEventComponent>,
+    UnwrapViewMut<EmptyKey, Events<DroppedOff>>,
+    UnsafeView<EntityId, IntentResult<DropoffIntent>>,
 );
 type Const<'a> = (UnwrapView<'a, EmptyKey, Intents<DropoffIntent>>,);
 
 pub fn dropoff_intents_update(
-    (mut energy_table, mut carry_table, mut events): Mut,
+    (mut energy_table, mut carry_table, mut dropoff_events, mut events, mut results): Mut,
     (intents,): Const,
 ) {
     profile!("DropoffSystem update");
 
-    events.clear();
+    dropoff_events.clear();
+    results.clear();
+
+    // structures that received energy in this tick
+    let mut filled = Vec::with_capacity(intents.len());
     for intent in intents.iter() {
+        let mut resolve = |result| {
+            results.insert_or_update(intent.bot, IntentResult::new(result));
+        };
         let s = tracing::span!(
             tracing::Level::INFO,
             "dropoff system iter",
@@ -32,6 +43,7 @@ pub fn dropoff_intents_update(
             Some(x) => x,
             None => {
                 warn!("Bot has no carry");
+                resolve(OperationResult::OperationFailed);
                 continue;
             }
         };
@@ -39,9 +51,24 @@ pub fn dropoff_intents_update(
             Some(x) => x,
             None => {
                 warn!("Structure has no energy");
+                resolve(OperationResult::InvalidTarget);
                 continue;
             }
         };
+        if carry_component.carry == 0 {
+            trace!("Bot has nothing to drop off");
+            resolve(OperationResult::Empty);
+            continue;
+        }
+        if store_component.energy >= store_component.energy_max {
+            trace!("Structure is full");
+            resolve(if filled.contains(&intent.structure) {
+                OperationResult::Contested
+            } else {
+                OperationResult::Full
+            });
+            continue;
+        }
         let dropoff = intent
             .amount
             .min(carry_component.carry)
@@ -50,6 +77,83 @@ pub fn dropoff_intents_update(
         store_component.energy += dropoff;
         carry_component.carry -= dropoff;
 
-        events.insert_or_update(intent.bot, DropoffEventComponent(intent.structure));
+        dropoff_events.insert_or_update(intent.bot, DropoffEventComponent(intent.structure));
+        events.0.push(DroppedOff {
+            bot: intent.bot,
+            structure: intent.structure,
+            amount: dropoff,
+        });
+        filled.push(intent.structure);
+        resolve(OperationResult::Ok);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::World;
+    use crate::storage::views::{FromWorld, FromWorldMut};
+
+    #[test]
+    fn dropoffs_into_a_structure_filled_in_the_same_tick_are_contested() {
+        let mut world = World::new();
+        let structure = world.insert_entity();
+        let bots = [world.insert_entity(), world.insert_entity()];
+
+        world
+            .unsafe_view::<EntityId, EnergyComponent>()
+            .insert_or_update(
+                structure,
+                EnergyComponent {
+                    energy: 90,
+                    energy_max: 100,
+                },
+            );
+        let mut intents = Vec::new();
+        for bot in bots.iter().copied() {
+            world
+                .unsafe_view::<EntityId, CarryComponent>()
+                .insert_or_update(
+                    bot,
+                    CarryComponent {
+                        carry: 50,
+                        carry_max: 50,
+                    },
+                );
+            intents.push(BotIntents {
+                entity_id: bot,
+                dropoff_intent: Some(DropoffIntent {
+                    bot,
+                    structure,
+                    amount: 50,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+        move_into_storage(&mut *world, intents);
+
+        dropoff_intents_update(
+            FromWorldMut::from_world_mut(&mut *world),
+            FromWorld::from_world(&*world),
+        );
+
+        let results = world.view::<EntityId, IntentResult<DropoffIntent>>();
+        assert_eq!(
+            results.get_by_id(bots[0]).map(|r| r.result),
+            Some(OperationResult::Ok)
+        );
+        assert_eq!(
+            results.get_by_id(bots[1]).map(|r| r.result),
+            Some(OperationResult::Contested)
+        );
+        assert_eq!(
+            world
+                .view::<EntityId, EnergyComponent>()
+                .get_by_id(structure)
+                .unwrap()
+                .energy,
+            100
+        );
     }
 }
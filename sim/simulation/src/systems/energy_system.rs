@@ -1,18 +1,10 @@
 use crate::components::{EnergyComponent, EnergyRegenComponent};
-use crate::indices::EntityId;
-use crate::join;
 use crate::profile;
-use crate::storage::views::{UnsafeView, View};
-use crate::tables::JoinIterator;
+use crate::storage::query::Query;
 
-pub fn energy_update(
-    mut energy: UnsafeView<EntityId, EnergyComponent>,
-    energy_regen: View<EntityId, EnergyRegenComponent>,
-) {
+pub fn energy_update(energy: Query<(&mut EnergyComponent, &EnergyRegenComponent)>, (): ()) {
     profile!("EnergySystem update");
-    let energy_it = energy.iter_mut();
-    let energy_regen_it = energy_regen.iter();
-    join!([energy_it, energy_regen_it]).for_each(|(_id, (e, er))| {
+    energy.into_iter().for_each(|(_id, (mut e, er))| {
         e.energy = (e.energy + er.amount).min(e.energy_max);
     });
 }
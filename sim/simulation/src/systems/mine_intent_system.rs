@@ -1,33 +1,44 @@
 use crate::components::{
-    CarryComponent, EnergyComponent, MineEventComponent, Resource, ResourceComponent,
+    game_config::GameConfig, CarryComponent, EnergyComponent, MineEventComponent, Resource,
+    ResourceComponent,
 };
+use crate::events::{Events, Mined};
 use crate::indices::*;
-use crate::intents::{Intents, MineIntent};
+use crate::intents::{IntentResult, Intents, MineIntent};
 use crate::profile;
-use crate::storage::views::{UnsafeView, UnwrapView, View};
+use crate::scripting_api::OperationResult;
+use crate::storage::views::{UnsafeView, UnwrapView, UnwrapViewMut, View};
 use tracing::{trace, warn};
 
-pub const MINE_AMOUNT: u16 = 10; // TODO: get from bot body
-
 type Mut = (
     UnsafeView<EntityId, EnergyComponent>,
     UnsafeView<EntityId, CarryComponent>,
     UnsafeView<EntityId, MineEventComponent>,
+    UnwrapViewMut<EmptyKey, Events<Mined>>,
+    UnsafeView<EntityId, IntentResult<MineIntent>>,
 );
 type Const<'a> = (
     View<'a, EntityId, ResourceComponent>,
     UnwrapView<'a, EmptyKey, Intents<MineIntent>>,
+    UnwrapView<'a, ConfigKey, GameConfig>,
 );
 
 pub fn mine_intents_update(
-    (mut energy_table, mut carry_table, mut event): Mut,
-    (resource_table, intents): Const,
+    (mut energy_table, mut carry_table, mut event, mut events, mut results): Mut,
+    (resource_table, intents, config): Const,
 ) {
     profile!("MineSystem update");
 
     event.clear();
+    results.clear();
 
+    // resources mined in this tick, to tell apart depleted resources from the ones emptied by
+    // other bots
+    let mut mined_resources = Vec::with_capacity(intents.len());
     for intent in intents.iter() {
+        let mut resolve = |result| {
+            results.insert_or_update(intent.bot, IntentResult::new(result));
+        };
         trace!("Bot {:?} is mining [{:?}]", intent.bot, intent.resource);
         match resource_table.get_by_id(intent.resource) {
             Some(ResourceComponent(Resource::Energy)) => {
@@ -35,12 +46,18 @@ pub fn mine_intents_update(
                     Some(resource_energy) => {
                         if resource_energy.energy == 0 {
                             trace!("Mineral is empty!");
+                            resolve(if mined_resources.contains(&intent.resource) {
+                                OperationResult::Contested
+                            } else {
+                                OperationResult::Empty
+                            });
                             continue;
                         }
                         resource_energy
                     }
                     None => {
                         warn!("MineIntent resource has no energy component!");
+                        resolve(OperationResult::InvalidTarget);
                         continue;
                     }
                 };
@@ -48,17 +65,30 @@ pub fn mine_intents_update(
                     Some(x) => x,
                     None => {
                         warn!("MineIntent bot {:?} has no carry component", intent.bot);
+                        resolve(OperationResult::OperationFailed);
                         continue;
                     }
                 };
+                if carry.carry >= carry.carry_max {
+                    trace!("Bot {:?} is full", intent.bot);
+                    resolve(OperationResult::Full);
+                    continue;
+                }
 
-                let mined = resource_energy.energy.min(MINE_AMOUNT); // Max amount that can be mined
+                let mined = resource_energy.energy.min(config.bot.mine_amount); // Max amount that can be mined
                 let mined = (carry.carry_max - carry.carry).min(mined); // Max amount the bot can carry
 
                 carry.carry += mined;
                 resource_energy.energy -= mined;
 
-                event.insert_or_update(intent.bot, MineEventComponent(intent.resource));
+                event.insert_or_update(intent.bot, MineEventComponent(intent.resource, mined));
+                events.0.push(Mined {
+                    bot: intent.bot,
+                    resource: intent.resource,
+                    amount: mined,
+                });
+                mined_resources.push(intent.resource);
+                resolve(OperationResult::Ok);
 
                 trace!(
                     "Mine succeeded new bot carry {:?} new resource energy {:?}",
@@ -67,7 +97,8 @@ pub fn mine_intents_update(
                 );
             }
             Some(ResourceComponent(Resource::Empty)) | None => {
-                warn!("Resource ({:?}) not found", intent.resource)
+                warn!("Resource ({:?}) not found", intent.resource);
+                resolve(OperationResult::InvalidTarget);
             }
         }
     }
@@ -1,112 +1,125 @@
+use crate::components as comp;
 use crate::indices::{EntityId, WorldPosition};
+use crate::lifecycle::DeleteCause;
 use crate::profile;
-use crate::storage::views::{DeferredDeleteEntityView, UnsafeView, View};
-use crate::tables::JoinIterator;
-use crate::{components as comp, join};
+use crate::storage::query::Query;
+use crate::storage::views::{DeferredDeleteEntityView, PositionsMut, View};
 use crate::{geometry::Axial, terrain::TileTerrainType};
 use rand::Rng;
 use tracing::{debug, error, trace};
 
-type Mut = (
-    UnsafeView<EntityId, comp::PositionComponent>,
-    UnsafeView<EntityId, comp::EnergyComponent>,
-    UnsafeView<EntityId, comp::RespawnTimer>,
+type Mut<'a> = (
+    PositionsMut,
+    Query<
+        'a,
+        (
+            &'a comp::ResourceComponent,
+            &'a mut comp::EnergyComponent,
+            &'a mut comp::RespawnTimer,
+        ),
+    >,
     DeferredDeleteEntityView,
 );
-type Const<'a> = (
-    View<'a, WorldPosition, comp::EntityComponent>,
-    View<'a, WorldPosition, comp::TerrainComponent>,
-    View<'a, EntityId, comp::ResourceComponent>,
-);
+type Const<'a> = (View<'a, WorldPosition, comp::TerrainComponent>,);
 
 pub fn mineral_update(
-    (mut entity_positions, mut energy, mut respawn_timer, mut delete_entity_deferred): Mut,
-    (position_entities, terrain_table, resources): Const,
+    (mut positions, minerals, mut delete_entity_deferred): Mut,
+    (terrain_table,): Const,
 ) {
     profile!("Mineral System update");
     debug!("update minerals system called");
 
     let mut rng = rand::thread_rng();
 
-    let minerals_it = resources
-        .iter()
-        .filter(|(_, r)| matches!(r.0, comp::Resource::Energy));
-    let entity_positions_it = entity_positions.iter_mut();
-    let energy_iter = energy.iter_mut();
-    let respawn_timer = respawn_timer.iter_mut();
-
-    // in case of an error we need to clean up the mineral
-    // however best not to clean it inside the iterator, hmmm???
-    join!([minerals_it, entity_positions_it, energy_iter, respawn_timer]).for_each(
-        |(id, (_resource, position, energy, respawn))| {
-            trace!(
-                "updating {:?} {:?} {:?} {:?} {:?}",
-                id,
-                _resource,
-                position,
-                energy,
-                respawn
-            );
-
-            if energy.energy > 0 {
-                return;
-            }
+    // collect the depleted minerals first, moving them while iterating the positions would
+    // invalidate the iterator
+    let mut respawns = Vec::new();
+    for (id, (resource, mut energy, mut respawn)) in minerals {
+        if !matches!(resource.0, comp::Resource::Energy) {
+            continue;
+        }
+        let position = match positions.positions.get_by_id(id) {
+            Some(position) => position,
+            None => continue,
+        };
+        trace!(
+            "updating {:?} {:?} {:?} {:?} {:?}",
+            id,
+            resource,
+            position,
+            energy,
+            respawn
+        );
+
+        if energy.energy > 0 {
+            continue;
+        }
 
-            respawn.0 -= 1;
-            if respawn.0 > 0 {
-                return;
-            }
+        respawn.0 -= 1;
+        if respawn.0 > 0 {
+            continue;
+        }
 
-            trace!("Respawning mineral {:?}", id);
-
-            respawn.0 = 2;
-
-            let position_entities = position_entities
-                .table
-                .at(position.0.room)
-                .expect("get room entities table");
-            let terrain_table = terrain_table
-                .table
-                .at(position.0.room)
-                .expect("get room terrain table");
-
-            let position_entities = View::from_table(position_entities);
-            let terrain_table = View::from_table(terrain_table);
-
-            // respawning
-            let pos = random_uncontested_pos_in_range(
-                position_entities,
-                terrain_table,
-                &mut rng,
-                position.0.pos,
-                30,
-                2000,
-            );
-            trace!(
-                "Mineral [{:?}] has been depleted, respawning at {:?}",
-                id,
-                pos
-            );
-            match pos {
-                Some(pos) => {
-                    energy.energy = energy.energy_max;
-                    position.0.pos = pos;
-                }
-                None => {
-                    error!("Failed to find adequate position for resource {:?}", id);
-                    unsafe {
-                        delete_entity_deferred.delete_entity(id);
-                    }
-                }
+        trace!("Respawning mineral {:?}", id);
+
+        respawn.0 = 2;
+        // minerals that fail to respawn are deleted at the end of the tick
+        energy.energy = energy.energy_max;
+        respawns.push((id, position.0));
+    }
+
+    for (id, position) in respawns {
+        let position_entities = positions
+            .layered_entities
+            .table
+            .at(position.room)
+            .expect("get room entities table");
+        let terrain = terrain_table
+            .table
+            .at(position.room)
+            .expect("get room terrain table");
+
+        let position_entities = View::from_table(position_entities);
+        let terrain = View::from_table(terrain);
+
+        // respawning
+        let pos = random_uncontested_pos_in_range(
+            position_entities,
+            terrain,
+            &mut rng,
+            position.pos,
+            30,
+            2000,
+        );
+        trace!(
+            "Mineral [{:?}] has been depleted, respawning at {:?}",
+            id,
+            pos
+        );
+        let moved = pos.and_then(|pos| {
+            positions
+                .move_entity(
+                    id,
+                    WorldPosition {
+                        room: position.room,
+                        pos,
+                    },
+                )
+                .ok()
+        });
+        if moved.is_none() {
+            error!("Failed to find adequate position for resource {:?}", id);
+            unsafe {
+                delete_entity_deferred.delete_entity(id, DeleteCause::RespawnFailed);
             }
-        },
-    );
+        }
+    }
 
     debug!("update minerals system done");
 }
 
 fn random_uncontested_pos_in_range(
-    position_entities_table: View<Axial, comp::EntityComponent>,
+    position_entities_table: View<Axial, comp::LayeredEntity>,
     terrain_table: View<Axial, comp::TerrainComponent>,
     rng: &mut rand::rngs::ThreadRng,
     center: Axial,
@@ -142,7 +155,7 @@ fn random_uncontested_pos_in_range(
             .at(pos)
             .map(|comp::TerrainComponent(t)| matches!(t, TileTerrainType::Plain))
             .unwrap_or(false)
-            && position_entities_table.count_in_range(pos, 1) == 0
+            && position_entities_table.count_in_range(pos, 1, None) == 0
         {
             result = Some(pos);
             break;
@@ -1,28 +1,54 @@
-use crate::components::{Bot, EntityComponent, PositionComponent, TerrainComponent};
+use crate::components::{Bot, TerrainComponent};
+use crate::events::{Events, MoveFailed, MoveFailure};
 use crate::indices::{EmptyKey, EntityId, WorldPosition};
-use crate::intents::{Intents, MoveIntent};
+use crate::intents::{IntentResult, Intents, MoveIntent};
 use crate::profile;
-use crate::storage::views::{UnsafeView, UnwrapViewMut, View};
+use crate::scripting_api::OperationResult;
+use crate::storage::views::{PositionsMut, UnsafeView, UnwrapViewMut, View};
 use crate::tables::traits::Table;
 use tracing::trace;
 
 type Mut = (
-    UnsafeView<EntityId, PositionComponent>,
+    PositionsMut,
     UnwrapViewMut<EmptyKey, Intents<MoveIntent>>,
+    UnwrapViewMut<EmptyKey, Events<MoveFailed>>,
+    UnsafeView<EntityId, IntentResult<MoveIntent>>,
 );
 type Const<'a> = (
     View<'a, EntityId, Bot>,
-    View<'a, WorldPosition, EntityComponent>,
     View<'a, WorldPosition, TerrainComponent>,
 );
 
 pub fn move_intents_update(
-    (mut positions, mut intents): Mut,
-    (bots, pos_entities, _terrain): Const,
+    (mut positions, mut intents, mut events, mut results): Mut,
+    (bots, _terrain): Const,
 ) {
     profile!(" MoveSystem update");
 
-    pre_process_move_intents(&mut intents.0);
+    results.clear();
+    let mut resolve = |intent: &MoveIntent, result: Result<(), MoveFailure>| {
+        let result = match result {
+            Ok(()) => OperationResult::Ok,
+            Err(reason) => {
+                events.0.push(MoveFailed {
+                    bot: intent.bot,
+                    position: intent.position,
+                    reason,
+                });
+                match reason {
+                    MoveFailure::NotABot => OperationResult::OperationFailed,
+                    MoveFailure::Occupied => OperationResult::InvalidTarget,
+                    MoveFailure::InvalidPosition => OperationResult::InvalidTarget,
+                    MoveFailure::LostTieBreak => OperationResult::LostTieBreak,
+                }
+            }
+        };
+        results.insert_or_update(intent.bot, IntentResult::new(result));
+    };
+
+    for intent in pre_process_move_intents(&mut intents.0) {
+        resolve(&intent, Err(MoveFailure::LostTieBreak));
+    }
     for intent in intents.iter() {
         trace!("Moving bot[{:?}] to {:?}", intent.bot, intent.position);
 
@@ -34,30 +60,43 @@ pub fn move_intents_update(
 
         if bots.get_by_id(intent.bot).is_none() {
             trace!("Bot by id {:?} does not exist", intent.bot);
+            resolve(intent, Err(MoveFailure::NotABot));
             continue;
         }
 
-        if pos_entities.get_by_id(intent.position).is_some() {
+        // check the live index, so earlier moves in this tick are taken into account
+        if positions
+            .entities_by_pos
+            .get_by_id(intent.position)
+            .is_some()
+        {
             trace!("Occupied {:?} ", intent.position);
+            resolve(intent, Err(MoveFailure::Occupied));
             continue;
         }
 
-        positions.insert_or_update(intent.bot, PositionComponent(intent.position));
+        if let Err(err) = positions.move_entity(intent.bot, intent.position) {
+            trace!("Failed to move bot {:?}: {:?}", intent.bot, err);
+            resolve(intent, Err(MoveFailure::InvalidPosition));
+            continue;
+        }
 
         trace!("Move successful");
+        resolve(intent, Ok(()));
     }
 }
 
-/// Remove duplicate positions.
+/// Remove duplicate positions, returns the removed intents.
 /// We assume that there are no duplicated entities
-fn pre_process_move_intents(move_intents: &mut Vec<MoveIntent>) {
+fn pre_process_move_intents(move_intents: &mut Vec<MoveIntent>) -> Vec<MoveIntent> {
     profile!("pre_process_move_intents");
 
     let len = move_intents.len();
     if len < 2 {
         // 0 and 1 long vectors do not have duplicates
-        return;
+        return Vec::new();
     }
+    let mut removed = Vec::new();
     move_intents.sort_unstable_by_key(|intent| intent.position);
     // move in reverse order because we want to remove invalid intents as we move,
     // swap_remove would change the last position, screwing with the ordering
@@ -67,9 +106,10 @@ fn pre_process_move_intents(move_intents: &mut Vec<MoveIntent>) {
         let b = &move_intents[current];
         if a.position == b.position {
             trace!("Duplicated position in move intents, removing {:?}", a);
-            move_intents.swap_remove(last);
+            removed.push(move_intents.swap_remove(last));
         }
     }
+    removed
 }
 
 #[cfg(test)]
@@ -112,8 +152,90 @@ mod tests {
             },
         ];
 
-        pre_process_move_intents(&mut intents);
+        let removed = pre_process_move_intents(&mut intents);
         assert_eq!(intents.len(), 2);
+        assert_eq!(removed.len(), 2);
+        assert!(removed
+            .iter()
+            .all(|intent| intent.position.pos == Axial::new(42, 69)));
         assert_ne!(intents[0].position, intents[1].position);
     }
+
+    #[test]
+    fn moves_to_occupied_positions_are_invalid() {
+        use crate::components::{EntityComponent, EntityLayer, LayeredEntity};
+        use crate::indices::Room;
+        use crate::intents::{move_into_storage, BotIntents};
+        use crate::prelude::World;
+        use crate::storage::views::{FromWorld, FromWorldMut};
+        use crate::tables::hex_grid::HexGrid;
+        use crate::terrain::TileTerrainType;
+
+        let mut world = World::new();
+        let room = Axial::new(0, 0);
+        let mut terrain = HexGrid::<TerrainComponent>::new(4);
+        for (_, tile) in terrain.iter_mut() {
+            *tile = TerrainComponent(TileTerrainType::Plain);
+        }
+        world
+            .unsafe_view::<WorldPosition, TerrainComponent>()
+            .table
+            .extend(std::iter::once((room, terrain)))
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(Room(room)))
+            .unwrap();
+        world
+            .unsafe_view::<WorldPosition, LayeredEntity>()
+            .extend_rooms(std::iter::once(Room(room)))
+            .unwrap();
+
+        let pos = |q| WorldPosition {
+            room,
+            pos: Axial::new(q, 4),
+        };
+        let blocker = world.insert_entity();
+        let bot = world.insert_entity();
+        let mut positions = PositionsMut::from_world_mut(&mut *world);
+        positions
+            .insert(blocker, pos(3), EntityLayer::Blocking)
+            .unwrap();
+        positions
+            .insert(bot, pos(4), EntityLayer::Blocking)
+            .unwrap();
+        world.unsafe_view::<EntityId, Bot>().insert(bot);
+
+        move_into_storage(
+            &mut *world,
+            vec![BotIntents {
+                entity_id: bot,
+                move_intent: Some(MoveIntent {
+                    bot,
+                    position: pos(3),
+                }),
+                ..Default::default()
+            }],
+        );
+        move_intents_update(
+            FromWorldMut::from_world_mut(&mut *world),
+            FromWorld::from_world(&*world),
+        );
+
+        assert_eq!(
+            world
+                .view::<EntityId, IntentResult<MoveIntent>>()
+                .get_by_id(bot)
+                .map(|r| r.result),
+            Some(OperationResult::InvalidTarget)
+        );
+        let events = world.view::<EmptyKey, Events<MoveFailed>>();
+        let reasons = events
+            .value
+            .iter()
+            .flat_map(|events| events.iter())
+            .map(|e| e.reason)
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, vec![MoveFailure::Occupied]);
+    }
 }
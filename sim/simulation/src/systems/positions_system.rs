@@ -1,27 +1,183 @@
-use crate::components::{EntityComponent, PositionComponent};
+use crate::components::{EntityComponent, EntityLayer, LayeredEntity, PositionComponent};
 use crate::indices::{EntityId, WorldPosition};
 use crate::profile;
-use crate::storage::views::{UnsafeView, View};
-use tracing::{debug, error};
+use crate::storage::views::View;
+use tracing::debug;
 
-type Mut = UnsafeView<WorldPosition, EntityComponent>;
-type Const<'a> = (View<'a, EntityId, PositionComponent>,);
+type Const<'a> = (
+    View<'a, EntityId, PositionComponent>,
+    View<'a, WorldPosition, EntityComponent>,
+    View<'a, WorldPosition, LayeredEntity>,
+);
 
-/// Reset the entity positions table
-pub fn positions_update(mut position_entities: Mut, (entity_positions,): Const) {
+/// The position indices are updated incrementally by `PositionsMut`.
+/// Debug builds panic if they are out of sync with the positions of the entities.
+pub fn positions_update((): (), (entity_positions, position_entities, layered_entities): Const) {
     profile!("PositionSystem update");
     debug!("update positions system called");
 
-    let mut positions = entity_positions
+    #[cfg(debug_assertions)]
+    check_consistency(entity_positions, position_entities, layered_entities);
+    #[cfg(not(debug_assertions))]
+    let _ = (entity_positions, position_entities, layered_entities);
+}
+
+#[cfg(debug_assertions)]
+fn check_consistency(
+    entity_positions: View<EntityId, PositionComponent>,
+    position_entities: View<WorldPosition, EntityComponent>,
+    layered_entities: View<WorldPosition, LayeredEntity>,
+) {
+    use std::collections::HashMap;
+
+    let layers = layered_entities
         .iter()
-        .map(|(id, PositionComponent(pos))| (*pos, EntityComponent(id)))
-        .collect::<Vec<_>>();
-
-    position_entities.clear();
-    position_entities
-        .extend_from_slice(positions.as_mut_slice())
-        .map_err(|e| {
-            error!("Failed to rebuild position_entities table {:?}", e);
-        })
-        .ok();
+        .map(|(pos, LayeredEntity { layer, id })| ((pos, *id), *layer))
+        .collect::<HashMap<_, _>>();
+    let mut blocking = 0;
+    let mut count = 0;
+    for (id, PositionComponent(pos)) in entity_positions.iter() {
+        count += 1;
+        match layers.get(&(*pos, id)) {
+            Some(EntityLayer::Blocking) => {
+                blocking += 1;
+                debug_assert!(
+                    position_entities
+                        .all_at(*pos)
+                        .any(|EntityComponent(e)| *e == id),
+                    "Entity {:?} at {:?} is missing from the blocking layer",
+                    id,
+                    pos
+                );
+            }
+            Some(EntityLayer::Ground) => {}
+            None => {
+                panic!(
+                    "Entity {:?} at {:?} is missing from the position index",
+                    id, pos
+                );
+            }
+        }
+    }
+    let layered_len = layered_entities.iter().count();
+    let blocking_len = position_entities.iter().count();
+    debug_assert!(
+        count == layered_len && blocking == blocking_len,
+        "The position index is out of sync, it holds {} entries ({} blocking) for {} positioned entities ({} blocking)",
+        layered_len,
+        blocking_len,
+        count,
+        blocking
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::storage::DeferredDeleteById;
+
+    #[test]
+    fn moves_keep_the_index_in_sync() {
+        let mut world = World::new();
+        let room = Axial::new(0, 0);
+        world
+            .unsafe_view::<WorldPosition, EntityComponent>()
+            .extend_rooms(std::iter::once(Room(room)))
+            .unwrap();
+
+        let a = world.insert_entity();
+        let b = world.insert_entity();
+        let from = WorldPosition {
+            room,
+            pos: Axial::new(1, 1),
+        };
+        let to = WorldPosition {
+            room,
+            pos: Axial::new(2, 1),
+        };
+
+        let mut positions = PositionsMut::from_world_mut(&mut *world);
+        positions.insert(a, from, EntityLayer::Blocking).unwrap();
+        positions.insert(b, from, EntityLayer::Ground).unwrap();
+        positions.move_entity(a, to).unwrap();
+
+        let blocking = world.view::<WorldPosition, EntityComponent>();
+        assert_eq!(blocking.at(to), Some(&EntityComponent(a)));
+        assert!(blocking.at(from).is_none());
+        assert_eq!(blocking.iter().count(), 1);
+        let layered = world.view::<WorldPosition, LayeredEntity>();
+        assert_eq!(
+            layered.all_at(from).copied().collect::<Vec<_>>(),
+            vec![LayeredEntity {
+                layer: EntityLayer::Ground,
+                id: b
+            }]
+        );
+        assert_eq!(layered.iter().count(), 2);
+
+        world.deferred_delete(b);
+        world.execute_deferred_deletes();
+
+        let layered = world.view::<WorldPosition, LayeredEntity>();
+        assert_eq!(layered.all_at(from).count(), 0);
+        assert_eq!(layered.iter().count(), 1);
+    }
+
+    #[test]
+    fn failed_moves_leave_the_entity_in_place() {
+        let mut world = World::new();
+        let room = Axial::new(0, 0);
+        let a = world.insert_entity();
+        let from = WorldPosition {
+            room,
+            pos: Axial::new(1, 1),
+        };
+        // not representable in the room tables
+        let to = WorldPosition {
+            room,
+            pos: Axial::new(-1, 1),
+        };
+
+        let mut positions = PositionsMut::from_world_mut(&mut *world);
+        positions.insert(a, from, EntityLayer::Blocking).unwrap();
+        assert!(positions.move_entity(a, to).is_err());
+
+        assert_eq!(
+            world.view::<EntityId, PositionComponent>().get_by_id(a),
+            Some(&PositionComponent(from))
+        );
+        let blocking = world.view::<WorldPosition, EntityComponent>();
+        assert_eq!(blocking.at(from), Some(&EntityComponent(a)));
+        assert_eq!(blocking.iter().count(), 1);
+        let layered = world.view::<WorldPosition, LayeredEntity>();
+        assert_eq!(
+            layered.all_at(from).copied().collect::<Vec<_>>(),
+            vec![LayeredEntity {
+                layer: EntityLayer::Blocking,
+                id: a
+            }]
+        );
+        assert_eq!(layered.iter().count(), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn unindexed_positions_panic_in_debug_builds() {
+        use super::positions_update;
+
+        let mut world = World::new();
+        let a = world.insert_entity();
+        world
+            .unsafe_view::<EntityId, PositionComponent>()
+            .insert_or_update(
+                a,
+                PositionComponent(WorldPosition {
+                    room: Axial::new(0, 0),
+                    pos: Axial::new(1, 1),
+                }),
+            );
+
+        positions_update((), FromWorld::from_world(&*world));
+    }
 }
@@ -0,0 +1,362 @@
+//! Executes systems in the order they were added.
+//!
+//! The tables a system reads and writes are derived from the types of its parameters (see
+//! `FromWorld::reads` and `FromWorldMut::writes`). Systems are grouped into stages: a system is
+//! placed into the stage after the last one holding a conflicting system, so systems that touch the
+//! same data still run in their declared order, while the systems of a stage run in parallel.
+//!
+//! Systems whose views alias a table fail to compile (see `NotAliased`). Aliasing accesses that do
+//! not name a table (deleting entities, ...) are rejected when the system is added. Debug builds
+//! also check at runtime that the views built by a system only touch the parts of the World it
+//! declared, see [borrows](crate::storage::borrows).
+//!
+use crate::diagnostics::Diagnostics;
+use crate::indices::EmptyKey;
+use crate::prelude::World;
+use crate::profile;
+use crate::storage::views::{FromWorld, FromWorldMut, NotAliased, WorldAccess};
+use chrono::Duration;
+use rayon::prelude::*;
+use std::ptr::{addr_of, NonNull};
+use tracing::debug;
+
+/// Parts of the World a system accesses
+#[derive(Debug, Clone, Default)]
+pub struct SystemAccess {
+    pub reads: Vec<WorldAccess>,
+    pub writes: Vec<WorldAccess>,
+}
+
+impl SystemAccess {
+    pub fn of<M: FromWorldMut, C: FromWorld<'static>>() -> Self {
+        let mut writes = Vec::new();
+        M::writes(&mut writes);
+        let mut reads = Vec::new();
+        <M as FromWorldMut>::reads(&mut reads);
+        C::reads(&mut reads);
+        Self { reads, writes }
+    }
+
+    /// Iterate over the accesses of `self` that prevent it from running in parallel with `other`
+    pub fn conflicts<'b>(
+        &'b self,
+        other: &'b SystemAccess,
+    ) -> impl Iterator<Item = &'b WorldAccess> + 'b {
+        let write_conflicts = self.writes.iter().filter(move |w| {
+            other
+                .writes
+                .iter()
+                .chain(other.reads.iter())
+                .any(|o| w.conflicts(o))
+        });
+        let read_conflicts = self
+            .reads
+            .iter()
+            .filter(move |r| other.writes.iter().any(|o| r.conflicts(o)));
+        write_conflicts.chain(read_conflicts)
+    }
+}
+
+/// Pointer to the World the systems are executed on.
+///
+/// No reference to the whole World is created from it while the systems run. Each system builds
+/// its views from the pointer (see `FromWorldMut::from_world_mut_ptr`), which borrow only the
+/// tables the system declared.
+#[derive(Clone, Copy)]
+struct WorldPtr(NonNull<World>);
+
+// Safety: the systems of a stage access disjoint parts of the World, see `Scheduler::stage_of`.
+// This holds as long as the views of the systems only touch the parts they report in
+// `FromWorld::reads`, `FromWorldMut::reads` and `FromWorldMut::writes`.
+unsafe impl Send for WorldPtr {}
+unsafe impl Sync for WorldPtr {}
+
+type SystemFn = Box<dyn Fn(WorldPtr) + Send + Sync>;
+
+struct System {
+    name: &'static str,
+    access: SystemAccess,
+    run: SystemFn,
+}
+
+impl System {
+    fn run_timed(&self, world: WorldPtr) -> (&'static str, Duration) {
+        // only the tracker is borrowed, the systems of the stage may point into the World
+        let borrows = unsafe { &*addr_of!((*world.0.as_ptr()).borrows) };
+        // the views built by the system are checked against its declared accesses
+        let _borrow = borrows.borrow(self.name, &self.access.reads, &self.access.writes);
+        let start = chrono::Utc::now();
+        (self.run)(world);
+        let end = chrono::Utc::now();
+        (self.name, end - start)
+    }
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    systems: Vec<System>,
+    /// indices of systems that may run in parallel
+    stages: Vec<Vec<usize>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a system after the already added ones.
+    ///
+    /// The name of the system is the name of `Sys`.
+    ///
+    /// Fails to compile if the parameters of the system borrow a table mutably more than once, or
+    /// both mutably and immutably.
+    ///
+    /// # Panics
+    ///
+    /// If the parameters alias other parts of the World, e.g. deleting entities while reading a
+    /// table
+    pub fn add<M, C, Sys>(&mut self, sys: Sys) -> &mut Self
+    where
+        M: FromWorldMut + 'static,
+        C: FromWorld<'static> + 'static,
+        Sys: Fn(M, C) + Send + Sync + 'static,
+    {
+        let () = NotAliased::<M, C>::CHECK;
+        let name = system_name::<Sys>();
+        let access = SystemAccess::of::<M, C>();
+        if let Some(aliased) = WorldAccess::find_aliased(&access.reads, &access.writes) {
+            panic!(
+                "System {} borrows {:?} mutably and also reads or writes it elsewhere",
+                name, aliased
+            );
+        }
+        let stage = self.stage_of(name, &access);
+
+        let run = move |world: WorldPtr| {
+            // the views are only alive during the call of `sys`
+            let m = unsafe { M::from_world_mut_ptr(world.0) };
+            let c = unsafe { C::from_world_ptr(world.0) };
+            sys(m, c);
+        };
+
+        let id = self.systems.len();
+        self.systems.push(System {
+            name,
+            access,
+            run: Box::new(run),
+        });
+        if stage == self.stages.len() {
+            self.stages.push(Vec::with_capacity(4));
+        }
+        self.stages[stage].push(id);
+        self
+    }
+
+    /// Names of the systems by stages
+    pub fn stages(&self) -> impl Iterator<Item = Vec<&'static str>> + '_ {
+        self.stages
+            .iter()
+            .map(move |stage| stage.iter().map(|i| self.systems[*i].name).collect())
+    }
+
+    /// Run all systems on the `world` and record their execution times in its `Diagnostics`
+    pub fn run(&self, world: &mut World) {
+        profile!("run_systems");
+
+        let ptr = WorldPtr(NonNull::from(&mut *world));
+        let mut timings = Vec::with_capacity(self.systems.len());
+        for stage in self.stages.iter() {
+            match stage.as_slice() {
+                [id] => timings.push(self.systems[*id].run_timed(ptr)),
+                _ => {
+                    let systems = &self.systems;
+                    let stage_timings: Vec<_> = stage
+                        .par_iter()
+                        .map(|id| systems[*id].run_timed(ptr))
+                        .collect();
+                    timings.extend(stage_timings);
+                }
+            }
+        }
+
+        let mut diag = world.unsafe_view::<EmptyKey, Diagnostics>();
+        let diag: &mut Diagnostics = diag.unwrap_mut_or_default();
+        for (name, duration) in timings {
+            diag.update_system(name, duration);
+        }
+    }
+
+    /// Find the first stage `access` can run in
+    fn stage_of(&self, name: &str, access: &SystemAccess) -> usize {
+        let mut result = 0;
+        for (i, stage) in self.stages.iter().enumerate() {
+            for system in stage.iter().map(|id| &self.systems[*id]) {
+                let conflicts: Vec<_> = access
+                    .conflicts(&system.access)
+                    .map(|access| match access {
+                        WorldAccess::Table { name, .. } => *name,
+                        WorldAccess::EntityIds => "entity ids",
+                        WorldAccess::DeferredDeletes => "deferred deletes",
+                        WorldAccess::Exclusive => "the whole world",
+                    })
+                    .collect();
+                if !conflicts.is_empty() {
+                    debug!(
+                        "System {} has to run after {}, because of {:?}",
+                        name, system.name, conflicts
+                    );
+                    result = i + 1;
+                }
+            }
+        }
+        result
+    }
+}
+
+fn system_name<Sys>() -> &'static str {
+    let name = std::any::type_name::<Sys>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{EnergyComponent, HpComponent};
+    use crate::indices::EntityId;
+    use crate::storage::views::{DeleteEntityView, StaticAccesses, UnsafeView, View};
+    use crate::tables::Component;
+
+    fn heal(mut hps: UnsafeView<EntityId, HpComponent>, (): ()) {
+        for (_, mut hp) in hps.iter_mut_tracked() {
+            hp.hp = hp.hp_max;
+        }
+    }
+
+    fn charge(mut energy: UnsafeView<EntityId, EnergyComponent>, (): ()) {
+        for (_, mut e) in energy.iter_mut_tracked() {
+            e.energy = e.energy_max;
+        }
+    }
+
+    fn drain_healthy(
+        mut energy: UnsafeView<EntityId, EnergyComponent>,
+        hps: View<EntityId, HpComponent>,
+    ) {
+        for (id, mut e) in energy.iter_mut_tracked() {
+            if hps
+                .get_by_id(id)
+                .map(|hp| hp.hp == hp.hp_max)
+                .unwrap_or(false)
+            {
+                e.energy = 0;
+            }
+        }
+    }
+
+    #[test]
+    fn conflicting_systems_run_in_the_declared_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add(heal).add(charge).add(drain_healthy);
+
+        let stages: Vec<_> = scheduler.stages().collect();
+        assert_eq!(stages, vec![vec!["heal", "charge"], vec!["drain_healthy"]]);
+
+        let mut world = World::new();
+        let id = world.insert_entity();
+        world
+            .unsafe_view::<EntityId, HpComponent>()
+            .insert_or_update(id, HpComponent { hp: 1, hp_max: 10 });
+        world
+            .unsafe_view::<EntityId, EnergyComponent>()
+            .insert_or_update(
+                id,
+                EnergyComponent {
+                    energy: 1,
+                    energy_max: 10,
+                },
+            );
+
+        scheduler.run(&mut *world);
+
+        let energy = world.view::<EntityId, EnergyComponent>();
+        assert_eq!(energy.get_by_id(id).unwrap().energy, 0);
+    }
+
+    fn purge_dead(mut deletes: DeleteEntityView, hps: View<EntityId, HpComponent>) {
+        for (id, hp) in hps.iter() {
+            if hp.hp == 0 {
+                unsafe { deletes.delete_entity(id) };
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn systems_aliasing_the_world_are_rejected() {
+        // deleting entities writes every table, which only the runtime check knows about
+        Scheduler::new().add(purge_dead);
+    }
+
+    #[test]
+    fn aliased_tables_are_found_at_compile_time() {
+        type Mut = (UnsafeView<EntityId, HpComponent>,);
+        type Const<'a> = (View<'a, EntityId, HpComponent>,);
+
+        let aliased = StaticAccesses::find_aliased(
+            <Const<'static> as FromWorld<'static>>::READS,
+            <Mut as FromWorldMut>::WRITES,
+        );
+        assert_eq!(
+            aliased,
+            Some(<HpComponent as Component<EntityId>>::TABLE_NAME)
+        );
+        let aliased = StaticAccesses::find_aliased(
+            StaticAccesses::NONE,
+            <(Mut, Mut) as FromWorldMut>::WRITES,
+        );
+        assert!(aliased.is_some());
+        let aliased = StaticAccesses::find_aliased(
+            <Const<'static> as FromWorld<'static>>::READS,
+            <(UnsafeView<EntityId, EnergyComponent>,) as FromWorldMut>::WRITES,
+        );
+        assert_eq!(aliased, None);
+    }
+
+    /// Declares no access, but builds a view of the hp table
+    struct Sneaky(UnsafeView<EntityId, HpComponent>);
+
+    impl FromWorldMut for Sneaky {
+        unsafe fn from_world_mut_ptr(w: NonNull<World>) -> Self {
+            Self(UnsafeView::from_world_mut_ptr(w))
+        }
+
+        fn writes(_out: &mut Vec<WorldAccess>) {}
+    }
+
+    fn sneaky(Sneaky(mut hps): Sneaky, (): ()) {
+        for (_, mut hp) in hps.iter_mut_tracked() {
+            hp.hp = 0;
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn views_of_undeclared_tables_are_rejected() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add(sneaky);
+
+        let mut world = World::new();
+        scheduler.run(&mut *world);
+    }
+
+    #[test]
+    fn borrows_are_released_after_the_run() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add(heal).add(charge).add(drain_healthy);
+
+        let mut world = World::new();
+        scheduler.run(&mut *world);
+        assert_eq!(world.borrows.active_borrows(), 0);
+    }
+}
@@ -5,7 +5,8 @@ use crate::{
     intents::*,
     prelude::{EmptyKey, World},
     profile,
-    storage::views::{FromWorld, UnwrapView},
+    scripting_api::OperationResult,
+    storage::views::{FromWorld, UnwrapView, WorldAccess},
 };
 use cao_lang::prelude::*;
 use futures::StreamExt;
@@ -65,6 +66,11 @@ pub async fn execute_scripts(
                 num_scripts_ran: 0,
                 num_scripts_errored: 0,
             };
+            // scripts may read any table, the views built by the scripts of the chunk are checked
+            // against this borrow
+            let _borrow = storage
+                .borrows
+                .borrow("execute_scripts", &[WorldAccess::Exclusive], &[]);
             let data = ScriptExecutionData::unsafe_default();
 
             let conf = UnwrapView::<ConfigKey, GameConfig>::from_world(storage);
@@ -178,6 +184,8 @@ pub struct ScriptExecutionData {
     pub entity_id: EntityId,
     pub user_id: Option<UserId>,
     pub intents: BotIntents,
+    /// Results of the actions taken by the script, in the order they were called
+    pub results: Vec<(&'static str, OperationResult)>,
     storage: *const World,
 }
 
@@ -198,6 +206,7 @@ impl ScriptExecutionData {
             entity_id: Default::default(),
             user_id: None,
             intents: Default::default(),
+            results: Vec::new(),
             storage: std::ptr::null(),
         }
     }
@@ -211,6 +220,7 @@ impl ScriptExecutionData {
         Self {
             storage: storage as *const _,
             intents,
+            results: Vec::new(),
             entity_id,
             user_id,
         }
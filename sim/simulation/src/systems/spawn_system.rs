@@ -10,64 +10,79 @@ mod spawn_intent_system;
 pub use continous_spawn_system::update as update_cont_spawns;
 pub use spawn_intent_system::update as update_spawn_intents;
 
-use crate::indices::{EntityId, UserId};
-use crate::join;
+use crate::events::{Events, Spawned};
+use crate::indices::{ConfigKey, EmptyKey, EntityId, UserId};
 use crate::profile;
-use crate::storage::views::{UnsafeView, View};
-use crate::tables::{JoinIterator, Table};
-use crate::{components::*, entity_archetypes::init_bot};
+use crate::storage::query::Query;
+use crate::storage::views::{UnsafeView, UnwrapView, UnwrapViewMut, View};
+use crate::tables::Table;
+use crate::{components::game_config::GameConfig, components::*, entity_archetypes::init_bot};
 use tracing::{trace, warn};
 
-type SpawnSystemMut = (
-    UnsafeView<EntityId, SpawnComponent>,
-    UnsafeView<EntityId, SpawnQueueComponent>,
-    UnsafeView<EntityId, EnergyComponent>,
+type SpawnSystemMut<'a> = (
+    Query<
+        'a,
+        (
+            &'a mut SpawnComponent,
+            Option<&'a mut EnergyComponent>,
+            Option<&'a mut SpawnQueueComponent>,
+        ),
+    >,
     (
         UnsafeView<EntityId, SpawnBotComponent>,
         UnsafeView<EntityId, Bot>,
         UnsafeView<EntityId, HpComponent>,
         UnsafeView<EntityId, DecayComponent>,
         UnsafeView<EntityId, CarryComponent>,
-        UnsafeView<EntityId, PositionComponent>,
+        PositionsMut,
         UnsafeView<EntityId, OwnedEntity>,
         UnsafeView<EntityId, EntityScript>,
+        UnsafeView<EntityId, VisionComponent>,
     ),
+    UnwrapViewMut<EmptyKey, Events<Spawned>>,
+);
+
+type SpawnSystemConst<'a> = (
+    View<'a, UserId, EntityScript>,
+    UnwrapView<'a, ConfigKey, GameConfig>,
 );
 
 pub fn update_spawns(
-    (mut spawns, mut spawn_queue, mut energy, spawn_views): SpawnSystemMut,
-    user_default_scripts: View<UserId, EntityScript>,
+    (spawns, spawn_views, mut events): SpawnSystemMut,
+    spawn_const: SpawnSystemConst,
 ) {
     profile!("SpawnSystem update");
+    let (_, config) = spawn_const;
 
-    let ss = spawns.iter_mut().filter(|(_, c)| c.spawning.is_none());
-    let en = energy.iter_mut().filter(|(_, e)| e.energy == e.energy_max);
-    let sq = spawn_queue.iter_mut();
-    join!([ss, en, sq]).for_each(|(_spawn_id, (spawn, energy, queue))| {
-        // spawns with 500 energy and no currently spawning bot
-        if let Some(bot) = queue.queue.pop_back() {
-            energy.energy -= 500;
-            spawn.time_to_spawn = 10;
-            spawn.spawning = Some(bot);
+    let mut finished = Vec::new();
+    for (spawn_id, (mut spawn, energy, queue)) in spawns {
+        // spawns with full energy and no currently spawning bot start spawning the next bot
+        if let (None, Some(mut energy), Some(mut queue)) = (spawn.spawning, energy, queue) {
+            if energy.energy == energy.energy_max {
+                if let Some(bot) = queue.queue.pop_back() {
+                    energy.energy = energy.energy.saturating_sub(config.spawn.bot_cost);
+                    spawn.time_to_spawn = config.spawn.spawn_time;
+                    spawn.spawning = Some(bot);
+                }
+            }
         }
-    });
-
-    spawns
-        .iter_mut()
-        .filter(|(_spawn_id, spawn_component)| spawn_component.spawning.is_some())
-        .filter_map(|(spawn_id, spawn_component)| {
-            spawn_component.time_to_spawn -= 1;
-            if spawn_component.time_to_spawn == 0 {
-                let bot = spawn_component.spawning.map(|b| (spawn_id, b));
-                spawn_component.spawning = None;
-                bot
-            } else {
-                None
+        if let Some(bot) = spawn.spawning {
+            spawn.time_to_spawn -= 1;
+            if spawn.time_to_spawn == 0 {
+                spawn.spawning = None;
+                finished.push((spawn_id, bot));
             }
-        })
-        .for_each(|(spawn_id, entity_id)| {
-            spawn_bot(spawn_id, entity_id, spawn_views, user_default_scripts)
-        });
+        }
+    }
+
+    for (spawn_id, entity_id) in finished {
+        if spawn_bot(spawn_id, entity_id, spawn_views, spawn_const) {
+            events.0.push(Spawned {
+                spawn: spawn_id,
+                bot: entity_id,
+            });
+        }
+    }
 }
 
 type SpawnBotMut = (
@@ -76,19 +91,21 @@ type SpawnBotMut = (
     UnsafeView<EntityId, HpComponent>,
     UnsafeView<EntityId, DecayComponent>,
     UnsafeView<EntityId, CarryComponent>,
-    UnsafeView<EntityId, PositionComponent>,
+    PositionsMut,
     UnsafeView<EntityId, OwnedEntity>,
     UnsafeView<EntityId, EntityScript>,
+    UnsafeView<EntityId, VisionComponent>,
 );
 
 /// Spawns a bot from a spawn.
-/// Removes the spawning bot from the spawn and initializes a bot in the world
+/// Removes the spawning bot from the spawn and initializes a bot in the world.
+/// Returns whether the bot was spawned
 fn spawn_bot(
     spawn_id: EntityId,
     entity_id: EntityId,
-    (mut spawn_bots, bots, hps, decay, carry, positions, owned, script_table): SpawnBotMut,
-    user_default_scripts: View<UserId, EntityScript>,
-) {
+    (mut spawn_bots, bots, hps, decay, carry, positions, owned, script_table, vision): SpawnBotMut,
+    spawn_const: SpawnSystemConst,
+) -> bool {
     trace!(
         "spawn_bot spawn_id: {:?} entity_id: {:?}",
         spawn_id,
@@ -99,7 +116,7 @@ fn spawn_bot(
         Some(_) => (),
         None => {
             warn!("Spawning bot {:?} was not found", entity_id);
-            return;
+            return false;
         }
     };
 
@@ -108,6 +125,7 @@ fn spawn_bot(
         .map(|OwnedEntity { owner_id }| owner_id.0);
 
     let pos = positions
+        .positions
         .get_by_id(spawn_id)
         .copied()
         .expect("Spawn should have position")
@@ -117,8 +135,17 @@ fn spawn_bot(
         entity_id,
         owner,
         pos,
-        (bots, hps, decay, carry, positions, owned, script_table),
-        user_default_scripts,
+        (
+            bots,
+            hps,
+            decay,
+            carry,
+            positions,
+            owned,
+            script_table,
+            vision,
+        ),
+        spawn_const,
     );
 
     trace!(
@@ -126,4 +153,5 @@ fn spawn_bot(
         spawn_id,
         entity_id
     );
+    true
 }
@@ -0,0 +1,113 @@
+use crate::components::{
+    LayeredEntity, OwnedEntity, PositionComponent, Visibility, VisionComponent,
+};
+use crate::indices::{EntityId, UserId, WorldPosition};
+use crate::profile;
+use crate::storage::query::Query;
+use crate::storage::views::{UnsafeView, View};
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+
+type Mut<'a> = (
+    UnsafeView<UserId, Visibility>,
+    Query<'a, (&'a VisionComponent, &'a PositionComponent, &'a OwnedEntity)>,
+);
+type Const<'a> = (
+    View<'a, EntityId, OwnedEntity>,
+    View<'a, WorldPosition, LayeredEntity>,
+);
+
+/// Users see their own entities and every entity in the vision range of their entities
+pub fn visibility_update((mut visibility, observers): Mut, (owners, entities_by_pos): Const) {
+    profile!("VisibilitySystem update");
+    debug!("update visibility system called");
+
+    let mut visible = BTreeMap::<UserId, Visibility>::new();
+    for (id, OwnedEntity { owner_id }) in owners.iter() {
+        visible.entry(*owner_id).or_default().0.insert(id);
+    }
+
+    observers.into_iter().for_each(
+        |(_id, (VisionComponent { range }, PositionComponent(pos), OwnedEntity { owner_id }))| {
+            let room = match entities_by_pos.table.at(pos.room) {
+                Some(room) => room,
+                None => {
+                    warn!("Room {:?} is not in the entities table", pos.room);
+                    return;
+                }
+            };
+            let seen = &mut visible.entry(*owner_id).or_default().0;
+            room.query_range(pos.pos, *range as u32, None, &mut |_, entity| {
+                seen.insert(entity.id);
+            });
+        },
+    );
+
+    visibility.clear();
+    for (user_id, seen) in visible {
+        visibility.insert_or_update(user_id, seen);
+    }
+
+    debug!("update visibility system done");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::EntityLayer;
+    use crate::geometry::Axial;
+    use crate::prelude::World;
+    use crate::storage::views::{FromWorld, FromWorldMut, PositionsMut};
+
+    #[test]
+    fn users_see_the_entities_in_range_of_their_entities() {
+        let mut world = World::new();
+        let room = Axial::new(0, 0);
+        world
+            .unsafe_view::<WorldPosition, LayeredEntity>()
+            .extend_rooms(std::iter::once(crate::indices::Room(room)))
+            .unwrap();
+
+        let user = UserId(uuid::Uuid::new_v4());
+        let place = |world: &mut World, q, r, vision: Option<u16>, owner: Option<UserId>| {
+            let id = world.insert_entity();
+            let pos = WorldPosition {
+                room,
+                pos: Axial::new(q, r),
+            };
+            PositionsMut::from_world_mut(world)
+                .insert(id, pos, EntityLayer::Blocking)
+                .unwrap();
+            if let Some(range) = vision {
+                world
+                    .unsafe_view::<EntityId, VisionComponent>()
+                    .insert_or_update(id, VisionComponent { range });
+            }
+            if let Some(owner_id) = owner {
+                world
+                    .unsafe_view::<EntityId, OwnedEntity>()
+                    .insert_or_update(id, OwnedEntity { owner_id });
+            }
+            id
+        };
+
+        let observer = place(&mut world, 10, 10, Some(3), Some(user));
+        let blind = place(&mut world, 30, 30, None, Some(user));
+        let near = place(&mut world, 12, 10, None, None);
+        let far = place(&mut world, 20, 10, None, None);
+        let near_blind = place(&mut world, 31, 30, None, None);
+
+        visibility_update(
+            FromWorldMut::from_world_mut(&mut *world),
+            FromWorld::from_world(&*world),
+        );
+
+        let visibility = world.view::<UserId, Visibility>();
+        let seen = visibility.get_by_id(user).unwrap();
+        assert!(seen.contains(observer));
+        assert!(seen.contains(blind));
+        assert!(seen.contains(near));
+        assert!(!seen.contains(far));
+        assert!(!seen.contains(near_blind));
+    }
+}
@@ -1,8 +1,10 @@
+use super::change_tracking::{ChangeTracker, RowMut};
 use super::*;
 use crate::components::LogEntry;
 use crate::indices::EntityTime;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::ptr::{addr_of_mut, NonNull};
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct BTreeTable<Id, Row>
@@ -11,6 +13,8 @@ where
     Row: TableRow,
 {
     data: BTreeMap<Id, Row>,
+    #[serde(skip)]
+    changes: ChangeTracker<Id>,
 }
 
 impl<Id, Row> BTreeTable<Id, Row>
@@ -21,6 +25,7 @@ where
     pub fn new() -> Self {
         Self {
             data: BTreeMap::new(),
+            changes: Default::default(),
         }
     }
 
@@ -28,8 +33,23 @@ where
         self.data.iter().map(|(id, row)| (*id, row))
     }
 
+    /// Tracked tables count every yielded row as updated, use `iter_mut_tracked` to record only
+    /// the rows that are written.
     pub fn iter_mut(&mut self) -> impl TableIterator<Id, &mut Row> {
-        self.data.iter_mut().map(|(id, row)| (*id, row))
+        let changes = &mut self.changes;
+        self.data.iter_mut().map(move |(id, row)| {
+            changes.updated(*id);
+            (*id, row)
+        })
+    }
+
+    /// Yields [RowMut](RowMut) guards, which mark their row as updated when it is written through
+    /// them. Only worth it on tracked tables, see [change_tracking](super::change_tracking)
+    pub fn iter_mut_tracked(&mut self) -> impl TableIterator<Id, RowMut<'_, Id, Row>> {
+        let changes = NonNull::from(&mut self.changes);
+        self.data
+            .iter_mut()
+            .map(move |(id, row)| (*id, unsafe { RowMut::new(*id, row, changes) }))
     }
 
     pub fn get_by_id(&self, id: Id) -> Option<&Row> {
@@ -37,7 +57,11 @@ where
     }
 
     pub fn get_by_id_mut(&mut self, id: Id) -> Option<&mut Row> {
-        self.data.get_mut(&id)
+        let row = self.data.get_mut(&id);
+        if row.is_some() {
+            self.changes.updated(id);
+        }
+        row
     }
 
     pub fn get_by_ids(&self, ids: &[Id]) -> Vec<(Id, &Row)> {
@@ -53,7 +77,10 @@ where
     }
 
     pub fn insert_or_update(&mut self, id: Id, row: Row) -> bool {
-        self.data.insert(id, row);
+        match self.data.insert(id, row) {
+            Some(_) => self.changes.updated(id),
+            None => self.changes.inserted(id),
+        }
         true
     }
 
@@ -66,6 +93,9 @@ where
     }
 
     pub fn clear(&mut self) {
+        for id in self.data.keys() {
+            self.changes.deleted(*id);
+        }
         self.data.clear();
     }
 }
@@ -79,7 +109,11 @@ where
     type Row = Row;
 
     fn delete(&mut self, id: Id) -> Option<Row> {
-        self.data.remove(&id)
+        let row = self.data.remove(&id);
+        if row.is_some() {
+            self.changes.deleted(id);
+        }
+        row
     }
 
     fn get_by_id(&self, id: Id) -> Option<&Row> {
@@ -87,6 +121,48 @@ where
     }
 }
 
+impl<Id, Row> TrackedTable for BTreeTable<Id, Row>
+where
+    Id: TableId,
+    Row: TableRow,
+{
+    fn change_tracker(&self) -> &ChangeTracker<Id> {
+        &self.changes
+    }
+
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Id> {
+        &mut self.changes
+    }
+}
+
+impl<Id, Row> QueryTable for BTreeTable<Id, Row>
+where
+    Id: TableId,
+    Row: TableRow,
+{
+    fn row_count(&self) -> usize {
+        self.data.len()
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(self.data.keys().copied())
+    }
+}
+
+impl<Id, Row> QueryTableMut for BTreeTable<Id, Row>
+where
+    Id: TableId,
+    Row: TableRow,
+{
+    unsafe fn row_mut<'a>(table: NonNull<Self>, id: Id) -> Option<RowMut<'a, Id, Row>> {
+        let table = table.as_ptr();
+        // the map hands out the value only, the rows already borrowed are left alone
+        let row = (*addr_of_mut!((*table).data)).get_mut(&id)?;
+        let changes = NonNull::new_unchecked(addr_of_mut!((*table).changes));
+        Some(RowMut::new(id, row, changes))
+    }
+}
+
 impl LogTable for BTreeTable<EntityTime, LogEntry> {
     fn get_logs_by_time(&self, time: u64) -> Vec<(EntityTime, LogEntry)> {
         self.data
@@ -0,0 +1,308 @@
+//! Opt-in change tracking of tables.
+//!
+//! Tracked tables record the ids they inserted, updated or deleted during a tick. At the end of
+//! the tick `World::post_process` commits the recorded changes, which are then available via
+//! `ChangesView` until the end of the next tick.
+//!
+//! `iter_mut_tracked` and the `&mut C` terms of typed queries yield [RowMut](RowMut) guards, which
+//! mark their row as updated when it is written through them. Untracked tables are iterated by
+//! `iter_mut`, which yields plain references. Other mutable accesses to a row (`iter_mut`,
+//! `get_by_id_mut`, ...) count as an update, even if the row was not actually changed.
+//!
+use super::TableId;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub enum Change {
+    /// The row did not exist at the start of the tick
+    Inserted,
+    /// The row existed at the start and at the end of the tick
+    Updated,
+    /// The row existed at the start, but not at the end of the tick
+    Deleted,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeSet<Id: TableId> {
+    changes: BTreeMap<Id, Change>,
+}
+
+impl<Id: TableId> Default for ChangeSet<Id> {
+    fn default() -> Self {
+        Self {
+            changes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<Id: TableId> ChangeSet<Id> {
+    pub fn get(&self, id: Id) -> Option<Change> {
+        self.changes.get(&id).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id, Change)> + '_ {
+        self.changes.iter().map(|(id, change)| (*id, *change))
+    }
+
+    pub fn inserted(&self) -> impl Iterator<Item = Id> + '_ {
+        self.ids_by(Change::Inserted)
+    }
+
+    pub fn updated(&self) -> impl Iterator<Item = Id> + '_ {
+        self.ids_by(Change::Updated)
+    }
+
+    pub fn deleted(&self) -> impl Iterator<Item = Id> + '_ {
+        self.ids_by(Change::Deleted)
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    fn ids_by(&self, change: Change) -> impl Iterator<Item = Id> + '_ {
+        self.iter()
+            .filter(move |(_, c)| *c == change)
+            .map(|(id, _)| id)
+    }
+
+    /// Merge a new change of `id` into the set
+    fn record(&mut self, id: Id, change: Change) {
+        use Change::*;
+
+        let merged = match (self.changes.get(&id).copied(), change) {
+            (None, change) => Some(change),
+            // the row did not exist at the start of the tick, so it's still new
+            (Some(Inserted), Inserted) | (Some(Inserted), Updated) => Some(Inserted),
+            // the row was born and died in the same tick
+            (Some(Inserted), Deleted) => None,
+            (Some(Updated), Deleted) | (Some(Deleted), Deleted) => Some(Deleted),
+            (Some(Updated), _) | (Some(Deleted), Inserted) | (Some(Deleted), Updated) => {
+                Some(Updated)
+            }
+        };
+        match merged {
+            Some(change) => {
+                self.changes.insert(id, change);
+            }
+            None => {
+                self.changes.remove(&id);
+            }
+        }
+    }
+}
+
+/// Records the changes of a single table.
+///
+/// Disabled trackers do not record anything.
+#[derive(Debug, Clone)]
+pub struct ChangeTracker<Id: TableId> {
+    enabled: bool,
+    pending: ChangeSet<Id>,
+    committed: ChangeSet<Id>,
+}
+
+impl<Id: TableId> Default for ChangeTracker<Id> {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pending: Default::default(),
+            committed: Default::default(),
+        }
+    }
+}
+
+impl<Id: TableId> ChangeTracker<Id> {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+        self.pending.clear();
+        self.committed.clear();
+    }
+
+    /// Changes of the last committed tick
+    pub fn changes(&self) -> &ChangeSet<Id> {
+        &self.committed
+    }
+
+    /// Changes recorded since the last commit
+    pub fn pending(&self) -> &ChangeSet<Id> {
+        &self.pending
+    }
+
+    /// Publish the pending changes and start recording a new change set
+    pub fn commit(&mut self) {
+        self.committed = std::mem::take(&mut self.pending);
+    }
+
+    #[inline]
+    pub fn inserted(&mut self, id: Id) {
+        if self.enabled {
+            self.pending.record(id, Change::Inserted);
+        }
+    }
+
+    #[inline]
+    pub fn updated(&mut self, id: Id) {
+        if self.enabled {
+            self.pending.record(id, Change::Updated);
+        }
+    }
+
+    #[inline]
+    pub fn deleted(&mut self, id: Id) {
+        if self.enabled {
+            self.pending.record(id, Change::Deleted);
+        }
+    }
+}
+
+/// Mutable reference to a row of a tracked table.
+/// The row is marked as updated the first time it is accessed mutably.
+pub struct RowMut<'a, Id: TableId, Row> {
+    id: Id,
+    row: &'a mut Row,
+    marked: bool,
+    // the guards of a single `iter_mut_tracked` call or query share the tracker, they are not Send so
+    // they are only ever used by a single thread
+    changes: NonNull<ChangeTracker<Id>>,
+    _m: PhantomData<&'a mut ChangeTracker<Id>>,
+}
+
+impl<'a, Id: TableId, Row> RowMut<'a, Id, Row> {
+    /// # Safety
+    ///
+    /// `changes` must be valid for `'a` and may only be accessed through the guards of the same
+    /// iteration for `'a`
+    pub(crate) unsafe fn new(
+        id: Id,
+        row: &'a mut Row,
+        changes: NonNull<ChangeTracker<Id>>,
+    ) -> Self {
+        Self {
+            id,
+            row,
+            marked: false,
+            changes,
+            _m: PhantomData,
+        }
+    }
+}
+
+impl<'a, Id: TableId, Row> Deref for RowMut<'a, Id, Row> {
+    type Target = Row;
+
+    fn deref(&self) -> &Row {
+        self.row
+    }
+}
+
+impl<'a, Id: TableId, Row> DerefMut for RowMut<'a, Id, Row> {
+    fn deref_mut(&mut self) -> &mut Row {
+        if !self.marked {
+            self.marked = true;
+            unsafe { self.changes.as_mut().updated(self.id) };
+        }
+        self.row
+    }
+}
+
+impl<'a, Id: TableId, Row: fmt::Debug> fmt::Debug for RowMut<'a, Id, Row> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.row.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_within_a_tick_are_merged() {
+        let mut tracker = ChangeTracker::<u32>::default();
+        tracker.enable();
+
+        tracker.inserted(1);
+        tracker.updated(1);
+
+        tracker.inserted(2);
+        tracker.deleted(2);
+
+        tracker.updated(3);
+        tracker.deleted(3);
+
+        tracker.deleted(4);
+        tracker.inserted(4);
+
+        let pending = tracker.pending();
+        assert_eq!(pending.get(1), Some(Change::Inserted));
+        assert_eq!(pending.get(2), None);
+        assert_eq!(pending.get(3), Some(Change::Deleted));
+        assert_eq!(pending.get(4), Some(Change::Updated));
+    }
+
+    #[test]
+    fn commit_publishes_the_pending_changes() {
+        let mut tracker = ChangeTracker::<u32>::default();
+        tracker.inserted(1);
+        assert!(
+            tracker.pending().is_empty(),
+            "disabled trackers record nothing"
+        );
+
+        tracker.enable();
+        tracker.inserted(1);
+        assert!(tracker.changes().is_empty());
+
+        tracker.commit();
+        assert_eq!(tracker.changes().inserted().collect::<Vec<_>>(), vec![1]);
+        assert!(tracker.pending().is_empty());
+
+        tracker.commit();
+        assert!(tracker.changes().is_empty());
+    }
+
+    #[test]
+    fn only_rows_written_through_the_guard_are_updated() {
+        use crate::tables::btree_table::BTreeTable;
+        use crate::tables::TrackedTable;
+
+        let mut table = BTreeTable::<u32, i32>::new();
+        table.change_tracker_mut().enable();
+        table.insert_or_update(1, 1);
+        table.insert_or_update(2, 2);
+        table.change_tracker_mut().commit();
+
+        for (id, mut row) in table.iter_mut_tracked() {
+            if id == 2 {
+                *row += 1;
+            } else {
+                assert_eq!(*row, 1);
+            }
+        }
+
+        let pending = table.change_tracker().pending();
+        assert_eq!(pending.get(1), None);
+        assert_eq!(pending.get(2), Some(Change::Updated));
+    }
+}
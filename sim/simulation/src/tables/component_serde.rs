@@ -0,0 +1,49 @@
+//! Serialization of the tables of archetypes.
+//!
+//! Components declare whether their tables are serialized via `#[derive(CaoComponent)]`, the
+//! `archetype!` macro routes the (de)serialization of its fields through the functions of this
+//! module.
+//!
+use super::{Component, Table, TableId};
+use serde::{Deserializer, Serializer};
+
+/// (De)serialization of the table of a component. Implemented by `#[derive(CaoComponent)]`
+pub trait ComponentSerde<Id: TableId>: Component<Id> {
+    /// Transient tables are skipped when serializing and start out empty when deserializing
+    const TRANSIENT: bool;
+
+    fn serialize_table<S: Serializer>(
+        table: &<Self as Component<Id>>::Table,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+
+    fn deserialize_table<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<<Self as Component<Id>>::Table, D::Error>;
+}
+
+pub fn serialize<T, S>(table: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Table,
+    T::Row: ComponentSerde<T::Id, Table = T>,
+    S: Serializer,
+{
+    <T::Row as ComponentSerde<T::Id>>::serialize_table(table, serializer)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Table,
+    T::Row: ComponentSerde<T::Id, Table = T>,
+    D: Deserializer<'de>,
+{
+    <T::Row as ComponentSerde<T::Id>>::deserialize_table(deserializer)
+}
+
+pub fn is_transient<T>(_table: &T) -> bool
+where
+    T: Table,
+    T::Row: ComponentSerde<T::Id, Table = T>,
+{
+    <T::Row as ComponentSerde<T::Id>>::TRANSIENT
+}
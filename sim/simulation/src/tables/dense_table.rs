@@ -4,9 +4,11 @@
 //!
 mod serde_impl;
 
+use super::change_tracking::{ChangeTracker, RowMut};
 use super::*;
 use mem::MaybeUninit;
 use std::mem;
+use std::ptr::{addr_of_mut, NonNull};
 
 #[derive(Default, Debug)]
 pub struct DenseTable<Id, Row>
@@ -21,6 +23,8 @@ where
 
     // stats
     count: usize,
+
+    changes: ChangeTracker<Id>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -64,6 +68,7 @@ where
             offset: 0,
             ids: Vec::with_capacity(size),
             data: Vec::with_capacity(size),
+            changes: Default::default(),
         }
     }
 
@@ -82,6 +87,7 @@ where
             offset,
             ids: vec![None; len],
             data: Vec::with_capacity(len),
+            changes: Default::default(),
         };
         res.data.resize_with(len, MaybeUninit::uninit);
         let mut data = data.into_iter();
@@ -99,6 +105,9 @@ where
             if id < last {
                 return Err(VecTableError::UnsortedValues);
             }
+            if id.as_usize() == last.as_usize() {
+                return Err(VecTableError::DuplicateEntry(id));
+            }
             last = id;
             let i = id.as_usize() - offset;
             res.ids[i] = Some(id);
@@ -115,6 +124,7 @@ where
             offset: 0,
             ids: Vec::with_capacity(size.min(cap)),
             data: Vec::with_capacity(size.min(cap)),
+            changes: Default::default(),
         }
     }
 
@@ -134,13 +144,22 @@ where
             self.ids.resize(i + 1, None);
             self.data.resize_with(i + 1, MaybeUninit::uninit);
         }
-        if self.ids[i].is_some() {
+        if let Some(old_id) = self.ids[i] {
             let _old: Row =
                 unsafe { mem::replace(&mut self.data[i], MaybeUninit::new(row)).assume_init() };
+            if old_id == id {
+                self.changes.updated(id);
+            } else {
+                // the slot is reused by a new generation of the id
+                self.ids[i] = Some(id);
+                self.changes.deleted(old_id);
+                self.changes.inserted(id);
+            }
         } else {
             self.count += 1;
             self.data[i] = MaybeUninit::new(row);
             self.ids[i] = Some(id);
+            self.changes.inserted(id);
         }
         true
     }
@@ -153,7 +172,8 @@ where
         let ind = ind - self.offset;
         self.ids
             .get(ind)
-            .and_then(|id| id.map(|_| unsafe { &*self.data[ind].as_ptr() }))
+            .filter(|stored| **stored == Some(id))
+            .map(|_| unsafe { &*self.data[ind].as_ptr() })
     }
 
     pub fn get_by_id_mut(&mut self, id: Id) -> Option<&mut Row> {
@@ -163,9 +183,14 @@ where
         }
         let ind = ind - self.offset;
         let ptr = self.data.as_mut_ptr();
+        let changes = &mut self.changes;
         self.ids
             .get(ind)
-            .and_then(move |id| id.map(move |_| unsafe { &mut *(*ptr.add(ind)).as_mut_ptr() }))
+            .filter(|stored| **stored == Some(id))
+            .map(move |_| {
+                changes.updated(id);
+                unsafe { &mut *(*ptr.add(ind)).as_mut_ptr() }
+            })
     }
 
     /// This table might have 'gaps' in the storage
@@ -187,33 +212,59 @@ where
             })
     }
 
+    /// Tracked tables count every yielded row as updated, use `iter_mut_tracked` to record only
+    /// the rows that are written.
     pub fn iter_mut(&mut self) -> impl TableIterator<Id, &mut Row> {
         let data = &mut self.data;
+        let changes = &mut self.changes;
         self.ids
             .iter()
             .enumerate()
             .filter_map(|(i, k)| k.map(|id| (i, id)))
             .map(move |(i, id)| {
+                changes.updated(id);
                 let row = unsafe { &mut *data[i].as_mut_ptr() };
                 (id, row)
             })
     }
 
+    /// Yields [RowMut](RowMut) guards, which mark their row as updated when it is written through
+    /// them. Only worth it on tracked tables, see [change_tracking](super::change_tracking)
+    pub fn iter_mut_tracked(&mut self) -> impl TableIterator<Id, RowMut<'_, Id, Row>> {
+        let data = &mut self.data;
+        let changes = NonNull::from(&mut self.changes);
+        self.ids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, k)| k.map(|id| (i, id)))
+            .map(move |(i, id)| {
+                let row = unsafe { &mut *data[i].as_mut_ptr() };
+                (id, unsafe { RowMut::new(id, row, changes) })
+            })
+    }
+
     pub fn contains_id(&self, id: Id) -> bool {
         let i = id.as_usize();
         if i < self.offset {
             return false;
         }
         let i = i - self.offset;
-        // contains if data has this key AND it is Some
-        self.ids.get(i).map(|x| x.is_some()).unwrap_or(false)
+        // contains if data has this key AND it is the same id
+        // ids sharing the index may differ, e.g. in their generation
+        self.ids.get(i).map(|x| *x == Some(id)).unwrap_or(false)
     }
 
     pub fn clear(&mut self) {
-        for (i, _) in self.ids.iter().enumerate().filter(|(_, i)| i.is_some()) {
+        for (i, id) in self
+            .ids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, id)| id.map(|id| (i, id)))
+        {
             // drop set values
             let _val =
                 unsafe { mem::replace(&mut self.data[i], MaybeUninit::uninit()).assume_init() };
+            self.changes.deleted(id);
         }
         self.count = 0;
         self.offset = 0;
@@ -240,6 +291,7 @@ where
         self.ids[ind] = None;
         let res = mem::replace(&mut self.data[ind], MaybeUninit::uninit());
         let res = unsafe { res.assume_init() };
+        self.changes.deleted(id);
         Some(res)
     }
 
@@ -248,6 +300,53 @@ where
     }
 }
 
+impl<Id, Row> TrackedTable for DenseTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    fn change_tracker(&self) -> &ChangeTracker<Id> {
+        &self.changes
+    }
+
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Id> {
+        &mut self.changes
+    }
+}
+
+impl<Id, Row> QueryTable for DenseTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    fn row_count(&self) -> usize {
+        self.count
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(self.ids.iter().filter_map(|id| *id))
+    }
+}
+
+impl<Id, Row> QueryTableMut for DenseTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    unsafe fn row_mut<'a>(table: NonNull<Self>, id: Id) -> Option<RowMut<'a, Id, Row>> {
+        let table = table.as_ptr();
+        if !(*table).contains_id(id) {
+            return None;
+        }
+        let ind = id.as_usize() - (*table).offset;
+        // only the header of `data` is borrowed, the rows already borrowed are left alone
+        let ptr = (*addr_of_mut!((*table).data)).as_mut_ptr();
+        let row = &mut *(*ptr.add(ind)).as_mut_ptr();
+        let changes = NonNull::new_unchecked(addr_of_mut!((*table).changes));
+        Some(RowMut::new(id, row, changes))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::indices::EntityId;
@@ -304,4 +403,28 @@ mod tests {
             assert_eq!(*f, 2);
         }
     }
+
+    #[test]
+    fn stale_generations_do_not_resolve() {
+        let mut table = DenseTable::new();
+        let old = EntityId::new(3, 0);
+        let new = old.next_gen();
+        assert_eq!(old.as_usize(), new.as_usize());
+
+        table.insert_or_update(old, 1);
+        assert_eq!(table.get_by_id(new), None);
+        assert!(!table.contains_id(new));
+        assert!(table.delete(new).is_none());
+
+        table.insert_or_update(new, 2);
+        assert_eq!(table.count(), 1);
+        assert_eq!(table.get_by_id(old), None);
+        assert!(table.get_by_id_mut(old).is_none());
+        assert!(!table.contains_id(old));
+        assert_eq!(table.get_by_id(new), Some(&2));
+        assert_eq!(
+            table.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![new]
+        );
+    }
 }
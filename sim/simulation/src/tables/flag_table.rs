@@ -1,6 +1,7 @@
 use std::mem;
 
-use super::{Table, TableId, TableIterator, TableRow};
+use super::change_tracking::ChangeTracker;
+use super::{QueryTable, Table, TableId, TableIterator, TableRow, TrackedTable};
 
 /// Flag table does not hold Rows. Designed for 0 sized 'flag' components
 #[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -11,6 +12,8 @@ where
 {
     ids: Vec<Id>,
     default: Row,
+    #[serde(skip)]
+    changes: ChangeTracker<Id>,
 }
 
 impl<Id, Row> SparseFlagTable<Id, Row>
@@ -27,6 +30,9 @@ where
     }
 
     pub fn clear(&mut self) {
+        for id in self.ids.iter() {
+            self.changes.deleted(*id);
+        }
         self.ids.clear();
     }
 
@@ -35,6 +41,7 @@ where
             Ok(_) => {}
             Err(i) => {
                 self.ids.insert(i, id);
+                self.changes.inserted(id);
             }
         }
     }
@@ -51,6 +58,7 @@ where
     fn delete(&mut self, id: Self::Id) -> Option<Self::Row> {
         self.ids.binary_search(&id).ok().map(|i| {
             self.ids.remove(i);
+            self.changes.deleted(id);
             mem::take(&mut self.default)
         })
     }
@@ -59,3 +67,31 @@ where
         self.ids.binary_search(&id).map(|_| &self.default).ok()
     }
 }
+
+impl<Id, Row> TrackedTable for SparseFlagTable<Id, Row>
+where
+    Id: TableId,
+    Row: TableRow + Default,
+{
+    fn change_tracker(&self) -> &ChangeTracker<Id> {
+        &self.changes
+    }
+
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Id> {
+        &mut self.changes
+    }
+}
+
+impl<Id, Row> QueryTable for SparseFlagTable<Id, Row>
+where
+    Id: TableId,
+    Row: TableRow + Default,
+{
+    fn row_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(self.ids.iter().copied())
+    }
+}
@@ -2,15 +2,20 @@
 //! Tables are generic collections that store game data split by [shape] components.
 //!
 pub mod btree_table;
+pub mod change_tracking;
+pub mod component_serde;
 pub mod dense_table;
 pub mod flag_table;
 pub mod hex_grid;
 pub mod iterators;
 pub mod morton_hierarchy;
+pub mod morton_multi_table;
 pub mod morton_table;
+pub mod sparse_set_table;
 pub mod traits;
 pub mod unique_table;
 
+pub use self::component_serde::ComponentSerde;
 pub use self::iterators::*;
 pub use self::morton_hierarchy::*;
 pub use self::traits::*;
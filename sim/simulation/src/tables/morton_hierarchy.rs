@@ -1,4 +1,5 @@
 use super::hex_grid::HexGrid;
+use super::morton_multi_table::{Layered, MortonMultiTable};
 use super::morton_table::{MortonKey, MortonTable};
 use super::*;
 use crate::geometry::Axial;
@@ -21,6 +22,7 @@ pub enum ExtendFailure {
 
 pub type MortonMortonTable<T> = RoomMortonTable<MortonTable<T>, T>;
 pub type MortonGridTable<T> = RoomMortonTable<HexGrid<T>, T>;
+pub type MortonMultiMortonTable<T> = RoomMortonTable<MortonMultiTable<T>, T>;
 
 pub trait SpacialStorage<Row: TableRow>:
     Table<Id = Axial, Row = Row> + std::fmt::Debug + 'static + Default
@@ -306,6 +308,65 @@ impl<Row> MortonMortonTable<Row>
 where
     Row: TableRow + Default,
 {
+    /// Delete the rows at `id` that satisfy `pred`, keeping the others.
+    /// Returns the number of rows deleted
+    pub fn delete_if<F>(&mut self, id: WorldPosition, pred: F) -> usize
+    where
+        F: FnMut(&Row) -> bool,
+    {
+        self.table
+            .at_mut(id.room)
+            .map(|room| room.delete_if(id.pos, pred))
+            .unwrap_or(0)
+    }
+
+    /// Every row at the position
+    pub fn all_at(&self, id: WorldPosition) -> impl Iterator<Item = &Row> {
+        self.table
+            .at(id.room)
+            .into_iter()
+            .flat_map(move |room| room.all_at(id.pos))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (WorldPosition, &Row)> {
+        self.iter_rooms().flat_map(|(room_id, room)| {
+            room.iter().map(move |(pos, item)| {
+                (
+                    WorldPosition {
+                        room: room_id.0,
+                        pos,
+                    },
+                    item,
+                )
+            })
+        })
+    }
+}
+
+impl<Row> MortonMultiMortonTable<Row>
+where
+    Row: TableRow + Layered + Default,
+{
+    /// Delete the rows at `id` that satisfy `pred`, keeping the others.
+    /// Returns the number of rows deleted
+    pub fn delete_if<F>(&mut self, id: WorldPosition, pred: F) -> usize
+    where
+        F: FnMut(&Row) -> bool,
+    {
+        self.table
+            .at_mut(id.room)
+            .map(|room| room.delete_if(id.pos, pred))
+            .unwrap_or(0)
+    }
+
+    /// Every row at the position, on any layer
+    pub fn all_at(&self, id: WorldPosition) -> impl Iterator<Item = &Row> {
+        self.table
+            .at(id.room)
+            .into_iter()
+            .flat_map(move |room| room.all_at(id.pos))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (WorldPosition, &Row)> {
         self.iter_rooms().flat_map(|(room_id, room)| {
             room.iter().map(move |(pos, item)| {
@@ -0,0 +1,252 @@
+//! Spatial multi-map, several rows may share a position.
+//! Every row is tagged with the layer it occupies, queries may be restricted to a single layer.
+//!
+use super::morton_table::{ExtendFailure, MortonTable};
+use super::*;
+use crate::geometry::Axial;
+
+/// Rows of a [MortonMultiTable](MortonMultiTable) know which layer they occupy
+pub trait Layered {
+    type Layer: Copy + Eq + std::fmt::Debug;
+
+    fn layer(&self) -> Self::Layer;
+}
+
+#[derive(Debug, Default)]
+pub struct MortonMultiTable<Row>
+where
+    Row: TableRow,
+{
+    table: MortonTable<Row>,
+}
+
+impl<Row> MortonMultiTable<Row>
+where
+    Row: TableRow + Layered,
+{
+    pub fn new() -> Self {
+        Self {
+            table: MortonTable::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Axial, &Row)> {
+        self.table.iter()
+    }
+
+    /// Add the row to the position, keeping the rows already there
+    pub fn insert(&mut self, pos: Axial, row: Row) -> Result<(), ExtendFailure> {
+        self.table.insert(pos, row)
+    }
+
+    pub fn extend<It>(&mut self, it: It) -> Result<(), ExtendFailure>
+    where
+        It: Iterator<Item = (Axial, Row)>,
+    {
+        self.table.extend(it)
+    }
+
+    /// Delete the rows at `pos` that satisfy `pred`, keeping the others.
+    /// Returns the number of rows deleted
+    pub fn delete_if<F>(&mut self, pos: Axial, pred: F) -> usize
+    where
+        F: FnMut(&Row) -> bool,
+    {
+        self.table.delete_if(pos, pred)
+    }
+
+    /// Every row at the position, on any layer
+    pub fn all_at(&self, pos: Axial) -> impl Iterator<Item = &Row> {
+        self.table.all_at(pos)
+    }
+
+    /// The first row at the position on the given layer, if any
+    pub fn at_layer(&self, pos: Axial, layer: Row::Layer) -> Option<&Row> {
+        self.all_at(pos).find(|row| row.layer() == layer)
+    }
+
+    pub fn contains_key(&self, pos: Axial) -> bool {
+        self.table.contains_key(pos)
+    }
+
+    /// Return [min, max) of the bounds of this table
+    pub fn bounds(&self) -> (Axial, Axial) {
+        self.table.bounds()
+    }
+
+    /// Visit the rows in the circle (center, radius) on the given layer, or on every layer if
+    /// `layer` is `None`
+    pub fn query_range<'a, Op>(
+        &'a self,
+        center: Axial,
+        radius: u32,
+        layer: Option<Row::Layer>,
+        op: &mut Op,
+    ) where
+        Op: FnMut(Axial, &'a Row),
+    {
+        self.table
+            .query_range(center, radius, &mut |pos, row: &'a Row| {
+                if on_layer(row, layer) {
+                    op(pos, row);
+                }
+            });
+    }
+
+    /// If any found return the closest one to `center` and the distance to it.
+    pub fn find_closest_by_filter<F>(
+        &self,
+        center: Axial,
+        layer: Option<Row::Layer>,
+        filter: F,
+    ) -> Option<(u32, Axial, &Row)>
+    where
+        F: Fn(Axial, &Row) -> bool,
+    {
+        self.table
+            .find_closest_by_filter(center, |pos, row| on_layer(row, layer) && filter(pos, row))
+    }
+
+    /// Count the rows closer to `center` than `radius` on the given layer, or on every layer if
+    /// `layer` is `None`
+    pub fn count_in_range(&self, center: Axial, radius: u32, layer: Option<Row::Layer>) -> u32 {
+        self.table.count_in_range_if(center, radius, |pos, row| {
+            center.dist(pos) < radius && on_layer(row, layer)
+        })
+    }
+}
+
+#[inline]
+fn on_layer<Row: Layered>(row: &Row, layer: Option<Row::Layer>) -> bool {
+    layer.map(|layer| row.layer() == layer).unwrap_or(true)
+}
+
+impl<Row> Table for MortonMultiTable<Row>
+where
+    Row: TableRow + Layered,
+{
+    type Id = Axial;
+    type Row = Row;
+
+    /// delete all values at id and return the first one, if any
+    fn delete(&mut self, id: Axial) -> Option<Row> {
+        self.table.delete(id)
+    }
+
+    fn get_by_id(&self, id: Axial) -> Option<&Row> {
+        self.table.at(id)
+    }
+}
+
+impl<Row> SpacialStorage<Row> for MortonMultiTable<Row>
+where
+    Row: TableRow + Layered + Default,
+{
+    type ExtendFailure = ExtendFailure;
+    fn clear(&mut self) {
+        MortonMultiTable::clear(self);
+    }
+
+    fn contains_key(&self, pos: Axial) -> bool {
+        MortonMultiTable::contains_key(self, pos)
+    }
+
+    fn insert(&mut self, id: Axial, row: Row) -> Result<(), Self::ExtendFailure> {
+        MortonMultiTable::insert(self, id, row)
+    }
+
+    fn extend<It>(&mut self, it: It) -> Result<(), Self::ExtendFailure>
+    where
+        It: Iterator<Item = (Axial, Row)>,
+    {
+        MortonMultiTable::extend(self, it)
+    }
+
+    fn at(&self, pos: Axial) -> Option<&Row> {
+        self.table.at(pos)
+    }
+    fn at_mut(&mut self, pos: Axial) -> Option<&mut Row> {
+        self.table.at_mut(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    struct Stacked {
+        layer: u8,
+        id: u32,
+    }
+
+    impl Layered for Stacked {
+        type Layer = u8;
+
+        fn layer(&self) -> u8 {
+            self.layer
+        }
+    }
+
+    fn table() -> MortonMultiTable<Stacked> {
+        let mut table = MortonMultiTable::new();
+        let pos = Axial::new(10, 10);
+        table.insert(pos, Stacked { layer: 0, id: 1 }).unwrap();
+        table.insert(pos, Stacked { layer: 1, id: 2 }).unwrap();
+        table.insert(pos, Stacked { layer: 1, id: 3 }).unwrap();
+        table
+            .insert(Axial::new(12, 10), Stacked { layer: 1, id: 4 })
+            .unwrap();
+        table
+            .insert(Axial::new(30, 30), Stacked { layer: 0, id: 5 })
+            .unwrap();
+        table
+    }
+
+    #[test]
+    fn rows_share_the_position() {
+        let mut table = table();
+        let pos = Axial::new(10, 10);
+
+        let mut ids = table.all_at(pos).map(|s| s.id).collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(table.at_layer(pos, 0).map(|s| s.id), Some(1));
+
+        assert_eq!(table.delete_if(pos, |s| s.id == 1), 1);
+        assert!(table.at_layer(pos, 0).is_none());
+        assert_eq!(table.all_at(pos).count(), 2);
+    }
+
+    #[test]
+    fn queries_respect_the_layers() {
+        let table = table();
+        let center = Axial::new(10, 10);
+
+        assert_eq!(table.count_in_range(center, 5, None), 4);
+        assert_eq!(table.count_in_range(center, 5, Some(0)), 1);
+        assert_eq!(table.count_in_range(center, 5, Some(1)), 3);
+
+        let mut seen = vec![];
+        table.query_range(center, 5, Some(1), &mut |_, s| seen.push(s.id));
+        seen.sort_unstable();
+        assert_eq!(seen, vec![2, 3, 4]);
+
+        let (dist, pos, row) = table
+            .find_closest_by_filter(Axial::new(13, 10), Some(0), |_, _| true)
+            .unwrap();
+        assert_eq!((dist, pos, row.id), (3, center, 1));
+    }
+}
@@ -21,10 +21,12 @@ pub use self::morton_key::*;
 pub use self::skiplist::*;
 
 use self::litmax_bigmin::round_down_to_one_less_than_pow_two;
+use super::change_tracking::{ChangeTracker, RowMut};
 use super::*;
 use crate::geometry::Axial;
 use litmax_bigmin::litmax_bigmin;
 use std::convert::{TryFrom, TryInto};
+use std::ptr::NonNull;
 use thiserror::Error;
 
 // at most 15 bits long non-negative integers
@@ -49,6 +51,7 @@ pub struct MortonTable<Row> {
     // SkipList contains the last item of every bucket
     skiplist: SkipList,
     bucket_size: u32,
+    changes: ChangeTracker<Axial>,
 }
 
 impl<Row> std::fmt::Debug for MortonTable<Row>
@@ -72,6 +75,7 @@ where
             skiplist: Default::default(),
             keys: Default::default(),
             values: Default::default(),
+            changes: Default::default(),
         }
     }
 }
@@ -86,6 +90,7 @@ where
             bucket_size: 0,
             keys: vec![],
             values: vec![],
+            changes: Default::default(),
         }
     }
 
@@ -118,6 +123,7 @@ where
             bucket_size: 0,
             values: Vec::with_capacity(cap),
             keys: Vec::with_capacity(cap),
+            changes: Default::default(),
         }
     }
 
@@ -129,8 +135,23 @@ where
         self.keys.len()
     }
 
+    /// Tracked tables count every yielded row as updated, use `iter_mut_tracked` to record only
+    /// the rows that are written.
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (Axial, &mut Row)> {
-        self.values.iter_mut().map(|(p, v)| (*p, v))
+        let changes = &mut self.changes;
+        self.values.iter_mut().map(move |(p, v)| {
+            changes.updated(*p);
+            (*p, v)
+        })
+    }
+
+    /// Yields [RowMut](RowMut) guards, which mark their row as updated when it is written through
+    /// them. Only worth it on tracked tables, see [change_tracking](super::change_tracking)
+    pub fn iter_mut_tracked(&mut self) -> impl Iterator<Item = (Axial, RowMut<'_, Axial, Row>)> {
+        let changes = NonNull::from(&mut self.changes);
+        self.values
+            .iter_mut()
+            .map(move |(p, v)| (*p, unsafe { RowMut::new(*p, v, changes) }))
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (Axial, &Row)> {
@@ -148,6 +169,9 @@ where
     }
 
     pub fn clear(&mut self) {
+        for (id, _) in self.values.iter() {
+            self.changes.deleted(*id);
+        }
         self.keys.clear();
         self.values.clear();
         self.rebuild_skip_list();
@@ -159,6 +183,8 @@ where
         It: Iterator<Item = (Axial, Row)>,
         Row: Default,
     {
+        // the keys already in the table stay sorted while pushing the new ones
+        let old_len = self.keys.len();
         for (id, value) in it {
             if !self.intersects(id) {
                 return Err(ExtendFailure::OutOfBounds(id));
@@ -167,6 +193,13 @@ where
             // if the id is in bounds this transformation is safe
             let [x, y] = [x as u16, y as u16];
             let key = MortonKey::new(x, y);
+            if self.changes.is_enabled() {
+                if self.keys[..old_len].binary_search(&key).is_ok() {
+                    self.changes.updated(id);
+                } else {
+                    self.changes.inserted(id);
+                }
+            }
             self.keys.push(key);
             self.values.push((id, value));
         }
@@ -215,10 +248,16 @@ where
         let [x, y] = id.as_array();
         let [x, y] = [x as u16, y as u16];
 
-        let ind = self
-            .keys
-            .binary_search(&MortonKey::new(x, y))
-            .unwrap_or_else(|i| i);
+        let ind = match self.keys.binary_search(&MortonKey::new(x, y)) {
+            Ok(i) => {
+                self.changes.updated(id);
+                i
+            }
+            Err(i) => {
+                self.changes.inserted(id);
+                i
+            }
+        };
         self.keys.insert(ind, MortonKey::new(x, y));
         self.values.insert(ind, (id, row));
         self.rebuild_skip_list();
@@ -229,6 +268,7 @@ where
     pub fn update(&mut self, id: Axial, row: Row) -> Option<&Row> {
         self.find_key(id)
             .map(move |ind| {
+                self.changes.updated(id);
                 self.values[ind].1 = row;
                 &self.values[ind].1
             })
@@ -242,12 +282,44 @@ where
     {
         self.find_key(id)
             .map(move |ind| {
+                self.changes.updated(id);
                 f(&mut self.values[ind].1);
                 &self.values[ind].1
             })
             .ok()
     }
 
+    /// Delete the rows at `id` that satisfy `pred`, keeping the others.
+    /// Returns the number of rows deleted
+    pub fn delete_if<F>(&mut self, id: Axial, mut pred: F) -> usize
+    where
+        F: FnMut(&Row) -> bool,
+    {
+        let (begin, end) = self.duplicates_range(id);
+        let mut ind = begin;
+        let mut deleted = 0;
+        let mut remaining = 0;
+        for _ in begin..end {
+            if pred(&self.values[ind].1) {
+                self.keys.remove(ind);
+                self.values.remove(ind);
+                deleted += 1;
+            } else {
+                ind += 1;
+                remaining += 1;
+            }
+        }
+        if deleted > 0 {
+            self.rebuild_skip_list();
+            if remaining > 0 {
+                self.changes.updated(id);
+            } else {
+                self.changes.deleted(id);
+            }
+        }
+        deleted
+    }
+
     /// Return a reference to the new Row if it's in the map or None otherwise
     pub fn insert_or_update(&mut self, id: Axial, row: Row) -> Result<(), ExtendFailure> {
         if !self.intersects(id) {
@@ -255,9 +327,11 @@ where
         }
         match self.find_key(id) {
             Ok(ind) => {
+                self.changes.updated(id);
                 self.values[ind].1 = row;
             }
             Err(ind) => {
+                self.changes.inserted(id);
                 let [x, y] = id.as_array();
                 let [x, y] = [x as u16, y as u16];
                 self.keys.insert(ind, MortonKey::new(x, y));
@@ -285,9 +359,37 @@ where
             return None;
         }
 
-        self.find_key(id)
-            .map(move |ind| &mut self.values[ind].1)
-            .ok()
+        let ind = self.find_key(id).ok()?;
+        self.changes.updated(id);
+        Some(&mut self.values[ind].1)
+    }
+
+    /// Returns every item with given id
+    pub fn all_at(&self, id: Axial) -> impl Iterator<Item = &Row> {
+        let (begin, end) = self.duplicates_range(id);
+        self.values[begin..end].iter().map(|(_, row)| row)
+    }
+
+    /// Returns the [begin, end) range of the items with given id
+    fn duplicates_range(&self, id: Axial) -> (usize, usize) {
+        if !self.intersects(id) {
+            return (0, 0);
+        }
+        let ind = match self.find_key(id) {
+            Ok(i) => i,
+            Err(_) => return (0, 0),
+        };
+        let key = self.keys[ind];
+        // `find_key` may return any of the duplicated keys
+        let mut begin = ind;
+        while begin > 0 && self.keys[begin - 1] == key {
+            begin -= 1;
+        }
+        let mut end = ind + 1;
+        while end < self.keys.len() && self.keys[end] == key {
+            end += 1;
+        }
+        (begin, end)
     }
 
     #[inline]
@@ -588,6 +690,7 @@ where
         }
 
         self.rebuild_skip_list();
+        self.changes.deleted(id);
 
         Some(val)
     }
@@ -597,6 +700,19 @@ where
     }
 }
 
+impl<Row> TrackedTable for MortonTable<Row>
+where
+    Row: TableRow,
+{
+    fn change_tracker(&self) -> &ChangeTracker<Axial> {
+        &self.changes
+    }
+
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Axial> {
+        &mut self.changes
+    }
+}
+
 impl<Row> SpacialStorage<Row> for MortonTable<Row>
 where
     Row: TableRow + Default,
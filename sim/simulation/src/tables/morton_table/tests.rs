@@ -332,3 +332,27 @@ fn dedupe_simple() {
     assert_eq!(positions.len(), 128);
     assert_eq!(cnt, 128);
 }
+
+#[test]
+fn delete_if_keeps_the_other_rows_at_the_position() {
+    let mut table = MortonTable::new();
+    let pos = Axial::new(3, 4);
+    for i in 0..4 {
+        table.insert(pos, i).unwrap();
+    }
+    table.insert(Axial::new(3, 5), 1).unwrap();
+
+    assert_eq!(table.delete_if(pos, |v| v % 2 == 1), 2);
+
+    let mut remaining = table
+        .iter()
+        .filter(|(p, _)| *p == pos)
+        .map(|(_, v)| *v)
+        .collect::<Vec<_>>();
+    remaining.sort_unstable();
+    assert_eq!(remaining, vec![0, 2]);
+    assert_eq!(table.at(Axial::new(3, 5)), Some(&1));
+
+    assert_eq!(table.delete_if(pos, |_| true), 2);
+    assert!(!table.contains_key(pos));
+}
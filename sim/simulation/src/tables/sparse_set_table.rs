@@ -0,0 +1,449 @@
+//! Sparse set table. Rows are stored packed, in two parallel arrays of ids and rows, while a sparse
+//! index maps the `as_usize` of ids to their position in the packed arrays.
+//!
+//! Memory is allocated for the rows actually inserted (plus a `u32` per id in the domain), so
+//! this is the better fit for components only a few entities have.
+//! Inserts append to the packed arrays and deletes swap-remove from them, so both are O(1).
+//! Iterators still yield the rows in ascending id order, as `JoinIterator` requires: while the
+//! packed arrays are sorted (ids are allocated in increasing order, so usually they are) they are
+//! walked directly, otherwise `iter` walks a sorted permutation of them and the mutable iterators
+//! sort them in place first.
+//!
+use super::change_tracking::{ChangeTracker, RowMut};
+use super::*;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::ptr::{addr_of_mut, NonNull};
+
+const EMPTY: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub struct SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    /// position of the row in the packed arrays, indexed by the `as_usize` of the ids
+    sparse: Vec<u32>,
+    ids: Vec<Id>,
+    rows: Vec<Row>,
+    /// the packed arrays are in ascending id order
+    sorted: bool,
+
+    changes: ChangeTracker<Id>,
+}
+
+impl<Id, Row> Default for SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Id, Row> SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            sparse: Vec::new(),
+            ids: Vec::with_capacity(cap),
+            rows: Vec::with_capacity(cap),
+            sorted: true,
+            changes: Default::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Position of the id in the packed arrays
+    fn position(&self, id: Id) -> Option<usize> {
+        let pos = *self.sparse.get(id.as_usize())?;
+        // ids sharing the index (e.g. generations of an entity) share the slot
+        if pos == EMPTY || self.ids[pos as usize] != id {
+            return None;
+        }
+        Some(pos as usize)
+    }
+
+    /// Restore the id order of the packed arrays
+    pub fn sort(&mut self) {
+        if self.sorted {
+            return;
+        }
+        let mut packed = self
+            .ids
+            .drain(..)
+            .zip(self.rows.drain(..))
+            .collect::<Vec<_>>();
+        packed.sort_unstable_by_key(|(id, _)| *id);
+        for (pos, (id, row)) in packed.into_iter().enumerate() {
+            self.sparse[id.as_usize()] = pos as u32;
+            self.ids.push(id);
+            self.rows.push(row);
+        }
+        self.sorted = true;
+    }
+
+    /// Positions of the packed rows in ascending id order
+    fn sorted_positions(&self) -> Option<Vec<u32>> {
+        if self.sorted {
+            return None;
+        }
+        let mut positions = (0..self.ids.len() as u32).collect::<Vec<_>>();
+        positions.sort_unstable_by_key(|pos| self.ids[*pos as usize]);
+        Some(positions)
+    }
+
+    pub fn contains_id(&self, id: Id) -> bool {
+        self.position(id).is_some()
+    }
+
+    pub fn get_by_id(&self, id: Id) -> Option<&Row> {
+        self.position(id).map(|pos| &self.rows[pos])
+    }
+
+    pub fn get_by_id_mut(&mut self, id: Id) -> Option<&mut Row> {
+        let pos = self.position(id)?;
+        self.changes.updated(id);
+        Some(&mut self.rows[pos])
+    }
+
+    pub fn insert_or_update(&mut self, id: Id, row: Row) -> bool {
+        let ind = id.as_usize();
+        if ind >= self.sparse.len() {
+            self.sparse.resize(ind + 1, EMPTY);
+        }
+        let pos = self.sparse[ind];
+        if pos != EMPTY {
+            let pos = pos as usize;
+            self.rows[pos] = row;
+            let old_id = self.ids[pos];
+            if old_id == id {
+                self.changes.updated(id);
+            } else {
+                // the slot is reused by a new generation of the id
+                self.ids[pos] = id;
+                self.changes.deleted(old_id);
+                self.changes.inserted(id);
+            }
+            return true;
+        }
+        if self.ids.last().map(|last| *last > id).unwrap_or(false) {
+            self.sorted = false;
+        }
+        self.sparse[ind] = self.ids.len() as u32;
+        self.ids.push(id);
+        self.rows.push(row);
+        self.changes.inserted(id);
+        true
+    }
+
+    /// Allocates a sorted permutation of the rows if they are out of order, call `sort` before
+    /// iterating repeatedly.
+    pub fn iter(&self) -> impl TableIterator<Id, &Row> {
+        let positions = self.sorted_positions();
+        (0..self.ids.len()).map(move |i| {
+            let pos = positions.as_ref().map(|p| p[i] as usize).unwrap_or(i);
+            (self.ids[pos], &self.rows[pos])
+        })
+    }
+
+    /// Tracked tables count every yielded row as updated, use `iter_mut_tracked` to record only
+    /// the rows that are written.
+    pub fn iter_mut(&mut self) -> impl TableIterator<Id, &mut Row> {
+        self.sort();
+        let changes = &mut self.changes;
+        self.ids
+            .iter()
+            .copied()
+            .zip(self.rows.iter_mut())
+            .map(move |(id, row)| {
+                changes.updated(id);
+                (id, row)
+            })
+    }
+
+    /// Yields [RowMut](RowMut) guards, which mark their row as updated when it is written through
+    /// them. Only worth it on tracked tables, see [change_tracking](super::change_tracking)
+    pub fn iter_mut_tracked(&mut self) -> impl TableIterator<Id, RowMut<'_, Id, Row>> {
+        self.sort();
+        let changes = NonNull::from(&mut self.changes);
+        self.ids
+            .iter()
+            .copied()
+            .zip(self.rows.iter_mut())
+            .map(move |(id, row)| (id, unsafe { RowMut::new(id, row, changes) }))
+    }
+
+    /// The packed ids, in ascending order only if the table is sorted
+    pub fn ids(&self) -> &[Id] {
+        self.ids.as_slice()
+    }
+
+    /// The packed rows, in the order of `ids`
+    pub fn rows(&self) -> &[Row] {
+        self.rows.as_slice()
+    }
+
+    /// Fast path of `JoinIterator` when `self` is the smaller table: walk the packed rows and look
+    /// each of them up in `other`, instead of walking both tables.
+    ///
+    /// Yields the rows in ascending id order, like `JoinIterator` does.
+    pub fn join<'a, T>(&'a self, other: &'a T) -> impl TableIterator<Id, (&'a Row, &'a T::Row)> + 'a
+    where
+        T: Table<Id = Id>,
+    {
+        self.iter()
+            .filter_map(move |(id, row)| other.get_by_id(id).map(|r| (id, (row, r))))
+    }
+
+    /// Join two sparse sets by walking the smaller one
+    pub fn join_sparse<'a, Row2>(
+        &'a self,
+        other: &'a SparseSetTable<Id, Row2>,
+    ) -> Box<dyn Iterator<Item = (Id, (&'a Row, &'a Row2))> + 'a>
+    where
+        Row2: TableRow,
+    {
+        if self.len() <= other.len() {
+            Box::new(self.join(other))
+        } else {
+            Box::new(
+                other
+                    .join(self)
+                    .map(|(id, (other_row, row))| (id, (row, other_row))),
+            )
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for id in self.ids.iter() {
+            self.changes.deleted(*id);
+        }
+        self.sparse.clear();
+        self.ids.clear();
+        self.rows.clear();
+        self.sorted = true;
+    }
+}
+
+impl<Id, Row> Table for SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    type Id = Id;
+    type Row = Row;
+
+    fn delete(&mut self, id: Id) -> Option<Row> {
+        let pos = self.position(id)?;
+        self.sparse[id.as_usize()] = EMPTY;
+        self.ids.swap_remove(pos);
+        let row = self.rows.swap_remove(pos);
+        // the last row was moved into the hole
+        if let Some(moved) = self.ids.get(pos) {
+            self.sparse[moved.as_usize()] = pos as u32;
+            self.sorted = false;
+        }
+        self.changes.deleted(id);
+        Some(row)
+    }
+
+    fn get_by_id(&self, id: Id) -> Option<&Row> {
+        SparseSetTable::get_by_id(self, id)
+    }
+}
+
+impl<Id, Row> TrackedTable for SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    fn change_tracker(&self) -> &ChangeTracker<Id> {
+        &self.changes
+    }
+
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Id> {
+        &mut self.changes
+    }
+}
+
+impl<Id, Row> QueryTable for SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    fn row_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(self.iter().map(|(id, _)| id))
+    }
+}
+
+impl<Id, Row> QueryTableMut for SparseSetTable<Id, Row>
+where
+    Id: SerialId,
+    Row: TableRow,
+{
+    unsafe fn row_mut<'a>(table: NonNull<Self>, id: Id) -> Option<RowMut<'a, Id, Row>> {
+        let table = table.as_ptr();
+        let pos = (*table).position(id)?;
+        // only the header of `rows` is borrowed, the rows already borrowed are left alone
+        let row = &mut *(*addr_of_mut!((*table).rows)).as_mut_ptr().add(pos);
+        let changes = NonNull::new_unchecked(addr_of_mut!((*table).changes));
+        Some(RowMut::new(id, row, changes))
+    }
+}
+
+impl<Id, Row> Serialize for SparseSetTable<Id, Row>
+where
+    Id: SerialId + Serialize,
+    Row: TableRow + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("SparseSetTable", 1)?;
+        let data = self.iter().collect::<Vec<_>>();
+        state.serialize_field("data", &data)?;
+        state.end()
+    }
+}
+
+impl<'de, Id, Row> Deserialize<'de> for SparseSetTable<Id, Row>
+where
+    Id: SerialId + Deserialize<'de>,
+    Row: TableRow + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Packed<Id, Row> {
+            data: Vec<(Id, Row)>,
+        }
+
+        let Packed { data } = Packed::deserialize(deserializer)?;
+        let mut res = Self::with_capacity(data.len());
+        for (id, row) in data {
+            res.insert_or_update(id, row);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indices::EntityId;
+    use crate::tables::btree_table::BTreeTable;
+
+    fn rows(table: &SparseSetTable<EntityId, u32>) -> Vec<u32> {
+        table.iter().map(|(_, row)| *row).collect()
+    }
+
+    #[test]
+    fn rows_are_iterated_in_id_order() {
+        let mut table = SparseSetTable::new();
+        for i in [8u32, 2, 5, 1000, 3].iter() {
+            table.insert_or_update(EntityId::new(*i, 0), *i);
+        }
+        assert_eq!(table.len(), 5);
+        assert_eq!(rows(&table), vec![2, 3, 5, 8, 1000]);
+        assert_eq!(table.rows(), &[8, 2, 5, 1000, 3], "inserts append");
+
+        assert_eq!(table.delete(EntityId::new(2, 0)), Some(2));
+        assert_eq!(table.delete(EntityId::new(2, 0)), None);
+        assert_eq!(table.rows(), &[8, 3, 5, 1000], "deletes swap-remove");
+        assert_eq!(rows(&table), vec![3, 5, 8, 1000]);
+        for i in [3u32, 5, 8, 1000].iter() {
+            assert_eq!(table.get_by_id(EntityId::new(*i, 0)), Some(i));
+        }
+
+        table.sort();
+        assert_eq!(table.rows(), &[3, 5, 8, 1000]);
+        for i in [3u32, 5, 8, 1000].iter() {
+            assert_eq!(table.get_by_id(EntityId::new(*i, 0)), Some(i));
+        }
+        let ids = table
+            .iter_mut()
+            .map(|(id, _)| id.index())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 5, 8, 1000]);
+
+        // stale generations do not resolve
+        let reused = EntityId::new(8, 1);
+        assert!(!table.contains_id(reused));
+        table.insert_or_update(reused, 42);
+        assert!(!table.contains_id(EntityId::new(8, 0)));
+        assert_eq!(table.get_by_id(reused), Some(&42));
+        assert_eq!(table.len(), 4);
+    }
+
+    #[test]
+    fn join_fast_path_matches_join_iterator() {
+        let mut sparse = SparseSetTable::new();
+        let mut other = SparseSetTable::new();
+        let mut bt = BTreeTable::new();
+        for i in (0..64).step_by(3).rev() {
+            sparse.insert_or_update(EntityId::new(i, 0), i);
+        }
+        for i in (0..64).step_by(2) {
+            bt.insert_or_update(EntityId::new(i, 0), i * 2);
+            other.insert_or_update(EntityId::new(i, 0), i * 2);
+        }
+
+        let expected = JoinIterator::new(sparse.iter(), bt.iter())
+            .map(|(id, (a, b))| (id, *a, *b))
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+
+        let actual = sparse
+            .join(&bt)
+            .map(|(id, (a, b))| (id, *a, *b))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+
+        let actual = sparse
+            .join_sparse(&other)
+            .map(|(id, (a, b))| (id, *a, *b))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut table = SparseSetTable::new();
+        for i in [7u32, 1, 4].iter() {
+            table.insert_or_update(EntityId::new(*i, 0), *i as f32);
+        }
+
+        let s = serde_json::to_string(&table).unwrap();
+        let res: SparseSetTable<EntityId, f32> = serde_json::from_str(s.as_str()).unwrap();
+
+        assert_eq!(
+            res.iter().collect::<Vec<_>>(),
+            table.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(res.get_by_id(EntityId::new(4, 0)), Some(&4.0));
+    }
+}
@@ -1,6 +1,8 @@
+use super::change_tracking::{ChangeTracker, RowMut};
 use crate::components;
 use crate::indices::EntityTime;
 use serde::Serialize;
+use std::ptr::NonNull;
 
 /// TableIds may be used as indices of tables
 pub trait TableId:
@@ -12,6 +14,9 @@ pub trait TableId:
 /// Intended to be used in tables that reserve memory for N items where N is the largest Id
 /// inserted.
 /// e.g. inserting ids 0, 3, 4, 10 will reserve memory for 11 ([0..10]) items.
+///
+/// Distinct ids may share their `as_usize` index (e.g. generations of an entity id), such ids
+/// occupy the same slot and only the one inserted last resolves.
 pub trait SerialId: TableId {
     /// Return the next Id in the domain after `self`.
     fn next(&self) -> Self;
@@ -40,6 +45,10 @@ impl<T: 'static + std::fmt::Debug> TableRow for T {}
 /// store them.
 pub trait Component<Id: TableId>: TableRow {
     type Table: Table<Row = Self> + std::fmt::Debug + Default;
+
+    /// Identifies the table of the component, unique per component and key. Views are checked
+    /// for aliasing tables by it at compile time, see `storage::views::NotAliased`
+    const TABLE_NAME: &'static str;
 }
 
 pub trait Table {
@@ -57,6 +66,36 @@ pub trait Table {
     }
 }
 
+/// Tables that can record the changes made to them. See [change_tracking](super::change_tracking)
+pub trait TrackedTable: Table {
+    fn change_tracker(&self) -> &ChangeTracker<Self::Id>;
+    fn change_tracker_mut(&mut self) -> &mut ChangeTracker<Self::Id>;
+}
+
+/// Tables that typed queries may walk. See [query](crate::storage::query)
+pub trait QueryTable: Table {
+    /// Number of rows in the table, used to pick the table driving a query
+    fn row_count(&self) -> usize;
+    /// Ids of the rows, in ascending order
+    fn ids(&self) -> Box<dyn Iterator<Item = Self::Id> + '_>;
+}
+
+/// Tables whose rows typed queries may borrow mutably
+pub trait QueryTableMut: QueryTable {
+    /// Borrow the row of `id` without borrowing the rest of the table, so the rows of distinct ids
+    /// may be borrowed at the same time. The row is marked updated when it is written through the
+    /// guard.
+    ///
+    /// # Safety
+    ///
+    /// `table` must be valid for `'a`, the row of `id` may not be borrowed elsewhere and the table
+    /// may not be modified by other means for `'a`
+    unsafe fn row_mut<'a>(
+        table: NonNull<Self>,
+        id: Self::Id,
+    ) -> Option<RowMut<'a, Self::Id, Self::Row>>;
+}
+
 pub trait LogTable {
     fn get_logs_by_time(&self, time: u64) -> Vec<(EntityTime, components::LogEntry)>;
 }
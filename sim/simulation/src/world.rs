@@ -1,32 +1,42 @@
+use crate::archetype;
 use crate::components::*;
 use crate::diagnostics::Diagnostics;
+use crate::events::*;
 use crate::indices::*;
 use crate::intents::*;
+use crate::lifecycle::{self, DeleteCause, DeletedEntities, EntityDeletion, EntityDeletions};
 use crate::storage::{
     self,
-    views::{UnsafeView, View},
+    borrows::{BorrowKind, BorrowTracker},
+    query::{Query, QueryTerm},
+    views::{ChangesView, FromWorld, UnsafeView, View, WorldAccess},
 };
 use crate::tables::btree_table::BTreeTable;
 use crate::tables::dense_table::DenseTable;
-use crate::tables::flag_table::SparseFlagTable;
 use crate::tables::morton_hierarchy::ExtendFailure;
-use crate::tables::morton_hierarchy::MortonGridTable;
-use crate::tables::morton_hierarchy::MortonMortonTable;
-use crate::tables::morton_table::MortonTable;
-use crate::tables::unique_table::UniqueTable;
 use crate::tables::Component;
+use crate::tables::Table;
 use crate::tables::TableId;
+use crate::tables::TrackedTable;
 use crate::Time;
-use crate::{archetype, tables::hex_grid::HexGrid};
 use crate::{components::game_config::GameConfig, prelude::Axial};
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::pin::Pin;
+use std::ptr::{addr_of_mut, NonNull};
+
+// Components declare their keys, tables and serde behaviour with `#[derive(CaoComponent)]`, the
+// lists below only name their fields in the stores. A derive sees only the item it is attached to,
+// so the stores can not be assembled from the components alone: adding a component still takes a
+// line here.
+// Tables marked with `cao_storage_track` record their changes, see `tables::change_tracking`
 
 archetype!(
     module pos2_store key Axial,
-    table RoomConnections : MortonTable<RoomConnections> = room_connections,
-    table RoomComponent : MortonTable<RoomComponent> = rooms,
-    table OwnedEntity : MortonTable<OwnedEntity> = owner
+    component RoomConnections = room_connections,
+    component RoomComponent = rooms,
+    component BiomeComponent = biomes,
+    attr cao_storage_track component OwnedEntity = owner
 
     iterby rooms
 );
@@ -34,28 +44,33 @@ archetype!(
 archetype!(
     module entity_store key EntityId,
 
-    table Bot : SparseFlagTable<EntityId, Bot>  = bot,
-    table PositionComponent : DenseTable<EntityId, PositionComponent> = pos,
-    table SpawnBotComponent : DenseTable<EntityId, SpawnBotComponent> = spawnbot,
-    table CarryComponent : DenseTable<EntityId, CarryComponent> = carry,
-    table Structure : SparseFlagTable<EntityId, Structure> = structure,
-    table HpComponent : DenseTable<EntityId, HpComponent> = hp,
-    table EnergyRegenComponent : DenseTable<EntityId, EnergyRegenComponent> = energyregen,
-    table EnergyComponent : DenseTable<EntityId, EnergyComponent> = energy,
-    table ResourceComponent : BTreeTable<EntityId, ResourceComponent> = resource,
-    table DecayComponent : DenseTable<EntityId, DecayComponent> = decay,
-    table EntityScript : DenseTable<EntityId, EntityScript> = script,
-    table SpawnComponent : DenseTable<EntityId, SpawnComponent> = spawn,
-    table SpawnQueueComponent : DenseTable<EntityId, SpawnQueueComponent> = spawnqueue,
-    table OwnedEntity : DenseTable<EntityId, OwnedEntity> = owner,
-    table MeleeAttackComponent : DenseTable<EntityId, MeleeAttackComponent> = melee,
-    table SayComponent : DenseTable<EntityId, SayComponent> = say,
-    table MineEventComponent : BTreeTable<EntityId, MineEventComponent> = mine_intents,
-    table DropoffEventComponent : BTreeTable<EntityId, DropoffEventComponent> = dropoff_intents,
-    table RespawnTimer : BTreeTable<EntityId, RespawnTimer> = respawn_timer,
-
-    attr serde(skip) table PathCacheComponent : DenseTable<EntityId,PathCacheComponent>= pathcache,
-    attr serde(skip) table ScriptHistory : DenseTable<EntityId,ScriptHistory>= script_history
+    attr cao_storage_track component Bot = bot,
+    attr cao_storage_track component PositionComponent = pos,
+    component SpawnBotComponent = spawnbot,
+    attr cao_storage_track component CarryComponent = carry,
+    attr cao_storage_track component Structure = structure,
+    attr cao_storage_track component HpComponent = hp,
+    component EnergyRegenComponent = energyregen,
+    component VisionComponent = vision,
+    attr cao_storage_track component EnergyComponent = energy,
+    attr cao_storage_track component ResourceComponent = resource,
+    component DecayComponent = decay,
+    component EntityScript = script,
+    component SpawnComponent = spawn,
+    component SpawnQueueComponent = spawnqueue,
+    attr cao_storage_track component OwnedEntity = owner,
+    component MeleeAttackComponent = melee,
+    component SayComponent = say,
+    component MineEventComponent = mine_intents,
+    component DropoffEventComponent = dropoff_intents,
+    component RespawnTimer = respawn_timer,
+    component IntentResult<MoveIntent> = move_results,
+    component IntentResult<MeleeIntent> = melee_results,
+    component IntentResult<MineIntent> = mine_results,
+    component IntentResult<DropoffIntent> = dropoff_results,
+
+    component PathCacheComponent = pathcache,
+    component ScriptHistory = script_history
 
     iterby bot
     iterby structure
@@ -65,10 +80,11 @@ archetype!(
 archetype!(
     module user_store key UserId,
 
-    table UserComponent : SparseFlagTable<UserId, UserComponent> = user,
-    table EntityScript: BTreeTable<UserId, EntityScript> = user_default_script,
-    table Rooms : BTreeTable<UserId, Rooms>= user_rooms,
-    table UserProperties : BTreeTable<UserId, UserProperties> = user_props
+    attr cao_storage_track component UserComponent = user,
+    component EntityScript = user_default_script,
+    attr cao_storage_track component Rooms = user_rooms,
+    component UserProperties = user_props,
+    component Visibility = visibility
 
     iterby user
 );
@@ -76,50 +92,56 @@ archetype!(
 archetype!(
     module resource_store key EmptyKey,
 
-    table Time : UniqueTable<EmptyKey, Time> = time,
-    table Intents<MoveIntent> : UniqueTable<EmptyKey, Intents<MoveIntent>> = move_intents,
-    table Intents<SpawnIntent> : UniqueTable<EmptyKey, Intents<SpawnIntent>> = spawn_intents,
-    table Intents<MineIntent> : UniqueTable<EmptyKey, Intents<MineIntent>> = mine_intents,
-    table Intents<DropoffIntent> : UniqueTable<EmptyKey, Intents<DropoffIntent>> = dropoff_intents,
-    table Intents<LogIntent> : UniqueTable<EmptyKey, Intents<LogIntent>> = log_intents,
-    table Intents<CachePathIntent> : UniqueTable<EmptyKey, Intents<CachePathIntent>> = update_path_cache_intents,
-    table Intents<MutPathCacheIntent> : UniqueTable<EmptyKey, Intents<MutPathCacheIntent>> = mut_path_cache_intents,
-    table Intents<MeleeIntent> : UniqueTable<EmptyKey, Intents<MeleeIntent>> = melee_intents,
-    table Intents<ScriptHistoryEntry> : UniqueTable<EmptyKey, Intents<ScriptHistoryEntry>> = script_history_intents,
-    table Intents<DeleteEntityIntent> : UniqueTable<EmptyKey, Intents<DeleteEntityIntent>> = delete_entity_intents,
-    table Intents<SayIntent> : UniqueTable<EmptyKey, Intents<SayIntent>> = say_intents,
-
-    table Diagnostics : UniqueTable<EmptyKey, Diagnostics> = diagnostics
+    component Time = time,
+    component Intents<MoveIntent> = move_intents,
+    component Intents<SpawnIntent> = spawn_intents,
+    component Intents<MineIntent> = mine_intents,
+    component Intents<DropoffIntent> = dropoff_intents,
+    component Intents<LogIntent> = log_intents,
+    component Intents<CachePathIntent> = update_path_cache_intents,
+    component Intents<MutPathCacheIntent> = mut_path_cache_intents,
+    component Intents<MeleeIntent> = melee_intents,
+    component Intents<ScriptHistoryEntry> = script_history_intents,
+    component Intents<DeleteEntityIntent> = delete_entity_intents,
+    component Intents<SayIntent> = say_intents,
+
+    component Events<EntityDied> = entity_died_events,
+    component Events<Attacked> = attacked_events,
+    component Events<Mined> = mined_events,
+    component Events<DroppedOff> = dropped_off_events,
+    component Events<Spawned> = spawned_events,
+    component Events<MoveFailed> = move_failed_events,
+
+    component Diagnostics = diagnostics,
+    component EntityDeletions = entity_deletions
 );
 
 archetype!(
     module config_store key ConfigKey,
 
-    table RoomProperties : UniqueTable<ConfigKey, RoomProperties> = room_properties,
-    table GameConfig : UniqueTable<ConfigKey, GameConfig>= game_config
+    component RoomProperties = room_properties,
+    component GameConfig = game_config
 );
 
 archetype!(
     module positions_store key WorldPosition,
     // don't forget to implement these in `reset_world_storage`
-    table TerrainComponent : MortonGridTable<TerrainComponent> = point_terrain,
-    attr serde(skip) table EntityComponent : MortonMortonTable<EntityComponent> = point_entity
+    component TerrainComponent = point_terrain,
+    component EntityComponent = point_entity,
+    component LayeredEntity = point_layered_entity
 );
 
 archetype!(
     module script_store key ScriptId,
-    table CompiledScriptComponent : BTreeTable<ScriptId, CompiledScriptComponent> = compiled_script,
-    table CaoIrComponent : BTreeTable<ScriptId, CaoIrComponent> = cao_ir
+    component CompiledScriptComponent = compiled_script,
+    component CaoIrComponent = cao_ir
 );
 
 impl<Id: TableId> Component<Id> for LogEntry {
     type Table = BTreeTable<Id, Self>;
-}
-impl Component<Axial> for TerrainComponent {
-    type Table = HexGrid<Self>;
-}
-impl Component<Axial> for EntityComponent {
-    type Table = MortonTable<Self>;
+
+    // shared by the keys, which only makes the aliasing check of views stricter
+    const TABLE_NAME: &'static str = "LogEntry";
 }
 
 #[derive(Debug, Serialize)]
@@ -135,9 +157,18 @@ pub struct World {
 
     #[serde(skip)]
     pub deferred_deletes: entity_store::DeferredDeletes,
+    /// Why the entities marked for deletion are deleted
+    #[serde(skip)]
+    delete_causes: BTreeMap<EntityId, DeleteCause>,
 
     pub next_entity: EntityId,
     pub free_entity_list: Vec<EntityId>,
+    /// The current generation of every allocated entity
+    pub live_entities: DenseTable<EntityId, ()>,
+
+    /// Parts of the World borrowed by the running systems and scripts
+    #[serde(skip)]
+    pub borrows: BorrowTracker,
 }
 
 macro_rules! impl_hastable {
@@ -153,6 +184,13 @@ macro_rules! impl_hastable {
             fn unsafe_view(&mut self) -> UnsafeView<$module::Key, C> {
                 self.$field.unsafe_view()
             }
+
+            unsafe fn table_ptr(this: NonNull<Self>) -> NonNull<C::Table> {
+                let archetype = addr_of_mut!((*this.as_ptr()).$field);
+                <$module::Archetype as storage::HasTable<$module::Key, C>>::table_ptr(
+                    NonNull::new_unchecked(archetype),
+                )
+            }
         }
     };
 }
@@ -173,6 +211,12 @@ impl storage::HasTable<EntityTime, LogEntry> for World {
     fn unsafe_view(&mut self) -> UnsafeView<EntityTime, LogEntry> {
         UnsafeView::from_table(&mut self.entity_logs)
     }
+
+    unsafe fn table_ptr(
+        this: NonNull<Self>,
+    ) -> NonNull<<LogEntry as Component<EntityTime>>::Table> {
+        NonNull::new_unchecked(addr_of_mut!((*this.as_ptr()).entity_logs))
+    }
 }
 
 impl World {
@@ -191,15 +235,21 @@ impl World {
             scripts: Default::default(),
             positions: Default::default(),
             deferred_deletes: Default::default(),
+            delete_causes: Default::default(),
             next_entity: EntityId::default(),
             free_entity_list: Default::default(),
+            live_entities: Default::default(),
+            borrows: Default::default(),
 
             user: Default::default(),
         });
 
+        res.enable_change_tracking();
+
         // initialize the intent tables
         let botints = crate::intents::BotIntents::default();
         crate::intents::move_into_storage(&mut *res, vec![botints]);
+        crate::events::clear_events(&mut *res);
         res
     }
 
@@ -207,6 +257,8 @@ impl World {
     where
         Self: storage::HasTable<Id, C>,
     {
+        self.borrows
+            .record(WorldAccess::table::<Id, C>(), BorrowKind::Read);
         <Self as storage::HasTable<Id, C>>::view(self)
     }
 
@@ -214,9 +266,24 @@ impl World {
     where
         Self: storage::HasTable<Id, C>,
     {
+        self.borrows
+            .record(WorldAccess::table::<Id, C>(), BorrowKind::Write);
         <Self as storage::HasTable<Id, C>>::unsafe_view(self)
     }
 
+    /// Typed query over the entity tables, see [query](crate::storage::query)
+    pub fn query<'a, Q: QueryTerm<'a>>(&'a mut self) -> Query<'a, Q> {
+        Query::new(self)
+    }
+
+    pub fn view_changes<Id: TableId, C: Component<Id>>(&self) -> ChangesView<Id, C>
+    where
+        Self: storage::HasTable<Id, C>,
+        C::Table: TrackedTable<Id = Id>,
+    {
+        ChangesView::from_world(self)
+    }
+
     pub fn time(&self) -> u64 {
         let view = &self.resources.time.value;
         view.map(|Time(t)| t).unwrap_or(0)
@@ -224,11 +291,16 @@ impl World {
 
     /// Perform post-tick cleanup on the storage
     pub fn post_process(&mut self) {
-        for e in self.deferred_deletes.entityid.iter().copied() {
-            self.free_entity_list.push(e);
-        }
-        self.deferred_deletes.execute_all(&mut self.entities);
-        self.deferred_deletes.clear();
+        let now = self.time();
+        self.resources
+            .entity_deletions
+            .value
+            .get_or_insert_with(Default::default)
+            .0
+            .retain(|deletion| deletion.time >= now);
+
+        self.execute_deferred_deletes();
+        self.commit_changes();
 
         self.resources.time.value = self
             .resources
@@ -238,19 +310,165 @@ impl World {
             .or(Some(Time(1)));
     }
 
-    pub fn insert_entity(&mut self) -> EntityId {
-        use crate::tables::SerialId;
+    fn enable_change_tracking(&mut self) {
+        self.entities.enable_change_tracking();
+        self.room.enable_change_tracking();
+        self.user.enable_change_tracking();
+        self.config.enable_change_tracking();
+        self.resources.enable_change_tracking();
+        self.scripts.enable_change_tracking();
+        self.positions.enable_change_tracking();
+    }
 
-        if let Some(entity_id) = self.free_entity_list.pop() {
-            return entity_id;
+    /// Publish the changes recorded by the tracked tables since the last commit.
+    /// The changes are available via `ChangesView` until the next commit.
+    pub fn commit_changes(&mut self) {
+        self.entities.commit_changes();
+        self.room.commit_changes();
+        self.user.commit_changes();
+        self.config.commit_changes();
+        self.resources.commit_changes();
+        self.scripts.commit_changes();
+        self.positions.commit_changes();
+    }
+
+    /// Mark the entity for deletion, the first cause given for an entity is recorded
+    pub fn deferred_delete_with_cause(&mut self, id: EntityId, cause: DeleteCause) {
+        unsafe { Self::deferred_delete_raw(NonNull::from(self), id, cause) }
+    }
+
+    /// `deferred_delete_with_cause` through a pointer to the World, only the deferred deletes are
+    /// borrowed
+    ///
+    /// # Safety
+    ///
+    /// `world` must point to a live World and no other references to its deferred deletes may be
+    /// alive
+    pub(crate) unsafe fn deferred_delete_raw(
+        world: NonNull<World>,
+        id: EntityId,
+        cause: DeleteCause,
+    ) {
+        use crate::storage::DeferredDeleteById;
+
+        let world = world.as_ptr();
+        (*addr_of_mut!((*world).deferred_deletes)).deferred_delete(id);
+        (*addr_of_mut!((*world).delete_causes))
+            .entry(id)
+            .or_insert(cause);
+    }
+
+    /// Delete the given entities right away, for changes made outside of the tick, e.g. by
+    /// commands.
+    ///
+    /// The deletes deferred by other callers are left for the `post_process` of the tick. The
+    /// given entities are dropped from them, so their ids can not be freed twice.
+    pub fn delete_entities(&mut self, ids: impl IntoIterator<Item = EntityId>, cause: DeleteCause) {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let mut pending = std::mem::take(&mut self.deferred_deletes);
+        let mut pending_causes = std::mem::take(&mut self.delete_causes);
+        pending.entityid.retain(|id| !ids.contains(id));
+        pending_causes.retain(|id, _| !ids.contains(id));
+
+        for id in ids {
+            self.deferred_delete_with_cause(id, cause);
+        }
+        self.execute_deferred_deletes();
+
+        self.deferred_deletes = pending;
+        self.delete_causes = pending_causes;
+    }
+
+    /// Delete the entities marked for deletion and free their ids.
+    ///
+    /// Runs the on-delete hooks of the components referencing the deleted entities and records an
+    /// `EntityDeletion` event for each of them, see [lifecycle](crate::lifecycle)
+    pub fn execute_deferred_deletes(&mut self) {
+        let mut deleted = Vec::with_capacity(self.deferred_deletes.entityid.len());
+        let mut positions = Vec::with_capacity(deleted.capacity());
+        for e in self.deferred_deletes.entityid.iter().copied() {
+            // stale ids and repeated deletes must not free the index again
+            if self.live_entities.delete(e).is_none() {
+                continue;
+            }
+            self.free_entity_list.push(e);
+            deleted.push(e);
+            // keep the position index in sync
+            if let Some(PositionComponent(pos)) = self.entities.pos.get_by_id(e) {
+                positions.push(*pos);
+                self.positions
+                    .point_entity
+                    .delete_if(*pos, |EntityComponent(id)| *id == e);
+                self.positions
+                    .point_layered_entity
+                    .delete_if(*pos, |LayeredEntity { id, .. }| *id == e);
+            }
         }
+        self.deferred_deletes.execute_all(&mut self.entities);
+        self.deferred_deletes.clear();
+        let mut causes = std::mem::take(&mut self.delete_causes);
+
+        let time = self.time();
+        let events = deleted
+            .iter()
+            .map(|id| EntityDeletion {
+                id: *id,
+                cause: causes.remove(id).unwrap_or_default(),
+                time,
+                seen_by: self
+                    .user
+                    .visibility
+                    .iter()
+                    .filter(|(_, visibility)| visibility.contains(*id))
+                    .map(|(user_id, _)| user_id)
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        lifecycle::run_delete_hooks(self, &DeletedEntities::new(deleted, positions));
+
+        self.resources
+            .entity_deletions
+            .value
+            .get_or_insert_with(Default::default)
+            .0
+            .extend(events);
+    }
+
+    pub fn insert_entity(&mut self) -> EntityId {
+        unsafe { Self::insert_entity_raw(NonNull::from(self)) }
+    }
 
-        // if no freed id is available then allocate a new entity
-        let res = self.next_entity;
-        self.next_entity = self.next_entity.next();
+    /// `insert_entity` through a pointer to the World, only the entity id allocator is borrowed
+    ///
+    /// # Safety
+    ///
+    /// `world` must point to a live World and no other references to its entity id allocator may
+    /// be alive
+    pub(crate) unsafe fn insert_entity_raw(world: NonNull<World>) -> EntityId {
+        use crate::tables::SerialId;
+
+        let world = world.as_ptr();
+        let next_entity = &mut *addr_of_mut!((*world).next_entity);
+        let res = match (*addr_of_mut!((*world).free_entity_list)).pop() {
+            // bump the generation so references to the deleted entity do not resolve
+            Some(entity_id) => entity_id.next_gen(),
+            // if no freed id is available then allocate a new entity
+            None => {
+                let res = *next_entity;
+                *next_entity = next_entity.next();
+                res
+            }
+        };
+        (*addr_of_mut!((*world).live_entities)).insert_or_update(res, ());
         res
     }
 
+    /// Returns whether the id refers to an entity that has not been deleted
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.live_entities.contains_id(id)
+    }
+
     pub fn queen_tag(&self) -> Option<&str> {
         self.config
             .game_config
@@ -278,6 +496,7 @@ impl World {
 
         clear_table!(TerrainComponent);
         clear_table!(EntityComponent);
+        clear_table!(LayeredEntity);
 
         Ok(self)
     }
@@ -293,6 +512,7 @@ where
 
     fn clear_defers(&mut self) {
         self.deferred_deletes.clear_defers();
+        self.delete_causes.clear();
     }
 
     fn execute<Store: storage::DeleteById<EntityId>>(&mut self, store: &mut Store) {
@@ -350,4 +570,120 @@ mod tests {
         let structures: Vec<_> = world.entities.iterby_structure().collect();
         serde_json::to_string_pretty(&structures).unwrap();
     }
+
+    #[test]
+    fn delete_entities_leaves_the_deferred_deletes_of_the_tick() {
+        let mut world = World::new();
+        let a = world.insert_entity();
+        let b = world.insert_entity();
+        world.entities.bot.insert(a);
+        world.entities.bot.insert(b);
+
+        world.deferred_delete_with_cause(a, DeleteCause::Intent);
+        world.delete_entities(std::iter::once(b), DeleteCause::Admin);
+
+        assert!(world.entities.bot.contains_id(&a));
+        assert!(!world.entities.bot.contains_id(&b));
+
+        world.post_process();
+        assert!(!world.entities.bot.contains_id(&a));
+        let mut causes = world
+            .resources
+            .entity_deletions
+            .value
+            .as_ref()
+            .unwrap()
+            .0
+            .iter()
+            .map(|e| (e.id, e.cause))
+            .collect::<Vec<_>>();
+        causes.sort_by_key(|(id, _)| *id);
+        assert_eq!(
+            causes,
+            vec![(a, DeleteCause::Intent), (b, DeleteCause::Admin)]
+        );
+    }
+
+    #[test]
+    fn components_returns_every_component_of_the_entity() {
+        let mut world = World::new();
+        let bot = world.insert_entity();
+        world.entities.bot.insert(bot);
+        world
+            .entities
+            .hp
+            .insert_or_update(bot, HpComponent { hp: 3, hp_max: 10 });
+
+        let components = world.entities.components(bot);
+        assert!(!components.is_empty());
+        let value = serde_json::to_value(&components).unwrap();
+        assert_eq!(value["hp"]["hp"], 3);
+        assert!(value.get("bot").is_some());
+        assert!(value.get("energy").is_none());
+
+        let empty = world.insert_entity();
+        assert!(world.entities.components(empty).is_empty());
+    }
+
+    #[test]
+    fn transient_components_are_not_serialized() {
+        let mut world = World::new();
+        let bot = world.insert_entity();
+        world
+            .entities
+            .hp
+            .insert_or_update(bot, HpComponent { hp: 3, hp_max: 10 });
+        world
+            .entities
+            .pathcache
+            .insert_or_update(bot, PathCacheComponent::default());
+
+        let value = serde_json::to_value(&world.entities).unwrap();
+        assert!(value.get("hp").is_some());
+        assert!(value.get("pathcache").is_none());
+
+        let entities: entity_store::Archetype = serde_json::from_value(value).unwrap();
+        assert_eq!(entities.hp.get_by_id(bot).map(|hp| hp.hp), Some(3));
+        assert!(entities.pathcache.get_by_id(bot).is_none());
+    }
+
+    #[test]
+    fn post_process_commits_the_changes_of_the_tick() {
+        use crate::storage::DeferredDeleteById;
+        use crate::tables::change_tracking::Change;
+
+        let mut world = World::new();
+
+        let bot = world.insert_entity();
+        let dead = world.insert_entity();
+        world.entities.bot.insert(bot);
+        world.entities.bot.insert(dead);
+        world
+            .entities
+            .hp
+            .insert_or_update(bot, HpComponent { hp: 10, hp_max: 10 });
+        world.post_process();
+
+        let changes = world.view_changes::<EntityId, Bot>();
+        assert_eq!(changes.get(bot), Some(Change::Inserted));
+        assert_eq!(changes.get(dead), Some(Change::Inserted));
+
+        world.entities.hp.get_by_id_mut(bot).unwrap().hp -= 1;
+        world.deferred_delete(dead);
+        world.post_process();
+
+        let changes = world.view_changes::<EntityId, Bot>();
+        assert_eq!(changes.get(bot), None);
+        assert_eq!(changes.get(dead), Some(Change::Deleted));
+        let changes = world.view_changes::<EntityId, HpComponent>();
+        assert_eq!(changes.updated().collect::<Vec<_>>(), vec![bot]);
+
+        // untracked tables record nothing
+        world
+            .entities
+            .melee
+            .insert_or_update(bot, MeleeAttackComponent { strength: 1 });
+        world.post_process();
+        assert!(world.entities.melee.change_tracker().changes().is_empty());
+    }
 }
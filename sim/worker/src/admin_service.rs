@@ -0,0 +1,137 @@
+use crate::audit::{AuditEntry, AuditLog};
+use crate::auth::Auth;
+use crate::input::admin::{self, AdminError, Change};
+use crate::protos::{cao_admin, cao_common};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+#[derive(Clone)]
+pub struct AdminService {
+    world: Arc<tokio::sync::Mutex<crate::World>>,
+    auth: Auth,
+    audit: Arc<AuditLog>,
+}
+
+impl std::fmt::Debug for AdminService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminService").finish()
+    }
+}
+
+impl AdminService {
+    pub fn new(
+        world: Arc<tokio::sync::Mutex<crate::World>>,
+        auth: Auth,
+        audit: Arc<AuditLog>,
+    ) -> Self {
+        Self { world, auth, audit }
+    }
+
+    /// Run the mutation and record it in the audit trail under the name of `operator`
+    async fn mutate<F>(
+        &self,
+        operator: String,
+        action: &'static str,
+        audit: Option<&cao_admin::AuditInfo>,
+        mutation: F,
+    ) -> Result<Response<cao_admin::CommandResult>, Status>
+    where
+        F: FnOnce(&mut caolo_sim::prelude::World) -> Result<Change, AdminError>,
+    {
+        let audit = audit.ok_or_else(|| Status::invalid_argument("Missing audit info"))?;
+        let mut w = self.world.lock().await;
+        let change = mutation(&mut *w).map_err(to_status)?;
+        self.audit.record(&AuditEntry {
+            timestamp: chrono::Utc::now(),
+            tick: w.time(),
+            operator,
+            comment: audit.comment.clone(),
+            reason: audit.reason.clone(),
+            action,
+            entity_id: change.entity_id.0,
+            before: change.before,
+            after: change.after,
+        });
+        Ok(Response::new(cao_admin::CommandResult {}))
+    }
+}
+
+fn to_status(err: AdminError) -> Status {
+    match err {
+        AdminError::EntityNotFound(_) => Status::not_found(err.to_string()),
+        _ => Status::invalid_argument(err.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl cao_admin::admin_server::Admin for AdminService {
+    #[tracing::instrument]
+    async fn get_entity(
+        &self,
+        request: Request<cao_admin::EntityId>,
+    ) -> Result<Response<cao_admin::EntityComponents>, Status> {
+        self.auth.authorize_admin(request.metadata())?;
+        let id = admin::parse_entity_id(request.get_ref().id).map_err(to_status)?;
+        let components = {
+            let w = self.world.lock().await;
+            admin::get_entity(&*w, id).map_err(to_status)?
+        };
+        Ok(Response::new(cao_admin::EntityComponents {
+            components: Some(cao_common::Json {
+                value: serde_json::to_vec(&components).unwrap(),
+            }),
+        }))
+    }
+
+    #[tracing::instrument]
+    async fn set_component(
+        &self,
+        request: Request<cao_admin::SetComponentCommand>,
+    ) -> Result<Response<cao_admin::CommandResult>, Status> {
+        let operator = self.auth.authorize_admin(request.metadata())?;
+        let msg = request.get_ref();
+        self.mutate(operator, "setComponent", msg.audit.as_ref(), |w| {
+            admin::set_component(w, msg)
+        })
+        .await
+    }
+
+    #[tracing::instrument]
+    async fn remove_component(
+        &self,
+        request: Request<cao_admin::RemoveComponentCommand>,
+    ) -> Result<Response<cao_admin::CommandResult>, Status> {
+        let operator = self.auth.authorize_admin(request.metadata())?;
+        let msg = request.get_ref();
+        self.mutate(operator, "removeComponent", msg.audit.as_ref(), |w| {
+            admin::remove_component(w, msg)
+        })
+        .await
+    }
+
+    #[tracing::instrument]
+    async fn teleport(
+        &self,
+        request: Request<cao_admin::TeleportCommand>,
+    ) -> Result<Response<cao_admin::CommandResult>, Status> {
+        let operator = self.auth.authorize_admin(request.metadata())?;
+        let msg = request.get_ref();
+        self.mutate(operator, "teleport", msg.audit.as_ref(), |w| {
+            admin::teleport(w, msg)
+        })
+        .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_entity(
+        &self,
+        request: Request<cao_admin::DeleteEntityCommand>,
+    ) -> Result<Response<cao_admin::CommandResult>, Status> {
+        let operator = self.auth.authorize_admin(request.metadata())?;
+        let msg = request.get_ref();
+        self.mutate(operator, "deleteEntity", msg.audit.as_ref(), |w| {
+            admin::delete_entity(w, msg)
+        })
+        .await
+    }
+}
@@ -0,0 +1,95 @@
+//! Audit trail of the administrative mutations.
+//!
+//! Every entry is logged under the `audit` target and, if `Config::audit_log` is set, appended to
+//! that file as a line of JSON.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// World time at the time of the mutation
+    pub tick: u64,
+    /// Operator named by the token of the caller
+    pub operator: String,
+    /// Free text supplied by the caller
+    pub comment: String,
+    pub reason: String,
+    pub action: &'static str,
+    pub entity_id: u64,
+    /// Components of the entity before the mutation
+    pub before: Value,
+    /// Components of the entity after the mutation, null if it was deleted
+    pub after: Value,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    pub fn open(path: Option<&Path>) -> std::io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self { file })
+    }
+
+    pub fn record(&self, entry: &AuditEntry) {
+        let line = serde_json::to_string(entry).expect("Failed to serialize audit entry");
+        info!(target: "audit", "{}", line);
+        if let Some(file) = self.file.as_ref() {
+            let mut file = file.lock().unwrap();
+            if let Err(err) = writeln!(file, "{}", line) {
+                error!("Failed to write the audit log: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_appended_as_json_lines() {
+        let path = std::env::temp_dir().join(format!("cao-audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = AuditLog::open(Some(&path)).unwrap();
+        for action in ["teleport", "deleteEntity"].iter() {
+            log.record(&AuditEntry {
+                timestamp: Utc::now(),
+                tick: 42,
+                operator: "support".to_owned(),
+                comment: String::new(),
+                reason: "stuck bot".to_owned(),
+                action,
+                entity_id: 1,
+                before: Value::Null,
+                after: Value::Null,
+            });
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["action"], "deleteEntity");
+        assert_eq!(lines[0]["operator"], "support");
+    }
+}
@@ -0,0 +1,370 @@
+//! Authentication of gRPC callers.
+//!
+//! Callers send a token signed with the secret shared by the worker and the trusted services in
+//! the `authorization` metadata:
+//!
+//! ```text
+//! authorization: Bearer <subject>.<expires>.<signature>
+//! ```
+//!
+//! - `subject` is either the hyphenated UUID of a user, `service` or `service:<operator>`
+//! - `expires` is the expiration time of the token in seconds since the Unix epoch
+//! - `signature` is the hex encoded HMAC-SHA256 of `<subject>.<expires>`
+//!
+//! User tokens may only act on behalf of their own user. Service tokens, meant for the API, may
+//! act on behalf of any user. The administrative endpoints additionally require the token to name
+//! the operator calling them, who is recorded in the audit trail. Operator names may not contain
+//! `.`.
+//!
+//! If no secret is configured every call is treated as if it came from a service, except for the
+//! administrative endpoints, which are disabled without a secret.
+use crate::protos::cao_common;
+use caolo_sim::indices::UserId;
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tonic::{metadata::MetadataMap, Status};
+use tracing::debug;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SERVICE_SUBJECT: &str = "service";
+const OPERATOR_PREFIX: &str = "service:";
+
+/// Shared secret, redacted when printed
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Caller {
+    Service,
+    User(UserId),
+}
+
+/// Verified contents of a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Claims {
+    caller: Caller,
+    /// Operator named by a service token
+    operator: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuthError {
+    #[error("Missing authorization token")]
+    MissingToken,
+    #[error("Malformed authorization token")]
+    Malformed,
+    #[error("Invalid token signature")]
+    BadSignature,
+    #[error("Token has expired")]
+    Expired,
+}
+
+impl From<AuthError> for Status {
+    fn from(err: AuthError) -> Self {
+        Status::unauthenticated(err.to_string())
+    }
+}
+
+#[derive(Clone)]
+pub struct Auth {
+    secret: Option<Secret>,
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auth")
+            .field("enabled", &self.secret.is_some())
+            .finish()
+    }
+}
+
+impl Auth {
+    pub fn new(secret: Option<Secret>) -> Self {
+        Self { secret }
+    }
+
+    /// Identify the caller by the token in `metadata`
+    pub fn caller(&self, metadata: &MetadataMap) -> Result<Caller, AuthError> {
+        self.claims(metadata).map(|claims| claims.caller)
+    }
+
+    fn claims(&self, metadata: &MetadataMap) -> Result<Claims, AuthError> {
+        let secret = match self.secret.as_ref() {
+            Some(s) => s,
+            None => {
+                return Ok(Claims {
+                    caller: Caller::Service,
+                    operator: None,
+                })
+            }
+        };
+        let token = metadata
+            .get("authorization")
+            .ok_or(AuthError::MissingToken)?
+            .to_str()
+            .map_err(|_| AuthError::Malformed)?;
+        let token = token.strip_prefix("Bearer ").ok_or(AuthError::Malformed)?;
+        verify(secret, token, now())
+    }
+
+    /// The caller must be a service or the user identified by `user_id`
+    pub fn authorize_user(
+        &self,
+        metadata: &MetadataMap,
+        user_id: Option<&cao_common::Uuid>,
+    ) -> Result<Caller, Status> {
+        let caller = self.caller(metadata)?;
+        match caller {
+            Caller::Service => Ok(caller),
+            Caller::User(UserId(id)) => {
+                let claimed = user_id.and_then(|u| uuid::Uuid::from_slice(u.data.as_slice()).ok());
+                if claimed != Some(id) {
+                    debug!("User {} tried to act on behalf of {:?}", id, claimed);
+                    return Err(Status::permission_denied(
+                        "The user of the request does not match the caller",
+                    ));
+                }
+                Ok(caller)
+            }
+        }
+    }
+
+    /// The caller must be a service
+    pub fn authorize_service(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        match self.caller(metadata)? {
+            Caller::Service => Ok(()),
+            Caller::User(_) => Err(Status::permission_denied(
+                "Only services may call this endpoint",
+            )),
+        }
+    }
+
+    /// The caller must be a service holding a signed token naming its operator.
+    /// Returns the name of the operator.
+    ///
+    /// Unlike `authorize_service` this refuses every call if no secret is configured.
+    pub fn authorize_admin(&self, metadata: &MetadataMap) -> Result<String, Status> {
+        if self.secret.is_none() {
+            return Err(Status::permission_denied(
+                "The administrative endpoints are disabled without an auth secret",
+            ));
+        }
+        match self.claims(metadata)? {
+            Claims {
+                caller: Caller::Service,
+                operator: Some(operator),
+            } => Ok(operator),
+            Claims {
+                caller: Caller::Service,
+                operator: None,
+            } => Err(Status::permission_denied(
+                "Administrative calls require a token naming the operator",
+            )),
+            Claims {
+                caller: Caller::User(_),
+                ..
+            } => Err(Status::permission_denied(
+                "Only services may call this endpoint",
+            )),
+        }
+    }
+}
+
+fn subject(caller: Caller) -> String {
+    match caller {
+        Caller::Service => SERVICE_SUBJECT.to_owned(),
+        Caller::User(UserId(id)) => id.to_hyphenated().to_string(),
+    }
+}
+
+fn mac(secret: &Secret, payload: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.0.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn verify(secret: &Secret, token: &str, now: u64) -> Result<Claims, AuthError> {
+    let mut parts = token.rsplitn(2, '.');
+    let signature = parts.next().ok_or(AuthError::Malformed)?;
+    let payload = parts.next().ok_or(AuthError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| AuthError::Malformed)?;
+    mac(secret, payload)
+        .verify(signature.as_slice())
+        .map_err(|_| AuthError::BadSignature)?;
+
+    let mut parts = payload.splitn(2, '.');
+    let subject = parts.next().ok_or(AuthError::Malformed)?;
+    let expires: u64 = parts
+        .next()
+        .ok_or(AuthError::Malformed)?
+        .parse()
+        .map_err(|_| AuthError::Malformed)?;
+    if expires < now {
+        return Err(AuthError::Expired);
+    }
+    if subject == SERVICE_SUBJECT {
+        return Ok(Claims {
+            caller: Caller::Service,
+            operator: None,
+        });
+    }
+    if let Some(operator) = subject.strip_prefix(OPERATOR_PREFIX) {
+        if operator.is_empty() {
+            return Err(AuthError::Malformed);
+        }
+        return Ok(Claims {
+            caller: Caller::Service,
+            operator: Some(operator.to_owned()),
+        });
+    }
+    uuid::Uuid::parse_str(subject)
+        .map(|id| Claims {
+            caller: Caller::User(UserId(id)),
+            operator: None,
+        })
+        .map_err(|_| AuthError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(auth: &Auth, caller: Caller, expires: u64) -> String {
+        sign_subject(auth, subject(caller).as_str(), expires)
+    }
+
+    fn sign_subject(auth: &Auth, subject: &str, expires: u64) -> String {
+        let payload = format!("{}.{}", subject, expires);
+        let signature = mac(auth.secret.as_ref().unwrap(), payload.as_str())
+            .finalize()
+            .into_bytes();
+        format!("{}.{}", payload, hex::encode(signature))
+    }
+
+    fn metadata(token: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        metadata
+    }
+
+    fn pb_uuid(id: uuid::Uuid) -> cao_common::Uuid {
+        cao_common::Uuid {
+            data: id.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn users_may_only_act_on_behalf_of_themselves() {
+        let auth = Auth::new(Some(Secret("winnie".to_owned())));
+        let user = uuid::Uuid::new_v4();
+        let other = uuid::Uuid::new_v4();
+
+        let token = sign(&auth, Caller::User(UserId(user)), now() + 60);
+        let md = metadata(token.as_str());
+
+        assert_eq!(auth.caller(&md), Ok(Caller::User(UserId(user))));
+        auth.authorize_user(&md, Some(&pb_uuid(user))).unwrap();
+
+        let err = auth.authorize_user(&md, Some(&pb_uuid(other))).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = auth.authorize_user(&md, None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = auth.authorize_service(&md).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn services_may_act_on_behalf_of_anyone() {
+        let auth = Auth::new(Some(Secret("winnie".to_owned())));
+        let token = sign(&auth, Caller::Service, now() + 60);
+        let md = metadata(token.as_str());
+
+        auth.authorize_user(&md, Some(&pb_uuid(uuid::Uuid::new_v4())))
+            .unwrap();
+        auth.authorize_service(&md).unwrap();
+    }
+
+    #[test]
+    fn admin_calls_are_attributed_to_the_operator_of_the_token() {
+        let auth = Auth::new(Some(Secret("winnie".to_owned())));
+
+        let token = sign_subject(&auth, "service:support", now() + 60);
+        let md = metadata(token.as_str());
+        assert_eq!(auth.caller(&md), Ok(Caller::Service));
+        assert_eq!(auth.authorize_admin(&md).unwrap(), "support");
+
+        let token = sign(&auth, Caller::Service, now() + 60);
+        let err = auth.authorize_admin(&metadata(token.as_str())).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        let token = sign_subject(&auth, "service:", now() + 60);
+        assert_eq!(
+            auth.caller(&metadata(token.as_str())),
+            Err(AuthError::Malformed)
+        );
+    }
+
+    #[test]
+    fn invalid_tokens_are_rejected() {
+        let auth = Auth::new(Some(Secret("winnie".to_owned())));
+        let forger = Auth::new(Some(Secret("tigger".to_owned())));
+        let user = Caller::User(UserId(uuid::Uuid::new_v4()));
+
+        assert_eq!(
+            auth.caller(&MetadataMap::new()),
+            Err(AuthError::MissingToken)
+        );
+
+        let forged = sign(&forger, user, now() + 60);
+        assert_eq!(
+            auth.caller(&metadata(forged.as_str())),
+            Err(AuthError::BadSignature)
+        );
+
+        let expired = sign(&auth, user, now() - 1);
+        assert_eq!(
+            auth.caller(&metadata(expired.as_str())),
+            Err(AuthError::Expired)
+        );
+
+        assert_eq!(
+            auth.caller(&metadata("service.123")),
+            Err(AuthError::Malformed)
+        );
+    }
+
+    #[test]
+    fn everyone_is_a_service_without_a_secret() {
+        let auth = Auth::new(None);
+        assert_eq!(auth.caller(&MetadataMap::new()), Ok(Caller::Service));
+    }
+
+    #[test]
+    fn admin_is_disabled_without_a_secret() {
+        let auth = Auth::new(None);
+        let err = auth.authorize_admin(&MetadataMap::new()).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+}
@@ -1,11 +1,16 @@
+use crate::auth::Auth;
+use crate::input::config;
 use crate::input::structures;
 use crate::input::users;
+use crate::world_service::SharedRoomCache;
 use crate::{input::rooms, protos::cao_commands};
 use tonic::{Request, Response, Status};
 
 #[derive(Clone)]
 pub struct CommandService {
     world: std::sync::Arc<tokio::sync::Mutex<crate::World>>,
+    room_cache: SharedRoomCache,
+    auth: Auth,
 }
 
 impl std::fmt::Debug for CommandService {
@@ -15,8 +20,16 @@ impl std::fmt::Debug for CommandService {
 }
 
 impl CommandService {
-    pub fn new(world: std::sync::Arc<tokio::sync::Mutex<crate::World>>) -> Self {
-        Self { world }
+    pub fn new(
+        world: std::sync::Arc<tokio::sync::Mutex<crate::World>>,
+        room_cache: SharedRoomCache,
+        auth: Auth,
+    ) -> Self {
+        Self {
+            world,
+            room_cache,
+            auth,
+        }
     }
 }
 
@@ -27,6 +40,8 @@ impl cao_commands::command_server::Command for CommandService {
         &self,
         request: Request<cao_commands::PlaceStructureCommand>,
     ) -> Result<Response<cao_commands::CommandResult>, Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().owner_id.as_ref())?;
         let mut w = self.world.lock().await;
         structures::place_structure(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_commands::CommandResult {}))
@@ -38,10 +53,15 @@ impl cao_commands::command_server::Command for CommandService {
         &self,
         request: tonic::Request<cao_commands::TakeRoomCommand>,
     ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
         let mut w = self.world.lock().await;
         rooms::take_room(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_commands::CommandResult {}))
-            .map_err(|err| Status::invalid_argument(err.to_string()))
+            .map_err(|err| match err {
+                rooms::TakeRoomError::StarterKit(_) => Status::resource_exhausted(err.to_string()),
+                _ => Status::invalid_argument(err.to_string()),
+            })
     }
 
     #[tracing::instrument]
@@ -49,9 +69,73 @@ impl cao_commands::command_server::Command for CommandService {
         &self,
         request: tonic::Request<cao_commands::RegisterUserCommand>,
     ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        // the level limits the rooms of the user, so users may not register themselves
+        self.auth.authorize_service(request.metadata())?;
         let mut w = self.world.lock().await;
         users::register_user(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_commands::CommandResult {}))
             .map_err(|err| Status::invalid_argument(err.to_string()))
     }
+
+    #[tracing::instrument]
+    async fn unregister_user(
+        &self,
+        request: tonic::Request<cao_commands::UnregisterUserCommand>,
+    ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
+        let mut w = self.world.lock().await;
+        users::unregister_user(&mut *w, request.get_ref())
+            .map(|_: ()| Response::new(cao_commands::CommandResult {}))
+            .map_err(|err| Status::invalid_argument(err.to_string()))
+    }
+
+    #[tracing::instrument]
+    async fn reset_user(
+        &self,
+        request: tonic::Request<cao_commands::ResetUserCommand>,
+    ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
+        let mut w = self.world.lock().await;
+        users::reset_user(&mut *w, request.get_ref())
+            .map(|_: ()| Response::new(cao_commands::CommandResult {}))
+            .map_err(|err| match err {
+                users::UnregisterUserError::StarterKit(_) => {
+                    Status::resource_exhausted(err.to_string())
+                }
+                _ => Status::invalid_argument(err.to_string()),
+            })
+    }
+
+    #[tracing::instrument]
+    async fn expand_world(
+        &self,
+        request: tonic::Request<cao_commands::ExpandWorldCommand>,
+    ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        self.auth.authorize_service(request.metadata())?;
+        let mut w = self.world.lock().await;
+        rooms::expand_world(&mut *w, request.get_ref())
+            .map_err(|err| Status::internal(err.to_string()))?;
+        // new rooms were added and the edge rooms got new bridges
+        self.room_cache.write().await.refresh(&w);
+        Ok(Response::new(cao_commands::CommandResult {}))
+    }
+
+    #[tracing::instrument]
+    async fn reload_config(
+        &self,
+        request: tonic::Request<cao_commands::ReloadConfigCommand>,
+    ) -> Result<tonic::Response<cao_commands::CommandResult>, tonic::Status> {
+        self.auth.authorize_service(request.metadata())?;
+        let mut w = self.world.lock().await;
+        config::reload_config(&mut *w, request.get_ref())
+            .map(|_: ()| Response::new(cao_commands::CommandResult {}))
+            .map_err(|err| match err {
+                config::ReloadConfigError::NoConfigFile => {
+                    Status::failed_precondition(err.to_string())
+                }
+                _ => Status::invalid_argument(err.to_string()),
+            })
+    }
 }
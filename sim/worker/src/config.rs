@@ -1,55 +1,247 @@
-use serde::Serialize;
-use std::env;
+//! Worker configuration.
+//!
+//! The configuration is read from the YAML file at `CAO_CONFIG_FILE`, if set. Missing keys take
+//! their default values. The legacy environment variables are applied on top of the file, so
+//! existing deployments keep working.
+//!
+//! ```yaml
+//! n_actors: 1000
+//! world_buff_size: 1
+//! script_chunk_size: 1024
+//! service_addr: "[::1]:50051"
+//! metrics_addr: "127.0.0.1:9000"
+//! # authentication of the gRPC callers is disabled if not set
+//! auth_secret: "hunter2"
+//! # append the administrative mutations to this file
+//! audit_log: "/var/log/caolo/audit.jsonl"
+//! game:
+//!   world_radius: 6
+//!   room_radius: 16
+//!   target_tick_ms: 200
+//!   spawn:
+//!     bot_cost: 500
+//!     spawn_time: 10
+//! ```
+use crate::auth::Secret;
+use caolo_sim::components::game_config::{GameConfig, GameConfigError};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path:?}: {err}")]
+    Io { path: PathBuf, err: std::io::Error },
+    #[error("Failed to parse config file {path:?}: {err}")]
+    Parse {
+        path: PathBuf,
+        err: serde_yaml::Error,
+    },
+    #[error("Environment variable {name} has invalid value {value:?}")]
+    InvalidEnv { name: &'static str, value: String },
+    #[error("Invalid service address {0:?}")]
+    InvalidServiceAddr(String),
+    #[error("Invalid metrics address {0:?}")]
+    InvalidMetricsAddr(String),
+    #[error("Invalid game config: {0}")]
+    InvalidGameConfig(#[from] GameConfigError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
-    pub world_radius: u32,
-    pub room_radius: u32,
     pub n_actors: u32,
-    pub target_tick_ms: u64,
     /// Number of previous world states to hold on to, for slow clients
     pub world_buff_size: u64,
+    pub script_chunk_size: usize,
+    pub service_addr: String,
+    /// Address of the Prometheus metrics endpoint, if built with the `metrics` feature
+    pub metrics_addr: String,
+    /// Secret shared with the trusted services to sign the tokens of gRPC callers, see `auth`
+    pub auth_secret: Option<Secret>,
+    /// File to append the audit trail of the Admin service to, see `audit`
+    pub audit_log: Option<PathBuf>,
+    pub game: GameConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
+        let n_actors = 1000;
         Self {
-            n_actors: 10,
-            room_radius: 8,
-            world_radius: 8,
-            target_tick_ms: 200,
+            n_actors,
             world_buff_size: 1,
+            script_chunk_size: 1024,
+            service_addr: "[::1]:50051".to_owned(),
+            metrics_addr: "127.0.0.1:9000".to_owned(),
+            auth_secret: None,
+            audit_log: None,
+            game: GameConfig {
+                world_radius: world_radius_for(n_actors),
+                room_radius: 16,
+                target_tick_ms: 200,
+                ..Default::default()
+            },
         }
     }
 }
 
+/// Smallest world radius that fits `n_actors`
+fn world_radius_for(n_actors: u32) -> u32 {
+    let a = n_actors as f32;
+    ((a * 1.0 / (3.0 * 3.0f32.sqrt())).powf(0.33)).ceil() as u32
+}
+
 impl Config {
-    pub fn load() -> Self {
-        let n_actors = env::var("CAO_N_ACTORS")
-            .ok()
-            .and_then(|s| s.parse::<u32>().ok())
-            .unwrap_or(1000);
-        Self {
-            n_actors,
-            room_radius: std::env::var("CAO_ROOM_RADIUS")
-                .map(|w| w.parse().expect("expected room radius to be an integer"))
-                .unwrap_or(16),
-            world_radius: std::env::var("CAO_MAP_OVERWORLD_RADIUS")
-                .map(|w| {
-                    w.parse()
-                        .expect("expected map overworld radius to be an integer")
-                })
-                .unwrap_or_else(|_| {
-                    let a = n_actors as f32;
-                    ((a * 1.0 / (3.0 * 3.0f32.sqrt())).powf(0.33)).ceil() as u32
-                }),
-            target_tick_ms: std::env::var("CAO_TARGET_TICK_LATENCY_MS")
-                .map(|i| i.parse::<u64>().unwrap())
-                .unwrap_or(200),
-            world_buff_size: std::env::var("CAO_WORLD_BUFFER")
-                .map(|i| i.parse::<u64>().unwrap())
-                .unwrap_or(1),
+    /// Path of the config file, if any
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("CAO_CONFIG_FILE").map(PathBuf::from)
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match Self::path() {
+            Some(path) => Self::from_file(&path)?,
+            None => {
+                // without a file the world radius follows the number of actors
+                let mut config = Self::default();
+                if let Some(n_actors) = parse_env("CAO_N_ACTORS")? {
+                    config.n_actors = n_actors;
+                    config.game.world_radius = world_radius_for(n_actors);
+                }
+                config
+            }
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let file = std::fs::File::open(path).map_err(|err| ConfigError::Io {
+            path: path.to_owned(),
+            err,
+        })?;
+        serde_yaml::from_reader(file).map_err(|err| ConfigError::Parse {
+            path: path.to_owned(),
+            err,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.service_addr()?;
+        self.metrics_addr()?;
+        self.game.validate()?;
+        Ok(())
+    }
+
+    pub fn service_addr(&self) -> Result<SocketAddr, ConfigError> {
+        self.service_addr
+            .parse()
+            .map_err(|_| ConfigError::InvalidServiceAddr(self.service_addr.clone()))
+    }
+
+    pub fn metrics_addr(&self) -> Result<SocketAddr, ConfigError> {
+        self.metrics_addr
+            .parse()
+            .map_err(|_| ConfigError::InvalidMetricsAddr(self.metrics_addr.clone()))
+    }
+
+    /// Override the values set by the legacy environment variables
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(n_actors) = parse_env("CAO_N_ACTORS")? {
+            self.n_actors = n_actors;
+        }
+        if let Some(radius) = parse_env("CAO_MAP_OVERWORLD_RADIUS")? {
+            self.game.world_radius = radius;
         }
+        if let Some(radius) = parse_env("CAO_ROOM_RADIUS")? {
+            self.game.room_radius = radius;
+        }
+        if let Some(ms) = parse_env("CAO_TARGET_TICK_LATENCY_MS")? {
+            self.game.target_tick_ms = ms;
+        }
+        if let Some(size) = parse_env("CAO_WORLD_BUFFER")? {
+            self.world_buff_size = size;
+        }
+        if let Some(size) = parse_env("CAO_QUEEN_SCRIPT_CHUNK_SIZE")? {
+            self.script_chunk_size = size;
+        }
+        if let Ok(tag) = env::var("CAO_QUEEN_TAG") {
+            self.game.queen_tag = tag;
+        }
+        if let Ok(addr) = env::var("CAO_SERVICE_ADDR") {
+            self.service_addr = addr;
+        }
+        if let Ok(addr) = env::var("CAO_METRICS_ADDR") {
+            self.metrics_addr = addr;
+        }
+        match env::var("CAO_AUTH_SECRET") {
+            Ok(secret) if !secret.is_empty() => self.auth_secret = Some(Secret(secret)),
+            _ => {}
+        }
+        match env::var_os("CAO_AUDIT_LOG") {
+            Some(path) if !path.is_empty() => self.audit_log = Some(PathBuf::from(path)),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn parse_env<T: FromStr>(name: &'static str) -> Result<Option<T>, ConfigError> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidEnv { name, value }),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_keys_take_the_defaults() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+n_actors: 12
+game:
+  room_radius: 8
+  spawn:
+    bot_cost: 200
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.n_actors, 12);
+        assert_eq!(config.game.room_radius, 8);
+        assert_eq!(config.game.spawn.bot_cost, 200);
+        assert_eq!(config.game.spawn.spawn_time, 10);
+        assert_eq!(config.world_buff_size, 1);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn invalid_game_config_is_an_error() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+game:
+  target_tick_ms: 0
+"#,
+        )
+        .unwrap();
+
+        let err = config.validate().unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidGameConfig(GameConfigError::Zero {
+                field: "target_tick_ms"
+            })
+        ));
     }
 }
@@ -1,4 +1,6 @@
 //! Handle inputs received via the message bus
+pub mod admin;
+pub mod config;
 pub mod rooms;
 pub mod script_update;
 pub mod structures;
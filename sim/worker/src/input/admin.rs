@@ -0,0 +1,412 @@
+use crate::protos::cao_admin::{
+    DeleteEntityCommand, RemoveComponentCommand, SetComponentCommand, TeleportCommand,
+};
+use caolo_sim::prelude::*;
+use serde_json::Value;
+use std::convert::TryFrom;
+use thiserror::Error;
+use tracing::info;
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("Entity {0:?} does not exist")]
+    EntityNotFound(EntityId),
+    #[error("{0} is not a valid entity id")]
+    BadEntityId(i64),
+    #[error("Unknown component {0:?}")]
+    UnknownComponent(String),
+    #[error("Component {0:?} can not be set directly, use Teleport instead")]
+    UseTeleport(String),
+    #[error("Failed to deserialize component {component:?}: {err}")]
+    BadValue {
+        component: String,
+        err: serde_json::Error,
+    },
+    #[error("Entity {0:?} has no position")]
+    NotPositioned(EntityId),
+    #[error("Position {0:?} is not valid")]
+    InvalidPosition(WorldPosition),
+    #[error("Position {0:?} is taken")]
+    TakenPosition(WorldPosition),
+    #[error("Missing expected field {0}")]
+    MissingField(&'static str),
+}
+
+/// State of the entity before and after a mutation, recorded in the audit trail
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub entity_id: EntityId,
+    pub before: Value,
+    pub after: Value,
+}
+
+pub fn parse_entity_id(id: i64) -> Result<EntityId, AdminError> {
+    u64::try_from(id)
+        .map(EntityId)
+        .map_err(|_| AdminError::BadEntityId(id))
+}
+
+/// Every component of the entity, keyed by the name of their table
+pub fn get_entity(world: &World, id: EntityId) -> Result<Value, AdminError> {
+    let components = world.entities.components(id);
+    if components.is_empty() {
+        return Err(AdminError::EntityNotFound(id));
+    }
+    Ok(serde_json::to_value(&components).expect("Failed to serialize components"))
+}
+
+/// Implement `set_component` and `remove_component` for the editable components.
+///
+/// `flag` components are inserted by their id only, `table` components are deserialized from the
+/// value.
+macro_rules! editable_components {
+    (
+        flag { $($flag_name: literal => $flag: ty),* $(,)? }
+        table { $($name: literal => $row: ty),* $(,)? }
+    ) => {
+        fn set_component_value(
+            world: &mut World,
+            id: EntityId,
+            component: &str,
+            value: Value,
+        ) -> Result<(), AdminError> {
+            let bad_value = |err| AdminError::BadValue {
+                component: component.to_owned(),
+                err,
+            };
+            match component {
+                $(
+                    $flag_name => {
+                        let _: $flag = serde_json::from_value(value).map_err(bad_value)?;
+                        world.unsafe_view::<EntityId, $flag>().insert(id);
+                    }
+                )*
+                $(
+                    $name => {
+                        let row: $row = serde_json::from_value(value).map_err(bad_value)?;
+                        world.unsafe_view::<EntityId, $row>().insert_or_update(id, row);
+                    }
+                )*
+                "pos" => return Err(AdminError::UseTeleport(component.to_owned())),
+                _ => return Err(AdminError::UnknownComponent(component.to_owned())),
+            }
+            Ok(())
+        }
+
+        fn remove_component_value(
+            world: &mut World,
+            id: EntityId,
+            component: &str,
+        ) -> Result<(), AdminError> {
+            match component {
+                $(
+                    $flag_name => {
+                        world.unsafe_view::<EntityId, $flag>().delete(id);
+                    }
+                )*
+                $(
+                    $name => {
+                        world.unsafe_view::<EntityId, $row>().delete(id);
+                    }
+                )*
+                "pos" => return Err(AdminError::UseTeleport(component.to_owned())),
+                _ => return Err(AdminError::UnknownComponent(component.to_owned())),
+            }
+            Ok(())
+        }
+    };
+}
+
+// the names match the table names of `entity_store`, so they match the output of `get_entity`
+editable_components!(
+    flag {
+        "bot" => Bot,
+        "structure" => Structure,
+    }
+    table {
+        "spawnbot" => SpawnBotComponent,
+        "carry" => CarryComponent,
+        "hp" => HpComponent,
+        "energyregen" => EnergyRegenComponent,
+        "vision" => VisionComponent,
+        "energy" => EnergyComponent,
+        "resource" => ResourceComponent,
+        "decay" => DecayComponent,
+        "script" => EntityScript,
+        "spawn" => SpawnComponent,
+        "spawnqueue" => SpawnQueueComponent,
+        "owner" => OwnedEntity,
+        "melee" => MeleeAttackComponent,
+        "respawn_timer" => RespawnTimer,
+        "pathcache" => PathCacheComponent,
+    }
+);
+
+pub fn set_component(world: &mut World, msg: &SetComponentCommand) -> Result<Change, AdminError> {
+    let id = parse_entity_id(msg.entity_id)?;
+    let value = msg
+        .value
+        .as_ref()
+        .ok_or(AdminError::MissingField("value"))?;
+    let value: Value =
+        serde_json::from_slice(value.value.as_slice()).map_err(|err| AdminError::BadValue {
+            component: msg.component.clone(),
+            err,
+        })?;
+
+    let before = get_entity(world, id)?;
+    set_component_value(world, id, msg.component.as_str(), value)?;
+    info!("Set component {} of entity {:?}", msg.component, id);
+    Ok(Change {
+        entity_id: id,
+        before,
+        after: get_entity(world, id)?,
+    })
+}
+
+pub fn remove_component(
+    world: &mut World,
+    msg: &RemoveComponentCommand,
+) -> Result<Change, AdminError> {
+    let id = parse_entity_id(msg.entity_id)?;
+    let before = get_entity(world, id)?;
+    remove_component_value(world, id, msg.component.as_str())?;
+    info!("Removed component {} of entity {:?}", msg.component, id);
+    Ok(Change {
+        entity_id: id,
+        before,
+        // removing the last component deletes the entity
+        after: get_entity(world, id).unwrap_or(Value::Null),
+    })
+}
+
+/// Move the entity to the position, keeping the position index consistent
+pub fn teleport(world: &mut World, msg: &TeleportCommand) -> Result<Change, AdminError> {
+    let id = parse_entity_id(msg.entity_id)?;
+    let position = msg
+        .position
+        .as_ref()
+        .ok_or(AdminError::MissingField("position"))?;
+    let pos = position
+        .pos
+        .as_ref()
+        .ok_or(AdminError::MissingField("position.pos"))?;
+    let room = position
+        .room
+        .as_ref()
+        .ok_or(AdminError::MissingField("position.room"))?;
+    let to = WorldPosition {
+        room: Axial::new(room.q, room.r),
+        pos: Axial::new(pos.q, pos.r),
+    };
+
+    let before = get_entity(world, id)?;
+    let from = world
+        .view::<EntityId, PositionComponent>()
+        .get_by_id(id)
+        .map(|PositionComponent(pos)| *pos)
+        .ok_or(AdminError::NotPositioned(id))?;
+
+    let is_walkable = world
+        .view::<WorldPosition, TerrainComponent>()
+        .get_by_id(to)
+        .map(|TerrainComponent(t)| t.is_walkable())
+        .unwrap_or(false);
+    if !is_walkable {
+        return Err(AdminError::InvalidPosition(to));
+    }
+    // only the blocking layer is single occupancy
+    let layer = PositionsMut::from_world_mut(world).layer_of(id);
+    let occupant = world
+        .view::<WorldPosition, EntityComponent>()
+        .get_by_id(to)
+        .map(|EntityComponent(e)| *e);
+    if layer == Some(EntityLayer::Blocking) && occupant.map(|e| e != id).unwrap_or(false) {
+        return Err(AdminError::TakenPosition(to));
+    }
+
+    PositionsMut::from_world_mut(world)
+        .move_entity(id, to)
+        .map_err(|_| AdminError::InvalidPosition(to))?;
+    // the cached path starts from the old position
+    world
+        .unsafe_view::<EntityId, PathCacheComponent>()
+        .delete(id);
+
+    info!("Teleported entity {:?} from {:?} to {:?}", id, from, to);
+    Ok(Change {
+        entity_id: id,
+        before,
+        after: get_entity(world, id)?,
+    })
+}
+
+pub fn delete_entity(world: &mut World, msg: &DeleteEntityCommand) -> Result<Change, AdminError> {
+    let id = parse_entity_id(msg.entity_id)?;
+    let before = get_entity(world, id)?;
+    world.delete_entities(std::iter::once(id), DeleteCause::Admin);
+
+    info!("Deleted entity {:?}", id);
+    Ok(Change {
+        entity_id: id,
+        before,
+        after: Value::Null,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::cao_common;
+
+    fn setup() -> (std::pin::Pin<Box<World>>, EntityId, WorldPosition) {
+        let mut exc = SimpleExecutor;
+        let mut world = exc.initialize(GameConfig {
+            world_radius: 1,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *world, 1);
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *world)).unwrap();
+
+        let (id, PositionComponent(pos)) = world
+            .view::<EntityId, PositionComponent>()
+            .iter()
+            .find(|(id, _)| world.view::<EntityId, Bot>().contains_id(id))
+            .map(|(id, pos)| (id, *pos))
+            .expect("a bot");
+        (world, id, pos)
+    }
+
+    fn pb_position(pos: WorldPosition) -> cao_common::WorldPosition {
+        cao_common::WorldPosition {
+            room: Some(cao_common::Axial {
+                q: pos.room.q,
+                r: pos.room.r,
+            }),
+            pos: Some(cao_common::Axial {
+                q: pos.pos.q,
+                r: pos.pos.r,
+            }),
+        }
+    }
+
+    #[test]
+    fn can_set_and_remove_components() {
+        let (mut world, id, _) = setup();
+
+        let change = set_component(
+            &mut world,
+            &SetComponentCommand {
+                entity_id: id.0 as i64,
+                component: "hp".to_owned(),
+                value: Some(cao_common::Json {
+                    value: br#"{"hp": 1, "hpMax": 2}"#.to_vec(),
+                }),
+                audit: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(change.after["hp"]["hp"], 1);
+        assert_eq!(
+            world
+                .view::<EntityId, HpComponent>()
+                .get_by_id(id)
+                .unwrap()
+                .hp,
+            1
+        );
+
+        let change = remove_component(
+            &mut world,
+            &RemoveComponentCommand {
+                entity_id: id.0 as i64,
+                component: "hp".to_owned(),
+                audit: None,
+            },
+        )
+        .unwrap();
+        assert!(change.before.get("hp").is_some());
+        assert!(change.after.get("hp").is_none());
+
+        let err = remove_component(
+            &mut world,
+            &RemoveComponentCommand {
+                entity_id: id.0 as i64,
+                component: "pos".to_owned(),
+                audit: None,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, AdminError::UseTeleport(_)));
+    }
+
+    #[test]
+    fn teleport_keeps_the_position_index_consistent() {
+        let (mut world, id, from) = setup();
+
+        let to = WorldPosition {
+            room: from.room,
+            pos: from
+                .pos
+                .hex_neighbours()
+                .iter()
+                .copied()
+                .find(|p| {
+                    let p = WorldPosition {
+                        room: from.room,
+                        pos: *p,
+                    };
+                    world
+                        .view::<WorldPosition, TerrainComponent>()
+                        .get_by_id(p)
+                        .map(|TerrainComponent(t)| t.is_walkable())
+                        .unwrap_or(false)
+                        && world
+                            .view::<WorldPosition, EntityComponent>()
+                            .get_by_id(p)
+                            .is_none()
+                })
+                .expect("a free neighbour"),
+        };
+
+        teleport(
+            &mut world,
+            &TeleportCommand {
+                entity_id: id.0 as i64,
+                position: Some(pb_position(to)),
+                audit: None,
+            },
+        )
+        .unwrap();
+
+        let entities_by_pos = world.view::<WorldPosition, EntityComponent>();
+        assert!(entities_by_pos.get_by_id(from).is_none());
+        assert_eq!(entities_by_pos.get_by_id(to).map(|e| e.0), Some(id));
+        assert_eq!(
+            world
+                .view::<EntityId, PositionComponent>()
+                .get_by_id(id)
+                .map(|p| p.0),
+            Some(to)
+        );
+
+        delete_entity(
+            &mut world,
+            &DeleteEntityCommand {
+                entity_id: id.0 as i64,
+                audit: None,
+            },
+        )
+        .unwrap();
+        assert!(world
+            .view::<WorldPosition, EntityComponent>()
+            .get_by_id(to)
+            .is_none());
+        assert!(matches!(
+            get_entity(&world, id),
+            Err(AdminError::EntityNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::{Config, ConfigError};
+use crate::protos::cao_commands::ReloadConfigCommand;
+use caolo_sim::components::game_config::{GameConfig, GameConfigError};
+use caolo_sim::prelude::*;
+use thiserror::Error;
+use tracing::{debug, info};
+
+#[derive(Debug, Error)]
+pub enum ReloadConfigError {
+    #[error("No config file was given, set CAO_CONFIG_FILE to reload the config")]
+    NoConfigFile,
+    #[error("Failed to load the config: {0}")]
+    ConfigError(#[from] ConfigError),
+    #[error("Failed to apply the config: {0}")]
+    GameConfigError(#[from] GameConfigError),
+}
+
+/// Re-read the config file and replace the gameplay constants of the running world
+pub fn reload_config(
+    world: &mut World,
+    _msg: &ReloadConfigCommand,
+) -> Result<(), ReloadConfigError> {
+    debug!("Reloading config");
+
+    if Config::path().is_none() {
+        return Err(ReloadConfigError::NoConfigFile);
+    }
+    let config = Config::load()?;
+    let mut game_config = world.unsafe_view::<ConfigKey, GameConfig>();
+    game_config
+        .value
+        .as_mut()
+        .expect("expected the world to have a GameConfig")
+        .reload(config.game)?;
+
+    info!("Reloaded config {:?}", game_config.value);
+    Ok(())
+}
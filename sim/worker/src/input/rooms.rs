@@ -1,8 +1,10 @@
-use crate::protos::cao_commands::TakeRoomCommand;
+use crate::protos::cao_commands::{ExpandWorldCommand, TakeRoomCommand};
 use anyhow::Context;
+use caolo_sim::init::InitError;
+use caolo_sim::map_generation::MapGenError;
 use caolo_sim::prelude::*;
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, info};
 use uuid::Uuid;
 
 #[derive(Debug, Error)]
@@ -19,6 +21,8 @@ pub enum TakeRoomError {
     MissingField(&'static str),
     #[error("Failed to parse uuid {0}")]
     UuidError(anyhow::Error),
+    #[error("Failed to spawn the starter kit: {0}")]
+    StarterKit(InitError),
 }
 
 pub fn take_room(world: &mut World, msg: &TakeRoomCommand) -> Result<(), TakeRoomError> {
@@ -68,6 +72,13 @@ pub fn take_room(world: &mut World, msg: &TakeRoomCommand) -> Result<(), TakeRoo
     let mut rooms = rooms.cloned().unwrap_or_else(Rooms::default);
     rooms.0.push(Room(room_id));
 
+    // spawn the kit first, so the room is not taken if there is no place for it
+    if num_rooms == 0 {
+        debug!("First room of user {}, spawning starter kit", user_id);
+        caolo_sim::init::init_starter_kit(world, UserId(user_id), Room(room_id))
+            .map_err(TakeRoomError::StarterKit)?;
+    }
+
     world
         .unsafe_view::<Axial, OwnedEntity>()
         .insert_or_update(
@@ -85,3 +96,19 @@ pub fn take_room(world: &mut World, msg: &TakeRoomCommand) -> Result<(), TakeRoo
 
     Ok(())
 }
+
+#[derive(Debug, Error)]
+pub enum ExpandWorldError {
+    #[error("Failed to generate the new rooms: {0}")]
+    MapGenError(MapGenError),
+}
+
+pub fn expand_world(world: &mut World, _msg: &ExpandWorldCommand) -> Result<(), ExpandWorldError> {
+    debug!("Expanding world");
+
+    let new_rooms =
+        caolo_sim::executor::expand_world(world).map_err(ExpandWorldError::MapGenError)?;
+
+    info!("Expanded the world by {} rooms", new_rooms.len());
+    Ok(())
+}
@@ -2,6 +2,7 @@ use crate::protos::cao_script::{
     SetDefaultScriptCommand, UpdateEntityScriptCommand, UpdateScriptCommand,
 };
 use caolo_sim::{self, prelude::*, tables::JoinIterator};
+use std::convert::TryInto;
 use thiserror::Error;
 use tracing::{debug, error};
 
@@ -9,6 +10,8 @@ use tracing::{debug, error};
 pub enum UpdateProgramError {
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("{0} is not a valid entity id")]
+    BadEntityId(i64),
     #[error("Missing expected field {0}")]
     MissingField(&'static str),
     #[error("Failed to parse uuid {0}")]
@@ -111,7 +114,11 @@ pub fn update_entity_script(storage: &mut World, msg: &UpdateEntityScriptCommand
     let user_id =
         uuid::Uuid::from_slice(user_id).map_err(|err| UpdateProgramError::UuidError(err.into()))?;
 
-    let entity_id = EntityId(msg.entity_id);
+    let entity_id = EntityId(
+        msg.entity_id
+            .try_into()
+            .map_err(|_| UpdateProgramError::BadEntityId(msg.entity_id))?,
+    );
 
     let owned_entities_table: View<EntityId, OwnedEntity> = storage.view();
 
@@ -170,3 +177,25 @@ pub fn set_default_script(storage: &mut World, msg: &SetDefaultScriptCommand) ->
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::cao_common;
+
+    #[test]
+    fn negative_entity_ids_are_rejected() {
+        let mut world = World::new();
+        let msg = UpdateEntityScriptCommand {
+            user_id: Some(cao_common::Uuid {
+                data: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            }),
+            entity_id: -1,
+            script_id: None,
+        };
+
+        let res = update_entity_script(&mut *world, &msg);
+
+        assert!(matches!(res, Err(UpdateProgramError::BadEntityId(-1))));
+    }
+}
@@ -1,8 +1,11 @@
-use crate::protos::cao_commands::RegisterUserCommand;
+use crate::protos::{
+    cao_commands::{RegisterUserCommand, ResetUserCommand, UnregisterUserCommand},
+    cao_common,
+};
 use caolo_sim::{prelude::*, query};
 use std::{convert::TryFrom, num::TryFromIntError};
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, info};
 use uuid::Uuid;
 
 #[derive(Debug, Error)]
@@ -60,3 +63,290 @@ pub fn register_user(
 
     Ok(())
 }
+
+#[derive(Debug, Error)]
+pub enum UnregisterUserError {
+    #[error("User by id {0} was not registered")]
+    NotRegistered(Uuid),
+    #[error("Failed to parse uuid {0}")]
+    UuidError(anyhow::Error),
+    #[error("Missing expected field {0}")]
+    MissingField(&'static str),
+    #[error("Failed to spawn the starter kit: {0}")]
+    StarterKit(caolo_sim::init::InitError),
+}
+
+pub fn unregister_user(
+    world: &mut World,
+    msg: &UnregisterUserCommand,
+) -> Result<(), UnregisterUserError> {
+    debug!("Unregister user");
+
+    let user_id = parse_registered_user(world, msg.user_id.as_ref())?;
+
+    release_user(world, user_id);
+
+    query!(
+        mutate
+        world
+        {
+            UserId, UserComponent,
+                .delete(user_id);
+            UserId, Rooms,
+                .delete(user_id);
+            UserId, UserProperties,
+                .delete(user_id);
+        }
+    );
+
+    Ok(())
+}
+
+pub fn reset_user(world: &mut World, msg: &ResetUserCommand) -> Result<(), UnregisterUserError> {
+    debug!("Reset user");
+
+    let user_id = parse_registered_user(world, msg.user_id.as_ref())?;
+
+    let Rooms(rooms) = release_user(world, user_id);
+
+    let mut new_rooms = Rooms::default();
+    if msg.respawn {
+        match rooms.first().copied() {
+            Some(room) => {
+                caolo_sim::init::respawn_starter_kit(world, user_id, room)
+                    .map_err(UnregisterUserError::StarterKit)?;
+                world
+                    .unsafe_view::<Axial, OwnedEntity>()
+                    .insert_or_update(room.0, OwnedEntity { owner_id: user_id })
+                    .expect("expected the released room to be valid");
+                new_rooms.0.push(room);
+            }
+            None => {
+                info!("User {:?} had no rooms, skipping respawn", user_id);
+            }
+        }
+    }
+    world
+        .unsafe_view::<UserId, Rooms>()
+        .insert_or_update(user_id, new_rooms);
+
+    Ok(())
+}
+
+fn parse_registered_user(
+    world: &World,
+    user_id: Option<&cao_common::Uuid>,
+) -> Result<UserId, UnregisterUserError> {
+    let user_id = user_id
+        .ok_or(UnregisterUserError::MissingField("user_id"))?
+        .data
+        .as_slice();
+    let user_id = uuid::Uuid::from_slice(user_id)
+        .map_err(|err| UnregisterUserError::UuidError(err.into()))?;
+
+    if !world
+        .view::<UserId, UserProperties>()
+        .reborrow()
+        .contains(UserId(user_id))
+    {
+        return Err(UnregisterUserError::NotRegistered(user_id));
+    }
+    Ok(UserId(user_id))
+}
+
+/// Delete every entity of the user, release their rooms and drop their default script.
+///
+/// Returns the rooms the user owned, the user is left without rooms.
+fn release_user(world: &mut World, user_id: UserId) -> Rooms {
+    let owned_entities = world
+        .view::<EntityId, OwnedEntity>()
+        .iter()
+        .filter(|(_, OwnedEntity { owner_id })| *owner_id == user_id)
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    info!(
+        "Deleting {} entities of user {:?}",
+        owned_entities.len(),
+        user_id
+    );
+    world.delete_entities(owned_entities, DeleteCause::OwnerRemoved);
+
+    let mut room_owners = world.unsafe_view::<Axial, OwnedEntity>();
+    let owned_rooms = room_owners
+        .iter()
+        .filter(|(_, OwnedEntity { owner_id })| *owner_id == user_id)
+        .map(|(room, _)| room)
+        .collect::<Vec<_>>();
+    for room in owned_rooms {
+        room_owners.delete(room);
+    }
+
+    world.unsafe_view::<UserId, EntityScript>().delete(user_id);
+
+    let rooms = world
+        .view::<UserId, Rooms>()
+        .reborrow()
+        .get_by_id(user_id)
+        .cloned()
+        .unwrap_or_default();
+    world
+        .unsafe_view::<UserId, Rooms>()
+        .insert_or_update(user_id, Rooms::default());
+    rooms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::cao_commands::TakeRoomCommand;
+
+    fn setup_user(world: &mut World) -> (Uuid, Room) {
+        let user_id = Uuid::new_v4();
+        let uuid = cao_common::Uuid {
+            data: user_id.as_bytes().to_vec(),
+        };
+        register_user(
+            world,
+            &RegisterUserCommand {
+                user_id: Some(uuid.clone()),
+                level: 3,
+            },
+        )
+        .unwrap();
+        let room = world
+            .view::<Axial, RoomComponent>()
+            .iter()
+            .map(|(room, _)| room)
+            .next()
+            .unwrap();
+        crate::input::rooms::take_room(
+            world,
+            &TakeRoomCommand {
+                user_id: Some(uuid),
+                room_id: Some(cao_common::Axial {
+                    q: room.q,
+                    r: room.r,
+                }),
+            },
+        )
+        .unwrap();
+        (user_id, Room(room))
+    }
+
+    fn assert_no_dangling_positions(world: &World) {
+        let positions = world.view::<EntityId, PositionComponent>();
+        for (pos, EntityComponent(id)) in world.view::<WorldPosition, EntityComponent>().iter() {
+            assert_eq!(
+                positions.get_by_id(*id).map(|PositionComponent(p)| *p),
+                Some(pos),
+                "position index entry {:?} at {:?} is dangling",
+                id,
+                pos
+            );
+        }
+        for (pos, LayeredEntity { id, .. }) in world.view::<WorldPosition, LayeredEntity>().iter() {
+            assert_eq!(
+                positions.get_by_id(*id).map(|PositionComponent(p)| *p),
+                Some(pos),
+                "layered position index entry {:?} at {:?} is dangling",
+                id,
+                pos
+            );
+        }
+    }
+
+    #[test]
+    fn unregister_deletes_everything_of_the_user() {
+        let mut world =
+            caolo_sim::prelude::SimpleExecutor.initialize(caolo_sim::executor::GameConfig {
+                world_radius: 2,
+                room_radius: 10,
+                ..Default::default()
+            });
+        caolo_sim::init::init_world_entities(&mut *world, 4);
+        let (user_id, room) = setup_user(&mut *world);
+
+        let num_entities = world.view::<EntityId, PositionComponent>().iter().count();
+
+        unregister_user(
+            &mut *world,
+            &UnregisterUserCommand {
+                user_id: Some(cao_common::Uuid {
+                    data: user_id.as_bytes().to_vec(),
+                }),
+            },
+        )
+        .unwrap();
+
+        let user_id = UserId(user_id);
+        assert!(world
+            .view::<EntityId, OwnedEntity>()
+            .iter()
+            .all(|(_, OwnedEntity { owner_id })| *owner_id != user_id));
+        assert!(world.view::<EntityId, PositionComponent>().iter().count() < num_entities);
+        assert!(!world.view::<Axial, OwnedEntity>().contains_key(room.0));
+        assert!(!world
+            .view::<UserId, UserProperties>()
+            .reborrow()
+            .contains(user_id));
+        assert!(world
+            .view::<UserId, Rooms>()
+            .reborrow()
+            .get_by_id(user_id)
+            .is_none());
+        assert_no_dangling_positions(&world);
+    }
+
+    #[test]
+    fn reset_with_respawn_keeps_the_first_room() {
+        let mut world =
+            caolo_sim::prelude::SimpleExecutor.initialize(caolo_sim::executor::GameConfig {
+                world_radius: 2,
+                room_radius: 10,
+                ..Default::default()
+            });
+        caolo_sim::init::init_world_entities(&mut *world, 4);
+        let (user_id, room) = setup_user(&mut *world);
+        let num_resources = world.view::<EntityId, ResourceComponent>().iter().count();
+
+        reset_user(
+            &mut *world,
+            &ResetUserCommand {
+                user_id: Some(cao_common::Uuid {
+                    data: user_id.as_bytes().to_vec(),
+                }),
+                respawn: true,
+            },
+        )
+        .unwrap();
+
+        let user_id = UserId(user_id);
+        let rooms = world
+            .view::<UserId, Rooms>()
+            .reborrow()
+            .get_by_id(user_id)
+            .cloned()
+            .unwrap();
+        assert_eq!(rooms.0, vec![room]);
+        assert_eq!(
+            world
+                .view::<Axial, OwnedEntity>()
+                .at(room.0)
+                .map(|OwnedEntity { owner_id }| *owner_id),
+            Some(user_id)
+        );
+        let num_spawns = world
+            .view::<EntityId, OwnedEntity>()
+            .iter()
+            .filter(|(_, OwnedEntity { owner_id })| *owner_id == user_id)
+            .filter(|(id, _)| world.view::<EntityId, Structure>().contains_id(id))
+            .count();
+        assert_eq!(num_spawns, 1);
+        // resources are not owned, the ones of the first kit stay in the room
+        assert_eq!(
+            world.view::<EntityId, ResourceComponent>().iter().count(),
+            num_resources
+        );
+        assert_no_dangling_positions(&world);
+    }
+}
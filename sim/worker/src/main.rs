@@ -1,11 +1,17 @@
+mod audit;
+mod auth;
 mod config;
 mod input;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod protos;
 
+mod admin_service;
 mod command_service;
 mod scripting_service;
 mod world_service;
 
+use crate::protos::cao_admin::admin_server::AdminServer;
 use crate::protos::cao_commands::command_server::CommandServer;
 use crate::protos::cao_script::scripting_server::ScriptingServer;
 use crate::protos::cao_world::world_server::WorldServer;
@@ -15,8 +21,7 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tracing::{debug, info, warn, Instrument};
-use uuid::Uuid;
+use tracing::{debug, error, info, warn, Instrument};
 
 #[cfg(not(target_env = "msvc"))]
 #[global_allocator]
@@ -39,17 +44,26 @@ async fn game_loop(
     world: Arc<tokio::sync::Mutex<World>>,
     mut executor: SimpleExecutor,
     outpayload: Arc<tokio::sync::broadcast::Sender<Arc<world_service::Payload>>>,
-    tick_latency: Duration,
 ) {
     loop {
         let start = Instant::now();
         let mut pl = world_service::Payload::default();
+        let tick_latency;
         {
             // free the world mutex at the end of this scope
             let mut world = world.lock().await;
             executor.forward(&mut *world).await.unwrap();
 
             pl.update(&world);
+            #[cfg(feature = "metrics")]
+            metrics::record_tick(&world, outpayload.receiver_count());
+            // the tick latency may be changed by reloading the config
+            tick_latency = Duration::from_millis(
+                world
+                    .view::<caolo_sim::indices::ConfigKey, caolo_sim::executor::GameConfig>()
+                    .unwrap_value()
+                    .target_tick_ms,
+            );
         }
 
         if outpayload.receiver_count() > 0 {
@@ -75,7 +89,13 @@ fn main() {
     init();
     let sim_rt = caolo_sim::RuntimeGuard::new();
 
-    let config = config::Config::load();
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Failed to load config: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     let _sentry = env::var("SENTRY_URI")
         .ok()
@@ -86,40 +106,22 @@ fn main() {
 
     info!("Loaded config {:?}", config);
 
-    let script_chunk_size = env::var("CAO_QUEEN_SCRIPT_CHUNK_SIZE")
-        .ok()
-        .and_then(|x| x.parse().ok())
-        .unwrap_or(1024);
-
-    let tick_latency = Duration::from_millis(config.target_tick_ms);
-
-    info!(
-        "Loaded Queen params:\nScript chunk size: {}\nTick latency: {:?}",
-        script_chunk_size, tick_latency
-    );
-
-    let tag = env::var("CAO_QUEEN_TAG").unwrap_or_else(|_| Uuid::new_v4().to_string());
+    let tag = config.game.queen_tag.clone();
     let world_span = tracing::error_span!("world-service", queen_tag = tag.as_str());
     let game_loop_span = tracing::error_span!("game-loop", queen_tag = tag.as_str());
 
     info!("Creating cao executor with tag {}", tag);
     let mut executor = SimpleExecutor;
     info!("Init storage");
-    let mut world = executor.initialize(caolo_sim::executor::GameConfig {
-        world_radius: config.world_radius,
-        room_radius: config.room_radius,
-        queen_tag: tag.clone(),
-        ..Default::default()
-    });
+    let mut world = executor.initialize(config.game.clone());
 
     info!("Starting with {} actors", config.n_actors);
 
     caolo_sim::init::init_world_entities(&mut world, config.n_actors as usize);
 
-    let addr = env::var("CAO_SERVICE_ADDR")
-        .ok()
-        .map(|x| x.parse().expect("failed to parse cao service address"))
-        .unwrap_or_else(|| "[::1]:50051".parse().unwrap());
+    let addr = config
+        .service_addr()
+        .expect("expected the service address to be validated");
 
     info!("Starting the game loop. Starting the service on {:?}", addr);
 
@@ -133,38 +135,69 @@ fn main() {
             .radius as i32,
     );
 
-    let terrain = world
-        .view::<caolo_sim::prelude::WorldPosition, caolo_sim::prelude::TerrainComponent>()
-        .iter_rooms()
-        .map(|(room_id, room_terrain)| {
-            (
-                room_id.0,
-                room_terrain.iter().map(|(_, t)| t).copied().collect(),
-            )
-        })
-        .collect();
+    let room_cache = Arc::new(tokio::sync::RwLock::new(
+        world_service::RoomCache::from_world(&world),
+    ));
 
     let world = Arc::new(tokio::sync::Mutex::new(world));
 
+    if config.auth_secret.is_none() {
+        warn!("No auth secret was configured, gRPC callers are not authenticated and the admin service is disabled");
+    }
+    let auth = auth::Auth::new(config.auth_secret.clone());
+    let audit_log = match audit::AuditLog::open(config.audit_log.as_deref()) {
+        Ok(log) => Arc::new(log),
+        Err(err) => {
+            error!(
+                "Failed to open the audit log {:?}: {}",
+                config.audit_log, err
+            );
+            std::process::exit(1);
+        }
+    };
+
     let server = tonic::transport::Server::builder()
         .trace_fn(move |_| tracing::error_span!("service", queen_tag = tag.as_str()))
         .add_service(CommandServer::new(
-            crate::command_service::CommandService::new(Arc::clone(&world)),
+            crate::command_service::CommandService::new(
+                Arc::clone(&world),
+                Arc::clone(&room_cache),
+                auth.clone(),
+            ),
         ))
+        .add_service(AdminServer::new(crate::admin_service::AdminService::new(
+            Arc::clone(&world),
+            auth.clone(),
+            audit_log,
+        )))
         .add_service(ScriptingServer::new(
-            crate::scripting_service::ScriptingService::new(Arc::clone(&world)),
+            crate::scripting_service::ScriptingService::new(Arc::clone(&world), auth.clone()),
         ))
         .add_service(WorldServer::new(crate::world_service::WorldService::new(
+            Arc::clone(&world),
             Arc::clone(&outpayload),
             room_bounds,
-            Arc::new(terrain),
+            room_cache,
+            auth,
             world_span,
         )))
         .serve(addr);
 
-    let game_loop = game_loop(world, executor, outpayload, tick_latency).instrument(game_loop_span);
+    let game_loop = game_loop(world, executor, outpayload).instrument(game_loop_span);
+
+    #[cfg(feature = "metrics")]
+    let metrics_addr = config
+        .metrics_addr()
+        .expect("expected the metrics address to be validated");
 
     sim_rt.block_on(async move {
+        #[cfg(feature = "metrics")]
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(metrics_addr).await {
+                error!("Metrics server failed: {}", err);
+            }
+        });
+
         let (a, _) = futures::join!(server, game_loop);
         a.unwrap();
     });
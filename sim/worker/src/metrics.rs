@@ -0,0 +1,187 @@
+//! Prometheus metrics of the worker.
+//!
+//! Only compiled with the `metrics` feature. The metrics are served in the text exposition format
+//! on `http://<metrics_addr>/metrics`, see `Config::metrics_addr`.
+use caolo_sim::diagnostics::{Diagnostics, StatsField};
+use caolo_sim::prelude::*;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::{convert::Infallible, net::SocketAddr};
+use tracing::info;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+struct Metrics {
+    registry: Registry,
+    tick_latency: Histogram,
+    scripts_ran: IntCounter,
+    scripts_errored: IntCounter,
+    entities: IntGaugeVec,
+    users: IntGauge,
+    subscribers: IntGauge,
+    lagged_payloads: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("cao".to_owned()), None).unwrap();
+
+        let tick_latency = Histogram::with_opts(
+            HistogramOpts::new("tick_latency_ms", "Time it took to execute a tick").buckets(vec![
+                5., 10., 25., 50., 100., 200., 400., 800., 1600., 3200.,
+            ]),
+        )
+        .unwrap();
+        let scripts_ran =
+            IntCounter::new("scripts_ran_total", "Number of scripts executed").unwrap();
+        let scripts_errored =
+            IntCounter::new("scripts_errored_total", "Number of scripts that failed").unwrap();
+        let entities = IntGaugeVec::new(
+            Opts::new("entities", "Number of entities at the end of the last tick"),
+            &["kind"],
+        )
+        .unwrap();
+        let users = IntGauge::new("users", "Number of registered users").unwrap();
+        let subscribers = IntGauge::new(
+            "world_subscribers",
+            "Number of clients subscribed to the world entities",
+        )
+        .unwrap();
+        let lagged_payloads = IntCounter::new(
+            "world_lagged_payloads_total",
+            "Number of world payloads skipped by subscribers that lagged behind",
+        )
+        .unwrap();
+
+        registry.register(Box::new(tick_latency.clone())).unwrap();
+        registry.register(Box::new(scripts_ran.clone())).unwrap();
+        registry
+            .register(Box::new(scripts_errored.clone()))
+            .unwrap();
+        registry.register(Box::new(entities.clone())).unwrap();
+        registry.register(Box::new(users.clone())).unwrap();
+        registry.register(Box::new(subscribers.clone())).unwrap();
+        registry
+            .register(Box::new(lagged_payloads.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            tick_latency,
+            scripts_ran,
+            scripts_errored,
+            entities,
+            users,
+            subscribers,
+            lagged_payloads,
+        }
+    }
+}
+
+/// Record the state of the `world` after a tick
+pub fn record_tick(world: &World, subscribers: usize) {
+    let metrics = &*METRICS;
+    metrics.subscribers.set(subscribers as i64);
+    metrics
+        .users
+        .set(world.view::<UserId, UserComponent>().iter().count() as i64);
+
+    let diagnostics = world.view::<EmptyKey, Diagnostics>();
+    let diag = match diagnostics.value.as_ref() {
+        Some(diag) => diag,
+        None => return,
+    };
+    if let Some(latency) = current(diag.tick_latency()) {
+        metrics.tick_latency.observe(latency);
+    }
+    if let Some(ran) = current(diag.scripts_ran()) {
+        metrics.scripts_ran.inc_by(ran as u64);
+    }
+    if let Some(errored) = current(diag.scripts_error()) {
+        metrics.scripts_errored.inc_by(errored as u64);
+    }
+    for (kind, stats) in diag.entities_by_kind() {
+        if let Some(count) = current(stats) {
+            metrics
+                .entities
+                .with_label_values(&[kind])
+                .set(count as i64);
+        }
+    }
+}
+
+/// Record that a subscriber skipped `payloads` number of world payloads
+pub fn record_lag(payloads: u64) {
+    METRICS.lagged_payloads.inc_by(payloads);
+}
+
+/// Stats are NaN before their first update
+fn current(stats: &StatsField) -> Option<f64> {
+    let current = stats.current();
+    (!current.is_nan()).then(|| current)
+}
+
+fn encode() -> Vec<u8> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::with_capacity(4096);
+    encoder
+        .encode(&METRICS.registry.gather(), &mut buffer)
+        .expect("Failed to encode metrics");
+    buffer
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match req.uri().path() {
+        "/metrics" => Response::builder()
+            .header(
+                hyper::header::CONTENT_TYPE,
+                TextEncoder::new().format_type(),
+            )
+            .body(Body::from(encode())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.expect("Failed to build the response"))
+}
+
+/// Serve the metrics on `addr` until the server fails
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    info!("Serving metrics on http://{}/metrics", addr);
+    let make_service = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+    Server::bind(&addr).serve(make_service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_recorded_after_a_tick() {
+        let mut exc = SimpleExecutor;
+        let mut w = exc.initialize(GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *w, 12);
+
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+
+        record_tick(&w, 3);
+        record_lag(2);
+
+        let text = String::from_utf8(encode()).unwrap();
+        assert!(text.contains("cao_tick_latency_ms_count"));
+        assert!(text.contains("cao_world_subscribers 3"));
+        assert!(text.contains(r#"cao_entities{kind="bots"}"#));
+    }
+}
@@ -17,3 +17,7 @@ pub mod cao_world {
 pub mod cao_intents {
     tonic::include_proto!("cao_intents");
 }
+
+pub mod cao_admin {
+    tonic::include_proto!("cao_admin");
+}
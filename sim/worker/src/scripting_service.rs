@@ -1,3 +1,4 @@
+use crate::auth::Auth;
 use crate::input::script_update;
 use crate::protos::cao_common;
 use crate::protos::cao_script;
@@ -9,6 +10,7 @@ use tracing::debug;
 #[derive(Clone)]
 pub struct ScriptingService {
     world: std::sync::Arc<tokio::sync::Mutex<crate::World>>,
+    auth: Auth,
 }
 
 impl std::fmt::Debug for ScriptingService {
@@ -18,8 +20,8 @@ impl std::fmt::Debug for ScriptingService {
 }
 
 impl ScriptingService {
-    pub fn new(world: std::sync::Arc<tokio::sync::Mutex<crate::World>>) -> Self {
-        Self { world }
+    pub fn new(world: std::sync::Arc<tokio::sync::Mutex<crate::World>>, auth: Auth) -> Self {
+        Self { world, auth }
     }
 }
 
@@ -27,8 +29,9 @@ impl ScriptingService {
 impl cao_script::scripting_server::Scripting for ScriptingService {
     async fn list_scripts(
         &self,
-        _request: tonic::Request<cao_script::Empty>,
+        request: tonic::Request<cao_script::Empty>,
     ) -> Result<tonic::Response<cao_script::ScriptList>, tonic::Status> {
+        self.auth.caller(request.metadata())?;
         let ids;
         {
             let w = self.world.lock().await;
@@ -51,6 +54,7 @@ impl cao_script::scripting_server::Scripting for ScriptingService {
         &self,
         request: tonic::Request<cao_common::Uuid>,
     ) -> Result<tonic::Response<cao_script::CompilationUnit>, tonic::Status> {
+        self.auth.caller(request.metadata())?;
         let msg: &cao_common::Uuid = request.get_ref();
         let id = msg.data.as_slice();
         let id = uuid::Uuid::from_slice(id).map_err(|err| {
@@ -79,6 +83,8 @@ impl cao_script::scripting_server::Scripting for ScriptingService {
         &self,
         request: tonic::Request<cao_script::UpdateEntityScriptCommand>,
     ) -> Result<tonic::Response<cao_script::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
         let mut w = self.world.lock().await;
         script_update::update_entity_script(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_script::CommandResult {}))
@@ -89,6 +95,8 @@ impl cao_script::scripting_server::Scripting for ScriptingService {
         &self,
         request: tonic::Request<cao_script::UpdateScriptCommand>,
     ) -> Result<tonic::Response<cao_script::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
         let mut w = self.world.lock().await;
         script_update::update_program(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_script::CommandResult {}))
@@ -99,6 +107,8 @@ impl cao_script::scripting_server::Scripting for ScriptingService {
         &self,
         request: tonic::Request<cao_script::SetDefaultScriptCommand>,
     ) -> Result<tonic::Response<cao_script::CommandResult>, tonic::Status> {
+        self.auth
+            .authorize_user(request.metadata(), request.get_ref().user_id.as_ref())?;
         let mut w = self.world.lock().await;
         script_update::set_default_script(&mut *w, request.get_ref())
             .map(|_: ()| Response::new(cao_script::CommandResult {}))
@@ -109,6 +119,7 @@ impl cao_script::scripting_server::Scripting for ScriptingService {
         &self,
         request: tonic::Request<cao_script::EntityId>,
     ) -> Result<tonic::Response<cao_common::Uuid>, tonic::Status> {
+        self.auth.caller(request.metadata())?;
         let w = self.world.lock().await;
         match w
             .view::<caolo_sim::prelude::EntityId, caolo_sim::prelude::EntityScript>()
@@ -1,10 +1,17 @@
+mod room_cache;
 mod ser_bots;
+mod ser_diagnostics;
+mod ser_events;
 mod ser_resources;
 mod ser_structures;
 mod util;
 
-use caolo_sim::prelude::{Axial, Hexagon, TerrainComponent, World};
-use std::collections::HashMap;
+pub use room_cache::{RoomCache, SharedRoomCache};
+
+use caolo_sim::lifecycle::EntityDeletions;
+use caolo_sim::map_generation::biome::Biome;
+use caolo_sim::prelude::{Axial, EmptyKey, Hexagon, TerrainComponent, UserId, Visibility, World};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::{
     broadcast::{error::RecvError, Sender},
@@ -14,14 +21,17 @@ use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
 use tracing::{info, log::warn, Instrument};
 
+use crate::auth::Auth;
 use crate::protos::cao_common;
 use crate::protos::cao_world;
 
 #[derive(Clone)]
 pub struct WorldService {
+    world: Arc<tokio::sync::Mutex<crate::World>>,
     entities: WorldPayloadSender,
     room_bounds: Hexagon,
-    terrain: Arc<HashMap<Axial, Vec<TerrainComponent>>>,
+    rooms: SharedRoomCache,
+    auth: Auth,
     tracing_span: tracing::Span,
 }
 
@@ -36,19 +46,28 @@ type WorldPayloadSender = Arc<Sender<Arc<Payload>>>;
 #[derive(Default, Debug)]
 pub struct Payload {
     pub payload_by_room: HashMap<Axial, cao_world::RoomEntities>,
+    pub diagnostics: cao_world::Diagnostics,
+    pub events: cao_world::GameEvents,
+    /// Ids of the entities visible to the users, including the entities deleted in the tick that
+    /// were visible before the deletion
+    pub visibility: HashMap<UserId, HashSet<i64>>,
 }
 
 impl WorldService {
     pub fn new(
+        world: Arc<tokio::sync::Mutex<crate::World>>,
         entities: WorldPayloadSender,
         room_bounds: Hexagon,
-        terrain: Arc<HashMap<Axial, Vec<TerrainComponent>>>,
+        rooms: SharedRoomCache,
+        auth: Auth,
         span: tracing::Span,
     ) -> Self {
         Self {
+            world,
             entities,
             room_bounds,
-            terrain,
+            rooms,
+            auth,
             tracing_span: span,
         }
     }
@@ -70,31 +89,99 @@ impl Payload {
             &mut self.payload_by_room,
             caolo_sim::prelude::FromWorld::from_world(world),
         );
+        self.diagnostics =
+            ser_diagnostics::diagnostics_payload(caolo_sim::prelude::FromWorld::from_world(world));
+        self.events = ser_events::events_payload(caolo_sim::prelude::FromWorld::from_world(world));
+        self.visibility = world
+            .view::<UserId, Visibility>()
+            .iter()
+            .map(|(user_id, Visibility(ids))| (user_id, ids.iter().map(|id| id.0 as i64).collect()))
+            .collect();
+        // the entities deleted in this tick are no longer in `Visibility`, but the users that saw
+        // them must still receive the events about them
+        if let Some(EntityDeletions(deletions)) =
+            world.view::<EmptyKey, EntityDeletions>().value.as_ref()
+        {
+            for deletion in deletions.iter() {
+                for user_id in deletion.seen_by.iter() {
+                    self.visibility
+                        .entry(*user_id)
+                        .or_default()
+                        .insert(deletion.id.0 as i64);
+                }
+            }
+        }
     }
 }
 
+/// Filter the entities of a room to the ones in `visible`.
+/// The result is empty if the user can not see anything in the room
+fn visible_entities(
+    pl: &cao_world::RoomEntities,
+    visible: &HashSet<i64>,
+) -> cao_world::RoomEntities {
+    let mut pl = pl.clone();
+    pl.bots.retain(|bot| visible.contains(&bot.id));
+    pl.structures
+        .retain(|structure| visible.contains(&structure.id));
+    pl.resources
+        .retain(|resource| visible.contains(&resource.id));
+    pl
+}
+
+/// Filter the events to the ones involving an entity in `visible`
+fn visible_events(events: &cao_world::GameEvents, visible: &HashSet<i64>) -> cao_world::GameEvents {
+    let mut events = events.clone();
+    events
+        .events
+        .retain(|event| ser_events::event_entities(event).any(|id| visible.contains(&id)));
+    events
+}
+
 #[tonic::async_trait]
 impl cao_world::world_server::World for WorldService {
     type EntitiesStream = ReceiverStream<Result<cao_world::RoomEntities, Status>>;
+    type StreamDiagnosticsStream = ReceiverStream<Result<cao_world::Diagnostics, Status>>;
+    type EventsStream = ReceiverStream<Result<cao_world::GameEvents, Status>>;
 
     async fn entities(
         &self,
-        _r: tonic::Request<cao_world::Empty>,
+        r: tonic::Request<cao_world::EntitiesRequest>,
     ) -> Result<tonic::Response<Self::EntitiesStream>, tonic::Status> {
-        let addr = _r.remote_addr();
+        let addr = r.remote_addr();
+        // only services may see every entity
+        match r.get_ref().user_id.as_ref() {
+            Some(user_id) => {
+                self.auth.authorize_user(r.metadata(), Some(user_id))?;
+            }
+            None => self.auth.authorize_service(r.metadata())?,
+        }
+        let user_id = match r.get_ref().user_id.as_ref() {
+            Some(user_id) => Some(UserId(
+                uuid::Uuid::from_slice(user_id.data.as_slice())
+                    .map_err(|err| Status::invalid_argument(err.to_string()))?,
+            )),
+            None => None,
+        };
 
-        info!("Subscribing new client to world entities. Addr: {:?}", addr);
+        info!(
+            "Subscribing new client to world entities. Addr: {:?} User: {:?}",
+            addr, user_id
+        );
 
         let (tx, rx) = mpsc::channel(4);
 
         let mut entities_rx = self.entities.subscribe();
         tokio::spawn(
             async move {
+                let nothing_visible = HashSet::new();
                 'main_send: loop {
                     let w = match entities_rx.recv().await {
                         Ok(w) => w,
                         Err(RecvError::Lagged(l)) => {
                             warn!("Entities stream is lagging behind by {} messages", l);
+                            #[cfg(feature = "metrics")]
+                            crate::metrics::record_lag(l);
                             continue 'main_send;
                         }
                         Err(RecvError::Closed) => {
@@ -102,8 +189,18 @@ impl cao_world::world_server::World for WorldService {
                             break 'main_send;
                         }
                     };
+                    // clients without a user see every entity
+                    let visible = user_id.map(|id| w.visibility.get(&id));
                     for (_, pl) in w.payload_by_room.iter() {
-                        if tx.send(Ok(pl.clone())).await.is_err() {
+                        // rooms without visible entities are sent too, so clients drop the
+                        // entities they saw there in the previous tick
+                        let pl = match visible {
+                            None => pl.clone(),
+                            Some(visible) => {
+                                visible_entities(pl, visible.unwrap_or(&nothing_visible))
+                            }
+                        };
+                        if tx.send(Ok(pl)).await.is_err() {
                             info!("World entities client lost {:?}", addr);
                             break 'main_send;
                         }
@@ -138,7 +235,8 @@ impl cao_world::world_server::World for WorldService {
         let q = request.get_ref().q;
         let r = request.get_ref().r;
         let p = Axial::new(q, r);
-        let room = self
+        let rooms = self.rooms.read().await;
+        let room = rooms
             .terrain
             .get(&p)
             .ok_or_else(|| tonic::Status::not_found("Room does not exist"))?;
@@ -162,7 +260,8 @@ impl cao_world::world_server::World for WorldService {
         &self,
         _: tonic::Request<cao_world::Empty>,
     ) -> Result<tonic::Response<cao_world::RoomList>, tonic::Status> {
-        let room_ids = self
+        let cache = self.rooms.read().await;
+        let room_ids = cache
             .terrain
             .keys()
             .map(|point| cao_common::Axial {
@@ -170,7 +269,150 @@ impl cao_world::world_server::World for WorldService {
                 r: point.r,
             })
             .collect();
-        Ok(tonic::Response::new(cao_world::RoomList { room_ids }))
+        let rooms = cache
+            .terrain
+            .keys()
+            .map(|point| {
+                let biome = cache.biomes.get(point).copied().unwrap_or_default();
+                cao_world::RoomInfo {
+                    room_id: Some(cao_common::Axial {
+                        q: point.q,
+                        r: point.r,
+                    }),
+                    biome: biome_to_pb(biome).into(),
+                }
+            })
+            .collect();
+        Ok(tonic::Response::new(cao_world::RoomList {
+            room_ids,
+            rooms,
+        }))
+    }
+
+    async fn get_diagnostics(
+        &self,
+        _: tonic::Request<cao_world::Empty>,
+    ) -> Result<tonic::Response<cao_world::Diagnostics>, tonic::Status> {
+        let world = self.world.lock().await;
+        let diagnostics = ser_diagnostics::diagnostics_payload(
+            caolo_sim::prelude::FromWorld::from_world(&**world),
+        );
+        Ok(tonic::Response::new(diagnostics))
+    }
+
+    async fn stream_diagnostics(
+        &self,
+        r: tonic::Request<cao_world::Empty>,
+    ) -> Result<tonic::Response<Self::StreamDiagnosticsStream>, tonic::Status> {
+        let addr = r.remote_addr();
+
+        info!("Subscribing new client to diagnostics. Addr: {:?}", addr);
+
+        let (tx, rx) = mpsc::channel(4);
+
+        let mut entities_rx = self.entities.subscribe();
+        tokio::spawn(
+            async move {
+                loop {
+                    let w = match entities_rx.recv().await {
+                        Ok(w) => w,
+                        Err(RecvError::Lagged(l)) => {
+                            warn!("Diagnostics stream is lagging behind by {} messages", l);
+                            #[cfg(feature = "metrics")]
+                            crate::metrics::record_lag(l);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            warn!("Entities channel was closed");
+                            break;
+                        }
+                    };
+                    if tx.send(Ok(w.diagnostics.clone())).await.is_err() {
+                        info!("Diagnostics client lost {:?}", addr);
+                        break;
+                    }
+                }
+            }
+            .instrument(self.tracing_span.clone()),
+        );
+
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn events(
+        &self,
+        r: tonic::Request<cao_world::EventsRequest>,
+    ) -> Result<tonic::Response<Self::EventsStream>, tonic::Status> {
+        let addr = r.remote_addr();
+        // only services may see every event
+        match r.get_ref().user_id.as_ref() {
+            Some(user_id) => {
+                self.auth.authorize_user(r.metadata(), Some(user_id))?;
+            }
+            None => self.auth.authorize_service(r.metadata())?,
+        }
+        let user_id = match r.get_ref().user_id.as_ref() {
+            Some(user_id) => Some(UserId(
+                uuid::Uuid::from_slice(user_id.data.as_slice())
+                    .map_err(|err| Status::invalid_argument(err.to_string()))?,
+            )),
+            None => None,
+        };
+
+        info!(
+            "Subscribing new client to game events. Addr: {:?} User: {:?}",
+            addr, user_id
+        );
+
+        let (tx, rx) = mpsc::channel(4);
+
+        let mut entities_rx = self.entities.subscribe();
+        tokio::spawn(
+            async move {
+                loop {
+                    let w = match entities_rx.recv().await {
+                        Ok(w) => w,
+                        Err(RecvError::Lagged(l)) => {
+                            warn!("Events stream is lagging behind by {} messages", l);
+                            #[cfg(feature = "metrics")]
+                            crate::metrics::record_lag(l);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            warn!("Entities channel was closed");
+                            break;
+                        }
+                    };
+                    let events = match user_id {
+                        None => w.events.clone(),
+                        // users without visibility see nothing
+                        Some(user_id) => match w.visibility.get(&user_id) {
+                            Some(visible) => visible_events(&w.events, visible),
+                            None => cao_world::GameEvents {
+                                world_time: w.events.world_time,
+                                events: Vec::new(),
+                            },
+                        },
+                    };
+                    if tx.send(Ok(events)).await.is_err() {
+                        info!("Events client lost {:?}", addr);
+                        break;
+                    }
+                }
+            }
+            .instrument(self.tracing_span.clone()),
+        );
+
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+fn biome_to_pb(biome: Biome) -> cao_world::Biome {
+    match biome {
+        Biome::Plains => cao_world::Biome::Plains,
+        Biome::Hills => cao_world::Biome::Hills,
+        Biome::Mountains => cao_world::Biome::Mountains,
+        Biome::Wastes => cao_world::Biome::Wastes,
     }
 }
 
@@ -195,4 +437,210 @@ mod tests {
 
         assert!(!pl.payload_by_room.is_empty());
     }
+
+    #[test]
+    fn users_only_receive_their_visible_entities() {
+        let mut exc = caolo_sim::prelude::SimpleExecutor;
+        let mut w = exc.initialize(caolo_sim::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *w, 12);
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+
+        let mut pl = Payload::default();
+        pl.update(&w);
+
+        let (user_id, visible) = pl
+            .visibility
+            .iter()
+            .find(|(_, visible)| !visible.is_empty())
+            .expect("a user with visible entities");
+        let filtered: Vec<_> = pl
+            .payload_by_room
+            .values()
+            .map(|room| visible_entities(room, visible))
+            .collect();
+
+        assert_eq!(filtered.len(), pl.payload_by_room.len());
+        assert!(
+            filtered.iter().any(|room| !room.bots.is_empty()),
+            "{:?} sees nothing",
+            user_id
+        );
+        let total: usize = pl
+            .payload_by_room
+            .values()
+            .map(|room| room.bots.len())
+            .sum();
+        let seen: usize = filtered.iter().map(|room| room.bots.len()).sum();
+        assert!(seen < total);
+        for room in filtered.iter() {
+            for bot in room.bots.iter() {
+                assert!(visible.contains(&bot.id));
+            }
+        }
+    }
+
+    #[test]
+    fn diagnostics_are_reported_after_a_tick() {
+        let mut exc = caolo_sim::prelude::SimpleExecutor;
+        let mut w = exc.initialize(caolo_sim::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *w, 12);
+
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+
+        let mut pl = Payload::default();
+        pl.update(&w);
+
+        let diag = &pl.diagnostics;
+        assert_eq!(diag.current.as_ref().unwrap().tick, w.time());
+        assert!(diag.systems.contains_key("decay_update"));
+        assert!(diag.intents.contains_key("move"));
+        assert!(diag.entities["bots"].current > 0.0);
+    }
+
+    #[test]
+    fn events_are_filtered_by_visibility() {
+        use caolo_sim::events::{Attacked, Events};
+        use caolo_sim::prelude::{Bot, EmptyKey, EntityId};
+
+        let mut exc = caolo_sim::prelude::SimpleExecutor;
+        let mut w = exc.initialize(caolo_sim::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *w, 12);
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+
+        let mut pl = Payload::default();
+        pl.update(&w);
+        let (user_id, visible) = pl
+            .visibility
+            .iter()
+            .find(|(_, visible)| !visible.is_empty())
+            .map(|(id, visible)| (*id, visible.clone()))
+            .expect("a user with visible entities");
+        let seen = w
+            .view::<UserId, Visibility>()
+            .get_by_id(user_id)
+            .and_then(|Visibility(ids)| ids.iter().next().copied())
+            .unwrap();
+        let unseen = w
+            .view::<EntityId, Bot>()
+            .iter()
+            .map(|(id, _)| id)
+            .find(|id| !visible.contains(&(id.0 as i64)))
+            .expect("an entity the user can not see");
+
+        let mut attacks = w.unsafe_view::<EmptyKey, Events<Attacked>>();
+        let attacks = attacks.value.as_mut().unwrap();
+        attacks.0.clear();
+        attacks.0.push(Attacked {
+            attacker: unseen,
+            defender: seen,
+            damage: 1,
+        });
+        attacks.0.push(Attacked {
+            attacker: unseen,
+            defender: unseen,
+            damage: 1,
+        });
+        pl.update(&w);
+
+        assert_eq!(pl.events.events.len(), 2);
+        let filtered = visible_events(&pl.events, &visible);
+        assert_eq!(filtered.events.len(), 1);
+        assert_eq!(filtered.world_time, w.time() as i64);
+    }
+    #[test]
+    fn users_receive_the_events_of_their_dead_entities() {
+        use caolo_sim::events::{Attacked, Events};
+        use caolo_sim::prelude::{Bot, EntityId, HpComponent, OwnedEntity};
+
+        let mut exc = caolo_sim::prelude::SimpleExecutor;
+        let mut w = exc.initialize(caolo_sim::executor::GameConfig {
+            world_radius: 2,
+            room_radius: 10,
+            ..Default::default()
+        });
+        caolo_sim::init::init_world_entities(&mut *w, 12);
+        let rt = caolo_sim::RuntimeGuard::new();
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+
+        let (defender, user_id) = w
+            .view::<EntityId, Bot>()
+            .iter()
+            .find_map(|(id, _)| {
+                w.view::<EntityId, OwnedEntity>()
+                    .get_by_id(id)
+                    .map(|owner| (id, owner.owner_id))
+            })
+            .expect("an owned bot");
+        let attacker = w
+            .view::<EntityId, Bot>()
+            .iter()
+            .map(|(id, _)| id)
+            .find(|id| {
+                !w.view::<UserId, Visibility>()
+                    .get_by_id(user_id)
+                    .unwrap()
+                    .contains(*id)
+            })
+            .expect("an entity the user can not see");
+
+        w.unsafe_view::<EntityId, HpComponent>()
+            .get_by_id_mut(defender)
+            .unwrap()
+            .hp = 0;
+        rt.block_on(exc.forward(&mut *w)).unwrap();
+        assert!(!w.is_alive(defender));
+        assert!(!w
+            .view::<UserId, Visibility>()
+            .get_by_id(user_id)
+            .map(|v| v.contains(defender))
+            .unwrap_or(false));
+
+        w.unsafe_view::<EmptyKey, Events<Attacked>>()
+            .value
+            .as_mut()
+            .unwrap()
+            .0
+            .push(Attacked {
+                attacker,
+                defender,
+                damage: 1,
+            });
+        let mut pl = Payload::default();
+        pl.update(&w);
+
+        let filtered = visible_events(&pl.events, &pl.visibility[&user_id]);
+        let died = filtered
+            .events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.event,
+                    Some(cao_world::game_event::Event::EntityDied(ref died))
+                        if died.id == defender.0 as i64
+                )
+            })
+            .count();
+        assert_eq!(died, 1);
+        let attacked = filtered
+            .events
+            .iter()
+            .filter(|e| matches!(e.event, Some(cao_world::game_event::Event::Attacked(_))))
+            .count();
+        assert_eq!(attacked, 1);
+    }
 }
@@ -0,0 +1,41 @@
+use caolo_sim::map_generation::biome::Biome;
+use caolo_sim::prelude::{Axial, BiomeComponent, TerrainComponent, World, WorldPosition};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type SharedRoomCache = Arc<tokio::sync::RwLock<RoomCache>>;
+
+/// Room level data that only changes when the map changes.
+/// Cached so room queries don't have to lock the World.
+#[derive(Debug, Default)]
+pub struct RoomCache {
+    pub terrain: HashMap<Axial, Vec<TerrainComponent>>,
+    pub biomes: HashMap<Axial, Biome>,
+}
+
+impl RoomCache {
+    pub fn from_world(world: &World) -> Self {
+        let mut res = Self::default();
+        res.refresh(world);
+        res
+    }
+
+    /// Rebuild the cache, call after the map changed
+    pub fn refresh(&mut self, world: &World) {
+        self.terrain = world
+            .view::<WorldPosition, TerrainComponent>()
+            .iter_rooms()
+            .map(|(room_id, room_terrain)| {
+                (
+                    room_id.0,
+                    room_terrain.iter().map(|(_, t)| t).copied().collect(),
+                )
+            })
+            .collect();
+        self.biomes = world
+            .view::<Axial, BiomeComponent>()
+            .iter()
+            .map(|(room_id, BiomeComponent(biome))| (room_id, *biome))
+            .collect();
+    }
+}
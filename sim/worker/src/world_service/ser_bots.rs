@@ -66,7 +66,7 @@ pub fn bot_payload(
             if bots.contains_id(entity_id) {
                 let entity_id = *entity_id;
                 accumulator.push(cao_world::Bot {
-                    id: entity_id.0.into(),
+                    id: entity_id.0 as i64,
                     pos: Some(cao_common::Axial { q: pos.q, r: pos.r }),
                     hp: hp
                         .get_by_id(entity_id)
@@ -124,7 +124,7 @@ pub fn bot_payload(
                             target_id: pl.0 as i64,
                         },
                     ),
-                    mine_intent: mine.get_by_id(entity_id).map(|MineEventComponent(pl)| {
+                    mine_intent: mine.get_by_id(entity_id).map(|MineEventComponent(pl, _)| {
                         cao_intents::MineIntent {
                             target_id: pl.0 as i64,
                         }
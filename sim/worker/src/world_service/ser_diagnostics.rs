@@ -0,0 +1,83 @@
+use crate::protos::cao_world;
+use caolo_sim::diagnostics::{Diagnostics, StatsField};
+use caolo_sim::prelude::*;
+use std::collections::HashMap;
+
+type DiagnosticsTables<'a> = (View<'a, EmptyKey, Diagnostics>, WorldTime);
+
+pub fn diagnostics_payload(
+    (diagnostics, WorldTime(time)): DiagnosticsTables,
+) -> cao_world::Diagnostics {
+    let diag = match diagnostics.value.as_ref() {
+        Some(diag) => diag,
+        None => {
+            // no tick was executed yet
+            return cao_world::Diagnostics {
+                current: Some(cao_world::diagnostics::Current {
+                    tick: time,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+        }
+    };
+
+    let tick = diag.tick_latency();
+    let [tick_min, tick_max] = tick.min_max();
+    let number_of_intents = diag
+        .intents_by_type()
+        .map(|(_, stats)| current_or_zero(stats))
+        .sum::<f64>();
+
+    cao_world::Diagnostics {
+        current: Some(cao_world::diagnostics::Current {
+            tick: time,
+            tick_latency_ms: current_or_zero(tick) as i64,
+            scripts_execution_ms: current_or_zero(diag.scripts_execution_time()) as i64,
+            systems_update_ms: current_or_zero(diag.systems_time()) as i64,
+            number_of_scripts_ran: current_or_zero(diag.scripts_ran()) as u64,
+            number_of_scripts_errored: current_or_zero(diag.scripts_error()) as u64,
+            number_of_intents: number_of_intents as u64,
+        }),
+        accumulated: Some(cao_world::diagnostics::Accumulated {
+            tick_latency_mean: tick.mean(),
+            tick_latency_std: tick.std(),
+            tick_latency_min: tick_min as i64,
+            tick_latency_max: tick_max as i64,
+            tick_latency_count: tick.count(),
+        }),
+        systems: stats_by_name(diag.systems_by_name()),
+        intents: stats_by_name(diag.intents_by_type()),
+        entities: stats_by_name(diag.entities_by_kind()),
+    }
+}
+
+/// Stats are NaN before their first update
+fn current_or_zero(stats: &StatsField) -> f64 {
+    let current = stats.current();
+    if current.is_nan() {
+        0.0
+    } else {
+        current
+    }
+}
+
+fn stats_by_name<'a>(
+    stats: impl Iterator<Item = (&'a str, &'a StatsField)>,
+) -> HashMap<String, cao_world::StatsField> {
+    stats
+        .map(|(name, stats)| {
+            let [min, max] = stats.min_max();
+            let pl = cao_world::StatsField {
+                name: stats.name().to_owned(),
+                current: current_or_zero(stats),
+                min,
+                max,
+                mean: stats.mean(),
+                std: stats.std(),
+                count: stats.count(),
+            };
+            (name.to_owned(), pl)
+        })
+        .collect()
+}
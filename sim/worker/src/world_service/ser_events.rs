@@ -0,0 +1,128 @@
+use crate::protos::cao_common;
+use crate::protos::cao_world;
+use cao_world::game_event::{self, Event};
+use caolo_sim::events::*;
+use caolo_sim::prelude::*;
+
+type EventTables<'a> = (
+    View<'a, EmptyKey, Events<EntityDied>>,
+    View<'a, EmptyKey, Events<Attacked>>,
+    View<'a, EmptyKey, Events<Mined>>,
+    View<'a, EmptyKey, Events<DroppedOff>>,
+    View<'a, EmptyKey, Events<Spawned>>,
+    View<'a, EmptyKey, Events<MoveFailed>>,
+    WorldTime,
+);
+
+pub fn events_payload(
+    (died, attacked, mined, dropped_off, spawned, move_failed, WorldTime(time)): EventTables,
+) -> cao_world::GameEvents {
+    let events = died
+        .value
+        .iter()
+        .flat_map(|events| events.iter())
+        .map(|e| Event::EntityDied(game_event::EntityDied { id: e.id.0 as i64 }))
+        .chain(
+            attacked
+                .value
+                .iter()
+                .flat_map(|events| events.iter())
+                .map(|e| {
+                    Event::Attacked(game_event::Attacked {
+                        attacker: e.attacker.0 as i64,
+                        defender: e.defender.0 as i64,
+                        damage: e.damage.into(),
+                    })
+                }),
+        )
+        .chain(
+            mined
+                .value
+                .iter()
+                .flat_map(|events| events.iter())
+                .map(|e| {
+                    Event::Mined(game_event::Mined {
+                        bot: e.bot.0 as i64,
+                        resource: e.resource.0 as i64,
+                        amount: e.amount.into(),
+                    })
+                }),
+        )
+        .chain(
+            dropped_off
+                .value
+                .iter()
+                .flat_map(|events| events.iter())
+                .map(|e| {
+                    Event::DroppedOff(game_event::DroppedOff {
+                        bot: e.bot.0 as i64,
+                        structure: e.structure.0 as i64,
+                        amount: e.amount.into(),
+                    })
+                }),
+        )
+        .chain(
+            spawned
+                .value
+                .iter()
+                .flat_map(|events| events.iter())
+                .map(|e| {
+                    Event::Spawned(game_event::Spawned {
+                        spawn: e.spawn.0 as i64,
+                        bot: e.bot.0 as i64,
+                    })
+                }),
+        )
+        .chain(
+            move_failed
+                .value
+                .iter()
+                .flat_map(|events| events.iter())
+                .map(|e| {
+                    Event::MoveFailed(game_event::MoveFailed {
+                        bot: e.bot.0 as i64,
+                        position: Some(cao_common::WorldPosition {
+                            room: Some(cao_common::Axial {
+                                q: e.position.room.q,
+                                r: e.position.room.r,
+                            }),
+                            pos: Some(cao_common::Axial {
+                                q: e.position.pos.q,
+                                r: e.position.pos.r,
+                            }),
+                        }),
+                        reason: move_failure_to_pb(e.reason).into(),
+                    })
+                }),
+        )
+        .map(|event| cao_world::GameEvent { event: Some(event) })
+        .collect();
+
+    cao_world::GameEvents {
+        world_time: time as i64,
+        events,
+    }
+}
+
+/// Ids of the entities taking part in the event
+pub fn event_entities(event: &cao_world::GameEvent) -> impl Iterator<Item = i64> {
+    let ids = match event.event.as_ref() {
+        Some(Event::EntityDied(e)) => [Some(e.id), None],
+        Some(Event::Attacked(e)) => [Some(e.attacker), Some(e.defender)],
+        Some(Event::Mined(e)) => [Some(e.bot), Some(e.resource)],
+        Some(Event::DroppedOff(e)) => [Some(e.bot), Some(e.structure)],
+        Some(Event::Spawned(e)) => [Some(e.spawn), Some(e.bot)],
+        Some(Event::MoveFailed(e)) => [Some(e.bot), None],
+        None => [None, None],
+    };
+    std::array::IntoIter::new(ids).flatten()
+}
+
+fn move_failure_to_pb(reason: MoveFailure) -> game_event::MoveFailure {
+    match reason {
+        MoveFailure::NotABot => game_event::MoveFailure::NotABot,
+        MoveFailure::Occupied => game_event::MoveFailure::Occupied,
+        MoveFailure::InvalidPosition => game_event::MoveFailure::InvalidPosition,
+        MoveFailure::LostTieBreak => game_event::MoveFailure::LostTieBreak,
+    }
+}
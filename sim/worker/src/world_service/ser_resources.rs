@@ -6,7 +6,7 @@ use crate::protos::cao_world;
 use caolo_sim::prelude::*;
 
 type ResourceTables<'a> = (
-    View<'a, WorldPosition, EntityComponent>,
+    View<'a, WorldPosition, LayeredEntity>,
     View<'a, EntityId, ResourceComponent>,
     View<'a, EntityId, EnergyComponent>,
     WorldTime,
@@ -37,14 +37,14 @@ pub fn resource_payload(
             room = Some(next_room);
             accumulator.clear();
         }
-        for (pos, EntityComponent(entity_id)) in entities.iter() {
+        for (pos, LayeredEntity { id: entity_id, .. }) in entities.iter() {
             let entity_id = *entity_id;
             if let Some(resource) = resource.get_by_id(entity_id) {
                 match resource.0 {
                     Resource::Empty => {}
                     Resource::Energy => {
                         accumulator.push(cao_world::Resource {
-                            id: entity_id.0.into(),
+                            id: entity_id.0 as i64,
                             pos: Some(cao_common::Axial { q: pos.q, r: pos.r }),
                             resource_type: energy.get_by_id(entity_id).copied().map(
                                 |EnergyComponent { energy, energy_max }: EnergyComponent| {
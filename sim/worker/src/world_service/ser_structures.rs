@@ -46,7 +46,7 @@ pub fn structure_payload(
             if structures.contains_id(entity_id) {
                 let entity_id = *entity_id;
                 let mut pl = cao_world::Structure {
-                    id: entity_id.0.into(),
+                    id: entity_id.0 as i64,
                     pos: Some(cao_common::Axial { q: pos.q, r: pos.r }),
                     hp: hp
                         .get_by_id(entity_id)
@@ -80,7 +80,7 @@ pub fn structure_payload(
                 if let Some(spawn) = spawn.get_by_id(entity_id) {
                     pl.structure_type = Some(cao_world::structure::StructureType::Spawn(
                         cao_world::structure::Spawn {
-                            spawning: spawn.spawning.map(|EntityId(id)| id.into()).unwrap_or(-1),
+                            spawning: spawn.spawning.map(|EntityId(id)| id as i64).unwrap_or(-1),
                             time_to_spawn: spawn.time_to_spawn.into(),
                             spawn_queue: spawn_q
                                 .get_by_id(entity_id)
@@ -88,7 +88,7 @@ pub fn structure_payload(
                                     queue
                                         .iter()
                                         .copied()
-                                        .map(|EntityId(id)| id.into())
+                                        .map(|EntityId(id)| id as i64)
                                         .collect()
                                 })
                                 .unwrap_or_default(),